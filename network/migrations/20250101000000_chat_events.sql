@@ -0,0 +1,12 @@
+-- nic_chat 방 이벤트 기록
+CREATE TABLE IF NOT EXISTS chat_events (
+    id          BIGSERIAL PRIMARY KEY,
+    room        TEXT        NOT NULL,
+    kind        TEXT        NOT NULL CHECK (kind IN ('message', 'join', 'part', 'quit', 'nick')),
+    nick        TEXT        NOT NULL,
+    body        TEXT,
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- /history: 방별 최근 N개
+CREATE INDEX IF NOT EXISTS chat_events_room_id_idx ON chat_events (room, id DESC);
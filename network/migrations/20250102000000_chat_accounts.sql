@@ -0,0 +1,22 @@
+-- 채팅 계정. nick은 소문자로 저장
+CREATE TABLE IF NOT EXISTS chat_accounts (
+    nick           TEXT        PRIMARY KEY,
+    password_hash  TEXT        NOT NULL,
+    -- /token으로 발급한 접속 토큰 (argon2 hash)
+    token_hash     TEXT,
+    role           TEXT        NOT NULL DEFAULT 'user' CHECK (role IN ('user', 'admin')),
+    created_at     TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- /ban. expires_at이 NULL이면 영구
+CREATE TABLE IF NOT EXISTS chat_bans (
+    id          BIGSERIAL   PRIMARY KEY,
+    kind        TEXT        NOT NULL CHECK (kind IN ('nick', 'ip')),
+    target      TEXT        NOT NULL,
+    reason      TEXT        NOT NULL DEFAULT '',
+    banned_by   TEXT        NOT NULL,
+    expires_at  TIMESTAMPTZ,
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS chat_bans_target_idx ON chat_bans (kind, target);
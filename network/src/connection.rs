@@ -3,14 +3,22 @@ use dotenv::dotenv;
 use std::env;
 
 pub async fn connection() -> Pool<Postgres> {
+    try_connection()
+        .await
+        .expect("Failed to create pool.")
+        .expect("DATABASE_URL must be set")
+}
+
+// DATABASE_URL이 없으면 Ok(None). DB 없이도 돌아가야 하는 서버에서 쓴다
+pub async fn try_connection() -> Result<Option<Pool<Postgres>>, sqlx::Error> {
     dotenv().ok();
-    let database_url = env::var("DATABASE_URL")
-    .expect("DATABASE_URL must be set");
+    let Ok(database_url) = env::var("DATABASE_URL") else {
+        return Ok(None);
+    };
 
     let pool = PgPoolOptions::new()
         .max_connections(5)
         .connect(&database_url)
-        .await
-        .expect("Failed to create pool.");
-    pool
+        .await?;
+    Ok(Some(pool))
 }
@@ -1 +1,2 @@
-pub mod pnet;
\ No newline at end of file
+pub mod pnet;
+pub mod pnet2;
@@ -1,3 +1,6 @@
+use crate::tcp::conversation::ConversationTracker;
+use crate::tcp::stream_reassembly::{parse_segment, ReassemblyConfig};
+use crate::tcp::tls_fingerprint::{print_hello, TlsInspector};
 use pnet::datalink::{self, Channel::Ethernet};
 use pnet::packet::{
     ethernet::{EtherTypes, EthernetPacket},
@@ -6,6 +9,7 @@ use pnet::packet::{
     tcp::TcpPacket,
     Packet,
 };
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 fn tcp_flags_to_string(flags: u8) -> String {
     let mut parts = Vec::new();
@@ -42,7 +46,7 @@ fn tcp_flags_to_string(flags: u8) -> String {
     }
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+pub fn main() -> Result<(), Box<dyn std::error::Error>> {
     let interface_name = "en0";
 
     let interfaces = datalink::interfaces();
@@ -54,17 +58,34 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     println!("[INFO] listening on interface: {}", interface.name);
 
-    let mut config = datalink::Config::default();
-    config.read_timeout = None;
+    let config = datalink::Config {
+        read_timeout: None,
+        ..Default::default()
+    };
 
     let (_, mut rx) = match datalink::channel(&interface, config)? {
         Ethernet(tx, rx) => (tx, rx),
         _ => return Err("지원되지 않는 channel type".into()),
     };
 
+    // TCP payload는 재조립해서 TLS hello를 찾는다 (segment 여러 개에 걸쳐 와도 된다)
+    let mut tls =
+        ConversationTracker::new(ReassemblyConfig::default(), TlsInspector::new(print_hello));
+    let mut last_expire = Instant::now();
+
     loop {
         match rx.next() {
             Ok(packet) => {
+                let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+                if let Some(segment) = parse_segment(packet, now) {
+                    tls.process(&segment);
+                }
+
+                if last_expire.elapsed() >= Duration::from_secs(1) {
+                    tls.expire(now);
+                    last_expire = Instant::now();
+                }
+
                 // 1. Ethernet 프레임으로 해석
                 if let Some(eth) = EthernetPacket::new(packet) {
                     // 2. Ethernet 안에 IPv4가 들어있는 경우만 처리
@@ -94,4 +115,4 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
         }
     }
-}
\ No newline at end of file
+}
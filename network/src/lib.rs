@@ -8,5 +8,6 @@ pub mod rabbitmq;
 pub mod simd;
 pub mod tcp;
 pub mod udp;
+pub mod util;
 pub mod websocket;
 pub mod ethernet;
@@ -20,6 +20,22 @@ fn main() {
     // multi_tcp::main();
     // chat::main();
     // custom_protocol::main();
+    // tcp::packet::main();
+    // tcp::rpc::main();
+    // tcp::session::main();
+    // tcp::tcp_echo::tls_main();
+    // tcp::epoll::main().unwrap();
+    // tcp::stream_reassembly::main().unwrap();
+    // tcp::conversation::main().unwrap();
+    // tcp::http_sniffer::main().unwrap();
+    // tcp::tls_fingerprint::main().unwrap();
+    // ethernet::pnet2::main().unwrap();
+    // network::udp::dns::main().unwrap();
+    // network::udp::integration::main().unwrap();
+    // network::udp::dns_server::main().unwrap();
+    // network::udp::dns_resolver::main().unwrap();
+    // network::udp::dns_encrypted::main().unwrap();
+    // network::udp::flow::main().unwrap();
     // non_blocking::main();
     ethernet::pnet::main();
 }
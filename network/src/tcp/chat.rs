@@ -1,91 +1,181 @@
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::net::{TcpListener, TcpStream};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
 use tokio::sync::broadcast;
 
-async fn handle_client(
-    socket: TcpStream,
-    addr: std::net::SocketAddr,
-    tx: broadcast::Sender<String>,
-) {
+use std::sync::{Arc, Mutex};
+
+use super::slow_consumer::{self, ChatConfig, History, LineReader, Outbox};
+use super::tls;
+
+// 전체 채팅방 하나. 메시지마다 순번을 붙여 history에 남기고 broadcast한다
+struct ChatRoom {
+    tx: broadcast::Sender<(u64, String)>,
+    history: Mutex<History<String>>,
+    config: ChatConfig,
+}
+
+impl ChatRoom {
+    fn new(config: ChatConfig) -> Arc<Self> {
+        Arc::new(Self {
+            tx: broadcast::channel(config.room_capacity).0,
+            history: Mutex::new(History::new(config.history_len)),
+            config,
+        })
+    }
+
+    // 순번과 채널 순서가 어긋나지 않도록 lock 안에서 보낸다
+    fn publish(&self, msg: String) {
+        let mut history = self.history.lock().unwrap();
+        let seq = history.push(msg.clone());
+        let _ = self.tx.send((seq, msg));
+    }
+
+    fn subscribe(&self) -> (broadcast::Receiver<(u64, String)>, u64) {
+        let history = self.history.lock().unwrap();
+        (self.tx.subscribe(), history.last_seq())
+    }
+
+    fn since(&self, seq: u64) -> Vec<(u64, String)> {
+        self.history.lock().unwrap().since(seq)
+    }
+}
+
+async fn write_line<W>(writer: &mut W, outbox: &Outbox<String>, line: &str) -> std::io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let data = format!("{}\n", line);
+
+    // 상대가 안 읽어서 write가 막혀 있어도 kick되면 바로 빠져나온다
+    tokio::select! {
+        result = writer.write_all(data.as_bytes()) => result,
+        _ = outbox.kicked() => Err(std::io::Error::other("outbound queue overflow")),
+    }
+}
+
+// 막힌 write가 kick으로 끊긴 경우와 진짜 write 오류를 구분해서 알린다
+fn write_failed_msg(outbox: &Outbox<String>, addr: std::net::SocketAddr) -> String {
+    if outbox.is_kicked() {
+        println!("[WARN] Kicking slow consumer {}", addr);
+        format!("[{}] was disconnected for falling behind", addr)
+    } else {
+        format!("[{}] left due to error", addr)
+    }
+}
+
+async fn handle_client<S>(socket: S, addr: std::net::SocketAddr, room: Arc<ChatRoom>)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
     println!("[SERVER] Client connected: {}", addr);
 
-    let (reader, mut writer) = socket.into_split();
-    let mut reader = BufReader::new(reader);
-    let mut line = String::new();
+    let (reader, mut writer) = tokio::io::split(socket);
+    // 글자 수 제한을 UTF-8 최대 4바이트로 환산한 한 줄 상한
+    let max_line = room.config.max_message_len.saturating_mul(4);
+    let mut reader = LineReader::new(BufReader::new(reader), max_line);
 
-    // 이 클라이언트도 전체 채널을 구독
-    let mut rx = tx.subscribe();
+    // 이 클라이언트도 전체 채널을 구독. broadcast는 클라이언트별 outbox로 옮겨 담는다
+    let (outbox, mut inbox) =
+        Outbox::new(room.config.outbound_capacity, room.config.kick_threshold);
+    let (rx, last_seq) = room.subscribe();
+    let resync = room.config.resync.then(|| {
+        let room = room.clone();
+        move |seq| room.since(seq)
+    });
+    let forwarder = tokio::spawn(slow_consumer::forward(rx, outbox.clone(), last_seq, resync));
 
     // 입장 메시지
     let join_msg = format!("[{}] joined the chat", addr);
-    let _ = tx.send(join_msg);
+    room.publish(join_msg);
 
-    loop {
+    // 어떤 이유로 끝나든 나간다는 메시지는 한 번 남긴다
+    let leave_msg = loop {
         tokio::select! {
-            // 1) 클라이언트가 보낸 메시지 읽기
-            result = reader.read_line(&mut line) => {
-                match result {
-                    Ok(0) => {
-                        println!("[SERVER] Client disconnected: {}", addr);
-                        let leave_msg = format!("[{}] left the chat", addr);
-                        let _ = tx.send(leave_msg);
-                        return;
-                    }
-                    Ok(_) => {
-                        let msg = line.trim().to_string();
+            // 쓰기를 먼저 처리해야 자기 입력이 몰릴 때도 자기 outbox가 밀리지 않는다
+            biased;
 
-                        if !msg.is_empty() {
-                            let full_msg = format!("[{}] {}", addr, msg);
-                            let _ = tx.send(full_msg);
-                        }
+            // 1) outbox 한계 초과. 다른 클라이언트는 영향이 없지만 이 연결은 정리한다
+            _ = outbox.kicked() => {
+                println!("[WARN] Kicking slow consumer {}", addr);
+                break format!("[{}] was disconnected for falling behind", addr);
+            }
 
-                        line.clear();
-                    }
-                    Err(e) => {
-                        println!("[SERVER] Read error from {}: {}", addr, e);
-                        let leave_msg = format!("[{}] left due to error", addr);
-                        let _ = tx.send(leave_msg);
-                        return;
+            // 2) 다른 사람이 보낸 메시지를 이 클라이언트에게 전달
+            Some(msg) = inbox.recv() => {
+                // 따라오지 못해 버려진 메시지 수를 먼저 알린다
+                let dropped = outbox.take_dropped();
+                if dropped > 0 {
+                    let notice = format!("*** {} messages were dropped because you fell behind", dropped);
+                    if let Err(e) = write_line(&mut writer, &outbox, &notice).await {
+                        println!("[SERVER] Write error to {}: {}", addr, e);
+                        break write_failed_msg(&outbox, addr);
                     }
                 }
+
+                if let Err(e) = write_line(&mut writer, &outbox, &msg).await {
+                    println!("[SERVER] Write error to {}: {}", addr, e);
+                    break write_failed_msg(&outbox, addr);
+                }
             }
 
-            // 2) 다른 사람이 보낸 메시지를 이 클라이언트에게 전달
-            result = rx.recv() => {
+            // 3) 클라이언트가 보낸 메시지 읽기
+            result = reader.next_line() => {
                 match result {
-                    Ok(msg) => {
-                        if let Err(e) = writer.write_all(msg.as_bytes()).await {
+                    Ok(None) => {
+                        println!("[SERVER] Client disconnected: {}", addr);
+                        break format!("[{}] left the chat", addr);
+                    }
+                    // 잘린 메시지는 내보내지 않고 보낸 사람에게만 알린다
+                    Ok(Some(_)) if reader.truncated() => {
+                        let notice = format!("*** message is too long (max {} bytes), ignored", max_line);
+                        if let Err(e) = write_line(&mut writer, &outbox, &notice).await {
                             println!("[SERVER] Write error to {}: {}", addr, e);
-                            return;
+                            break write_failed_msg(&outbox, addr);
                         }
-                        if let Err(e) = writer.write_all(b"\n").await {
-                            println!("[SERVER] Write error to {}: {}", addr, e);
-                            return;
+                    }
+                    Ok(Some(line)) => {
+                        let msg = line.trim();
+
+                        if !msg.is_empty() {
+                            let full_msg = format!("[{}] {}", addr, msg);
+                            room.publish(full_msg);
                         }
                     }
                     Err(e) => {
-                        println!("[SERVER] Broadcast receive error for {}: {}", addr, e);
-                        return;
+                        println!("[SERVER] Read error from {}: {}", addr, e);
+                        break format!("[{}] left due to error", addr);
                     }
                 }
             }
         }
-    }
+    };
+
+    room.publish(leave_msg);
+    forwarder.abort();
 }
 
 async fn tcp_chat_server() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let listener = TcpListener::bind("127.0.0.1:8080").await?;
-    println!("[SERVER] TCP Chat Server listening on 127.0.0.1:8080");
 
-    // broadcast 채널 생성
-    let (tx, _) = broadcast::channel::<String>(100);
+    // 방 크기, history, outbox 한계는 CHAT_* 환경 변수로 조정
+    let room = ChatRoom::new(ChatConfig::from_env());
+
+    if let Some(acceptor) = tls::acceptor_from_env()? {
+        println!("[SERVER] TCP Chat Server (TLS) listening on 127.0.0.1:8080");
+        return tls::serve(listener, acceptor, move |socket, addr| {
+            handle_client(socket, addr, room.clone())
+        })
+        .await;
+    }
+
+    println!("[SERVER] TCP Chat Server listening on 127.0.0.1:8080");
 
     loop {
         let (socket, addr) = listener.accept().await?;
-        let tx_clone = tx.clone();
+        let room = room.clone();
 
         tokio::spawn(async move {
-            handle_client(socket, addr, tx_clone).await;
+            handle_client(socket, addr, room).await;
         });
     }
 }
@@ -93,4 +183,113 @@ async fn tcp_chat_server() -> Result<(), Box<dyn std::error::Error + Send + Sync
 #[tokio::main]
 pub async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     tcp_chat_server().await
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn test_addr() -> std::net::SocketAddr {
+        "127.0.0.1:4000".parse().unwrap()
+    }
+
+    fn history(room: &ChatRoom) -> Vec<String> {
+        room.since(0).into_iter().map(|(_, msg)| msg).collect()
+    }
+
+    #[tokio::test]
+    async fn kicked_while_writing_publishes_leave() {
+        let room = ChatRoom::new(ChatConfig {
+            outbound_capacity: 1,
+            kick_threshold: 2,
+            resync: false,
+            ..ChatConfig::default()
+        });
+
+        // 상대가 전혀 읽지 않아 64바이트 이후로 write가 막힌다
+        let (server, _client) = tokio::io::duplex(64);
+        let task = tokio::spawn(handle_client(server, test_addr(), room.clone()));
+
+        let result = tokio::time::timeout(Duration::from_secs(5), async {
+            let mut n = 0;
+            while !task.is_finished() {
+                room.publish(format!("flood #{}", n));
+                n += 1;
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        })
+        .await;
+        assert!(result.is_ok(), "slow consumer was never kicked");
+
+        let leave = format!("[{}] was disconnected for falling behind", test_addr());
+        assert_eq!(history(&room).iter().filter(|m| **m == leave).count(), 1);
+    }
+
+    #[tokio::test]
+    async fn disconnect_publishes_leave() {
+        let room = ChatRoom::new(ChatConfig::default());
+        let (server, client) = tokio::io::duplex(1024);
+        let task = tokio::spawn(handle_client(server, test_addr(), room.clone()));
+
+        let (client_reader, mut client_writer) = tokio::io::split(client);
+        client_writer.write_all(b"hello\n").await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        drop(client_writer);
+        drop(client_reader);
+
+        tokio::time::timeout(Duration::from_secs(5), task).await.unwrap().unwrap();
+
+        let addr = test_addr();
+        assert_eq!(
+            history(&room),
+            vec![
+                format!("[{}] joined the chat", addr),
+                format!("[{}] hello", addr),
+                format!("[{}] left the chat", addr),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn oversized_message_is_dropped_without_disconnecting() {
+        let room = ChatRoom::new(ChatConfig {
+            max_message_len: 8,
+            ..ChatConfig::default()
+        });
+        let (server, client) = tokio::io::duplex(64 * 1024);
+        let task = tokio::spawn(handle_client(server, test_addr(), room.clone()));
+
+        let (client_reader, mut client_writer) = tokio::io::split(client);
+        let mut client_reader = tokio::io::BufReader::new(client_reader);
+        client_writer
+            .write_all(format!("{}\nshort\n", "x".repeat(10_000)).as_bytes())
+            .await
+            .unwrap();
+
+        let mut notice = String::new();
+        loop {
+            notice.clear();
+            tokio::io::AsyncBufReadExt::read_line(&mut client_reader, &mut notice)
+                .await
+                .unwrap();
+            if notice.starts_with("***") {
+                break;
+            }
+        }
+        assert_eq!(notice, "*** message is too long (max 32 bytes), ignored\n");
+
+        drop(client_writer);
+        drop(client_reader);
+        tokio::time::timeout(Duration::from_secs(5), task).await.unwrap().unwrap();
+
+        let addr = test_addr();
+        assert_eq!(
+            history(&room),
+            vec![
+                format!("[{}] joined the chat", addr),
+                format!("[{}] short", addr),
+                format!("[{}] left the chat", addr),
+            ]
+        );
+    }
+}
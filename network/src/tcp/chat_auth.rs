@@ -0,0 +1,628 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use chrono::{DateTime, Local, Utc};
+use sqlx::postgres::PgRow;
+use sqlx::{PgPool, Row};
+
+use std::collections::HashSet;
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::Duration;
+
+// nic_chat 계정, 권한, 차단.
+// 계정은 chat_accounts에 argon2 hash로 두고, 접속할 때 비밀번호나 /token으로 받은 토큰으로 로그인한다.
+// 차단 목록은 메모리에서 확인하고 DB에는 기록만 한다. 서버를 다시 띄우면 DB에서 읽어 온다.
+
+const MIN_PASSWORD_LEN: usize = 8;
+// 비밀번호를 이만큼 틀리면 연결을 끊는다
+pub const MAX_LOGIN_ATTEMPTS: u32 = 3;
+const TOKEN_BYTES: usize = 24;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Role {
+    User,
+    Admin,
+}
+
+impl Role {
+    fn parse(role: &str) -> Self {
+        match role {
+            "admin" => Role::Admin,
+            _ => Role::User,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AuthMode {
+    // 로그인 없음. 누구나 아무 닉네임이나 쓴다
+    Off,
+    // 계정이 있는 닉네임만 비밀번호를 묻는다
+    Optional,
+    // 계정 없이는 들어올 수 없다
+    Required,
+}
+
+impl AuthMode {
+    // CHAT_AUTH=off|optional|required. 없으면 DB가 있을 때 optional
+    fn from_env(has_db: bool) -> Result<Self, String> {
+        match std::env::var("CHAT_AUTH").as_deref() {
+            Err(_) if has_db => Ok(AuthMode::Optional),
+            Err(_) | Ok("off") => Ok(AuthMode::Off),
+            Ok("optional") => Ok(AuthMode::Optional),
+            Ok("required") => Ok(AuthMode::Required),
+            Ok(other) => Err(format!("unknown CHAT_AUTH '{}' (off, optional, required)", other)),
+        }
+    }
+}
+
+// ==================== BANS ====================
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BanTarget {
+    // 소문자 닉네임
+    Nick(String),
+    Ip(IpAddr),
+}
+
+impl BanTarget {
+    // IP 주소로 읽히면 IP, 아니면 닉네임
+    pub fn parse(target: &str) -> Self {
+        match target.parse() {
+            Ok(ip) => BanTarget::Ip(ip),
+            Err(_) => BanTarget::Nick(target.to_ascii_lowercase()),
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            BanTarget::Nick(_) => "nick",
+            BanTarget::Ip(_) => "ip",
+        }
+    }
+
+    pub fn matches(&self, nick: Option<&str>, ip: IpAddr) -> bool {
+        match self {
+            BanTarget::Nick(banned) => nick.is_some_and(|nick| nick.eq_ignore_ascii_case(banned)),
+            BanTarget::Ip(banned) => *banned == ip,
+        }
+    }
+}
+
+impl fmt::Display for BanTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BanTarget::Nick(nick) => write!(f, "{}", nick),
+            BanTarget::Ip(ip) => write!(f, "{}", ip),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Ban {
+    pub target: BanTarget,
+    pub reason: String,
+    pub by: String,
+    // None이면 영구
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl Ban {
+    pub fn new(target: BanTarget, by: &str, duration: Option<Duration>, reason: &str) -> Self {
+        let expires_at = duration
+            .and_then(|d| i64::try_from(d.as_secs()).ok())
+            .and_then(|secs| Utc::now().timestamp().checked_add(secs))
+            .and_then(|at| DateTime::from_timestamp(at, 0));
+
+        Self {
+            target,
+            reason: reason.to_string(),
+            by: by.to_string(),
+            expires_at,
+        }
+    }
+
+    fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_none_or(|at| at > now)
+    }
+
+    fn from_row(row: &PgRow) -> Option<Self> {
+        let target: String = row.get("target");
+        let target = match row.get::<&str, _>("kind") {
+            "ip" => BanTarget::Ip(target.parse().ok()?),
+            _ => BanTarget::Nick(target),
+        };
+
+        Some(Self {
+            target,
+            reason: row.get("reason"),
+            by: row.get("banned_by"),
+            expires_at: row.get("expires_at"),
+        })
+    }
+}
+
+impl fmt::Display for Ban {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "banned by {}", self.by)?;
+        if !self.reason.is_empty() {
+            write!(f, " ({})", self.reason)?;
+        }
+        match self.expires_at {
+            Some(at) => write!(f, " until {}", at.with_timezone(&Local).format("%m-%d %H:%M")),
+            None => write!(f, " permanently"),
+        }
+    }
+}
+
+// "30s", "10m", "2h", "7d"
+pub fn parse_duration(text: &str) -> Option<Duration> {
+    if !text.is_ascii() || text.len() < 2 {
+        return None;
+    }
+
+    let (number, unit) = text.split_at(text.len() - 1);
+    let unit = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return None,
+    };
+
+    let n: u64 = number.parse().ok().filter(|n| *n > 0)?;
+    n.checked_mul(unit).map(Duration::from_secs)
+}
+
+// ==================== AUTH ====================
+
+#[derive(Debug)]
+pub enum AuthError {
+    Banned(Ban),
+    // 계정이 있는 닉네임인데 비밀번호를 보내지 않았다
+    SecretRequired,
+    InvalidCredentials,
+    AccountRequired,
+    AccountExists(String),
+    WeakPassword,
+    // DB 없이 띄웠거나 CHAT_AUTH=off
+    Disabled,
+    Database(sqlx::Error),
+    Internal(String),
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::Banned(ban) => write!(f, "you are {}", ban),
+            AuthError::SecretRequired => write!(f, "this nickname is registered, a password is required"),
+            AuthError::InvalidCredentials => write!(f, "invalid password or token"),
+            AuthError::AccountRequired => write!(f, "this server only accepts registered nicknames"),
+            AuthError::AccountExists(nick) => write!(f, "nickname '{}' is already registered", nick),
+            AuthError::WeakPassword => {
+                write!(f, "password must be at least {} characters", MIN_PASSWORD_LEN)
+            }
+            AuthError::Disabled => write!(f, "accounts are not enabled on this server"),
+            AuthError::Database(e) => write!(f, "database error: {}", e),
+            AuthError::Internal(e) => write!(f, "internal error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for AuthError {}
+
+impl From<sqlx::Error> for AuthError {
+    fn from(e: sqlx::Error) -> Self {
+        AuthError::Database(e)
+    }
+}
+
+pub struct Auth {
+    // None이면 계정 없이 차단만 메모리에서
+    pool: Option<PgPool>,
+    mode: AuthMode,
+    // 계정이 있는 닉네임(소문자). rename처럼 DB를 기다릴 수 없는 곳에서 확인한다
+    accounts: Mutex<HashSet<String>>,
+    bans: Mutex<Vec<Ban>>,
+}
+
+impl Auth {
+    pub fn disabled() -> Self {
+        Self {
+            pool: None,
+            mode: AuthMode::Off,
+            accounts: Mutex::new(HashSet::new()),
+            bans: Mutex::new(Vec::new()),
+        }
+    }
+
+    // 계정과 아직 유효한 차단을 읽어 온다. CHAT_ADMIN=nick:password 가 있으면 그 계정을 관리자로 맞춘다
+    pub async fn open(pool: Option<PgPool>) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let mode = AuthMode::from_env(pool.is_some())?;
+
+        let Some(pool) = pool else {
+            if mode != AuthMode::Off {
+                return Err("CHAT_AUTH needs DATABASE_URL".into());
+            }
+            return Ok(Self::disabled());
+        };
+
+        sqlx::migrate!("./migrations").run(&pool).await?;
+
+        let accounts = sqlx::query("SELECT nick FROM chat_accounts")
+            .fetch_all(&pool)
+            .await?
+            .iter()
+            .map(|row| row.get("nick"))
+            .collect();
+
+        let bans = sqlx::query(
+            "SELECT kind, target, reason, banned_by, expires_at FROM chat_bans \
+             WHERE expires_at IS NULL OR expires_at > now() ORDER BY id",
+        )
+        .fetch_all(&pool)
+        .await?
+        .iter()
+        .filter_map(Ban::from_row)
+        .collect();
+
+        let auth = Self {
+            pool: Some(pool),
+            mode,
+            accounts: Mutex::new(accounts),
+            bans: Mutex::new(bans),
+        };
+
+        if let Ok(admin) = std::env::var("CHAT_ADMIN") {
+            let (nick, password) = admin
+                .split_once(':')
+                .ok_or("CHAT_ADMIN must be nick:password")?;
+            auth.set_admin(nick, password).await?;
+            println!("[INFO] Admin account: {}", nick);
+        }
+
+        Ok(auth)
+    }
+
+    pub fn mode(&self) -> AuthMode {
+        self.mode
+    }
+
+    pub fn is_account(&self, nick: &str) -> bool {
+        self.mode != AuthMode::Off
+            && self
+                .accounts
+                .lock()
+                .unwrap()
+                .contains(&nick.to_ascii_lowercase())
+    }
+
+    // 로그인 전에 비밀번호를 물어야 하는지
+    pub fn requires_secret(&self, nick: &str) -> bool {
+        match self.mode {
+            AuthMode::Off => false,
+            AuthMode::Optional => self.is_account(nick),
+            AuthMode::Required => true,
+        }
+    }
+
+    // 닉네임이 없으면 IP만 확인한다. 만료된 차단은 이때 정리
+    pub fn check_ban(&self, nick: Option<&str>, ip: IpAddr) -> Result<(), AuthError> {
+        let now = Utc::now();
+        let mut bans = self.bans.lock().unwrap();
+        bans.retain(|ban| ban.is_active(now));
+
+        match bans.iter().find(|ban| ban.target.matches(nick, ip)) {
+            Some(ban) => Err(AuthError::Banned(ban.clone())),
+            None => Ok(()),
+        }
+    }
+
+    // 비밀번호 자리에는 /token으로 받은 토큰도 받는다
+    pub async fn login(&self, nick: &str, secret: Option<&str>, ip: IpAddr) -> Result<Role, AuthError> {
+        self.check_ban(Some(nick), ip)?;
+
+        let Some(pool) = self.pool.as_ref().filter(|_| self.mode != AuthMode::Off) else {
+            return Ok(Role::User);
+        };
+
+        let row = sqlx::query("SELECT password_hash, token_hash, role FROM chat_accounts WHERE nick = $1")
+            .bind(nick.to_ascii_lowercase())
+            .fetch_optional(pool)
+            .await?;
+
+        let Some(row) = row else {
+            return match self.mode {
+                AuthMode::Required => Err(AuthError::AccountRequired),
+                _ => Ok(Role::User),
+            };
+        };
+
+        let Some(secret) = secret.map(str::to_string) else {
+            return Err(AuthError::SecretRequired);
+        };
+
+        let role = Role::parse(row.get("role"));
+        let hashes: Vec<String> = std::iter::once(row.get("password_hash"))
+            .chain(row.get::<Option<String>, _>("token_hash"))
+            .collect();
+
+        // argon2는 일부러 느리다. runtime thread를 막지 않게
+        let verified = tokio::task::spawn_blocking(move || {
+            hashes.iter().any(|hash| verify_secret(&secret, hash))
+        })
+        .await
+        .map_err(|e| AuthError::Internal(e.to_string()))?;
+
+        if verified {
+            Ok(role)
+        } else {
+            Err(AuthError::InvalidCredentials)
+        }
+    }
+
+    // /register. 이미 계정이 있는 닉네임이면 실패
+    pub async fn create_account(&self, nick: &str, password: &str) -> Result<(), AuthError> {
+        let pool = self.accounts_pool()?;
+        if password.chars().count() < MIN_PASSWORD_LEN {
+            return Err(AuthError::WeakPassword);
+        }
+
+        let hash = hash_secret(password.to_string()).await?;
+        let key = nick.to_ascii_lowercase();
+
+        let result = sqlx::query(
+            "INSERT INTO chat_accounts (nick, password_hash) VALUES ($1, $2) ON CONFLICT (nick) DO NOTHING",
+        )
+        .bind(&key)
+        .bind(hash)
+        .execute(pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(AuthError::AccountExists(nick.to_string()));
+        }
+
+        self.accounts.lock().unwrap().insert(key);
+        Ok(())
+    }
+
+    // 비밀번호 대신 쓸 토큰을 새로 만든다. 이전 토큰은 더 이상 통하지 않는다
+    pub async fn issue_token(&self, nick: &str) -> Result<String, AuthError> {
+        let pool = self.accounts_pool()?;
+
+        let mut bytes = [0u8; TOKEN_BYTES];
+        OsRng.fill_bytes(&mut bytes);
+        let token: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+
+        let hash = hash_secret(token.clone()).await?;
+        let result = sqlx::query("UPDATE chat_accounts SET token_hash = $2 WHERE nick = $1")
+            .bind(nick.to_ascii_lowercase())
+            .bind(hash)
+            .execute(pool)
+            .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(AuthError::AccountRequired);
+        }
+
+        Ok(token)
+    }
+
+    async fn set_admin(&self, nick: &str, password: &str) -> Result<(), AuthError> {
+        let pool = self.accounts_pool()?;
+        let hash = hash_secret(password.to_string()).await?;
+        let key = nick.to_ascii_lowercase();
+
+        sqlx::query(
+            "INSERT INTO chat_accounts (nick, password_hash, role) VALUES ($1, $2, 'admin') \
+             ON CONFLICT (nick) DO UPDATE SET password_hash = EXCLUDED.password_hash, role = 'admin'",
+        )
+        .bind(&key)
+        .bind(hash)
+        .execute(pool)
+        .await?;
+
+        self.accounts.lock().unwrap().insert(key);
+        Ok(())
+    }
+
+    fn accounts_pool(&self) -> Result<&PgPool, AuthError> {
+        self.pool
+            .as_ref()
+            .filter(|_| self.mode != AuthMode::Off)
+            .ok_or(AuthError::Disabled)
+    }
+
+    // 같은 대상의 이전 차단은 덮어쓴다
+    pub fn ban(&self, ban: Ban) {
+        {
+            let mut bans = self.bans.lock().unwrap();
+            bans.retain(|b| b.target != ban.target);
+            bans.push(ban.clone());
+        }
+
+        let Some(pool) = self.pool.clone() else {
+            return;
+        };
+
+        tokio::spawn(async move {
+            let result = sqlx::query(
+                "INSERT INTO chat_bans (kind, target, reason, banned_by, expires_at) VALUES ($1, $2, $3, $4, $5)",
+            )
+            .bind(ban.target.kind())
+            .bind(ban.target.to_string())
+            .bind(&ban.reason)
+            .bind(&ban.by)
+            .bind(ban.expires_at)
+            .execute(&pool)
+            .await;
+
+            if let Err(e) = result {
+                println!("[ERROR] Failed to store ban on {}: {}", ban.target, e);
+            }
+        });
+    }
+
+    // 차단이 있었으면 true
+    pub fn unban(&self, target: &BanTarget) -> bool {
+        let removed = {
+            let mut bans = self.bans.lock().unwrap();
+            let before = bans.len();
+            bans.retain(|b| b.target != *target);
+            bans.len() != before
+        };
+
+        if let Some(pool) = self.pool.clone() {
+            let target = target.clone();
+            tokio::spawn(async move {
+                let result = sqlx::query("DELETE FROM chat_bans WHERE kind = $1 AND target = $2")
+                    .bind(target.kind())
+                    .bind(target.to_string())
+                    .execute(&pool)
+                    .await;
+
+                if let Err(e) = result {
+                    println!("[ERROR] Failed to remove ban on {}: {}", target, e);
+                }
+            });
+        }
+
+        removed
+    }
+}
+
+async fn hash_secret(secret: String) -> Result<String, AuthError> {
+    tokio::task::spawn_blocking(move || {
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::default()
+            .hash_password(secret.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+    })
+    .await
+    .map_err(|e| AuthError::Internal(e.to_string()))?
+    .map_err(|e| AuthError::Internal(e.to_string()))
+}
+
+fn verify_secret(secret: &str, hash: &str) -> bool {
+    PasswordHash::new(hash).is_ok_and(|parsed| {
+        Argon2::default()
+            .verify_password(secret.as_bytes(), &parsed)
+            .is_ok()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(text: &str) -> IpAddr {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn parse_duration_reads_one_unit_suffix() {
+        assert_eq!(parse_duration("30s"), Some(Duration::from_secs(30)));
+        assert_eq!(parse_duration("10m"), Some(Duration::from_secs(600)));
+        assert_eq!(parse_duration("2h"), Some(Duration::from_secs(7200)));
+        assert_eq!(parse_duration("7d"), Some(Duration::from_secs(7 * 86400)));
+
+        for text in [
+            "",
+            "s",
+            "10",
+            "0m",
+            "-5m",
+            "1.5h",
+            "5w",
+            "10M",
+            "ten m",
+            "5분",
+            "99999999999999999999d",
+        ] {
+            assert_eq!(parse_duration(text), None, "{:?}", text);
+        }
+        // 곱하다 넘치면 None
+        assert_eq!(parse_duration(&format!("{}d", u64::MAX / 60)), None);
+    }
+
+    #[test]
+    fn ban_target_parses_ips_and_lowercases_nicks() {
+        assert_eq!(BanTarget::parse("10.0.0.1"), BanTarget::Ip(ip("10.0.0.1")));
+        assert_eq!(BanTarget::parse("::1"), BanTarget::Ip(ip("::1")));
+        assert_eq!(
+            BanTarget::parse("Mallory"),
+            BanTarget::Nick("mallory".into())
+        );
+        assert_eq!(
+            BanTarget::parse("10.0.0.256"),
+            BanTarget::Nick("10.0.0.256".into())
+        );
+
+        let nick = BanTarget::parse("Mallory");
+        assert!(nick.matches(Some("MALLORY"), ip("10.0.0.1")));
+        assert!(!nick.matches(Some("mallory2"), ip("10.0.0.1")));
+        // IP만 확인할 때는 닉네임 차단에 걸리지 않는다
+        assert!(!nick.matches(None, ip("10.0.0.1")));
+
+        let addr = BanTarget::parse("10.0.0.1");
+        assert!(addr.matches(None, ip("10.0.0.1")));
+        assert!(addr.matches(Some("anyone"), ip("10.0.0.1")));
+        assert!(!addr.matches(None, ip("10.0.0.2")));
+    }
+
+    #[test]
+    fn ban_expires_after_its_duration() {
+        let now = Utc::now();
+        let permanent = Ban::new(BanTarget::parse("mallory"), "admin", None, "");
+        assert!(permanent.expires_at.is_none());
+        assert!(permanent.is_active(now + chrono::Duration::days(3650)));
+        assert_eq!(permanent.to_string(), "banned by admin permanently");
+
+        let timed = Ban::new(
+            BanTarget::parse("mallory"),
+            "admin",
+            Some(Duration::from_secs(600)),
+            "spam",
+        );
+        let expires_at = timed.expires_at.unwrap();
+        assert!(
+            (expires_at - now - chrono::Duration::seconds(600))
+                .num_seconds()
+                .abs()
+                <= 1
+        );
+        assert!(timed.is_active(now));
+        assert!(!timed.is_active(expires_at));
+        assert!(timed
+            .to_string()
+            .starts_with("banned by admin (spam) until "));
+    }
+
+    #[test]
+    fn check_ban_drops_expired_bans() {
+        let auth = Auth::disabled();
+        let mut expired = Ban::new(BanTarget::parse("10.0.0.1"), "admin", None, "");
+        expired.expires_at = Some(Utc::now() - chrono::Duration::seconds(1));
+        auth.ban(expired);
+        auth.ban(Ban::new(
+            BanTarget::parse("Mallory"),
+            "admin",
+            Some(Duration::from_secs(60)),
+            "",
+        ));
+
+        assert!(auth.check_ban(None, ip("10.0.0.1")).is_ok());
+        assert!(matches!(
+            auth.check_ban(Some("mallory"), ip("10.0.0.2")),
+            Err(AuthError::Banned(_))
+        ));
+        assert_eq!(auth.bans.lock().unwrap().len(), 1);
+
+        assert!(auth.unban(&BanTarget::parse("MALLORY")));
+        assert!(!auth.unban(&BanTarget::parse("mallory")));
+        assert!(auth.check_ban(Some("mallory"), ip("10.0.0.2")).is_ok());
+    }
+}
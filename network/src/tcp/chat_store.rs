@@ -0,0 +1,299 @@
+use sqlx::types::chrono::{DateTime, Local, Utc};
+use sqlx::{PgPool, Postgres, QueryBuilder, Row};
+use tokio::sync::mpsc;
+use tokio::time::{timeout_at, Instant};
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+// 채팅 이벤트를 Postgres에 남긴다.
+// 채팅 쪽은 record()로 queue에 넣기만 하고, 실제 INSERT는 writer task가 모아서 한 번에 한다.
+
+const QUEUE_CAPACITY: usize = 10_000;
+const BATCH_SIZE: usize = 500;
+const FLUSH_INTERVAL: Duration = Duration::from_millis(200);
+
+pub const MAX_HISTORY: i64 = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EventKind {
+    Message,
+    Join,
+    Part,
+    Quit,
+    Nick,
+}
+
+impl EventKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            EventKind::Message => "message",
+            EventKind::Join => "join",
+            EventKind::Part => "part",
+            EventKind::Quit => "quit",
+            EventKind::Nick => "nick",
+        }
+    }
+
+    fn parse(kind: &str) -> Option<Self> {
+        match kind {
+            "message" => Some(EventKind::Message),
+            "join" => Some(EventKind::Join),
+            "part" => Some(EventKind::Part),
+            "quit" => Some(EventKind::Quit),
+            "nick" => Some(EventKind::Nick),
+            _ => None,
+        }
+    }
+}
+
+// chat_events 한 줄
+#[derive(Debug, Clone)]
+pub struct StoredEvent {
+    pub room: String,
+    pub kind: EventKind,
+    pub nick: String,
+    // message면 본문, nick이면 새 닉네임
+    pub body: Option<String>,
+    pub at: DateTime<Utc>,
+}
+
+impl StoredEvent {
+    pub fn new(room: &str, kind: EventKind, nick: &str, body: Option<&str>) -> Self {
+        Self {
+            room: room.to_string(),
+            kind,
+            nick: nick.to_string(),
+            body: body.map(str::to_string),
+            at: Utc::now(),
+        }
+    }
+}
+
+impl fmt::Display for StoredEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let at = self.at.with_timezone(&Local).format("%m-%d %H:%M");
+        let body = self.body.as_deref().unwrap_or("");
+
+        match self.kind {
+            EventKind::Message => write!(f, "[#{} {}] <{}> {}", self.room, at, self.nick, body),
+            EventKind::Join => write!(f, "[#{} {}] {} joined", self.room, at, self.nick),
+            EventKind::Part => write!(f, "[#{} {}] {} left", self.room, at, self.nick),
+            EventKind::Quit => write!(f, "[#{} {}] {} quit", self.room, at, self.nick),
+            EventKind::Nick => {
+                write!(f, "[#{} {}] {} is now known as {}", self.room, at, self.nick, body)
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ChatStore {
+    pool: PgPool,
+    tx: mpsc::Sender<StoredEvent>,
+    // queue가 가득 차서 기록하지 못한 이벤트 수
+    dropped: Arc<AtomicU64>,
+}
+
+impl ChatStore {
+    // migrations/ 를 적용하고 writer task를 띄운다
+    pub async fn open(pool: PgPool) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        sqlx::migrate!("./migrations").run(&pool).await?;
+
+        let (tx, rx) = mpsc::channel(QUEUE_CAPACITY);
+        tokio::spawn(writer(pool.clone(), rx));
+
+        Ok(Self {
+            pool,
+            tx,
+            dropped: Arc::new(AtomicU64::new(0)),
+        })
+    }
+
+    // hot path. DB를 기다리지 않고, queue가 가득 차면 버린다
+    pub fn record(&self, event: StoredEvent) {
+        if self.tx.try_send(event).is_err() {
+            let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+            if dropped.is_power_of_two() {
+                println!("[WARN] Chat history queue full, {} events not stored", dropped);
+            }
+        }
+    }
+
+    // 방의 최근 메시지 limit개를 오래된 것부터
+    pub async fn recent(&self, room: &str, limit: i64) -> Result<Vec<StoredEvent>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT room, kind, nick, body, created_at FROM chat_events \
+             WHERE room = $1 AND kind = 'message' \
+             ORDER BY id DESC LIMIT $2",
+        )
+        .bind(room)
+        .bind(limit.clamp(0, MAX_HISTORY))
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut events = Vec::with_capacity(rows.len());
+        for row in rows.iter().rev() {
+            let kind: String = row.try_get("kind")?;
+            let Some(kind) = EventKind::parse(&kind) else {
+                continue;
+            };
+
+            events.push(StoredEvent {
+                room: row.try_get("room")?,
+                kind,
+                nick: row.try_get("nick")?,
+                body: row.try_get("body")?,
+                at: row.try_get("created_at")?,
+            });
+        }
+
+        Ok(events)
+    }
+}
+
+// 첫 이벤트가 오면 FLUSH_INTERVAL 동안 또는 BATCH_SIZE까지 모아서 한 번에 INSERT
+async fn writer(pool: PgPool, mut rx: mpsc::Receiver<StoredEvent>) {
+    let mut batch: Vec<StoredEvent> = Vec::with_capacity(BATCH_SIZE);
+
+    while let Some(event) = rx.recv().await {
+        batch.push(event);
+
+        let deadline = Instant::now() + FLUSH_INTERVAL;
+        while batch.len() < BATCH_SIZE {
+            match timeout_at(deadline, rx.recv()).await {
+                Ok(Some(event)) => batch.push(event),
+                // 채널이 닫혔거나 시간이 다 됨
+                Ok(None) | Err(_) => break,
+            }
+        }
+
+        if let Err(e) = insert_batch(&pool, &batch).await {
+            println!("[ERROR] Failed to store {} chat events: {}", batch.len(), e);
+        }
+        batch.clear();
+    }
+}
+
+async fn insert_batch(pool: &PgPool, batch: &[StoredEvent]) -> Result<(), sqlx::Error> {
+    let mut query: QueryBuilder<Postgres> =
+        QueryBuilder::new("INSERT INTO chat_events (room, kind, nick, body, created_at) ");
+
+    query.push_values(batch, |mut row, event| {
+        row.push_bind(&event.room)
+            .push_bind(event.kind.as_str())
+            .push_bind(&event.nick)
+            .push_bind(&event.body)
+            .push_bind(event.at);
+    });
+
+    query.build().execute(pool).await?;
+    Ok(())
+}
+
+// DATABASE_URL이 있을 때만 Postgres에 붙어서 돈다. 없으면 건너뛴다
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::SystemTime;
+
+    async fn open_store() -> Option<ChatStore> {
+        let Some(pool) = crate::connection::try_connection().await.unwrap() else {
+            println!("[WARN] DATABASE_URL not set, skipping chat_store test");
+            return None;
+        };
+        Some(ChatStore::open(pool).await.unwrap())
+    }
+
+    // 테스트끼리, 그리고 이전 실행과 섞이지 않도록 방 이름을 매번 새로 만든다
+    fn unique_room(name: &str) -> String {
+        let nanos = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        format!("test-{}-{}-{}", name, std::process::id(), nanos)
+    }
+
+    // writer task가 batch를 flush할 때까지 기다린다
+    async fn wait_for(store: &ChatStore, room: &str, count: usize) -> Vec<StoredEvent> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            let events = store.recent(room, MAX_HISTORY).await.unwrap();
+            if events.len() >= count || Instant::now() >= deadline {
+                return events;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+
+    #[test]
+    fn event_kind_round_trips() {
+        for kind in [
+            EventKind::Message,
+            EventKind::Join,
+            EventKind::Part,
+            EventKind::Quit,
+            EventKind::Nick,
+        ] {
+            assert_eq!(EventKind::parse(kind.as_str()), Some(kind));
+        }
+        assert_eq!(EventKind::parse("topic"), None);
+    }
+
+    #[tokio::test]
+    async fn recent_returns_messages_oldest_first() {
+        let Some(store) = open_store().await else {
+            return;
+        };
+        let room = unique_room("recent");
+
+        store.record(StoredEvent::new(&room, EventKind::Join, "alice", None));
+        for text in ["one", "two", "three"] {
+            store.record(StoredEvent::new(&room, EventKind::Message, "alice", Some(text)));
+        }
+        store.record(StoredEvent::new(&room, EventKind::Nick, "alice", Some("alicia")));
+
+        // join / nick은 history에 나오지 않는다
+        let events = wait_for(&store, &room, 3).await;
+        let bodies: Vec<_> = events.iter().map(|e| e.body.as_deref().unwrap()).collect();
+        assert_eq!(bodies, ["one", "two", "three"]);
+        assert!(events.iter().all(|e| e.kind == EventKind::Message && e.nick == "alice"));
+
+        let last_two = store.recent(&room, 2).await.unwrap();
+        assert_eq!(last_two[0].body.as_deref(), Some("two"));
+        assert_eq!(last_two[1].body.as_deref(), Some("three"));
+    }
+
+    #[tokio::test]
+    async fn recent_is_capped_at_max_history() {
+        let Some(store) = open_store().await else {
+            return;
+        };
+        let room = unique_room("cap");
+        let total = MAX_HISTORY as usize + 25;
+
+        for i in 0..total {
+            let body = format!("msg {}", i);
+            store.record(StoredEvent::new(&room, EventKind::Message, "bob", Some(&body)));
+        }
+
+        // 마지막 메시지가 보일 때까지 기다린 뒤 한도를 넘겨서 요청한다
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let last = format!("msg {}", total - 1);
+        let events = loop {
+            let events = store.recent(&room, MAX_HISTORY * 10).await.unwrap();
+            let done = events.last().and_then(|e| e.body.as_deref()) == Some(last.as_str());
+            if done || Instant::now() >= deadline {
+                break events;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        };
+
+        assert_eq!(events.len(), MAX_HISTORY as usize);
+        let first = format!("msg {}", total - MAX_HISTORY as usize);
+        assert_eq!(events[0].body.as_deref(), Some(first.as_str()));
+        assert_eq!(events.last().unwrap().body.as_deref(), Some(last.as_str()));
+    }
+}
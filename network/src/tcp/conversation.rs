@@ -0,0 +1,792 @@
+use crate::tcp::pcap::{PcapReader, LINKTYPE_ETHERNET};
+use crate::tcp::stream_reassembly::{
+    parse_segment, CloseReason, FlowKey, Reassembler, ReassemblyConfig, StreamEvent, TcpSegment,
+};
+use pnet::datalink::{self, Channel::Ethernet};
+use pnet::packet::tcp::TcpFlags;
+use serde_json::json;
+use chrono::DateTime;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// stream_reassembly는 방향마다 따로 돈다. 여기서 두 방향을 client/server 한 쌍의 conversation으로 묶어서
+// 방향과 timestamp가 붙은 순서대로의 바이트를 handler로 넘긴다.
+// client는 SYN(ACK 없음)을 보낸 쪽. SYN을 못 본 conversation은 port가 작은 쪽을 server로 친다.
+
+// ==================== TYPES ====================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    ClientToServer,
+    ServerToClient,
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Direction::ClientToServer => write!(f, "c->s"),
+            Direction::ServerToClient => write!(f, "s->c"),
+        }
+    }
+}
+
+// 한 방향의 누적 값
+#[derive(Debug, Clone, Default)]
+pub struct HalfSummary {
+    pub bytes: u64,
+    pub gap_bytes: u64,
+    pub close: Option<CloseReason>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Conversation {
+    pub id: u64,
+    pub client: SocketAddr,
+    pub server: SocketAddr,
+    // 캡처 시각 (UNIX epoch 기준)
+    pub start: Duration,
+    pub end: Duration,
+    pub to_server: HalfSummary,
+    pub to_client: HalfSummary,
+}
+
+impl Conversation {
+    pub fn key(&self, direction: Direction) -> FlowKey {
+        match direction {
+            Direction::ClientToServer => FlowKey {
+                src: self.client,
+                dst: self.server,
+            },
+            Direction::ServerToClient => FlowKey {
+                src: self.server,
+                dst: self.client,
+            },
+        }
+    }
+
+    pub fn direction_of(&self, key: &FlowKey) -> Direction {
+        if key.src == self.client {
+            Direction::ClientToServer
+        } else {
+            Direction::ServerToClient
+        }
+    }
+
+    pub fn half(&self, direction: Direction) -> &HalfSummary {
+        match direction {
+            Direction::ClientToServer => &self.to_server,
+            Direction::ServerToClient => &self.to_client,
+        }
+    }
+
+    fn half_mut(&mut self, direction: Direction) -> &mut HalfSummary {
+        match direction {
+            Direction::ClientToServer => &mut self.to_server,
+            Direction::ServerToClient => &mut self.to_client,
+        }
+    }
+}
+
+impl fmt::Display for Conversation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{} {} <-> {}", self.id, self.client, self.server)
+    }
+}
+
+// ==================== HANDLER ====================
+
+// conversation 단위 callback. on_data만 필수
+pub trait ConversationHandler {
+    fn on_open(&mut self, _conversation: &Conversation) {}
+
+    fn on_data(
+        &mut self,
+        conversation: &Conversation,
+        direction: Direction,
+        data: &[u8],
+        timestamp: Duration,
+    );
+
+    // offset부터 len바이트를 끝내 받지 못했다
+    fn on_gap(
+        &mut self,
+        _conversation: &Conversation,
+        _direction: Direction,
+        _offset: u64,
+        _len: u64,
+    ) {
+    }
+
+    // 두 방향이 모두 닫힌 뒤 한 번 불린다
+    fn on_close(&mut self, _conversation: &Conversation) {}
+}
+
+// 간단한 경우는 closure 하나로 바이트만 받는다
+impl<F> ConversationHandler for F
+where
+    F: FnMut(&Conversation, Direction, &[u8], Duration),
+{
+    fn on_data(
+        &mut self,
+        conversation: &Conversation,
+        direction: Direction,
+        data: &[u8],
+        timestamp: Duration,
+    ) {
+        self(conversation, direction, data, timestamp)
+    }
+}
+
+// ==================== TRACKER ====================
+
+pub struct ConversationTracker<H> {
+    reassembler: Reassembler,
+    handler: H,
+    conversations: HashMap<u64, Conversation>,
+    // 두 방향의 key가 모두 같은 id를 가리킨다
+    by_key: HashMap<FlowKey, u64>,
+    // port 재사용으로 끝난 conversation의 남은 방향. 닫힐 때까지 이벤트를 버린다
+    stale: HashSet<FlowKey>,
+    next_id: u64,
+    events: Vec<StreamEvent>,
+}
+
+impl<H: ConversationHandler> ConversationTracker<H> {
+    pub fn new(config: ReassemblyConfig, handler: H) -> Self {
+        Self {
+            reassembler: Reassembler::new(config),
+            handler,
+            conversations: HashMap::new(),
+            by_key: HashMap::new(),
+            stale: HashSet::new(),
+            next_id: 1,
+            events: Vec::new(),
+        }
+    }
+
+    pub fn reassembler(&self) -> &Reassembler {
+        &self.reassembler
+    }
+
+    pub fn handler(&self) -> &H {
+        &self.handler
+    }
+
+    pub fn handler_mut(&mut self) -> &mut H {
+        &mut self.handler
+    }
+
+    pub fn into_handler(self) -> H {
+        self.handler
+    }
+
+    pub fn active(&self) -> usize {
+        self.conversations.len()
+    }
+
+    pub fn process(&mut self, segment: &TcpSegment<'_>) {
+        // SYN이면 누가 client인지 확실히 안다
+        let client = if segment.flags & TcpFlags::SYN != 0 {
+            if segment.flags & TcpFlags::ACK != 0 {
+                Some(segment.key.dst)
+            } else {
+                Some(segment.key.src)
+            }
+        } else {
+            None
+        };
+
+        // 이 segment(FIN 등)로 conversation이 닫힐 수 있으니 dispatch 전에 늘려 둔다
+        if let Some(id) = self.by_key.get(&segment.key) {
+            if let Some(conversation) = self.conversations.get_mut(id) {
+                conversation.end = conversation.end.max(segment.timestamp);
+            }
+        }
+
+        let mut events = std::mem::take(&mut self.events);
+        self.reassembler.process(segment, &mut events);
+        self.dispatch(&mut events, Some((segment.key, client)), segment.timestamp);
+
+        // 데이터 없는 SYN도 conversation을 연다
+        if client.is_some() && self.reassembler.is_tracking(&segment.key) {
+            self.lookup_or_open(&segment.key, client, segment.timestamp);
+        }
+
+        self.events = events;
+    }
+
+    pub fn expire(&mut self, now: Duration) {
+        let mut events = std::mem::take(&mut self.events);
+        self.reassembler.expire(now, &mut events);
+        self.dispatch(&mut events, None, now);
+        self.events = events;
+    }
+
+    // 캡처가 끝났을 때. 남은 conversation을 모두 닫는다
+    pub fn finish(&mut self) {
+        let mut events = std::mem::take(&mut self.events);
+        self.reassembler.finish(&mut events);
+        self.dispatch(&mut events, None, Duration::ZERO);
+        self.events = events;
+    }
+
+    fn lookup_or_open(
+        &mut self,
+        key: &FlowKey,
+        client: Option<SocketAddr>,
+        timestamp: Duration,
+    ) -> u64 {
+        if let Some(id) = self.by_key.get(key) {
+            return *id;
+        }
+
+        let client = client.unwrap_or_else(|| guess_client(key));
+        let server = if client == key.src { key.dst } else { key.src };
+
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let conversation = Conversation {
+            id,
+            client,
+            server,
+            start: timestamp,
+            end: timestamp,
+            to_server: HalfSummary::default(),
+            to_client: HalfSummary::default(),
+        };
+
+        self.by_key.insert(*key, id);
+        self.by_key.insert(key.reverse(), id);
+        self.handler.on_open(&conversation);
+        self.conversations.insert(id, conversation);
+
+        id
+    }
+
+    fn dispatch(
+        &mut self,
+        events: &mut Vec<StreamEvent>,
+        hint: Option<(FlowKey, Option<SocketAddr>)>,
+        now: Duration,
+    ) {
+        let mut touched = Vec::new();
+
+        for event in events.drain(..) {
+            let (StreamEvent::Data { key, .. }
+            | StreamEvent::Gap { key, .. }
+            | StreamEvent::Closed { key, .. }) = &event;
+
+            if self.stale.contains(key) {
+                if let StreamEvent::Closed { .. } = event {
+                    self.stale.remove(key);
+                }
+                continue;
+            }
+
+            match event {
+                StreamEvent::Data {
+                    key,
+                    data,
+                    timestamp,
+                    ..
+                } => {
+                    let client = hint.and_then(|(k, c)| if k == key { c } else { None });
+                    let id = self.lookup_or_open(&key, client, timestamp);
+                    let Some(conversation) = self.conversations.get_mut(&id) else {
+                        continue;
+                    };
+
+                    let direction = conversation.direction_of(&key);
+                    conversation.half_mut(direction).bytes += data.len() as u64;
+                    conversation.end = conversation.end.max(timestamp);
+
+                    self.handler
+                        .on_data(conversation, direction, &data, timestamp);
+                }
+                StreamEvent::Gap { key, offset, len } => {
+                    let client = hint.and_then(|(k, c)| if k == key { c } else { None });
+                    let id = self.lookup_or_open(&key, client, now);
+                    let Some(conversation) = self.conversations.get_mut(&id) else {
+                        continue;
+                    };
+
+                    let direction = conversation.direction_of(&key);
+                    conversation.half_mut(direction).gap_bytes += len;
+
+                    self.handler.on_gap(conversation, direction, offset, len);
+                }
+                StreamEvent::Closed { key, reason, .. } => {
+                    // 이미 끝난 conversation의 나머지 방향이면 무시
+                    let Some(&id) = self.by_key.get(&key) else {
+                        continue;
+                    };
+                    let Some(conversation) = self.conversations.get_mut(&id) else {
+                        continue;
+                    };
+
+                    let direction = conversation.direction_of(&key);
+                    conversation.half_mut(direction).close = Some(reason);
+
+                    // port 재사용이면 반대 방향을 기다리지 않고 이전 conversation을 끝낸다
+                    if reason == CloseReason::Reused {
+                        if self.reassembler.is_tracking(&key.reverse()) {
+                            self.stale.insert(key.reverse());
+                        }
+                        self.close(id);
+                    } else if !touched.contains(&id) {
+                        touched.push(id);
+                    }
+                }
+            }
+        }
+
+        // 같은 batch 안에서 반대 방향 flush가 뒤따를 수 있어서 끝까지 보고 나서 닫는다
+        for id in touched {
+            let Some(conversation) = self.conversations.get(&id) else {
+                continue;
+            };
+
+            let forward = conversation.key(Direction::ClientToServer);
+            if !self.reassembler.is_tracking(&forward)
+                && !self.reassembler.is_tracking(&forward.reverse())
+            {
+                self.close(id);
+            }
+        }
+    }
+
+    fn close(&mut self, id: u64) {
+        let Some(conversation) = self.conversations.remove(&id) else {
+            return;
+        };
+
+        let forward = conversation.key(Direction::ClientToServer);
+        for key in [forward, forward.reverse()] {
+            if self.by_key.get(&key) == Some(&id) {
+                self.by_key.remove(&key);
+            }
+        }
+
+        self.handler.on_close(&conversation);
+    }
+}
+
+// SYN을 못 봤으면 well-known port 쪽이 server일 가능성이 높다
+fn guess_client(key: &FlowKey) -> SocketAddr {
+    if key.src.port() < key.dst.port() {
+        key.dst
+    } else {
+        key.src
+    }
+}
+
+// ==================== TCPFLOW OUTPUT ====================
+
+// tcpflow처럼 방향마다 파일 하나(SRCIP.SRCPORT-DSTIP.DSTPORT)에 바이트를 쓰고,
+// 끝난 conversation은 index.jsonl에 한 줄씩 남긴다
+pub struct FlowWriter {
+    dir: PathBuf,
+    files: HashMap<(u64, Direction), File>,
+    names: HashMap<(u64, Direction), String>,
+    // 같은 4-tuple이 다시 쓰이면 c1, c2 ... 를 붙인다
+    reused: HashMap<String, u32>,
+    index: File,
+}
+
+impl FlowWriter {
+    pub fn new(dir: impl AsRef<Path>) -> std::io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+
+        let index = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join("index.jsonl"))?;
+
+        Ok(Self {
+            dir,
+            files: HashMap::new(),
+            names: HashMap::new(),
+            reused: HashMap::new(),
+            index,
+        })
+    }
+
+    fn file_for(
+        &mut self,
+        conversation: &Conversation,
+        direction: Direction,
+    ) -> std::io::Result<&mut File> {
+        let slot = (conversation.id, direction);
+
+        if !self.files.contains_key(&slot) {
+            let key = conversation.key(direction);
+            let base = format!("{}-{}", flow_name(&key.src), flow_name(&key.dst));
+
+            let count = self.reused.entry(base.clone()).or_insert(0);
+            let name = if *count == 0 {
+                base
+            } else {
+                format!("{}c{}", base, count)
+            };
+            *count += 1;
+
+            let file = File::create(self.dir.join(&name))?;
+            self.names.insert(slot, name);
+            self.files.insert(slot, file);
+        }
+
+        Ok(self.files.get_mut(&slot).expect("file just inserted"))
+    }
+
+    fn write_index(&mut self, conversation: &Conversation) -> std::io::Result<()> {
+        let file_name = |direction| self.names.get(&(conversation.id, direction)).cloned();
+        let half = |summary: &HalfSummary, file: Option<String>| {
+            json!({
+                "bytes": summary.bytes,
+                "gap_bytes": summary.gap_bytes,
+                "close": summary.close.map(|reason| reason.to_string()),
+                "file": file,
+            })
+        };
+
+        let record = json!({
+            "id": conversation.id,
+            "client": conversation.client.to_string(),
+            "server": conversation.server.to_string(),
+            "start": rfc3339(conversation.start),
+            "end": rfc3339(conversation.end),
+            "duration_ms": conversation.end.saturating_sub(conversation.start).as_millis() as u64,
+            "client_to_server": half(&conversation.to_server, file_name(Direction::ClientToServer)),
+            "server_to_client": half(&conversation.to_client, file_name(Direction::ServerToClient)),
+        });
+
+        writeln!(self.index, "{}", record)?;
+        self.index.flush()
+    }
+}
+
+impl ConversationHandler for FlowWriter {
+    fn on_data(
+        &mut self,
+        conversation: &Conversation,
+        direction: Direction,
+        data: &[u8],
+        _timestamp: Duration,
+    ) {
+        let result = self
+            .file_for(conversation, direction)
+            .and_then(|file| file.write_all(data));
+
+        if let Err(e) = result {
+            eprintln!("[ERROR] {} {} 쓰기 실패: {}", conversation, direction, e);
+        }
+    }
+
+    fn on_gap(&mut self, conversation: &Conversation, direction: Direction, offset: u64, len: u64) {
+        println!(
+            "[WARN] {} {} gap offset={} len={}",
+            conversation, direction, offset, len
+        );
+    }
+
+    fn on_close(&mut self, conversation: &Conversation) {
+        for direction in [Direction::ClientToServer, Direction::ServerToClient] {
+            self.files.remove(&(conversation.id, direction));
+        }
+
+        if let Err(e) = self.write_index(conversation) {
+            eprintln!("[ERROR] index 기록 실패: {}", e);
+        }
+
+        for direction in [Direction::ClientToServer, Direction::ServerToClient] {
+            self.names.remove(&(conversation.id, direction));
+        }
+
+        println!(
+            "[INFO] {} closed c->s={}B s->c={}B",
+            conversation, conversation.to_server.bytes, conversation.to_client.bytes
+        );
+    }
+}
+
+// tcpflow 형식: 010.000.000.001.00080
+fn flow_name(addr: &SocketAddr) -> String {
+    match addr.ip() {
+        IpAddr::V4(ip) => {
+            let [a, b, c, d] = ip.octets();
+            format!("{:03}.{:03}.{:03}.{:03}.{:05}", a, b, c, d, addr.port())
+        }
+        IpAddr::V6(ip) => {
+            let hex: Vec<String> = ip.segments().iter().map(|s| format!("{:04x}", s)).collect();
+            format!("{}.{:05}", hex.join("."), addr.port())
+        }
+    }
+}
+
+fn rfc3339(timestamp: Duration) -> Option<String> {
+    DateTime::from_timestamp(timestamp.as_secs() as i64, timestamp.subsec_nanos())
+        .map(|at| at.to_rfc3339())
+}
+
+// ==================== CAPTURE ====================
+
+// 인터페이스에서 계속 캡처해서 tracker에 넣는다. 1초마다 idle stream을 정리한다
+pub fn capture_live<H: ConversationHandler>(
+    interface_name: &str,
+    tracker: &mut ConversationTracker<H>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let interfaces = datalink::interfaces();
+
+    let interface = interfaces
+        .into_iter()
+        .find(|iface| iface.name == interface_name)
+        .ok_or("인터페이스를 찾지 못했습니다.")?;
+
+    println!("[INFO] listening on interface: {}", interface.name);
+
+    let config = datalink::Config {
+        read_timeout: Some(Duration::from_secs(1)),
+        ..Default::default()
+    };
+
+    let (_, mut rx) = match datalink::channel(&interface, config)? {
+        Ethernet(tx, rx) => (tx, rx),
+        _ => return Err("unsupported channel".into()),
+    };
+
+    let mut last_expire = Instant::now();
+
+    loop {
+        match rx.next() {
+            Ok(packet) => {
+                // 읽기가 돌아온 뒤에 찍어야 패킷을 기다리며 막혀 있던 시간이 timestamp에 들어가지 않는다
+                let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+                if let Some(segment) = parse_segment(packet, now) {
+                    tracker.process(&segment);
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::TimedOut => {}
+            Err(e) => {
+                eprintln!("read error: {}", e);
+            }
+        }
+
+        if last_expire.elapsed() >= Duration::from_secs(1) {
+            tracker.expire(SystemTime::now().duration_since(UNIX_EPOCH)?);
+            last_expire = Instant::now();
+        }
+    }
+}
+
+// pcap 파일을 끝까지 읽고 남은 conversation을 닫는다. 시간은 패킷의 캡처 시각을 따른다
+pub fn read_pcap<H: ConversationHandler>(
+    path: &str,
+    tracker: &mut ConversationTracker<H>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut reader = PcapReader::open(path)?;
+
+    if reader.link_type() != LINKTYPE_ETHERNET {
+        return Err(format!("지원하지 않는 link type: {}", reader.link_type()).into());
+    }
+
+    println!("[INFO] reading {}", path);
+
+    let mut packets = 0u64;
+    let mut last_expire = Duration::ZERO;
+
+    while let Some(packet) = reader.next_packet()? {
+        packets += 1;
+
+        if let Some(segment) = parse_segment(&packet.data, packet.timestamp) {
+            tracker.process(&segment);
+        }
+
+        if packet.timestamp.saturating_sub(last_expire) >= Duration::from_secs(1) {
+            tracker.expire(packet.timestamp);
+            last_expire = packet.timestamp;
+        }
+    }
+
+    if reader.truncated() {
+        println!("[WARN] {} ends with a truncated record, ignored it", path);
+    }
+
+    tracker.finish();
+    println!("[INFO] {} packets read", packets);
+
+    Ok(())
+}
+
+// 인자로 pcap 파일을 주면 그 파일을, 없으면 en0을 캡처한다
+pub fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let output_dir = "flows";
+    println!("[INFO] writing flows to ./{}", output_dir);
+
+    let writer = FlowWriter::new(output_dir)?;
+    let mut tracker = ConversationTracker::new(ReassemblyConfig::default(), writer);
+
+    match std::env::args().nth(1) {
+        Some(path) => read_pcap(&path, &mut tracker),
+        None => capture_live("en0", &mut tracker),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tcp::stream_reassembly::TcpSegment;
+
+    const CLIENT: &str = "10.0.0.1:40000";
+    const SERVER: &str = "10.0.0.2:80";
+
+    #[derive(Default)]
+    struct Recorder {
+        log: Vec<String>,
+        closed: Vec<Conversation>,
+    }
+
+    impl ConversationHandler for Recorder {
+        fn on_open(&mut self, conversation: &Conversation) {
+            self.log.push(format!("open {}", conversation));
+        }
+
+        fn on_data(
+            &mut self,
+            _conversation: &Conversation,
+            direction: Direction,
+            data: &[u8],
+            timestamp: Duration,
+        ) {
+            self.log.push(format!(
+                "{} {} @{}",
+                direction,
+                String::from_utf8_lossy(data),
+                timestamp.as_secs()
+            ));
+        }
+
+        fn on_close(&mut self, conversation: &Conversation) {
+            self.log.push(format!("close {}", conversation));
+            self.closed.push(conversation.clone());
+        }
+    }
+
+    fn segment<'a>(from: &str, to: &str, seq: u32, flags: u8, payload: &'a [u8], at: u64) -> TcpSegment<'a> {
+        TcpSegment {
+            key: FlowKey {
+                src: from.parse().unwrap(),
+                dst: to.parse().unwrap(),
+            },
+            seq,
+            flags,
+            payload,
+            timestamp: Duration::from_secs(at),
+        }
+    }
+
+    // SYN, SYN-ACK, 요청, 응답, 양쪽 FIN
+    fn exchange() -> Vec<TcpSegment<'static>> {
+        vec![
+            segment(CLIENT, SERVER, 100, TcpFlags::SYN, b"", 10),
+            segment(SERVER, CLIENT, 500, TcpFlags::SYN | TcpFlags::ACK, b"", 11),
+            segment(CLIENT, SERVER, 101, TcpFlags::ACK, b"GET", 12),
+            segment(SERVER, CLIENT, 501, TcpFlags::ACK, b"OK", 13),
+            segment(CLIENT, SERVER, 104, TcpFlags::FIN | TcpFlags::ACK, b"", 14),
+            segment(SERVER, CLIENT, 503, TcpFlags::FIN | TcpFlags::ACK, b"", 15),
+        ]
+    }
+
+    #[test]
+    fn pairs_both_directions_and_closes_after_both_fins() {
+        let mut tracker = ConversationTracker::new(ReassemblyConfig::default(), Recorder::default());
+        for segment in exchange() {
+            tracker.process(&segment);
+        }
+
+        assert_eq!(tracker.active(), 0);
+        let recorder = tracker.into_handler();
+        let name = format!("#1 {} <-> {}", CLIENT, SERVER);
+        assert_eq!(
+            recorder.log,
+            [
+                format!("open {}", name),
+                "c->s GET @12".to_string(),
+                "s->c OK @13".to_string(),
+                format!("close {}", name),
+            ]
+        );
+
+        let conversation = &recorder.closed[0];
+        assert_eq!(conversation.start, Duration::from_secs(10));
+        assert_eq!(conversation.end, Duration::from_secs(15));
+        assert_eq!(conversation.to_server.bytes, 3);
+        assert_eq!(conversation.to_client.bytes, 2);
+        assert_eq!(conversation.to_server.close, Some(CloseReason::Fin));
+        assert_eq!(conversation.to_client.close, Some(CloseReason::Fin));
+    }
+
+    #[test]
+    fn syn_ack_alone_identifies_the_client() {
+        let mut tracker = ConversationTracker::new(ReassemblyConfig::default(), Recorder::default());
+        // 서버가 높은 port를 써도 SYN-ACK를 보낸 쪽이 server다
+        tracker.process(&segment("10.0.0.2:50000", "10.0.0.1:443", 500, TcpFlags::SYN | TcpFlags::ACK, b"", 1));
+        tracker.finish();
+
+        let conversation = &tracker.handler().closed[0];
+        assert_eq!(conversation.client, "10.0.0.1:443".parse().unwrap());
+        assert_eq!(conversation.server, "10.0.0.2:50000".parse().unwrap());
+    }
+
+    #[test]
+    fn midstream_conversations_treat_the_lower_port_as_server() {
+        let key = FlowKey {
+            src: SERVER.parse().unwrap(),
+            dst: CLIENT.parse().unwrap(),
+        };
+        assert_eq!(guess_client(&key), CLIENT.parse().unwrap());
+        assert_eq!(guess_client(&key.reverse()), CLIENT.parse().unwrap());
+    }
+
+    #[test]
+    fn timestamps_are_written_as_rfc3339_utc() {
+        assert_eq!(
+            rfc3339(Duration::from_secs(1_700_000_000)).as_deref(),
+            Some("2023-11-14T22:13:20+00:00")
+        );
+    }
+
+    #[test]
+    fn flow_writer_writes_tcpflow_files_and_an_index_line() {
+        let dir = std::env::temp_dir().join(format!("flows-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let writer = FlowWriter::new(&dir).unwrap();
+        let mut tracker = ConversationTracker::new(ReassemblyConfig::default(), writer);
+        for segment in exchange() {
+            tracker.process(&segment);
+        }
+        drop(tracker);
+
+        let to_server = "010.000.000.001.40000-010.000.000.002.00080";
+        let to_client = "010.000.000.002.00080-010.000.000.001.40000";
+        assert_eq!(fs::read(dir.join(to_server)).unwrap(), b"GET");
+        assert_eq!(fs::read(dir.join(to_client)).unwrap(), b"OK");
+
+        let index = fs::read_to_string(dir.join("index.jsonl")).unwrap();
+        let record: serde_json::Value = serde_json::from_str(index.trim()).unwrap();
+        assert_eq!(record["client"], CLIENT);
+        assert_eq!(record["duration_ms"], 5000);
+        assert_eq!(record["client_to_server"]["file"], to_server);
+        assert_eq!(record["server_to_client"]["bytes"], 2);
+        assert_eq!(record["server_to_client"]["close"], "fin");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,12 +1,118 @@
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tokio::net::{TcpListener, TcpStream};
+use tokio::time::timeout;
+
+use super::tls;
 
 const HEADER_SIZE: usize = 4;
 
+// ==================== CONFIG ====================
+
+#[derive(Debug, Clone)]
+pub struct FrameConfig {
+    // 이보다 큰 length header는 payload를 할당하기 전에 거절
+    pub max_frame_size: usize,
+    // 다음 frame의 첫 바이트를 기다리는 시간
+    pub idle_timeout: Duration,
+    // frame의 첫 바이트 이후 나머지가 모두 도착해야 하는 시간
+    pub frame_timeout: Duration,
+}
+
+impl Default for FrameConfig {
+    fn default() -> Self {
+        Self {
+            max_frame_size: 64 * 1024,
+            idle_timeout: Duration::from_secs(60),
+            frame_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+// ==================== ERRORS ====================
+
+#[derive(Debug)]
+pub enum FrameError {
+    Oversized { len: usize, max: usize },
+    Truncated { expected: usize, received: usize },
+    InvalidUtf8(std::string::FromUtf8Error),
+    IdleTimeout,
+    FrameTimeout,
+    Io(std::io::Error),
+}
+
+impl FrameError {
+    // 스트림이 다음 frame 경계에 맞춰져 있어 계속 읽을 수 있는지.
+    // Oversized는 payload를 버리면(skip_payload) 다시 맞출 수 있다.
+    pub fn is_recoverable(&self) -> bool {
+        matches!(self, FrameError::InvalidUtf8(_) | FrameError::Oversized { .. })
+    }
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameError::Oversized { len, max } => {
+                write!(f, "frame too large: {} bytes (max {})", len, max)
+            }
+            FrameError::Truncated { expected, received } => {
+                write!(f, "truncated frame: expected {} bytes, got {}", expected, received)
+            }
+            FrameError::InvalidUtf8(e) => write!(f, "invalid utf-8 payload: {}", e),
+            FrameError::IdleTimeout => write!(f, "idle timeout"),
+            FrameError::FrameTimeout => write!(f, "frame read timeout"),
+            FrameError::Io(e) => write!(f, "io error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for FrameError {}
+
+impl From<std::io::Error> for FrameError {
+    fn from(e: std::io::Error) -> Self {
+        FrameError::Io(e)
+    }
+}
+
+// ==================== ERROR POLICY ====================
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ErrorAction {
+    // 에러 frame을 보내고 연결 유지
+    Reply(String),
+    // 에러 frame을 보내고 종료
+    ReplyAndDisconnect(String),
+    Disconnect,
+}
+
+// 잘못된 frame을 받았을 때 서버가 어떻게 할지 결정.
+// 복구 불가능한 에러(is_recoverable() == false)는 Reply여도 연결을 끊는다.
+pub trait ErrorPolicy: Send + Sync {
+    fn on_frame_error(&self, addr: SocketAddr, err: &FrameError) -> ErrorAction;
+}
+
+pub struct DefaultPolicy;
+
+impl ErrorPolicy for DefaultPolicy {
+    fn on_frame_error(&self, _addr: SocketAddr, err: &FrameError) -> ErrorAction {
+        match err {
+            FrameError::InvalidUtf8(_) => ErrorAction::Reply(format!("error: {}", err)),
+            FrameError::Oversized { .. } => {
+                ErrorAction::ReplyAndDisconnect(format!("error: {}", err))
+            }
+            _ => ErrorAction::Disconnect,
+        }
+    }
+}
+
 // ==================== PROTOCOL HELPERS ====================
 
-async fn write_frame(
-    stream: &mut TcpStream,
+async fn write_frame<W: AsyncWrite + Unpin>(
+    stream: &mut W,
     message: &str,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let payload = message.as_bytes();
@@ -21,67 +127,198 @@ async fn write_frame(
     Ok(())
 }
 
-async fn read_frame(
-    stream: &mut TcpStream,
-) -> Result<Option<String>, Box<dyn std::error::Error + Send + Sync>> {
-    let mut header = [0u8; HEADER_SIZE];
+// EOF 전까지 buf를 채우고 실제로 읽은 바이트 수를 돌려준다
+async fn read_full<R: AsyncRead + Unpin>(stream: &mut R, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
 
-    // 먼저 길이 4바이트 읽기
-    match stream.read_exact(&mut header).await {
-        Ok(_) => {}
-        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
-            // 연결 종료
-            return Ok(None);
+    while filled < buf.len() {
+        let n = stream.read(&mut buf[filled..]).await?;
+        if n == 0 {
+            break;
         }
-        Err(e) => return Err(Box::new(e)),
+        filled += n;
+    }
+
+    Ok(filled)
+}
+
+async fn read_frame<R: AsyncRead + Unpin>(
+    stream: &mut R,
+    config: &FrameConfig,
+) -> Result<Option<String>, FrameError> {
+    let mut header = [0u8; HEADER_SIZE];
+
+    // 첫 바이트는 idle timeout으로 기다린다
+    let n = match timeout(config.idle_timeout, stream.read(&mut header)).await {
+        Ok(result) => result?,
+        Err(_) => return Err(FrameError::IdleTimeout),
+    };
+
+    if n == 0 {
+        // 연결 종료
+        return Ok(None);
+    }
+
+    // 나머지 header + payload는 frame timeout 안에 와야 한다
+    match timeout(config.frame_timeout, read_rest(stream, &mut header, n, config)).await {
+        Ok(result) => result.map(Some),
+        Err(_) => Err(FrameError::FrameTimeout),
+    }
+}
+
+async fn read_rest<R: AsyncRead + Unpin>(
+    stream: &mut R,
+    header: &mut [u8; HEADER_SIZE],
+    have: usize,
+    config: &FrameConfig,
+) -> Result<String, FrameError> {
+    let received = have + read_full(stream, &mut header[have..]).await?;
+    if received < HEADER_SIZE {
+        return Err(FrameError::Truncated {
+            expected: HEADER_SIZE,
+            received,
+        });
     }
 
-    let len = u32::from_be_bytes(header) as usize;
+    let len = u32::from_be_bytes(*header) as usize;
+
+    // 할당 전에 크기 검사
+    if len > config.max_frame_size {
+        return Err(FrameError::Oversized {
+            len,
+            max: config.max_frame_size,
+        });
+    }
 
     if len == 0 {
-        return Ok(Some(String::new()));
+        return Ok(String::new());
     }
 
     let mut payload = vec![0u8; len];
-    stream.read_exact(&mut payload).await?;
+    let received = read_full(stream, &mut payload).await?;
+    if received < len {
+        return Err(FrameError::Truncated {
+            expected: len,
+            received,
+        });
+    }
+
+    String::from_utf8(payload).map_err(FrameError::InvalidUtf8)
+}
 
-    let message = String::from_utf8(payload)?;
-    Ok(Some(message))
+// 거절한 frame의 payload를 읽어 버려서 다음 frame 경계로 맞춘다
+async fn skip_payload<R: AsyncRead + Unpin>(
+    stream: &mut R,
+    mut len: usize,
+    config: &FrameConfig,
+) -> Result<(), FrameError> {
+    let mut scratch = [0u8; 4096];
+
+    let skip = async {
+        while len > 0 {
+            let want = len.min(scratch.len());
+            let n = stream.read(&mut scratch[..want]).await?;
+            if n == 0 {
+                return Err(FrameError::Truncated {
+                    expected: len,
+                    received: 0,
+                });
+            }
+            len -= n;
+        }
+        Ok(())
+    };
+
+    match timeout(config.frame_timeout, skip).await {
+        Ok(result) => result,
+        Err(_) => Err(FrameError::FrameTimeout),
+    }
 }
 
 // ==================== SERVER ====================
 
-async fn handle_client(
-    mut socket: TcpStream,
-    addr: std::net::SocketAddr,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+async fn handle_client<S>(
+    mut socket: S,
+    addr: SocketAddr,
+    config: Arc<FrameConfig>,
+    policy: Arc<dyn ErrorPolicy>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
     println!("[SERVER] Client connected: {}", addr);
 
     loop {
-        match read_frame(&mut socket).await? {
-            Some(message) => {
+        let err = match read_frame(&mut socket, &config).await {
+            Ok(Some(message)) => {
                 println!("[SERVER] Received from {}: {}", addr, message);
 
                 let response = format!("echo: {}", message);
                 write_frame(&mut socket, &response).await?;
+                continue;
             }
-            None => {
+            Ok(None) => {
                 println!("[SERVER] Client disconnected: {}", addr);
                 return Ok(());
             }
+            Err(err) => err,
+        };
+
+        println!("[SERVER] Bad frame from {}: {}", addr, err);
+
+        let (reply, keep) = match policy.on_frame_error(addr, &err) {
+            ErrorAction::Reply(msg) => (Some(msg), err.is_recoverable()),
+            ErrorAction::ReplyAndDisconnect(msg) => (Some(msg), false),
+            ErrorAction::Disconnect => (None, false),
+        };
+
+        if let Some(msg) = reply {
+            write_frame(&mut socket, &msg).await?;
+        }
+
+        if !keep {
+            println!("[SERVER] Disconnecting {}: {}", addr, err);
+            return Ok(());
+        }
+
+        if let FrameError::Oversized { len, .. } = err {
+            if let Err(e) = skip_payload(&mut socket, len, &config).await {
+                println!("[SERVER] Disconnecting {}: {}", addr, e);
+                return Ok(());
+            }
         }
     }
 }
 
 async fn custom_protocol_server() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let listener = TcpListener::bind("127.0.0.1:8080").await?;
+
+    let config = Arc::new(FrameConfig::default());
+    let policy: Arc<dyn ErrorPolicy> = Arc::new(DefaultPolicy);
+
+    if let Some(acceptor) = tls::acceptor_from_env()? {
+        println!("[SERVER] Length-prefixed TLS server listening on 127.0.0.1:8080");
+        return tls::serve(listener, acceptor, move |socket, addr| {
+            let config = config.clone();
+            let policy = policy.clone();
+            async move {
+                if let Err(e) = handle_client(socket, addr, config, policy).await {
+                    println!("[SERVER] Error handling {}: {}", addr, e);
+                }
+            }
+        })
+        .await;
+    }
+
     println!("[SERVER] Length-prefixed server listening on 127.0.0.1:8080");
 
     loop {
         let (socket, addr) = listener.accept().await?;
+        let config = config.clone();
+        let policy = policy.clone();
 
         tokio::spawn(async move {
-            if let Err(e) = handle_client(socket, addr).await {
+            if let Err(e) = handle_client(socket, addr, config, policy).await {
                 println!("[SERVER] Error handling {}: {}", addr, e);
             }
         });
@@ -94,6 +331,8 @@ async fn custom_protocol_client() -> Result<(), Box<dyn std::error::Error + Send
     let mut stream = TcpStream::connect("127.0.0.1:8080").await?;
     println!("[CLIENT] Connected to server");
 
+    let config = FrameConfig::default();
+
     let messages = vec![
         "hello",
         "this is custom protocol",
@@ -105,7 +344,7 @@ async fn custom_protocol_client() -> Result<(), Box<dyn std::error::Error + Send
 
         write_frame(&mut stream, msg).await?;
 
-        match read_frame(&mut stream).await? {
+        match read_frame(&mut stream, &config).await? {
             Some(response) => {
                 println!("[CLIENT] Received: {}", response);
             }
@@ -138,4 +377,220 @@ pub async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
 
     Ok(())
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{duplex, DuplexStream};
+
+    fn short_config(max_frame_size: usize) -> FrameConfig {
+        FrameConfig {
+            max_frame_size,
+            idle_timeout: Duration::from_millis(50),
+            frame_timeout: Duration::from_millis(50),
+        }
+    }
+
+    fn client_config() -> FrameConfig {
+        FrameConfig {
+            max_frame_size: 1024,
+            idle_timeout: Duration::from_secs(2),
+            frame_timeout: Duration::from_secs(2),
+        }
+    }
+
+    fn addr() -> SocketAddr {
+        "127.0.0.1:9".parse().unwrap()
+    }
+
+    // Oversized도 Reply로 답하고 연결을 유지하는 정책
+    struct LenientPolicy;
+
+    impl ErrorPolicy for LenientPolicy {
+        fn on_frame_error(&self, _addr: SocketAddr, err: &FrameError) -> ErrorAction {
+            ErrorAction::Reply(format!("error: {}", err))
+        }
+    }
+
+    fn spawn_server(
+        config: FrameConfig,
+        policy: Arc<dyn ErrorPolicy>,
+    ) -> (DuplexStream, tokio::task::JoinHandle<()>) {
+        let (client, server) = duplex(64 * 1024);
+        let handle = tokio::spawn(async move {
+            handle_client(server, addr(), Arc::new(config), policy)
+                .await
+                .unwrap();
+        });
+        (client, handle)
+    }
+
+    #[tokio::test]
+    async fn frames_up_to_the_limit_are_accepted() {
+        let config = short_config(8);
+        let (mut tx, mut rx) = duplex(1024);
+
+        write_frame(&mut tx, "12345678").await.unwrap();
+        write_frame(&mut tx, "").await.unwrap();
+        write_frame(&mut tx, "123456789").await.unwrap();
+
+        assert_eq!(read_frame(&mut rx, &config).await.unwrap().as_deref(), Some("12345678"));
+        assert_eq!(read_frame(&mut rx, &config).await.unwrap().as_deref(), Some(""));
+        assert!(matches!(
+            read_frame(&mut rx, &config).await,
+            Err(FrameError::Oversized { len: 9, max: 8 })
+        ));
+
+        drop(tx);
+        // 거절한 payload를 버리면 스트림 끝까지 깔끔하게 읽힌다
+        skip_payload(&mut rx, 9, &config).await.unwrap();
+        assert!(read_frame(&mut rx, &config).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn read_frame_enforces_idle_and_frame_deadlines() {
+        let config = short_config(1024);
+
+        // 아무것도 오지 않으면 idle timeout
+        let (_tx, mut rx) = duplex(1024);
+        assert!(matches!(
+            read_frame(&mut rx, &config).await,
+            Err(FrameError::IdleTimeout)
+        ));
+
+        // header 일부만 오고 멈추면 frame timeout
+        let (mut tx, mut rx) = duplex(1024);
+        tx.write_all(&[0, 0]).await.unwrap();
+        assert!(matches!(
+            read_frame(&mut rx, &config).await,
+            Err(FrameError::FrameTimeout)
+        ));
+
+        // payload 도중에 멈춰도 frame timeout
+        let (mut tx, mut rx) = duplex(1024);
+        tx.write_all(&5u32.to_be_bytes()).await.unwrap();
+        tx.write_all(b"ab").await.unwrap();
+        assert!(matches!(
+            read_frame(&mut rx, &config).await,
+            Err(FrameError::FrameTimeout)
+        ));
+
+        // payload 도중에 끊기면 Truncated
+        drop(tx);
+        let (mut tx, mut rx) = duplex(1024);
+        tx.write_all(&5u32.to_be_bytes()).await.unwrap();
+        tx.write_all(b"ab").await.unwrap();
+        drop(tx);
+        assert!(matches!(
+            read_frame(&mut rx, &config).await,
+            Err(FrameError::Truncated {
+                expected: 5,
+                received: 2
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn skip_payload_times_out_on_a_stalled_peer() {
+        let config = short_config(4);
+        let (mut tx, mut rx) = duplex(1024);
+        tx.write_all(b"abc").await.unwrap();
+
+        assert!(matches!(
+            skip_payload(&mut rx, 10, &config).await,
+            Err(FrameError::FrameTimeout)
+        ));
+
+        drop(tx);
+        assert!(matches!(
+            skip_payload(&mut rx, 10, &config).await,
+            Err(FrameError::Truncated { .. })
+        ));
+    }
+
+    #[test]
+    fn default_policy_decisions() {
+        let policy = DefaultPolicy;
+        let invalid = FrameError::InvalidUtf8(String::from_utf8(vec![0xff]).unwrap_err());
+        let oversized = FrameError::Oversized { len: 9, max: 8 };
+
+        assert!(matches!(policy.on_frame_error(addr(), &invalid), ErrorAction::Reply(_)));
+        assert_eq!(
+            policy.on_frame_error(addr(), &oversized),
+            ErrorAction::ReplyAndDisconnect("error: frame too large: 9 bytes (max 8)".into())
+        );
+        for err in [
+            FrameError::IdleTimeout,
+            FrameError::FrameTimeout,
+            FrameError::Truncated {
+                expected: 4,
+                received: 1,
+            },
+        ] {
+            assert_eq!(policy.on_frame_error(addr(), &err), ErrorAction::Disconnect);
+        }
+
+        assert!(invalid.is_recoverable());
+        assert!(oversized.is_recoverable());
+        assert!(!FrameError::FrameTimeout.is_recoverable());
+    }
+
+    #[tokio::test]
+    async fn server_keeps_the_connection_after_invalid_utf8() {
+        let config = short_config(1024);
+        let (mut client, server) = spawn_server(config.clone(), Arc::new(DefaultPolicy));
+
+        client.write_all(&2u32.to_be_bytes()).await.unwrap();
+        client.write_all(&[0xff, 0xfe]).await.unwrap();
+        let reply = read_frame(&mut client, &config).await.unwrap().unwrap();
+        assert!(reply.starts_with("error: invalid utf-8"), "{}", reply);
+
+        write_frame(&mut client, "still here").await.unwrap();
+        assert_eq!(
+            read_frame(&mut client, &config).await.unwrap().as_deref(),
+            Some("echo: still here")
+        );
+
+        drop(client);
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn default_policy_disconnects_after_an_oversized_frame() {
+        // 서버만 4바이트로 제한하고 클라이언트는 에러 frame을 느긋하게 읽는다
+        let config = client_config();
+        let (mut client, server) = spawn_server(short_config(4), Arc::new(DefaultPolicy));
+
+        write_frame(&mut client, "too long").await.unwrap();
+        let reply = read_frame(&mut client, &config).await.unwrap().unwrap();
+        assert_eq!(reply, "error: frame too large: 8 bytes (max 4)");
+        assert!(read_frame(&mut client, &config).await.unwrap().is_none());
+
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn lenient_policy_skips_the_oversized_payload_and_resyncs() {
+        // 서버만 4바이트로 제한하고 클라이언트는 에러 frame을 느긋하게 읽는다
+        let config = client_config();
+        let (mut client, server) = spawn_server(short_config(4), Arc::new(LenientPolicy));
+
+        write_frame(&mut client, "too long").await.unwrap();
+        write_frame(&mut client, "ok").await.unwrap();
+
+        let reply = read_frame(&mut client, &config).await.unwrap().unwrap();
+        assert!(reply.starts_with("error: frame too large"), "{}", reply);
+        assert_eq!(
+            read_frame(&mut client, &config).await.unwrap().as_deref(),
+            Some("echo: ok")
+        );
+
+        // 복구 불가능한 에러는 Reply 정책이어도 답한 뒤 끊는다
+        client.write_all(&[0, 0]).await.unwrap();
+        let reply = read_frame(&mut client, &config).await.unwrap().unwrap();
+        assert_eq!(reply, "error: frame read timeout");
+        assert!(read_frame(&mut client, &config).await.unwrap().is_none());
+
+        server.await.unwrap();
+    }
+}
@@ -1,50 +1,351 @@
-use std::collections::HashMap;
-use std::io::{ErrorKind, Read, Write};
+use std::collections::{HashMap, VecDeque};
+use std::env;
+use std::fs;
+use std::io::{ErrorKind, IoSlice, IoSliceMut, Read, Write};
 use std::net::{SocketAddr, TcpListener, TcpStream};
-use std::os::fd::{AsRawFd, RawFd};
+use std::os::fd::{AsRawFd, FromRawFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::{Arc, mpsc};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use nix::errno::Errno;
-use nix::sys::epoll::{
-    EpollCreateFlags, EpollEvent, EpollFlags, EpollOp, epoll_create1, epoll_ctl, epoll_wait,
-};
-use nix::unistd::close;
+use nix::sys::epoll::{Epoll, EpollCreateFlags, EpollEvent, EpollFlags, EpollTimeout};
+use nix::sys::eventfd::{EfdFlags, EventFd};
+use nix::sys::signal::{SigSet, Signal};
+use nix::sys::signalfd::{SfdFlags, SignalFd};
+use nix::sys::socket::{ControlMessage, ControlMessageOwned, MsgFlags, recvmsg, sendmsg};
 
 const MAX_EVENTS: usize = 1024;
 const LISTENER_TOKEN: u64 = 1;
+const SIGNAL_TOKEN: u64 = 2;
+const WAKER_TOKEN: u64 = 3;
+const HANDOFF_TOKEN: u64 = 4;
+const FIRST_CLIENT_TOKEN: u64 = 5;
+
+// 종료 시 남은 write_buf를 비우는 데 허용하는 시간
+const SHUTDOWN_DEADLINE: Duration = Duration::from_secs(5);
+
+// 재시작된 프로세스가 listener fd를 받아올 Unix socket 경로
+const HANDOFF_ENV: &str = "EPOLL_HANDOFF_PATH";
+
+// 버퍼 풀 chunk 크기 / 재사용을 위해 보관할 최대 free chunk 수
+const CHUNK_SIZE: usize = 4096;
+const MAX_FREE_CHUNKS: usize = 1024;
+
+// 클라이언트별 outbound 버퍼 watermark
+const HIGH_WATERMARK: usize = 64 * 1024;
+const LOW_WATERMARK: usize = 16 * 1024;
+
+// 서버 전체 outbound 버퍼 메모리 예산
+const MEMORY_BUDGET: usize = 64 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy)]
+struct BufferConfig {
+    // outbound 버퍼가 이 크기 이상이면 EPOLLIN 해제 (읽기 중단)
+    high_watermark: usize,
+    // 읽기 중단 상태에서 이 크기 이하로 비워지면 EPOLLIN 재등록
+    low_watermark: usize,
+    // 모든 클라이언트가 사용할 수 있는 버퍼 메모리 총량
+    memory_budget: usize,
+    chunk_size: usize,
+}
+
+impl Default for BufferConfig {
+    fn default() -> Self {
+        Self {
+            high_watermark: HIGH_WATERMARK,
+            low_watermark: LOW_WATERMARK,
+            memory_budget: MEMORY_BUDGET,
+            chunk_size: CHUNK_SIZE,
+        }
+    }
+}
+
+// ==================== BUFFER POOL ====================
+
+// 고정 크기 chunk를 재사용하는 풀.
+// 클라이언트마다 Vec<u8>을 키우는 대신 chunk를 빌려 쓰고 반납한다.
+struct BufferPool {
+    chunk_size: usize,
+    free: Vec<Box<[u8]>>,
+    max_free: usize,
+    in_use: usize,
+    budget: usize,
+}
+
+impl BufferPool {
+    fn new(config: &BufferConfig) -> Self {
+        Self {
+            chunk_size: config.chunk_size,
+            free: Vec::new(),
+            max_free: MAX_FREE_CHUNKS,
+            in_use: 0,
+            budget: config.memory_budget,
+        }
+    }
+
+    // 예산을 넘으면 None
+    fn acquire(&mut self) -> Option<Box<[u8]>> {
+        if self.in_use + self.chunk_size > self.budget {
+            return None;
+        }
+
+        self.in_use += self.chunk_size;
+        Some(
+            self.free
+                .pop()
+                .unwrap_or_else(|| vec![0u8; self.chunk_size].into_boxed_slice()),
+        )
+    }
+
+    fn release(&mut self, chunk: Box<[u8]>) {
+        self.in_use -= self.chunk_size;
+        if self.free.len() < self.max_free {
+            self.free.push(chunk);
+        }
+    }
+
+    fn has_room(&self) -> bool {
+        self.in_use + self.chunk_size <= self.budget
+    }
+}
+
+struct Chunk {
+    data: Box<[u8]>,
+    // data[start..end] 구간이 아직 전송되지 않은 바이트
+    start: usize,
+    end: usize,
+}
+
+// chunk 리스트로 구성된 클라이언트별 outbound 큐
+struct OutQueue {
+    chunks: VecDeque<Chunk>,
+    len: usize,
+}
+
+impl OutQueue {
+    fn new() -> Self {
+        Self {
+            chunks: VecDeque::new(),
+            len: 0,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // 마지막 chunk의 남은 공간. 가득 찼으면 풀에서 새 chunk를 빌린다.
+    fn spare(&mut self, pool: &mut BufferPool) -> Option<&mut [u8]> {
+        let need_chunk = match self.chunks.back() {
+            Some(chunk) => chunk.end == chunk.data.len(),
+            None => true,
+        };
+
+        if need_chunk {
+            let data = pool.acquire()?;
+            self.chunks.push_back(Chunk {
+                data,
+                start: 0,
+                end: 0,
+            });
+        }
+
+        let chunk = self.chunks.back_mut()?;
+        Some(&mut chunk.data[chunk.end..])
+    }
+
+    fn commit(&mut self, n: usize) {
+        if let Some(chunk) = self.chunks.back_mut() {
+            chunk.end += n;
+            self.len += n;
+        }
+    }
+
+    fn front(&self) -> Option<&[u8]> {
+        self.chunks.front().map(|chunk| &chunk.data[chunk.start..chunk.end])
+    }
+
+    fn consume(&mut self, n: usize, pool: &mut BufferPool) {
+        if let Some(chunk) = self.chunks.front_mut() {
+            chunk.start += n;
+            self.len -= n;
+
+            if chunk.start == chunk.end {
+                if let Some(chunk) = self.chunks.pop_front() {
+                    pool.release(chunk.data);
+                }
+            }
+        }
+    }
+
+    fn clear(&mut self, pool: &mut BufferPool) {
+        while let Some(chunk) = self.chunks.pop_front() {
+            pool.release(chunk.data);
+        }
+        self.len = 0;
+    }
+}
+
+// ==================== CONTROL ====================
+
+// 다른 스레드에서 event loop로 보내는 명령
+#[derive(Debug, Clone, Copy)]
+enum Command {
+    Shutdown,
+    Reload,
+    Stats,
+}
+
+// mpsc 채널에 명령을 넣고 eventfd를 써서 epoll_wait를 깨운다
+#[derive(Clone)]
+struct ServerHandle {
+    tx: mpsc::Sender<Command>,
+    waker: Arc<EventFd>,
+}
+
+impl ServerHandle {
+    fn send(&self, command: Command) -> Result<(), Box<dyn std::error::Error>> {
+        self.tx.send(command).map_err(|_| "event loop is gone")?;
+        self.waker.write(1)?;
+        Ok(())
+    }
+}
+
+enum ServerState {
+    Running,
+    // 새 연결은 받지 않고 남은 write_buf만 비우는 중
+    Draining { deadline: Instant },
+}
+
+// ==================== SERVER ====================
+
+pub fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // 스레드를 만들기 전에 시그널을 막아야 모든 스레드에 mask가 상속되고
+    // signalfd로만 시그널을 받게 된다
+    let mut mask = SigSet::empty();
+    mask.add(Signal::SIGTERM);
+    mask.add(Signal::SIGINT);
+    mask.add(Signal::SIGHUP);
+    mask.thread_block()?;
+
+    let signal_fd = SignalFd::with_flags(&mask, SfdFlags::SFD_NONBLOCK | SfdFlags::SFD_CLOEXEC)?;
+
+    let waker = Arc::new(EventFd::from_flags(EfdFlags::EFD_NONBLOCK | EfdFlags::EFD_CLOEXEC)?);
+    let (tx, commands) = mpsc::channel();
+    let handle = ServerHandle {
+        tx,
+        waker: Arc::clone(&waker),
+    };
+
+    // stdin으로 받은 관리 명령을 event loop에 전달
+    thread::spawn(move || {
+        for line in std::io::stdin().lines().map_while(Result::ok) {
+            let command = match line.trim() {
+                "shutdown" => Command::Shutdown,
+                "reload" => Command::Reload,
+                "stats" => Command::Stats,
+                other => {
+                    eprintln!("[WARN] unknown command: {}", other);
+                    continue;
+                }
+            };
+
+            if handle.send(command).is_err() {
+                break;
+            }
+        }
+    });
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
     let addr: SocketAddr = "0.0.0.0:9000".parse()?;
-    let listener = TcpListener::bind(addr)?;
-    listener.set_nonblocking(true)?;
+    let mut listener = Some(open_listener(addr)?);
+
+    let config = BufferConfig::default();
+    let mut pool = BufferPool::new(&config);
 
-    println!("[INFO] listening on {}", addr);
+    println!(
+        "[INFO] listening on {} pid={} (high={} low={} budget={})",
+        addr,
+        std::process::id(),
+        config.high_watermark,
+        config.low_watermark,
+        config.memory_budget
+    );
 
-    let listener_fd = listener.as_raw_fd();
-    let epoll_fd = epoll_create1(EpollCreateFlags::EPOLL_CLOEXEC)?;
+    let epoll = Epoll::new(EpollCreateFlags::EPOLL_CLOEXEC)?;
+
+    if let Some(listener) = &listener {
+        epoll.add(listener, EpollEvent::new(EpollFlags::EPOLLIN, LISTENER_TOKEN))?;
+    }
 
-    let mut listener_event =
-        EpollEvent::new(EpollFlags::EPOLLIN, LISTENER_TOKEN);
-    epoll_ctl(epoll_fd, EpollOp::EpollCtlAdd, listener_fd, &mut listener_event)?;
+    epoll.add(&signal_fd, EpollEvent::new(EpollFlags::EPOLLIN, SIGNAL_TOKEN))?;
+    epoll.add(&*waker, EpollEvent::new(EpollFlags::EPOLLIN, WAKER_TOKEN))?;
 
-    let mut next_token: u64 = LISTENER_TOKEN + 1;
+    let mut next_token: u64 = FIRST_CLIENT_TOKEN;
     let mut clients: HashMap<RawFd, Client> = HashMap::new();
     let mut token_to_fd: HashMap<u64, RawFd> = HashMap::new();
 
+    let mut state = ServerState::Running;
+    let mut handoff: Option<Handoff> = None;
+
     let mut events = vec![EpollEvent::empty(); MAX_EVENTS];
 
     loop {
-        let nfds = epoll_wait(epoll_fd, &mut events, -1)?;
+        if let ServerState::Draining { deadline } = state {
+            if clients.is_empty() {
+                println!("[INFO] all clients flushed, exiting");
+                break;
+            }
+
+            if Instant::now() >= deadline {
+                println!(
+                    "[WARN] shutdown deadline reached, dropping {} clients",
+                    clients.len()
+                );
+                for (_, mut client) in clients.drain() {
+                    client.write_buf.clear(&mut pool);
+                }
+                break;
+            }
+        }
+
+        let timeout = match state {
+            ServerState::Running => EpollTimeout::NONE,
+            ServerState::Draining { deadline } => {
+                EpollTimeout::try_from(deadline.saturating_duration_since(Instant::now()))
+                    .unwrap_or(EpollTimeout::MAX)
+            }
+        };
+
+        let nfds = match epoll.wait(&mut events, timeout) {
+            Ok(n) => n,
+            Err(Errno::EINTR) => continue,
+            Err(e) => return Err(Box::new(e)),
+        };
+
+        let mut pending_commands = Vec::new();
 
         for event in events.iter().take(nfds) {
             let token = event.data();
             let flags = event.events();
 
             if token == LISTENER_TOKEN {
+                let Some(listener) = &listener else {
+                    continue;
+                };
+
                 loop {
                     match listener.accept() {
                         Ok((stream, peer_addr)) => {
                             if let Err(e) =
-                                handle_new_client(stream, peer_addr, epoll_fd, &mut next_token, &mut clients, &mut token_to_fd)
+                                handle_new_client(stream, peer_addr, &epoll, &mut next_token, &mut clients, &mut token_to_fd)
                             {
                                 eprintln!("[ERROR] accept handling failed: {}", e);
                             }
@@ -61,6 +362,53 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 continue;
             }
 
+            if token == SIGNAL_TOKEN {
+                while let Some(info) = signal_fd.read_signal()? {
+                    match Signal::try_from(info.ssi_signo as i32) {
+                        Ok(Signal::SIGTERM) | Ok(Signal::SIGINT) => {
+                            pending_commands.push(Command::Shutdown);
+                        }
+                        Ok(Signal::SIGHUP) => {
+                            pending_commands.push(Command::Reload);
+                        }
+                        _ => {}
+                    }
+                }
+                continue;
+            }
+
+            if token == WAKER_TOKEN {
+                let _ = waker.read();
+                pending_commands.extend(commands.try_iter());
+                continue;
+            }
+
+            if token == HANDOFF_TOKEN {
+                let (Some(h), Some(l)) = (&handoff, &listener) else {
+                    continue;
+                };
+
+                let done = match h.send_listener(l) {
+                    Ok(true) => {
+                        println!("[INFO] listener handed off to pid={}", h.child_pid);
+                        pending_commands.push(Command::Shutdown);
+                        true
+                    }
+                    Ok(false) => false,
+                    Err(e) => {
+                        eprintln!("[ERROR] listener handoff failed: {}", e);
+                        true
+                    }
+                };
+
+                if done {
+                    if let Some(h) = handoff.take() {
+                        let _ = epoll.delete(&h.socket);
+                    }
+                }
+                continue;
+            }
+
             let Some(&fd) = token_to_fd.get(&token) else {
                 continue;
             };
@@ -72,85 +420,111 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 || flags.contains(EpollFlags::EPOLLRDHUP)
             {
                 should_close = true;
-            } else {
-                if let Some(client) = clients.get_mut(&fd) {
-                    if flags.contains(EpollFlags::EPOLLIN) {
-                        match read_from_client(client) {
-                            Ok(ReadResult::Data(data)) => {
-                                println!(
-                                    "[RECV] fd={} peer={} bytes={} msg={}",
-                                    client.fd,
-                                    client.peer_addr,
-                                    data.len(),
-                                    String::from_utf8_lossy(&data).trim_end()
-                                );
-                                client.write_buf.extend_from_slice(&data);
-                            }
-                            Ok(ReadResult::Closed) => {
-                                should_close = true;
-                            }
-                            Ok(ReadResult::WouldBlock) => {}
-                            Err(e) => {
-                                eprintln!("[ERROR] read failed fd={}: {}", client.fd, e);
-                                should_close = true;
-                            }
+            } else if let Some(client) = clients.get_mut(&fd) {
+                if flags.contains(EpollFlags::EPOLLIN) && !client.reading_paused && !client.draining {
+                    match read_from_client(client, &mut pool, &config) {
+                        Ok(ReadResult::Data(n)) => {
+                            println!(
+                                "[RECV] fd={} peer={} bytes={} pending={}",
+                                client.fd,
+                                client.peer_addr,
+                                n,
+                                client.write_buf.len()
+                            );
                         }
-                    }
-
-                    if !client.write_buf.is_empty() {
-                        if let Err(e) = modify_interest(
-                            epoll_fd,
-                            client.fd,
-                            client.token,
-                            EpollFlags::EPOLLIN
-                                | EpollFlags::EPOLLOUT
-                                | EpollFlags::EPOLLRDHUP,
-                        ) {
-                            eprintln!("[ERROR] modify interest failed fd={}: {}", client.fd, e);
+                        Ok(ReadResult::Closed) => {
+                            should_close = true;
+                        }
+                        Ok(ReadResult::WouldBlock) => {}
+                        Err(e) => {
+                            eprintln!("[ERROR] read failed fd={}: {}", client.fd, e);
                             should_close = true;
                         }
                     }
+                }
 
-                    if !should_close && flags.contains(EpollFlags::EPOLLOUT) {
-                        match write_to_client(client) {
-                            Ok(()) => {
-                                let interest = if client.write_buf.is_empty() {
-                                    EpollFlags::EPOLLIN | EpollFlags::EPOLLRDHUP
-                                } else {
-                                    EpollFlags::EPOLLIN
-                                        | EpollFlags::EPOLLOUT
-                                        | EpollFlags::EPOLLRDHUP
-                                };
-
-                                if let Err(e) =
-                                    modify_interest(epoll_fd, client.fd, client.token, interest)
-                                {
-                                    eprintln!(
-                                        "[ERROR] modify interest failed fd={}: {}",
-                                        client.fd, e
-                                    );
-                                    should_close = true;
-                                }
-                            }
-                            Err(e) => {
-                                eprintln!("[ERROR] write failed fd={}: {}", client.fd, e);
-                                should_close = true;
-                            }
-                        }
+                if !should_close && flags.contains(EpollFlags::EPOLLOUT) {
+                    if let Err(e) = write_to_client(client, &mut pool) {
+                        eprintln!("[ERROR] write failed fd={}: {}", client.fd, e);
+                        should_close = true;
+                    }
+                }
+
+                // 종료 중에는 다 보낸 클라이언트부터 닫는다
+                if client.draining && client.write_buf.is_empty() {
+                    should_close = true;
+                }
+
+                if !should_close {
+                    if let Err(e) = update_interest(&epoll, client, &config) {
+                        eprintln!("[ERROR] modify interest failed fd={}: {}", client.fd, e);
+                        should_close = true;
                     }
                 }
             }
 
             if should_close {
-                if let Some(client) = clients.remove(&fd) {
-                    println!("[INFO] disconnect fd={} peer={}", client.fd, client.peer_addr);
-                    token_to_fd.remove(&client.token);
-                    let _ = epoll_ctl(epoll_fd, EpollOp::EpollCtlDel, client.fd, None);
-                    let _ = close(client.fd);
+                disconnect(&epoll, fd, &mut clients, &mut token_to_fd, &mut pool);
+            }
+        }
+
+        for command in pending_commands {
+            match command {
+                Command::Shutdown => {
+                    if let ServerState::Running = state {
+                        println!(
+                            "[INFO] shutting down, flushing {} clients (deadline {:?})",
+                            clients.len(),
+                            SHUTDOWN_DEADLINE
+                        );
+
+                        if let Some(listener) = listener.take() {
+                            let _ = epoll.delete(&listener);
+                        }
+
+                        begin_drain(&epoll, &mut clients, &mut token_to_fd, &mut pool, &config);
+                        state = ServerState::Draining {
+                            deadline: Instant::now() + SHUTDOWN_DEADLINE,
+                        };
+                    }
+                }
+                Command::Reload => {
+                    if handoff.is_some() || listener.is_none() {
+                        continue;
+                    }
+
+                    match Handoff::start() {
+                        Ok(h) => {
+                            let event = EpollEvent::new(EpollFlags::EPOLLIN, HANDOFF_TOKEN);
+                            if let Err(e) = epoll.add(&h.socket, event) {
+                                eprintln!("[ERROR] handoff register failed: {}", e);
+                                continue;
+                            }
+                            println!("[INFO] reload: spawned pid={}, waiting for handoff", h.child_pid);
+                            handoff = Some(h);
+                        }
+                        Err(e) => eprintln!("[ERROR] reload failed: {}", e),
+                    }
+                }
+                Command::Stats => {
+                    println!(
+                        "[STATS] clients={} buffered={} pool_in_use={} pool_free_chunks={}",
+                        clients.len(),
+                        clients.values().map(|c| c.write_buf.len()).sum::<usize>(),
+                        pool.in_use,
+                        pool.free.len()
+                    );
                 }
             }
         }
+
+        // 전역 예산 때문에 멈춘 클라이언트는 메모리가 반납되면 다시 읽기 시작
+        if pool.has_room() {
+            resume_budget_stalled(&epoll, &mut clients, &config);
+        }
     }
+
+    Ok(())
 }
 
 struct Client {
@@ -158,11 +532,18 @@ struct Client {
     token: u64,
     peer_addr: SocketAddr,
     stream: TcpStream,
-    write_buf: Vec<u8>,
+    write_buf: OutQueue,
+    // 현재 epoll에 등록된 interest
+    interest: EpollFlags,
+    reading_paused: bool,
+    // 전역 메모리 예산 부족으로 읽기를 멈춘 상태
+    budget_stalled: bool,
+    // 종료 중: 더 읽지 않고 write_buf만 비운다
+    draining: bool,
 }
 
 enum ReadResult {
-    Data(Vec<u8>),
+    Data(usize),
     Closed,
     WouldBlock,
 }
@@ -170,7 +551,7 @@ enum ReadResult {
 fn handle_new_client(
     stream: TcpStream,
     peer_addr: SocketAddr,
-    epoll_fd: RawFd,
+    epoll: &Epoll,
     next_token: &mut u64,
     clients: &mut HashMap<RawFd, Client>,
     token_to_fd: &mut HashMap<u64, RawFd>,
@@ -180,11 +561,8 @@ fn handle_new_client(
     let token = *next_token;
     *next_token += 1;
 
-    let mut event = EpollEvent::new(
-        EpollFlags::EPOLLIN | EpollFlags::EPOLLRDHUP,
-        token,
-    );
-    epoll_ctl(epoll_fd, EpollOp::EpollCtlAdd, fd, &mut event)?;
+    let interest = EpollFlags::EPOLLIN | EpollFlags::EPOLLRDHUP;
+    epoll.add(&stream, EpollEvent::new(interest, token))?;
 
     println!("[INFO] new client fd={} peer={}", fd, peer_addr);
 
@@ -195,7 +573,11 @@ fn handle_new_client(
             token,
             peer_addr,
             stream,
-            write_buf: Vec::new(),
+            write_buf: OutQueue::new(),
+            interest,
+            reading_paused: false,
+            budget_stalled: false,
+            draining: false,
         },
     );
     token_to_fd.insert(token, fd);
@@ -203,45 +585,60 @@ fn handle_new_client(
     Ok(())
 }
 
-fn read_from_client(client: &mut Client) -> Result<ReadResult, Box<dyn std::error::Error>> {
-    let mut buf = [0u8; 4096];
-    let mut out = Vec::new();
+// 풀에서 빌린 chunk에 바로 읽어 들인다 (echo이므로 읽은 데이터가 곧 outbound 데이터).
+// high watermark에 닿거나 예산이 바닥나면 남은 데이터는 커널 버퍼에 둔다.
+fn read_from_client(
+    client: &mut Client,
+    pool: &mut BufferPool,
+    config: &BufferConfig,
+) -> Result<ReadResult, Box<dyn std::error::Error>> {
+    let mut total = 0;
 
     loop {
-        match client.stream.read(&mut buf) {
+        if client.write_buf.len() >= config.high_watermark {
+            break;
+        }
+
+        let Some(space) = client.write_buf.spare(pool) else {
+            client.budget_stalled = true;
+            break;
+        };
+
+        match client.stream.read(space) {
             Ok(0) => {
-                if out.is_empty() {
+                if total == 0 {
                     return Ok(ReadResult::Closed);
-                } else {
-                    return Ok(ReadResult::Data(out));
                 }
+                break;
             }
             Ok(n) => {
-                out.extend_from_slice(&buf[..n]);
-                if n < buf.len() {
-                    return Ok(ReadResult::Data(out));
-                }
+                client.write_buf.commit(n);
+                total += n;
             }
             Err(e) if e.kind() == ErrorKind::WouldBlock => {
-                if out.is_empty() {
+                if total == 0 {
                     return Ok(ReadResult::WouldBlock);
-                } else {
-                    return Ok(ReadResult::Data(out));
                 }
+                break;
             }
             Err(e) => return Err(Box::new(e)),
         }
     }
+
+    Ok(ReadResult::Data(total))
 }
 
-fn write_to_client(client: &mut Client) -> Result<(), Box<dyn std::error::Error>> {
-    while !client.write_buf.is_empty() {
-        match client.stream.write(&client.write_buf) {
+fn write_to_client(
+    client: &mut Client,
+    pool: &mut BufferPool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    while let Some(data) = client.write_buf.front() {
+        match client.stream.write(data) {
             Ok(0) => {
                 return Err("write returned 0".into());
             }
             Ok(n) => {
-                client.write_buf.drain(..n);
+                client.write_buf.consume(n, pool);
             }
             Err(e) if e.kind() == ErrorKind::WouldBlock => {
                 break;
@@ -252,12 +649,474 @@ fn write_to_client(client: &mut Client) -> Result<(), Box<dyn std::error::Error>
     Ok(())
 }
 
+// watermark / 예산 상태에 맞춰 EPOLLIN, EPOLLOUT 등록을 갱신
+fn update_interest(
+    epoll: &Epoll,
+    client: &mut Client,
+    config: &BufferConfig,
+) -> nix::Result<()> {
+    let pending = client.write_buf.len();
+
+    if client.reading_paused {
+        if pending <= config.low_watermark && !client.budget_stalled {
+            client.reading_paused = false;
+            println!("[INFO] resume reading fd={} pending={}", client.fd, pending);
+        }
+    } else if pending >= config.high_watermark || client.budget_stalled {
+        client.reading_paused = true;
+        println!(
+            "[WARN] pause reading fd={} pending={} budget_stalled={}",
+            client.fd, pending, client.budget_stalled
+        );
+    }
+
+    let mut interest = EpollFlags::EPOLLRDHUP;
+    if !client.reading_paused && !client.draining {
+        interest |= EpollFlags::EPOLLIN;
+    }
+    if !client.write_buf.is_empty() {
+        interest |= EpollFlags::EPOLLOUT;
+    }
+
+    if interest != client.interest {
+        modify_interest(epoll, &client.stream, client.token, interest)?;
+        client.interest = interest;
+    }
+
+    Ok(())
+}
+
+fn resume_budget_stalled(
+    epoll: &Epoll,
+    clients: &mut HashMap<RawFd, Client>,
+    config: &BufferConfig,
+) {
+    for client in clients.values_mut().filter(|c| c.budget_stalled && !c.draining) {
+        client.budget_stalled = false;
+
+        if let Err(e) = update_interest(epoll, client, config) {
+            eprintln!("[ERROR] modify interest failed fd={}: {}", client.fd, e);
+        }
+    }
+}
+
 fn modify_interest(
-    epoll_fd: RawFd,
-    fd: RawFd,
+    epoll: &Epoll,
+    stream: &TcpStream,
     token: u64,
     flags: EpollFlags,
 ) -> nix::Result<()> {
     let mut event = EpollEvent::new(flags, token);
-    epoll_ctl(epoll_fd, EpollOp::EpollCtlMod, fd, &mut event)
-}
\ No newline at end of file
+    epoll.modify(stream, &mut event)
+}
+
+// epoll 등록을 해제하고 버퍼를 풀에 반납한다. fd는 TcpStream이 drop될 때 닫힌다.
+fn disconnect(
+    epoll: &Epoll,
+    fd: RawFd,
+    clients: &mut HashMap<RawFd, Client>,
+    token_to_fd: &mut HashMap<u64, RawFd>,
+    pool: &mut BufferPool,
+) {
+    if let Some(mut client) = clients.remove(&fd) {
+        println!("[INFO] disconnect fd={} peer={}", client.fd, client.peer_addr);
+        client.write_buf.clear(pool);
+        token_to_fd.remove(&client.token);
+        let _ = epoll.delete(&client.stream);
+    }
+}
+
+// 모든 클라이언트의 읽기를 멈추고, 보낼 데이터가 없는 클라이언트는 바로 닫는다
+fn begin_drain(
+    epoll: &Epoll,
+    clients: &mut HashMap<RawFd, Client>,
+    token_to_fd: &mut HashMap<u64, RawFd>,
+    pool: &mut BufferPool,
+    config: &BufferConfig,
+) {
+    let mut idle = Vec::new();
+
+    for client in clients.values_mut() {
+        client.draining = true;
+
+        if client.write_buf.is_empty() || update_interest(epoll, client, config).is_err() {
+            idle.push(client.fd);
+        }
+    }
+
+    for fd in idle {
+        disconnect(epoll, fd, clients, token_to_fd, pool);
+    }
+}
+
+// ==================== HOT RELOAD ====================
+
+// 재시작 시 listener를 넘겨받는다. HANDOFF_ENV가 없으면 새로 bind.
+fn open_listener(addr: SocketAddr) -> Result<TcpListener, Box<dyn std::error::Error>> {
+    let listener = match env::var_os(HANDOFF_ENV) {
+        Some(path) => {
+            println!("[INFO] taking over listener via {:?}", path);
+            receive_listener(Path::new(&path))?
+        }
+        None => TcpListener::bind(addr)?,
+    };
+
+    listener.set_nonblocking(true)?;
+    Ok(listener)
+}
+
+fn receive_listener(path: &Path) -> Result<TcpListener, Box<dyn std::error::Error>> {
+    let stream = UnixStream::connect(path)?;
+
+    let mut byte = [0u8; 1];
+    let mut iov = [IoSliceMut::new(&mut byte)];
+    let mut cmsg_buf = nix::cmsg_space!(RawFd);
+
+    let msg = recvmsg::<()>(
+        stream.as_raw_fd(),
+        &mut iov,
+        Some(&mut cmsg_buf),
+        MsgFlags::MSG_CMSG_CLOEXEC,
+    )?;
+
+    for cmsg in msg.cmsgs()? {
+        if let ControlMessageOwned::ScmRights(fds) = cmsg {
+            if let Some(&fd) = fds.first() {
+                // SCM_RIGHTS로 받은 fd는 이 프로세스 소유
+                return Ok(unsafe { TcpListener::from_raw_fd(fd) });
+            }
+        }
+    }
+
+    Err("handoff message carried no listener fd".into())
+}
+
+// 새 프로세스를 띄우고, 접속해 오면 SCM_RIGHTS로 listener fd를 넘긴다
+struct Handoff {
+    socket: UnixListener,
+    path: PathBuf,
+    child_pid: u32,
+}
+
+impl Handoff {
+    fn start() -> Result<Self, Box<dyn std::error::Error>> {
+        let path = env::temp_dir().join(format!("epoll-handoff-{}.sock", process::id()));
+        let _ = fs::remove_file(&path);
+
+        let socket = UnixListener::bind(&path)?;
+        socket.set_nonblocking(true)?;
+
+        let child = process::Command::new(env::current_exe()?)
+            .args(env::args_os().skip(1))
+            .env(HANDOFF_ENV, &path)
+            .spawn()?;
+
+        Ok(Self {
+            socket,
+            path,
+            child_pid: child.id(),
+        })
+    }
+
+    // 자식이 아직 접속하지 않았으면 Ok(false)
+    fn send_listener(&self, listener: &TcpListener) -> Result<bool, Box<dyn std::error::Error>> {
+        let stream = match self.socket.accept() {
+            Ok((stream, _)) => stream,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(false),
+            Err(e) => return Err(Box::new(e)),
+        };
+        stream.set_nonblocking(false)?;
+
+        let fds = [listener.as_raw_fd()];
+        let iov = [IoSlice::new(b"L")];
+        sendmsg::<()>(
+            stream.as_raw_fd(),
+            &iov,
+            &[ControlMessage::ScmRights(&fds)],
+            MsgFlags::empty(),
+            None,
+        )?;
+
+        Ok(true)
+    }
+}
+
+impl Drop for Handoff {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 테스트용 작은 버퍼 설정: chunk 1KB, watermark 8KB/2KB
+    fn small_config(memory_budget: usize) -> BufferConfig {
+        BufferConfig {
+            high_watermark: 8 * 1024,
+            low_watermark: 2 * 1024,
+            memory_budget,
+            chunk_size: 1024,
+        }
+    }
+
+    // loopback 연결을 하나 만들어 서버 쪽을 epoll에 등록하고, (peer, 서버 fd)를 돌려준다
+    fn connect(
+        epoll: &Epoll,
+        clients: &mut HashMap<RawFd, Client>,
+        token_to_fd: &mut HashMap<u64, RawFd>,
+        next_token: &mut u64,
+    ) -> (TcpStream, RawFd) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let peer = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, peer_addr) = listener.accept().unwrap();
+        let fd = stream.as_raw_fd();
+        handle_new_client(stream, peer_addr, epoll, next_token, clients, token_to_fd).unwrap();
+        (peer, fd)
+    }
+
+    // 서버 쪽 소켓에 읽을 데이터가 도착할 때까지 기다린다
+    fn wait_readable(epoll: &Epoll) {
+        let mut events = vec![EpollEvent::empty(); 8];
+        let n = epoll.wait(&mut events, 1000u16).unwrap();
+        assert!(n > 0, "no epoll event within 1s");
+    }
+
+    #[test]
+    fn out_queue_returns_chunks_to_pool() {
+        let config = small_config(MEMORY_BUDGET);
+        let mut pool = BufferPool::new(&config);
+        let mut queue = OutQueue::new();
+
+        let space = queue.spare(&mut pool).unwrap();
+        assert_eq!(space.len(), 1024);
+        space[..5].copy_from_slice(b"hello");
+        queue.commit(5);
+
+        assert_eq!(queue.front(), Some(&b"hello"[..]));
+        assert_eq!(pool.in_use, 1024);
+
+        queue.consume(2, &mut pool);
+        assert_eq!(queue.front(), Some(&b"llo"[..]));
+        queue.consume(3, &mut pool);
+
+        assert!(queue.is_empty());
+        assert_eq!(pool.in_use, 0);
+        assert_eq!(pool.free.len(), 1);
+
+        // 반납된 chunk를 다시 빌려 쓴다
+        queue.spare(&mut pool).unwrap();
+        assert!(pool.free.is_empty());
+    }
+
+    #[test]
+    fn pool_refuses_chunks_over_budget() {
+        let config = small_config(2 * 1024);
+        let mut pool = BufferPool::new(&config);
+
+        let a = pool.acquire().unwrap();
+        let _b = pool.acquire().unwrap();
+        assert!(pool.acquire().is_none());
+        assert!(!pool.has_room());
+
+        pool.release(a);
+        assert!(pool.has_room());
+    }
+
+    #[test]
+    fn reading_pauses_at_high_watermark_and_resumes_below_low() {
+        let config = small_config(MEMORY_BUDGET);
+        let mut pool = BufferPool::new(&config);
+        let epoll = Epoll::new(EpollCreateFlags::EPOLL_CLOEXEC).unwrap();
+        let mut clients = HashMap::new();
+        let mut token_to_fd = HashMap::new();
+        let mut next_token = FIRST_CLIENT_TOKEN;
+
+        let (mut peer, fd) = connect(&epoll, &mut clients, &mut token_to_fd, &mut next_token);
+        peer.write_all(&[7u8; 32 * 1024]).unwrap();
+        wait_readable(&epoll);
+
+        let client = clients.get_mut(&fd).unwrap();
+        let mut total = 0;
+        while client.write_buf.len() < config.high_watermark {
+            match read_from_client(client, &mut pool, &config).unwrap() {
+                ReadResult::Data(n) => total += n,
+                ReadResult::WouldBlock => thread::sleep(Duration::from_millis(10)),
+                ReadResult::Closed => panic!("peer closed"),
+            }
+        }
+
+        // high watermark에서 멈추고 나머지는 커널 버퍼에 남는다
+        assert_eq!(client.write_buf.len(), total);
+        assert!(total < 32 * 1024);
+        assert!(matches!(
+            read_from_client(client, &mut pool, &config).unwrap(),
+            ReadResult::Data(0)
+        ));
+
+        update_interest(&epoll, client, &config).unwrap();
+        assert!(client.reading_paused);
+        assert!(!client.interest.contains(EpollFlags::EPOLLIN));
+        assert!(client.interest.contains(EpollFlags::EPOLLOUT));
+
+        write_to_client(client, &mut pool).unwrap();
+        assert!(client.write_buf.is_empty());
+        assert_eq!(pool.in_use, 0);
+
+        update_interest(&epoll, client, &config).unwrap();
+        assert!(!client.reading_paused);
+        assert!(client.interest.contains(EpollFlags::EPOLLIN));
+        assert!(!client.interest.contains(EpollFlags::EPOLLOUT));
+
+        // 버퍼에 있던 데이터가 그대로 echo 된다
+        let mut echoed = vec![0u8; total];
+        peer.read_exact(&mut echoed).unwrap();
+        assert!(echoed.iter().all(|&b| b == 7));
+    }
+
+    #[test]
+    fn exhausted_budget_stalls_reading_until_memory_returns() {
+        let config = small_config(2 * 1024);
+        let mut pool = BufferPool::new(&config);
+        let epoll = Epoll::new(EpollCreateFlags::EPOLL_CLOEXEC).unwrap();
+        let mut clients = HashMap::new();
+        let mut token_to_fd = HashMap::new();
+        let mut next_token = FIRST_CLIENT_TOKEN;
+
+        let (mut peer, fd) = connect(&epoll, &mut clients, &mut token_to_fd, &mut next_token);
+        peer.write_all(&[1u8; 4 * 1024]).unwrap();
+        wait_readable(&epoll);
+
+        let client = clients.get_mut(&fd).unwrap();
+        while !client.budget_stalled {
+            if let ReadResult::WouldBlock = read_from_client(client, &mut pool, &config).unwrap() {
+                thread::sleep(Duration::from_millis(10));
+            }
+        }
+
+        // 예산(2 chunk)만큼만 버퍼링하고 watermark 전이라도 읽기를 멈춘다
+        assert_eq!(client.write_buf.len(), 2 * 1024);
+        assert!(!pool.has_room());
+        update_interest(&epoll, client, &config).unwrap();
+        assert!(client.reading_paused);
+        assert!(!client.interest.contains(EpollFlags::EPOLLIN));
+
+        write_to_client(client, &mut pool).unwrap();
+        assert!(pool.has_room());
+
+        resume_budget_stalled(&epoll, &mut clients, &config);
+        let client = &clients[&fd];
+        assert!(!client.budget_stalled);
+        assert!(!client.reading_paused);
+        assert!(client.interest.contains(EpollFlags::EPOLLIN));
+    }
+
+    #[test]
+    fn server_handle_wakes_event_loop() {
+        let epoll = Epoll::new(EpollCreateFlags::EPOLL_CLOEXEC).unwrap();
+        let waker = Arc::new(EventFd::from_flags(EfdFlags::EFD_NONBLOCK).unwrap());
+        epoll
+            .add(&*waker, EpollEvent::new(EpollFlags::EPOLLIN, WAKER_TOKEN))
+            .unwrap();
+
+        let (tx, commands) = mpsc::channel();
+        let handle = ServerHandle {
+            tx,
+            waker: Arc::clone(&waker),
+        };
+        thread::spawn(move || handle.send(Command::Shutdown).unwrap());
+
+        let mut events = vec![EpollEvent::empty(); 4];
+        let n = epoll.wait(&mut events, 1000u16).unwrap();
+        assert_eq!(n, 1);
+        assert_eq!(events[0].data(), WAKER_TOKEN);
+
+        waker.read().unwrap();
+        let received: Vec<Command> = commands.try_iter().collect();
+        assert!(matches!(received[..], [Command::Shutdown]));
+    }
+
+    #[test]
+    fn drain_closes_idle_clients_and_keeps_flushing_the_rest() {
+        let config = small_config(MEMORY_BUDGET);
+        let mut pool = BufferPool::new(&config);
+        let epoll = Epoll::new(EpollCreateFlags::EPOLL_CLOEXEC).unwrap();
+        let mut clients = HashMap::new();
+        let mut token_to_fd = HashMap::new();
+        let mut next_token = FIRST_CLIENT_TOKEN;
+
+        let (mut idle_peer, idle_fd) =
+            connect(&epoll, &mut clients, &mut token_to_fd, &mut next_token);
+        let (mut busy_peer, busy_fd) =
+            connect(&epoll, &mut clients, &mut token_to_fd, &mut next_token);
+
+        // busy 클라이언트에만 아직 보내지 않은 데이터를 쌓는다
+        busy_peer.write_all(b"pending reply").unwrap();
+        wait_readable(&epoll);
+        let busy = clients.get_mut(&busy_fd).unwrap();
+        while busy.write_buf.len() < b"pending reply".len() {
+            read_from_client(busy, &mut pool, &config).unwrap();
+        }
+
+        begin_drain(&epoll, &mut clients, &mut token_to_fd, &mut pool, &config);
+
+        // 보낼 것이 없는 클라이언트는 바로 닫힌다
+        assert!(!clients.contains_key(&idle_fd));
+        assert_eq!(token_to_fd.len(), 1);
+        let mut buf = [0u8; 16];
+        assert_eq!(idle_peer.read(&mut buf).unwrap(), 0);
+
+        // 남은 클라이언트는 더 읽지 않고 쓰기만 기다린다
+        let busy = clients.get_mut(&busy_fd).unwrap();
+        assert!(busy.draining);
+        assert!(!busy.interest.contains(EpollFlags::EPOLLIN));
+        assert!(busy.interest.contains(EpollFlags::EPOLLOUT));
+
+        write_to_client(busy, &mut pool).unwrap();
+        assert!(busy.write_buf.is_empty());
+        disconnect(&epoll, busy_fd, &mut clients, &mut token_to_fd, &mut pool);
+
+        let mut reply = Vec::new();
+        busy_peer.read_to_end(&mut reply).unwrap();
+        assert_eq!(reply, b"pending reply");
+        assert!(clients.is_empty());
+        assert_eq!(pool.in_use, 0);
+    }
+
+    #[test]
+    fn handoff_passes_listener_over_unix_socket() {
+        let path = env::temp_dir().join(format!("epoll-handoff-test-{}.sock", process::id()));
+        let _ = fs::remove_file(&path);
+        let socket = UnixListener::bind(&path).unwrap();
+        socket.set_nonblocking(true).unwrap();
+        let handoff = Handoff {
+            socket,
+            path: path.clone(),
+            child_pid: 0,
+        };
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // 아직 아무도 접속하지 않았으면 다음 이벤트를 기다린다
+        assert!(!handoff.send_listener(&listener).unwrap());
+
+        let receiver = thread::spawn(move || receive_listener(&path).unwrap());
+        while !handoff.send_listener(&listener).unwrap() {
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        let received = receiver.join().unwrap();
+        assert_eq!(received.local_addr().unwrap(), addr);
+
+        // 넘겨받은 fd로 새 연결을 accept 할 수 있다
+        let _client = TcpStream::connect(addr).unwrap();
+        assert!(received.accept().is_ok());
+
+        drop(handoff);
+        assert!(!env::temp_dir()
+            .join(format!("epoll-handoff-test-{}.sock", process::id()))
+            .exists());
+    }
+}
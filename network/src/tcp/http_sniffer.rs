@@ -0,0 +1,948 @@
+use crate::tcp::conversation::{
+    capture_live, read_pcap, Conversation, ConversationHandler, ConversationTracker, Direction,
+};
+use crate::tcp::stream_reassembly::ReassemblyConfig;
+use serde_json::json;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+// 재조립된 conversation 위에서 HTTP/1.0, 1.1 요청과 응답을 짝지어 트랜잭션 하나마다 기록을 남긴다.
+// keep-alive pipelining은 요청 queue 순서대로 응답을 붙이고, body 끝은 Content-Length / chunked / 연결 종료 순으로 판단한다.
+// 101 Switching Protocols나 CONNECT 터널 뒤로는 HTTP가 아니라서 그 conversation은 더 보지 않는다.
+
+// ==================== CONFIG ====================
+
+// 헤더가 이보다 길면 HTTP가 아니거나 깨진 것으로 본다
+const MAX_HEAD: usize = 64 * 1024;
+const MAX_LINE: usize = 4096;
+
+const METHODS: [&str; 9] = [
+    "GET", "POST", "PUT", "DELETE", "HEAD", "OPTIONS", "PATCH", "CONNECT", "TRACE",
+];
+
+// ==================== TRANSACTION ====================
+
+#[derive(Debug, Clone)]
+pub struct HttpTransaction {
+    pub client: SocketAddr,
+    pub server: SocketAddr,
+    pub method: Option<String>,
+    pub host: Option<String>,
+    pub path: Option<String>,
+    pub version: Option<String>,
+    pub user_agent: Option<String>,
+    pub status: Option<u16>,
+    pub reason: Option<String>,
+    pub content_type: Option<String>,
+    // 헤더 + body가 회선에서 차지한 바이트, body는 chunk를 푼 크기
+    pub request_bytes: u64,
+    pub request_body: u64,
+    pub response_bytes: u64,
+    pub response_body: u64,
+    // 각 메시지 첫 바이트의 캡처 시각
+    pub request_time: Option<Duration>,
+    pub response_time: Option<Duration>,
+    // 요청 첫 바이트부터 응답 첫 바이트까지
+    pub latency: Option<Duration>,
+    // 응답 끝까지 보지 못하고 연결이 끝났다
+    pub complete: bool,
+}
+
+impl HttpTransaction {
+    pub fn to_json(&self) -> serde_json::Value {
+        json!({
+            "client": self.client.to_string(),
+            "server": self.server.to_string(),
+            "method": self.method,
+            "host": self.host,
+            "path": self.path,
+            "version": self.version,
+            "user_agent": self.user_agent,
+            "status": self.status,
+            "reason": self.reason,
+            "content_type": self.content_type,
+            "request_bytes": self.request_bytes,
+            "request_body": self.request_body,
+            "response_bytes": self.response_bytes,
+            "response_body": self.response_body,
+            "request_time": self.request_time.map(|t| t.as_secs_f64()),
+            "response_time": self.response_time.map(|t| t.as_secs_f64()),
+            "latency_ms": self.latency.map(|l| l.as_secs_f64() * 1000.0),
+            "complete": self.complete,
+        })
+    }
+}
+
+impl fmt::Display for HttpTransaction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} -> {} {} {}{}",
+            self.client,
+            self.server,
+            self.method.as_deref().unwrap_or("-"),
+            self.host.as_deref().unwrap_or(""),
+            self.path.as_deref().unwrap_or("?"),
+        )?;
+
+        match self.status {
+            Some(status) => write!(f, " -> {} {}", status, self.reason.as_deref().unwrap_or(""))?,
+            None => write!(f, " -> (no response)")?,
+        }
+
+        write!(
+            f,
+            " req={}B resp={}B",
+            self.request_body, self.response_body
+        )?;
+
+        if let Some(latency) = self.latency {
+            write!(f, " {:.1}ms", latency.as_secs_f64() * 1000.0)?;
+        }
+
+        if !self.complete {
+            write!(f, " (incomplete)")?;
+        }
+
+        Ok(())
+    }
+}
+
+// ==================== MESSAGE PARSER ====================
+
+#[derive(Debug, Clone)]
+struct Head {
+    line: String,
+    headers: Vec<(String, String)>,
+}
+
+impl Head {
+    fn parse(raw: &[u8]) -> Option<Self> {
+        let text = std::str::from_utf8(raw).ok()?;
+        let mut lines = text.split("\r\n");
+
+        let line = lines.next()?.to_string();
+        let mut headers = Vec::new();
+
+        for header in lines.filter(|l| !l.is_empty()) {
+            let (name, value) = header.split_once(':')?;
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+
+        Some(Self { line, headers })
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn is_chunked(&self) -> bool {
+        // 마지막 coding이 chunked일 때만 chunk로 끝을 안다
+        self.header("Transfer-Encoding")
+            .and_then(|te| te.rsplit(',').next())
+            .map(|last| last.trim().eq_ignore_ascii_case("chunked"))
+            .unwrap_or(false)
+    }
+
+    // 값이 이상하면 Err. 여러 값이 다르면 request smuggling 방지 차원에서 깨진 것으로 본다
+    fn content_length(&self) -> Result<Option<u64>, ()> {
+        let mut length = None;
+
+        for (_, value) in self
+            .headers
+            .iter()
+            .filter(|(n, _)| n.eq_ignore_ascii_case("Content-Length"))
+        {
+            for part in value.split(',') {
+                let n: u64 = part.trim().parse().map_err(|_| ())?;
+                if length.is_some_and(|l| l != n) {
+                    return Err(());
+                }
+                length = Some(n);
+            }
+        }
+
+        Ok(length)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Framing {
+    Length(u64),
+    Chunked,
+    UntilClose,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    Head,
+    // 헤더를 돌려줬고 호출한 쪽이 body 길이를 정해 주길 기다린다
+    AwaitBody,
+    Length(u64),
+    ChunkSize,
+    ChunkData(u64),
+    ChunkEnd,
+    Trailer,
+    UntilClose,
+    // 동기를 잃었거나 HTTP가 아니게 됐다
+    Dead,
+}
+
+#[derive(Debug)]
+enum Parsed {
+    Head(Head, Duration),
+    End { body: u64, wire: u64 },
+}
+
+// 한 방향의 메시지 연속을 끊어 낸다. body 바이트는 세기만 하고 버린다
+struct MessageParser {
+    buf: Vec<u8>,
+    state: State,
+    started: Option<Duration>,
+    last_seen: Duration,
+    body: u64,
+    wire: u64,
+}
+
+impl MessageParser {
+    fn new() -> Self {
+        Self {
+            buf: Vec::new(),
+            state: State::Head,
+            started: None,
+            last_seen: Duration::ZERO,
+            body: 0,
+            wire: 0,
+        }
+    }
+
+    fn is_dead(&self) -> bool {
+        self.state == State::Dead
+    }
+
+    fn stop(&mut self) {
+        self.state = State::Dead;
+        self.buf.clear();
+    }
+
+    fn push(&mut self, data: &[u8], timestamp: Duration) {
+        if self.is_dead() {
+            return;
+        }
+
+        if self.started.is_none() {
+            self.started = Some(timestamp);
+        }
+
+        self.last_seen = timestamp;
+        self.buf.extend_from_slice(data);
+    }
+
+    fn start_body(&mut self, framing: Framing) {
+        if self.state != State::AwaitBody {
+            return;
+        }
+
+        self.state = match framing {
+            Framing::Length(n) => State::Length(n),
+            Framing::Chunked => State::ChunkSize,
+            Framing::UntilClose => State::UntilClose,
+        };
+    }
+
+    // 받지 못한 구간. body 안이면 길이만 세고 넘어가고, 헤더 중이면 동기를 잃는다
+    fn gap(&mut self, len: u64) {
+        match self.state {
+            State::Length(remaining) if self.buf.is_empty() && remaining >= len => {
+                self.state = State::Length(remaining - len);
+            }
+            State::ChunkData(remaining) if self.buf.is_empty() && remaining >= len => {
+                self.state = State::ChunkData(remaining - len);
+            }
+            State::UntilClose => {}
+            _ => {
+                self.stop();
+                return;
+            }
+        }
+
+        self.body += len;
+        self.wire += len;
+    }
+
+    // 연결이 끝났다. 끝을 close로만 아는 body면 여기서 메시지가 끝난다
+    fn close(&mut self) -> Option<Parsed> {
+        if self.state == State::UntilClose {
+            Some(self.finish_message())
+        } else {
+            None
+        }
+    }
+
+    fn finish_message(&mut self) -> Parsed {
+        let parsed = Parsed::End {
+            body: self.body,
+            wire: self.wire,
+        };
+
+        self.state = State::Head;
+        self.body = 0;
+        self.wire = 0;
+        self.started = if self.buf.is_empty() {
+            None
+        } else {
+            Some(self.last_seen)
+        };
+
+        parsed
+    }
+
+    fn consume(&mut self, remaining: u64) -> u64 {
+        let n = remaining.min(self.buf.len() as u64);
+        self.buf.drain(..n as usize);
+        self.body += n;
+        self.wire += n;
+        remaining - n
+    }
+
+    // 한 줄(CRLF 제외)을 꺼낸다. 아직 줄이 다 안 왔으면 None
+    fn take_line(&mut self) -> Option<Vec<u8>> {
+        let Some(end) = find(&self.buf, b"\r\n") else {
+            if self.buf.len() > MAX_LINE {
+                self.stop();
+            }
+            return None;
+        };
+
+        let mut line: Vec<u8> = self.buf.drain(..end + 2).collect();
+        self.wire += line.len() as u64;
+        line.truncate(end);
+
+        Some(line)
+    }
+
+    fn poll(&mut self) -> Option<Parsed> {
+        loop {
+            match self.state {
+                State::Head => {
+                    // 메시지 사이의 빈 줄은 허용된다
+                    while self.buf.starts_with(b"\r\n") {
+                        self.buf.drain(..2);
+                    }
+
+                    let Some(end) = find(&self.buf, b"\r\n\r\n") else {
+                        if self.buf.len() > MAX_HEAD {
+                            self.stop();
+                        }
+                        return None;
+                    };
+
+                    let raw: Vec<u8> = self.buf.drain(..end + 4).collect();
+                    let Some(head) = Head::parse(&raw[..end]) else {
+                        self.stop();
+                        return None;
+                    };
+
+                    self.state = State::AwaitBody;
+                    self.body = 0;
+                    self.wire = raw.len() as u64;
+
+                    let started = self.started.unwrap_or(self.last_seen);
+                    return Some(Parsed::Head(head, started));
+                }
+                State::AwaitBody | State::Dead => return None,
+                State::Length(remaining) => {
+                    let remaining = self.consume(remaining);
+                    if remaining > 0 {
+                        self.state = State::Length(remaining);
+                        return None;
+                    }
+                    return Some(self.finish_message());
+                }
+                State::ChunkSize => {
+                    let line = self.take_line()?;
+                    let text = String::from_utf8_lossy(&line);
+                    // chunk extension(;name=value)은 무시
+                    let size = text.split(';').next().unwrap_or("").trim();
+
+                    match u64::from_str_radix(size, 16) {
+                        Ok(0) => self.state = State::Trailer,
+                        Ok(n) => self.state = State::ChunkData(n),
+                        Err(_) => {
+                            self.stop();
+                            return None;
+                        }
+                    }
+                }
+                State::ChunkData(remaining) => {
+                    let remaining = self.consume(remaining);
+                    if remaining > 0 {
+                        self.state = State::ChunkData(remaining);
+                        return None;
+                    }
+                    self.state = State::ChunkEnd;
+                }
+                State::ChunkEnd => {
+                    if self.buf.len() < 2 {
+                        return None;
+                    }
+                    if !self.buf.starts_with(b"\r\n") {
+                        self.stop();
+                        return None;
+                    }
+                    self.buf.drain(..2);
+                    self.wire += 2;
+                    self.state = State::ChunkSize;
+                }
+                State::Trailer => {
+                    let line = self.take_line()?;
+                    if line.is_empty() {
+                        return Some(self.finish_message());
+                    }
+                }
+                State::UntilClose => {
+                    let n = self.buf.len() as u64;
+                    self.consume(n);
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+// ==================== FLOW ====================
+
+struct PendingRequest {
+    method: String,
+    host: Option<String>,
+    path: String,
+    version: String,
+    user_agent: Option<String>,
+    started: Duration,
+    body: u64,
+    wire: u64,
+}
+
+struct PendingResponse {
+    status: u16,
+    reason: String,
+    content_type: Option<String>,
+    started: Duration,
+    // 100 Continue 같은 중간 응답. 요청과 짝짓지 않는다
+    interim: bool,
+}
+
+// conversation 하나의 HTTP 상태
+struct HttpFlow {
+    client: SocketAddr,
+    server: SocketAddr,
+    request: MessageParser,
+    response: MessageParser,
+    // pipelining으로 응답을 기다리는 요청들. 앞에서부터 응답이 붙는다
+    requests: VecDeque<PendingRequest>,
+    response_head: Option<PendingResponse>,
+}
+
+impl HttpFlow {
+    fn new(conversation: &Conversation) -> Self {
+        Self {
+            client: conversation.client,
+            server: conversation.server,
+            request: MessageParser::new(),
+            response: MessageParser::new(),
+            requests: VecDeque::new(),
+            response_head: None,
+        }
+    }
+
+    fn feed(
+        &mut self,
+        direction: Direction,
+        data: &[u8],
+        timestamp: Duration,
+        out: &mut Vec<HttpTransaction>,
+    ) {
+        match direction {
+            Direction::ClientToServer => self.request.push(data, timestamp),
+            Direction::ServerToClient => self.response.push(data, timestamp),
+        }
+
+        self.drain(direction, out);
+    }
+
+    fn drain(&mut self, direction: Direction, out: &mut Vec<HttpTransaction>) {
+        match direction {
+            Direction::ClientToServer => {
+                while let Some(parsed) = self.request.poll() {
+                    self.on_request(parsed);
+                }
+            }
+            Direction::ServerToClient => {
+                while let Some(parsed) = self.response.poll() {
+                    self.on_response(parsed, out);
+                }
+            }
+        }
+    }
+
+    fn gap(&mut self, direction: Direction, len: u64, out: &mut Vec<HttpTransaction>) {
+        let parser = match direction {
+            Direction::ClientToServer => &mut self.request,
+            Direction::ServerToClient => &mut self.response,
+        };
+
+        parser.gap(len);
+
+        if parser.is_dead() {
+            println!(
+                "[WARN] {} -> {} {} gap {}B, HTTP 동기를 잃었습니다",
+                self.client, self.server, direction, len
+            );
+            return;
+        }
+
+        // gap이 body 끝까지 덮었으면 메시지가 여기서 끝난다
+        self.drain(direction, out);
+    }
+
+    fn close(&mut self, out: &mut Vec<HttpTransaction>) {
+        if let Some(parsed) = self.response.close() {
+            self.on_response(parsed, out);
+        }
+
+        // 응답 도중에 끝났으면 본 데까지 남긴다
+        if let Some(response) = self.response_head.take() {
+            if !response.interim {
+                let request = self.requests.pop_front();
+                let (body, wire) = (self.response.body, self.response.wire);
+                out.push(self.transaction(request, Some(response), body, wire, false));
+            }
+        }
+
+        while let Some(request) = self.requests.pop_front() {
+            out.push(self.transaction(Some(request), None, 0, 0, false));
+        }
+    }
+
+    fn on_request(&mut self, parsed: Parsed) {
+        match parsed {
+            Parsed::Head(head, started) => {
+                let mut parts = head.line.splitn(3, ' ');
+                let (Some(method), Some(target), Some(version)) =
+                    (parts.next(), parts.next(), parts.next())
+                else {
+                    self.request.stop();
+                    return;
+                };
+
+                if !version.starts_with("HTTP/1.") {
+                    self.request.stop();
+                    return;
+                }
+
+                let framing = if head.is_chunked() {
+                    Framing::Chunked
+                } else {
+                    match head.content_length() {
+                        Ok(length) => Framing::Length(length.unwrap_or(0)),
+                        Err(()) => {
+                            self.request.stop();
+                            return;
+                        }
+                    }
+                };
+
+                // 프록시 요청이면 target이 절대 URL이다
+                let (host, path) = split_target(target);
+
+                self.requests.push_back(PendingRequest {
+                    method: method.to_string(),
+                    host: host.or_else(|| head.header("Host").map(str::to_string)),
+                    path,
+                    version: version.to_string(),
+                    user_agent: head.header("User-Agent").map(str::to_string),
+                    started,
+                    body: 0,
+                    wire: 0,
+                });
+
+                self.request.start_body(framing);
+            }
+            Parsed::End { body, wire } => {
+                if let Some(request) = self.requests.back_mut() {
+                    request.body = body;
+                    request.wire = wire;
+                }
+            }
+        }
+    }
+
+    fn on_response(&mut self, parsed: Parsed, out: &mut Vec<HttpTransaction>) {
+        match parsed {
+            Parsed::Head(head, started) => {
+                let mut parts = head.line.splitn(3, ' ');
+                let version = parts.next().unwrap_or("");
+                let status = parts.next().and_then(|s| s.parse::<u16>().ok());
+                let reason = parts.next().unwrap_or("").to_string();
+
+                let Some(status) = status.filter(|_| version.starts_with("HTTP/1.")) else {
+                    self.response.stop();
+                    return;
+                };
+
+                let method = self.requests.front().map(|r| r.method.as_str());
+
+                let response = PendingResponse {
+                    status,
+                    reason,
+                    content_type: head.header("Content-Type").map(str::to_string),
+                    started,
+                    interim: (100..200).contains(&status) && status != 101,
+                };
+
+                // 101 이나 CONNECT 성공 뒤로는 다른 프로토콜이다
+                let tunnel =
+                    status == 101 || (method == Some("CONNECT") && (200..300).contains(&status));
+
+                if tunnel {
+                    let request = self.requests.pop_front();
+                    let wire = self.response.wire;
+                    out.push(self.transaction(request, Some(response), 0, wire, true));
+                    self.request.stop();
+                    self.response.stop();
+                    return;
+                }
+
+                let framing =
+                    if method == Some("HEAD") || response.interim || status == 204 || status == 304
+                    {
+                        Framing::Length(0)
+                    } else if head.is_chunked() {
+                        Framing::Chunked
+                    } else {
+                        match head.content_length() {
+                            Ok(Some(length)) => Framing::Length(length),
+                            Ok(None) => Framing::UntilClose,
+                            Err(()) => {
+                                self.response.stop();
+                                return;
+                            }
+                        }
+                    };
+
+                self.response_head = Some(response);
+                self.response.start_body(framing);
+            }
+            Parsed::End { body, wire } => {
+                let Some(response) = self.response_head.take() else {
+                    return;
+                };
+
+                if response.interim {
+                    return;
+                }
+
+                let request = self.requests.pop_front();
+                out.push(self.transaction(request, Some(response), body, wire, true));
+            }
+        }
+    }
+
+    fn transaction(
+        &self,
+        request: Option<PendingRequest>,
+        response: Option<PendingResponse>,
+        response_body: u64,
+        response_bytes: u64,
+        complete: bool,
+    ) -> HttpTransaction {
+        let latency = match (&request, &response) {
+            (Some(request), Some(response)) => {
+                Some(response.started.saturating_sub(request.started))
+            }
+            _ => None,
+        };
+
+        let (request_body, request_bytes) =
+            request.as_ref().map(|r| (r.body, r.wire)).unwrap_or((0, 0));
+
+        let (method, host, path, version, user_agent, request_time) = match request {
+            Some(r) => (
+                Some(r.method),
+                r.host,
+                Some(r.path),
+                Some(r.version),
+                r.user_agent,
+                Some(r.started),
+            ),
+            None => (None, None, None, None, None, None),
+        };
+
+        let (status, reason, content_type, response_time) = match response {
+            Some(r) => (
+                Some(r.status),
+                Some(r.reason),
+                r.content_type,
+                Some(r.started),
+            ),
+            None => (None, None, None, None),
+        };
+
+        HttpTransaction {
+            client: self.client,
+            server: self.server,
+            method,
+            host,
+            path,
+            version,
+            user_agent,
+            status,
+            reason,
+            content_type,
+            request_bytes,
+            request_body,
+            response_bytes,
+            response_body,
+            request_time,
+            response_time,
+            latency,
+            complete,
+        }
+    }
+}
+
+// "http://host:port/path?q" → (Some(host:port), "/path?q")
+fn split_target(target: &str) -> (Option<String>, String) {
+    let Some(rest) = target
+        .strip_prefix("http://")
+        .or_else(|| target.strip_prefix("https://"))
+    else {
+        return (None, target.to_string());
+    };
+
+    match rest.find('/') {
+        Some(i) => (Some(rest[..i].to_string()), rest[i..].to_string()),
+        None => (Some(rest.to_string()), "/".to_string()),
+    }
+}
+
+fn looks_like_request(data: &[u8]) -> bool {
+    METHODS.iter().any(|method| {
+        data.len() > method.len()
+            && data.starts_with(method.as_bytes())
+            && data[method.len()] == b' '
+    })
+}
+
+// ==================== SNIFFER ====================
+
+// 트랜잭션이 끝날 때마다 sink를 부른다
+pub struct HttpSniffer<F> {
+    // None이면 HTTP가 아닌 conversation이라 더 보지 않는다
+    flows: HashMap<u64, Option<HttpFlow>>,
+    sink: F,
+    out: Vec<HttpTransaction>,
+}
+
+impl<F: FnMut(&HttpTransaction)> HttpSniffer<F> {
+    pub fn new(sink: F) -> Self {
+        Self {
+            flows: HashMap::new(),
+            sink,
+            out: Vec::new(),
+        }
+    }
+
+    fn flush(&mut self) {
+        for transaction in self.out.drain(..) {
+            (self.sink)(&transaction);
+        }
+    }
+}
+
+impl<F: FnMut(&HttpTransaction)> ConversationHandler for HttpSniffer<F> {
+    fn on_data(
+        &mut self,
+        conversation: &Conversation,
+        direction: Direction,
+        data: &[u8],
+        timestamp: Duration,
+    ) {
+        // 첫 데이터로 HTTP인지 정한다. 서버가 먼저 말하면 응답 중간부터 잡은 경우만 HTTP로 본다
+        let flow = self.flows.entry(conversation.id).or_insert_with(|| {
+            let http = match direction {
+                Direction::ClientToServer => looks_like_request(data),
+                Direction::ServerToClient => data.starts_with(b"HTTP/1."),
+            };
+            http.then(|| HttpFlow::new(conversation))
+        });
+
+        if let Some(flow) = flow {
+            flow.feed(direction, data, timestamp, &mut self.out);
+        }
+
+        self.flush();
+    }
+
+    fn on_gap(
+        &mut self,
+        conversation: &Conversation,
+        direction: Direction,
+        _offset: u64,
+        len: u64,
+    ) {
+        if let Some(Some(flow)) = self.flows.get_mut(&conversation.id) {
+            flow.gap(direction, len, &mut self.out);
+        }
+
+        self.flush();
+    }
+
+    fn on_close(&mut self, conversation: &Conversation) {
+        if let Some(Some(mut flow)) = self.flows.remove(&conversation.id) {
+            flow.close(&mut self.out);
+        }
+
+        self.flush();
+    }
+}
+
+// ==================== MAIN ====================
+
+// 인자로 pcap 파일을 주면 그 파일을, 없으면 en0을 캡처한다
+pub fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let sniffer = HttpSniffer::new(|transaction: &HttpTransaction| {
+        println!("[HTTP] {}", transaction);
+    });
+
+    let mut tracker = ConversationTracker::new(ReassemblyConfig::default(), sniffer);
+
+    match std::env::args().nth(1) {
+        Some(path) => read_pcap(&path, &mut tracker),
+        None => capture_live("en0", &mut tracker),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tcp::conversation::HalfSummary;
+    use Direction::{ClientToServer as C2S, ServerToClient as S2C};
+
+    fn conversation() -> Conversation {
+        Conversation {
+            id: 1,
+            client: "10.0.0.1:40000".parse().unwrap(),
+            server: "10.0.0.2:80".parse().unwrap(),
+            start: Duration::ZERO,
+            end: Duration::ZERO,
+            to_server: HalfSummary::default(),
+            to_client: HalfSummary::default(),
+        }
+    }
+
+    // (방향, 바이트, 캡처 시각 ms)를 차례로 넣고 마지막에 연결을 닫는다
+    fn sniff(steps: &[(Direction, &[u8], u64)]) -> Vec<HttpTransaction> {
+        let mut seen = Vec::new();
+        let conversation = conversation();
+        let mut sniffer = HttpSniffer::new(|t: &HttpTransaction| seen.push(t.clone()));
+
+        for (direction, data, at) in steps {
+            sniffer.on_data(&conversation, *direction, data, Duration::from_millis(*at));
+        }
+        sniffer.on_close(&conversation);
+        drop(sniffer);
+
+        seen
+    }
+
+    #[test]
+    fn latency_is_measured_between_capture_timestamps() {
+        let seen = sniff(&[
+            (C2S, b"GET /index.html HTTP/1.1\r\nHost: example.com\r\nUser-Agent: curl\r\n\r\n", 10_000),
+            (S2C, b"HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: 5\r\n\r\nhe", 10_250),
+            (S2C, b"llo", 10_300),
+        ]);
+
+        assert_eq!(seen.len(), 1);
+        let t = &seen[0];
+        assert_eq!(t.method.as_deref(), Some("GET"));
+        assert_eq!(t.host.as_deref(), Some("example.com"));
+        assert_eq!(t.path.as_deref(), Some("/index.html"));
+        assert_eq!(t.user_agent.as_deref(), Some("curl"));
+        assert_eq!(t.status, Some(200));
+        assert_eq!(t.content_type.as_deref(), Some("text/html"));
+        assert_eq!(t.response_body, 5);
+        assert_eq!(t.request_time, Some(Duration::from_millis(10_000)));
+        assert_eq!(t.response_time, Some(Duration::from_millis(10_250)));
+        assert_eq!(t.latency, Some(Duration::from_millis(250)));
+        assert!(t.complete);
+    }
+
+    #[test]
+    fn pipelined_responses_pair_with_requests_in_order() {
+        let seen = sniff(&[
+            (C2S, b"GET /a HTTP/1.1\r\nHost: h\r\n\r\nPOST /b HTTP/1.1\r\nHost: h\r\nContent-Length: 3\r\n\r\nabc", 0),
+            (S2C, b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n", 10),
+            (S2C, b"HTTP/1.1 201 Created\r\nContent-Length: 2\r\n\r\nok", 30),
+        ]);
+
+        let summary: Vec<_> = seen
+            .iter()
+            .map(|t| (t.path.clone().unwrap(), t.status.unwrap(), t.request_body))
+            .collect();
+        assert_eq!(summary, [("/a".to_string(), 200, 0), ("/b".to_string(), 201, 3)]);
+    }
+
+    #[test]
+    fn chunked_body_is_counted_without_framing() {
+        let seen = sniff(&[
+            (C2S, b"GET / HTTP/1.1\r\nHost: h\r\n\r\n", 0),
+            (S2C, b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n4;x=y\r\nwiki\r\n5\r\npedia\r\n0\r\n\r\n", 5),
+        ]);
+
+        assert_eq!(seen.len(), 1);
+        assert_eq!(seen[0].response_body, 9);
+        assert!(seen[0].complete);
+    }
+
+    #[test]
+    fn body_until_close_ends_with_the_connection() {
+        let seen = sniff(&[
+            (C2S, b"GET / HTTP/1.0\r\n\r\n", 0),
+            (S2C, b"HTTP/1.0 200 OK\r\n\r\nstream", 5),
+            (S2C, b"ing", 6),
+        ]);
+
+        assert_eq!(seen.len(), 1);
+        assert_eq!(seen[0].response_body, 9);
+        assert!(seen[0].complete);
+    }
+
+    #[test]
+    fn unanswered_request_is_reported_incomplete_on_close() {
+        let seen = sniff(&[(C2S, b"GET /slow HTTP/1.1\r\nHost: h\r\n\r\n", 0)]);
+
+        assert_eq!(seen.len(), 1);
+        assert_eq!(seen[0].status, None);
+        assert_eq!(seen[0].latency, None);
+        assert!(!seen[0].complete);
+    }
+
+    #[test]
+    fn non_http_conversations_are_ignored() {
+        let seen = sniff(&[(C2S, b"\x16\x03\x01\x00\x05hello", 0), (S2C, b"HTTP/1.1 200 OK\r\n\r\n", 1)]);
+        assert!(seen.is_empty());
+    }
+}
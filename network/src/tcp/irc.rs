@@ -0,0 +1,751 @@
+use sqlx::types::chrono::Local;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+use super::chat_auth::AuthError;
+use super::nic_chat::{normalize_room, ChatError, Event, Hub, Session};
+use super::slow_consumer::{LineReader, Outbox};
+use super::tls;
+
+// nic_chat의 Hub를 그대로 쓰는 IRC(RFC 2812 일부) front-end.
+// 방 "rust"는 IRC에서 "#rust"로 보이고, 평문 클라이언트와 같은 방/닉네임을 공유한다.
+// 계정 비밀번호(또는 토큰)는 NICK/USER 전에 PASS로 보낸다. 관리자는 KILL로 /kick 한다.
+
+const SERVER_NAME: &str = "chat.local";
+const VERSION: &str = "network-0.1";
+// RFC 2812: CR-LF 포함 512바이트
+const MAX_LINE: usize = 512;
+
+// ==================== MESSAGE ====================
+
+// [":" prefix SPACE] command [params] [" :" trailing]
+#[derive(Debug, PartialEq)]
+pub struct IrcMessage {
+    pub prefix: Option<String>,
+    pub command: String,
+    pub params: Vec<String>,
+}
+
+impl IrcMessage {
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut rest = line.trim_end_matches(['\r', '\n']).trim_start();
+
+        let prefix = match rest.strip_prefix(':') {
+            Some(stripped) => {
+                let (prefix, after) = stripped.split_once(' ')?;
+                rest = after.trim_start();
+                Some(prefix.to_string())
+            }
+            None => None,
+        };
+
+        let (command, mut rest) = match rest.split_once(' ') {
+            Some((command, after)) => (command, after),
+            None => (rest, ""),
+        };
+        if command.is_empty() {
+            return None;
+        }
+
+        let mut params = Vec::new();
+        loop {
+            rest = rest.trim_start_matches(' ');
+            if rest.is_empty() {
+                break;
+            }
+            // trailing은 공백 포함 끝까지 한 인자
+            if let Some(trailing) = rest.strip_prefix(':') {
+                params.push(trailing.to_string());
+                break;
+            }
+            match rest.split_once(' ') {
+                Some((param, after)) => {
+                    params.push(param.to_string());
+                    rest = after;
+                }
+                None => {
+                    params.push(rest.to_string());
+                    break;
+                }
+            }
+        }
+
+        Some(Self {
+            prefix,
+            command: command.to_ascii_uppercase(),
+            params,
+        })
+    }
+}
+
+fn user_prefix(nick: &str) -> String {
+    format!("{}!{}@{}", nick, nick, SERVER_NAME)
+}
+
+fn channel(room: &str) -> String {
+    format!("#{}", room)
+}
+
+// Hub 이벤트 -> IRC 한 줄. 자기가 한 JOIN/PART는 바로 답하므로 여기서는 건너뛴다
+fn render(event: &Event, me: &str) -> Option<String> {
+    let is_me = |nick: &str| nick.eq_ignore_ascii_case(me);
+
+    match event {
+        Event::Message { room, from, text } if !is_me(from) => Some(format!(
+            ":{} PRIVMSG {} :{}",
+            user_prefix(from),
+            channel(room),
+            text
+        )),
+        Event::Private { from, to, text } if !is_me(from) || is_me(to) => {
+            Some(format!(":{} PRIVMSG {} :{}", user_prefix(from), to, text))
+        }
+        Event::Join { room, nick } if !is_me(nick) => {
+            Some(format!(":{} JOIN {}", user_prefix(nick), channel(room)))
+        }
+        Event::Part { room, nick } if !is_me(nick) => {
+            Some(format!(":{} PART {}", user_prefix(nick), channel(room)))
+        }
+        Event::Nick { old, new } => Some(format!(":{} NICK :{}", user_prefix(old), new)),
+        Event::Quit { nick } if !is_me(nick) => {
+            Some(format!(":{} QUIT :Quit", user_prefix(nick)))
+        }
+        Event::Notice(text) => Some(format!(":{} NOTICE {} :{}", SERVER_NAME, me, text)),
+        Event::History(stored) => Some(format!(":{} NOTICE {} :{}", SERVER_NAME, me, stored)),
+        _ => None,
+    }
+}
+
+// ==================== CONNECTION ====================
+
+struct Connection<W> {
+    writer: W,
+    outbox: Arc<Outbox<Event>>,
+    created: Arc<String>,
+    addr: IpAddr,
+    // 등록 전에는 "*"
+    nick: String,
+    password: Option<String>,
+    pending_nick: Option<String>,
+    user: Option<String>,
+    session: Option<Session>,
+}
+
+impl<W: AsyncWrite + Unpin> Connection<W> {
+    async fn send(&mut self, line: &str) -> std::io::Result<()> {
+        let data = format!("{}\r\n", line);
+
+        tokio::select! {
+            result = self.writer.write_all(data.as_bytes()) => result,
+            _ = self.outbox.kicked() => Err(std::io::Error::other(self.outbox.kick_reason())),
+        }
+    }
+
+    async fn numeric(&mut self, code: &str, args: &str) -> std::io::Result<()> {
+        let line = format!(":{} {} {} {}", SERVER_NAME, code, self.nick, args);
+        self.send(&line).await
+    }
+
+    async fn chat_error(&mut self, err: &ChatError) -> std::io::Result<()> {
+        match err {
+            ChatError::InvalidNick(nick) => {
+                self.numeric("432", &format!("{} :Erroneous nickname", nick)).await
+            }
+            ChatError::NickInUse(nick) => {
+                self.numeric("433", &format!("{} :Nickname is already in use", nick)).await
+            }
+            ChatError::NoSuchNick(nick) => {
+                self.numeric("401", &format!("{} :No such nick/channel", nick)).await
+            }
+            ChatError::InvalidRoom(room) => {
+                self.numeric("403", &format!("{} :No such channel", room)).await
+            }
+            ChatError::NotInRoom(room) => {
+                self.numeric("442", &format!("{} :You're not on that channel", channel(room)))
+                    .await
+            }
+            // JOIN을 두 번 보내는 클라이언트가 많다. 조용히 무시
+            ChatError::AlreadyInRoom(_) => Ok(()),
+            ChatError::NickReserved(nick) => {
+                self.numeric("433", &format!("{} :Nickname is registered", nick)).await
+            }
+            ChatError::PermissionDenied => {
+                self.numeric("481", ":Permission Denied- You're not an IRC operator").await
+            }
+            ChatError::Banned(_) | ChatError::RateLimited | ChatError::MessageTooLong(_) => {
+                let line = format!(":{} NOTICE {} :{}", SERVER_NAME, self.nick, err);
+                self.send(&line).await
+            }
+        }
+    }
+
+    async fn close_link(&mut self, reason: &str) -> std::io::Result<bool> {
+        self.send(&format!("ERROR :Closing Link: {} ({})", self.nick, reason))
+            .await?;
+        Ok(false)
+    }
+
+    // NICK과 USER가 둘 다 오면 로그인하고 Hub에 등록. false면 연결 종료
+    async fn try_register(&mut self, hub: &Arc<Hub>) -> std::io::Result<bool> {
+        let (Some(nick), Some(_)) = (self.pending_nick.clone(), self.user.as_ref()) else {
+            return Ok(true);
+        };
+
+        let role = match hub.auth().login(&nick, self.password.as_deref(), self.addr).await {
+            Ok(role) => role,
+            Err(e @ AuthError::Banned(_)) => {
+                println!("[WARN] Rejected banned IRC nickname {} from {}", nick, self.addr);
+                self.numeric("465", &format!(":{}", e)).await?;
+                return self.close_link("banned").await;
+            }
+            Err(e @ (AuthError::SecretRequired | AuthError::InvalidCredentials)) => {
+                println!("[WARN] Failed IRC login for {} from {}: {}", nick, self.addr, e);
+                self.numeric("464", ":Password incorrect").await?;
+                return self.close_link("bad password").await;
+            }
+            Err(e) => return self.close_link(&e.to_string()).await,
+        };
+
+        if let Err(e) = hub.register(&nick, self.addr, role, self.outbox.clone()) {
+            self.pending_nick = None;
+            self.chat_error(&e).await?;
+            return Ok(true);
+        }
+
+        self.nick = nick.clone();
+        self.session = Some(Session::new(nick.clone(), self.outbox.clone(), hub.config()));
+
+        let prefix = user_prefix(&nick);
+        let created = self.created.clone();
+        self.numeric("001", &format!(":Welcome to the Internet Relay Network {}", prefix))
+            .await?;
+        self.numeric("002", &format!(":Your host is {}, running version {}", SERVER_NAME, VERSION))
+            .await?;
+        self.numeric("003", &format!(":This server was created {}", created))
+            .await?;
+        self.numeric("004", &format!("{} {} o o", SERVER_NAME, VERSION)).await?;
+        self.numeric("422", ":MOTD File is missing").await?;
+        Ok(true)
+    }
+
+    async fn names(&mut self, hub: &Hub, room: &str) -> std::io::Result<()> {
+        let chan = channel(room);
+        if let Ok(members) = hub.who(room) {
+            self.numeric("353", &format!("= {} :{}", chan, members.join(" "))).await?;
+        }
+        self.numeric("366", &format!("{} :End of /NAMES list", chan)).await
+    }
+
+    // false면 연결 종료
+    async fn handle(&mut self, hub: &Arc<Hub>, msg: IrcMessage) -> std::io::Result<bool> {
+        let param = |i: usize| msg.params.get(i).cloned();
+
+        match msg.command.as_str() {
+            "PING" => {
+                let token = param(0).unwrap_or_else(|| SERVER_NAME.to_string());
+                self.send(&format!(":{} PONG {} :{}", SERVER_NAME, SERVER_NAME, token))
+                    .await?;
+                return Ok(true);
+            }
+            "PONG" | "CAP" => return Ok(true),
+            "PASS" => {
+                if self.session.is_some() {
+                    self.numeric("462", ":Unauthorized command (already registered)")
+                        .await?;
+                } else if let Some(password) = param(0) {
+                    self.password = Some(password);
+                } else {
+                    self.numeric("461", "PASS :Not enough parameters").await?;
+                }
+                return Ok(true);
+            }
+            "QUIT" => {
+                let reason = param(0).unwrap_or_else(|| "Client Quit".to_string());
+                self.send(&format!("ERROR :Closing Link: {} ({})", self.nick, reason))
+                    .await?;
+                return Ok(false);
+            }
+            "NICK" => {
+                let Some(nick) = param(0) else {
+                    self.numeric("431", ":No nickname given").await?;
+                    return Ok(true);
+                };
+
+                // 등록 후 NICK은 이름 변경. 결과는 Nick 이벤트로 돌아온다
+                if let Some(session) = self.session.as_mut() {
+                    let result = session.rename(hub, nick);
+                    self.nick = session.nick().to_string();
+                    if let Err(e) = result {
+                        self.chat_error(&e).await?;
+                    }
+                } else {
+                    self.pending_nick = Some(nick);
+                    return self.try_register(hub).await;
+                }
+                return Ok(true);
+            }
+            "USER" => {
+                if self.session.is_some() {
+                    self.numeric("462", ":Unauthorized command (already registered)")
+                        .await?;
+                } else if msg.params.len() < 4 {
+                    self.numeric("461", "USER :Not enough parameters").await?;
+                } else {
+                    self.user = param(0);
+                    return self.try_register(hub).await;
+                }
+                return Ok(true);
+            }
+            _ => {}
+        }
+
+        let Some(mut session) = self.session.take() else {
+            self.numeric("451", ":You have not registered").await?;
+            return Ok(true);
+        };
+
+        let result = self.registered(hub, &mut session, &msg).await;
+        self.session = Some(session);
+        result.map(|()| true)
+    }
+
+    async fn registered(
+        &mut self,
+        hub: &Arc<Hub>,
+        session: &mut Session,
+        msg: &IrcMessage,
+    ) -> std::io::Result<()> {
+        let need = |n: usize| msg.params.len() >= n;
+
+        match msg.command.as_str() {
+            "JOIN" if need(1) => {
+                for chan in msg.params[0].split(',') {
+                    match session.join(hub, chan).and_then(|()| normalize_room(chan)) {
+                        Ok(room) => {
+                            self.send(&format!(":{} JOIN {}", user_prefix(&self.nick), channel(&room)))
+                                .await?;
+                            self.names(hub, &room).await?;
+                        }
+                        Err(e) => self.chat_error(&e).await?,
+                    }
+                }
+            }
+            "PART" if need(1) => {
+                for chan in msg.params[0].split(',') {
+                    match session.part(hub, chan).and_then(|()| normalize_room(chan)) {
+                        Ok(room) => {
+                            self.send(&format!(":{} PART {}", user_prefix(&self.nick), channel(&room)))
+                                .await?;
+                        }
+                        Err(e) => self.chat_error(&e).await?,
+                    }
+                }
+            }
+            // NOTICE는 Hub에 따로 없어서 PRIVMSG처럼 전달하고, 규약대로 오류 응답은 하지 않는다
+            "PRIVMSG" | "NOTICE" => {
+                let notice = msg.command == "NOTICE";
+                if !need(2) {
+                    if !notice {
+                        self.numeric("461", &format!("{} :Not enough parameters", msg.command))
+                            .await?;
+                    }
+                    return Ok(());
+                }
+
+                for target in msg.params[0].split(',') {
+                    let result = if target.starts_with('#') {
+                        session.say(hub, target, &msg.params[1])
+                    } else {
+                        session.private(hub, target, &msg.params[1])
+                    };
+
+                    if let Err(e) = result {
+                        if !notice {
+                            self.chat_error(&e).await?;
+                        }
+                    }
+                }
+            }
+            "NAMES" => {
+                let rooms: Vec<String> = match msg.params.first() {
+                    Some(chans) => chans
+                        .split(',')
+                        .filter_map(|c| normalize_room(c).ok())
+                        .collect(),
+                    None => hub.list().into_iter().map(|(room, _)| room).collect(),
+                };
+                for room in rooms {
+                    self.names(hub, &room).await?;
+                }
+            }
+            // 관리자만. 결과는 같은 방 사람들에게 NOTICE로 간다
+            "KILL" if need(1) => {
+                if let Err(e) = hub.kick(session.nick(), &msg.params[0], msg.params.get(1).map(String::as_str)) {
+                    self.chat_error(&e).await?;
+                }
+            }
+            // 클라이언트가 JOIN 뒤에 자동으로 보내는 것들. 모드는 지원하지 않는다
+            "MODE" | "WHO" | "USERHOST" => {}
+            "JOIN" | "PART" | "KILL" => {
+                self.numeric("461", &format!("{} :Not enough parameters", msg.command))
+                    .await?;
+            }
+            other => {
+                self.numeric("421", &format!("{} :Unknown command", other)).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+async fn handle_client<S>(socket: S, addr: SocketAddr, hub: Arc<Hub>, created: Arc<String>)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    println!("[SERVER] IRC client connected: {}", addr);
+
+    let (reader, mut writer) = tokio::io::split(socket);
+
+    if let Err(e) = hub.auth().check_ban(None, addr.ip()) {
+        println!("[WARN] Rejected banned IRC address {}", addr);
+        let _ = writer
+            .write_all(format!("ERROR :Closing Link: {}\r\n", e).as_bytes())
+            .await;
+        return;
+    }
+
+    // CR-LF를 뺀 나머지만 담는다. 더 긴 줄은 잘라서 처리
+    let mut reader = LineReader::new(BufReader::new(reader), MAX_LINE - 2);
+
+    let config = hub.config().clone();
+    let (outbox, mut inbox) = Outbox::new(config.outbound_capacity, config.kick_threshold);
+
+    let mut conn = Connection {
+        writer,
+        outbox: outbox.clone(),
+        created,
+        addr: addr.ip(),
+        nick: "*".to_string(),
+        password: None,
+        pending_nick: None,
+        user: None,
+        session: None,
+    };
+
+    loop {
+        tokio::select! {
+            biased;
+
+            _ = outbox.kicked() => {
+                let reason = outbox.kick_reason();
+                println!("[WARN] Disconnecting IRC client {} ({}): {}", addr, conn.nick, reason);
+                let line = format!("ERROR :Closing Link: {} ({})\r\n", conn.nick, reason);
+                let _ = tokio::time::timeout(
+                    std::time::Duration::from_millis(100),
+                    conn.writer.write_all(line.as_bytes()),
+                )
+                .await;
+                break;
+            }
+
+            Some(event) = inbox.recv() => {
+                let dropped = outbox.take_dropped();
+                if dropped > 0 {
+                    let notice = Event::Notice(format!("{} messages were dropped because you fell behind", dropped));
+                    if let Some(line) = render(&notice, &conn.nick) {
+                        if conn.send(&line).await.is_err() {
+                            break;
+                        }
+                    }
+                }
+
+                if let Some(line) = render(&event, &conn.nick) {
+                    if let Err(e) = conn.send(&line).await {
+                        println!("[SERVER] IRC write error to {}: {}", addr, e);
+                        break;
+                    }
+                }
+            }
+
+            result = reader.next_line() => {
+                match result {
+                    Ok(None) => {
+                        println!("[SERVER] IRC client disconnected: {} ({})", addr, conn.nick);
+                        break;
+                    }
+                    Ok(Some(line)) => {
+                        let keep = match IrcMessage::parse(&line) {
+                            Some(msg) => conn.handle(&hub, msg).await,
+                            None => Ok(true),
+                        };
+
+                        match keep {
+                            Ok(true) => {}
+                            Ok(false) => {
+                                println!("[SERVER] IRC client quit: {} ({})", addr, conn.nick);
+                                break;
+                            }
+                            Err(e) => {
+                                println!("[SERVER] IRC write error to {}: {}", addr, e);
+                                break;
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        println!("[SERVER] IRC read error from {}: {}", addr, e);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    if let Some(session) = conn.session.take() {
+        session.close(&hub);
+    }
+}
+
+// nic_chat 서버가 같은 Hub로 같이 띄운다
+pub async fn serve(
+    addr: &str,
+    hub: Arc<Hub>,
+    acceptor: Option<TlsAcceptor>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let listener = TcpListener::bind(addr).await?;
+    let created = Arc::new(Local::now().format("%Y-%m-%d %H:%M:%S").to_string());
+
+    if let Some(acceptor) = acceptor {
+        println!("[SERVER] IRC front-end (TLS) listening on {}", addr);
+        return tls::serve(listener, acceptor, move |socket, addr| {
+            handle_client(socket, addr, hub.clone(), created.clone())
+        })
+        .await;
+    }
+
+    println!("[SERVER] IRC front-end listening on {}", addr);
+
+    loop {
+        let (socket, addr) = listener.accept().await?;
+        let hub = hub.clone();
+        let created = created.clone();
+
+        tokio::spawn(async move {
+            handle_client(socket, addr, hub, created).await;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tcp::chat_auth::Auth;
+    use crate::tcp::slow_consumer::ChatConfig;
+    use std::time::Duration;
+    use tokio::io::{AsyncBufReadExt, DuplexStream, ReadHalf, WriteHalf};
+
+    struct TestClient {
+        reader: BufReader<ReadHalf<DuplexStream>>,
+        writer: WriteHalf<DuplexStream>,
+    }
+
+    impl TestClient {
+        fn connect(hub: &Arc<Hub>) -> (Self, tokio::task::JoinHandle<()>) {
+            let (server, client) = tokio::io::duplex(64 * 1024);
+            let addr = "127.0.0.1:6667".parse().unwrap();
+            let created = Arc::new("now".to_string());
+            let task = tokio::spawn(handle_client(server, addr, hub.clone(), created));
+
+            let (reader, writer) = tokio::io::split(client);
+            let client = Self {
+                reader: BufReader::new(reader),
+                writer,
+            };
+            (client, task)
+        }
+
+        async fn send(&mut self, line: &str) {
+            self.writer.write_all(line.as_bytes()).await.unwrap();
+        }
+
+        // needle이 들어 있는 줄이 올 때까지 읽는다
+        async fn expect(&mut self, needle: &str) -> String {
+            tokio::time::timeout(Duration::from_secs(5), async {
+                loop {
+                    let mut line = String::new();
+                    let n = self.reader.read_line(&mut line).await.unwrap();
+                    assert!(n > 0, "connection closed while waiting for {:?}", needle);
+                    if line.contains(needle) {
+                        return line;
+                    }
+                }
+            })
+            .await
+            .unwrap_or_else(|_| panic!("timed out waiting for {:?}", needle))
+        }
+    }
+
+    fn hub() -> Arc<Hub> {
+        Hub::new(ChatConfig::default(), None, Auth::disabled())
+    }
+
+    #[test]
+    fn parses_prefix_params_and_trailing() {
+        let msg = IrcMessage::parse(":nick!u@h privmsg #rust :hello  world\r\n").unwrap();
+        assert_eq!(msg.prefix.as_deref(), Some("nick!u@h"));
+        assert_eq!(msg.command, "PRIVMSG");
+        assert_eq!(msg.params, ["#rust", "hello  world"]);
+
+        let msg = IrcMessage::parse("USER a 0 * :Real Name").unwrap();
+        assert_eq!(msg.params, ["a", "0", "*", "Real Name"]);
+
+        assert!(IrcMessage::parse("   \r\n").is_none());
+    }
+
+    #[tokio::test]
+    async fn oversized_multibyte_line_is_truncated_without_dropping_the_client() {
+        let hub = hub();
+        let (mut client, task) = TestClient::connect(&hub);
+
+        client.send("NICK alice\r\nUSER alice 0 * :Alice\r\n").await;
+        client.expect(" 001 alice ").await;
+
+        // 3바이트 글자로 512바이트를 넘긴다. 잘리는 위치가 글자 중간에 걸린다
+        let long = format!("PRIVMSG alice :{}\r\n", "가".repeat(400));
+        client.send(&long).await;
+        let echoed = client.expect("PRIVMSG alice :").await;
+        assert!(echoed.len() <= MAX_LINE + ":alice!alice@chat.local ".len());
+
+        client.send("PING :still-here\r\n").await;
+        client.expect("PONG chat.local :still-here").await;
+
+        // 연결이 끊기면 세션이 정리되어 같은 닉네임을 다시 쓸 수 있다
+        drop(client);
+        tokio::time::timeout(Duration::from_secs(5), task).await.unwrap().unwrap();
+
+        let (mut again, _task) = TestClient::connect(&hub);
+        again.send("NICK alice\r\nUSER alice 0 * :Alice\r\n").await;
+        again.expect(" 001 alice ").await;
+    }
+
+    async fn register(hub: &Arc<Hub>, nick: &str) -> TestClient {
+        let (mut client, _task) = TestClient::connect(hub);
+        client
+            .send(&format!("NICK {}\r\nUSER {} 0 * :{}\r\n", nick, nick, nick))
+            .await;
+        client.expect(&format!(" 001 {} ", nick)).await;
+        client
+    }
+
+    #[tokio::test]
+    async fn registration_needs_nick_and_user_then_sends_welcome() {
+        let hub = hub();
+        let (mut client, _task) = TestClient::connect(&hub);
+
+        client.send("JOIN #rust\r\n").await;
+        client.expect(":chat.local 451 * :You have not registered").await;
+        client.send("USER alice 0 *\r\n").await;
+        client.expect(":chat.local 461 * USER :Not enough parameters").await;
+        client.send("NICK\r\n").await;
+        client.expect(":chat.local 431 * :No nickname given").await;
+
+        // USER를 먼저 보내도 NICK이 오는 순간 등록된다
+        client.send("USER alice 0 * :Alice Liddell\r\n").await;
+        client.send("NICK alice\r\n").await;
+        let welcome = client.expect(" 001 ").await;
+        assert_eq!(
+            welcome.trim_end(),
+            ":chat.local 001 alice :Welcome to the Internet Relay Network alice!alice@chat.local"
+        );
+        let host = client.expect(" 002 ").await;
+        assert_eq!(
+            host.trim_end(),
+            ":chat.local 002 alice :Your host is chat.local, running version network-0.1"
+        );
+        let created = client.expect(" 003 ").await;
+        assert_eq!(created.trim_end(), ":chat.local 003 alice :This server was created now");
+        let info = client.expect(" 004 ").await;
+        assert_eq!(info.trim_end(), ":chat.local 004 alice chat.local network-0.1 o o");
+        client.expect(" 422 alice :MOTD File is missing").await;
+
+        client.send("USER again 0 * :Again\r\n").await;
+        client.expect(":chat.local 462 alice :Unauthorized command (already registered)").await;
+    }
+
+    #[tokio::test]
+    async fn nick_collision_gets_433() {
+        let hub = hub();
+        let mut alice = register(&hub, "alice").await;
+
+        // 등록 중 충돌: 다른 NICK을 보내면 그대로 등록을 이어 간다
+        let (mut bob, _task) = TestClient::connect(&hub);
+        bob.send("NICK ALICE\r\nUSER bob 0 * :Bob\r\n").await;
+        bob.expect(":chat.local 433 * ALICE :Nickname is already in use").await;
+        bob.send("NICK bob\r\n").await;
+        bob.expect(":chat.local 001 bob ").await;
+
+        // 등록 후 이름 변경 충돌
+        bob.send("NICK Alice\r\n").await;
+        bob.expect(":chat.local 433 bob Alice :Nickname is already in use").await;
+        bob.send("NICK bad!nick\r\n").await;
+        bob.expect(":chat.local 432 bob bad!nick :Erroneous nickname").await;
+
+        // 대소문자만 바꾸는 건 된다
+        alice.send("NICK Alice\r\n").await;
+        alice.expect(":alice!alice@chat.local NICK :Alice").await;
+    }
+
+    #[tokio::test]
+    async fn join_echoes_and_lists_names() {
+        let hub = hub();
+        let mut alice = register(&hub, "alice").await;
+        let mut bob = register(&hub, "bob").await;
+
+        alice.send("JOIN #Rust\r\n").await;
+        alice.expect(":alice!alice@chat.local JOIN #rust").await;
+        let names = alice.expect(" 353 ").await;
+        assert_eq!(names.trim_end(), ":chat.local 353 alice = #rust :alice");
+        alice.expect(":chat.local 366 alice #rust :End of /NAMES list").await;
+
+        bob.send("JOIN #rust,#rust\r\n").await;
+        bob.expect(":bob!bob@chat.local JOIN #rust").await;
+        let names = bob.expect(" 353 ").await;
+        assert_eq!(names.trim_end(), ":chat.local 353 bob = #rust :alice bob");
+        bob.expect(" 366 bob #rust ").await;
+        alice.expect(":bob!bob@chat.local JOIN #rust").await;
+
+        bob.send("PRIVMSG #rust :hi all\r\n").await;
+        alice.expect(":bob!bob@chat.local PRIVMSG #rust :hi all").await;
+
+        bob.send("JOIN #bad!room\r\n").await;
+        bob.expect(":chat.local 403 bob #bad!room :No such channel").await;
+        bob.send("PART #elsewhere\r\n").await;
+        bob.expect(":chat.local 442 bob #elsewhere :You're not on that channel").await;
+        bob.send("PART #rust\r\n").await;
+        bob.expect(":bob!bob@chat.local PART #rust").await;
+        alice.expect(":bob!bob@chat.local PART #rust").await;
+    }
+
+    #[tokio::test]
+    async fn ping_gets_pong_before_and_after_registration() {
+        let hub = hub();
+        let (mut client, _task) = TestClient::connect(&hub);
+
+        client.send("PING :early\r\n").await;
+        client.expect(":chat.local PONG chat.local :early").await;
+        client.send("PING\r\n").await;
+        client.expect(":chat.local PONG chat.local :chat.local").await;
+
+        client.send("NICK carol\r\nUSER carol 0 * :Carol\r\n").await;
+        client.expect(" 001 carol ").await;
+        client.send("PING 12345\r\n").await;
+        client.expect(":chat.local PONG chat.local :12345").await;
+
+        client.send("QUIT :bye now\r\n").await;
+        client.expect("ERROR :Closing Link: carol (bye now)").await;
+    }
+}
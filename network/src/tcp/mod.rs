@@ -1,8 +1,23 @@
 pub mod chat;
+pub mod chat_auth;
+pub mod chat_store;
+pub mod conversation;
 pub mod custom_protocol;
+pub mod epoll;
+pub mod http_sniffer;
+pub mod irc;
 pub mod multi_tcp;
 pub mod nic_chat;
 pub mod non_blocking;
+pub mod packet;
+pub mod pcap;
+pub mod rate_limit;
+pub mod rpc;
+pub mod session;
+pub mod slow_consumer;
+pub mod stream_reassembly;
 pub mod tcp_basic;
 pub mod tcp_echo;
+pub mod tls;
+pub mod tls_fingerprint;
 pub mod hft;
\ No newline at end of file
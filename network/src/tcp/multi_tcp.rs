@@ -1,7 +1,12 @@
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::{TcpListener, TcpStream};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpListener;
 
-async fn handle_client(mut socket: TcpStream, addr: std::net::SocketAddr) {
+use super::tls;
+
+async fn handle_client<S>(mut socket: S, addr: std::net::SocketAddr)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
     println!("[SERVER] Client connected: {}", addr);
 
     let mut buffer = [0u8; 1024];
@@ -36,6 +41,13 @@ async fn handle_client(mut socket: TcpStream, addr: std::net::SocketAddr) {
 
 async fn tcp_multi_client_server() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let listener = TcpListener::bind("127.0.0.1:8080").await?;
+
+    // TLS_CERT/TLS_KEY 등이 설정돼 있으면 TLS로 받는다
+    if let Some(acceptor) = tls::acceptor_from_env()? {
+        println!("[SERVER] Multi-client TLS server listening on 127.0.0.1:8080");
+        return tls::serve(listener, acceptor, handle_client).await;
+    }
+
     println!("[SERVER] Multi-client TCP server listening on 127.0.0.1:8080");
 
     loop {
@@ -1,138 +1,1214 @@
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::net::{TcpListener, TcpStream};
+use tokio::io::{AsyncBufRead, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
 use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
 
-async fn handle_client(
-    socket: TcpStream,
-    addr: std::net::SocketAddr,
-    tx: broadcast::Sender<String>,
-) {
-    println!("[SERVER] Client connected: {}", addr);
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-    let (reader, mut writer) = socket.into_split();
-    let mut reader = BufReader::new(reader);
+use super::chat_auth::{self, Auth, AuthError, Ban, BanTarget, Role, MAX_LOGIN_ATTEMPTS};
+use super::chat_store::{ChatStore, EventKind, StoredEvent};
+use super::irc;
+use super::rate_limit::TokenBucket;
+use super::slow_consumer::{self, ChatConfig, History, LineReader, Outbox};
+use super::tls;
+use crate::connection;
+use crate::websocket::gateway;
 
-    // 닉네임 입력 요청
-    if let Err(e) = writer.write_all(b"Enter your nickname: ").await {
-        println!("[SERVER] Failed to ask nickname {}: {}", addr, e);
-        return;
+const DEFAULT_ROOM: &str = "lobby";
+// 방에 들어갈 때 DB에서 다시 보여 주는 메시지 수. /history N으로 바꾼다
+const DEFAULT_REPLAY: i64 = 10;
+const MAX_NAME_LEN: usize = 16;
+// 한 줄에 메시지 본문 외에 붙는 명령과 대상 몫 (/msg nick ...)
+const LINE_OVERHEAD: usize = 256;
+
+// ==================== EVENTS ====================
+
+// 방 broadcast 채널과 개인 inbox로 오가는 이벤트. 화면에 찍는 형식은 Display가 정한다
+#[derive(Debug, Clone)]
+pub enum Event {
+    Message { room: String, from: String, text: String },
+    Private { from: String, to: String, text: String },
+    Join { room: String, nick: String },
+    Part { room: String, nick: String },
+    Nick { old: String, new: String },
+    Quit { nick: String },
+    Notice(String),
+    // DB에서 다시 읽어 온 지난 메시지
+    History(StoredEvent),
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::Message { room, from, text } => write!(f, "[#{}] <{}> {}", room, from, text),
+            Event::Private { from, to, text } => write!(f, "[PM {} -> {}] {}", from, to, text),
+            Event::Join { room, nick } => write!(f, "*** {} joined #{}", nick, room),
+            Event::Part { room, nick } => write!(f, "*** {} left #{}", nick, room),
+            Event::Nick { old, new } => write!(f, "*** {} is now known as {}", old, new),
+            Event::Quit { nick } => write!(f, "*** {} left the chat", nick),
+            Event::Notice(text) => write!(f, "*** {}", text),
+            Event::History(event) => write!(f, "{}", event),
+        }
+    }
+}
+
+// ==================== COMMANDS ====================
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Say(String),
+    Join(String),
+    Part(Option<String>),
+    Msg { to: String, text: String },
+    Nick(String),
+    Who(Option<String>),
+    List,
+    History(i64),
+    Register(String),
+    Token,
+    // 관리자 전용
+    Kick { nick: String, reason: Option<String> },
+    Ban { target: String, duration: Option<Duration>, reason: Option<String> },
+    Unban(String),
+    Help,
+    Quit,
+}
+
+#[derive(Debug)]
+pub enum CommandError {
+    Usage(&'static str),
+    Unknown(String),
+    // 한 줄 상한(바이트)을 넘어 잘린 입력
+    LineTooLong(usize),
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandError::Usage(usage) => write!(f, "usage: {}", usage),
+            CommandError::Unknown(cmd) => write!(f, "unknown command /{} (try /help)", cmd),
+            CommandError::LineTooLong(max) => write!(f, "line is too long (max {} bytes), ignored", max),
+        }
+    }
+}
+
+const HELP: &str = "commands: /join #room, /part [#room], /msg nick text, /nick name, /who [#room], /list, /history [N], /register password, /token, /quit";
+const ADMIN_HELP: &str = "admin: /kick nick [reason], /ban nick|ip [30s|10m|2h|7d] [reason], /unban nick|ip";
+
+// 남은 단어들을 공백 하나로 이어 붙인다. 없으면 None
+fn rest_of(words: std::str::SplitWhitespace<'_>) -> Option<String> {
+    let rest: Vec<&str> = words.collect();
+    (!rest.is_empty()).then(|| rest.join(" "))
+}
+
+// "/"로 시작하지 않으면 현재 방에 보내는 일반 메시지
+pub fn parse_command(line: &str) -> Result<Command, CommandError> {
+    let line = line.trim();
+
+    let Some(rest) = line.strip_prefix('/') else {
+        return Ok(Command::Say(line.to_string()));
+    };
+
+    let (name, args) = match rest.split_once(char::is_whitespace) {
+        Some((name, args)) => (name, args.trim()),
+        None => (rest, ""),
+    };
+
+    let mut words = args.split_whitespace();
+
+    match name.to_ascii_lowercase().as_str() {
+        "join" => match (words.next(), words.next()) {
+            (Some(room), None) => Ok(Command::Join(room.to_string())),
+            _ => Err(CommandError::Usage("/join #room")),
+        },
+        "part" | "leave" => match (words.next(), words.next()) {
+            (room, None) => Ok(Command::Part(room.map(str::to_string))),
+            _ => Err(CommandError::Usage("/part [#room]")),
+        },
+        "msg" => match args.split_once(char::is_whitespace) {
+            Some((to, text)) if !text.trim().is_empty() => Ok(Command::Msg {
+                to: to.to_string(),
+                text: text.trim().to_string(),
+            }),
+            _ => Err(CommandError::Usage("/msg nick text")),
+        },
+        "nick" => match (words.next(), words.next()) {
+            (Some(nick), None) => Ok(Command::Nick(nick.to_string())),
+            _ => Err(CommandError::Usage("/nick name")),
+        },
+        "who" => match (words.next(), words.next()) {
+            (room, None) => Ok(Command::Who(room.map(str::to_string))),
+            _ => Err(CommandError::Usage("/who [#room]")),
+        },
+        "list" if args.is_empty() => Ok(Command::List),
+        "list" => Err(CommandError::Usage("/list")),
+        "history" => match (words.next().map(str::parse::<i64>), words.next()) {
+            (None, None) => Ok(Command::History(DEFAULT_REPLAY)),
+            (Some(Ok(n)), None) if n > 0 => Ok(Command::History(n)),
+            _ => Err(CommandError::Usage("/history [N]")),
+        },
+        "register" => match (words.next(), words.next()) {
+            (Some(password), None) => Ok(Command::Register(password.to_string())),
+            _ => Err(CommandError::Usage("/register password")),
+        },
+        "token" if args.is_empty() => Ok(Command::Token),
+        "token" => Err(CommandError::Usage("/token")),
+        "kick" => match words.next() {
+            Some(nick) => Ok(Command::Kick {
+                nick: nick.to_string(),
+                reason: rest_of(words),
+            }),
+            None => Err(CommandError::Usage("/kick nick [reason]")),
+        },
+        "ban" => {
+            let Some(target) = words.next() else {
+                return Err(CommandError::Usage("/ban nick|ip [30s|10m|2h|7d] [reason]"));
+            };
+            // 기간이 없으면 영구. 기간처럼 안 읽히는 단어부터는 사유
+            let mut peek = words.clone();
+            let duration = peek.next().and_then(chat_auth::parse_duration);
+            if duration.is_some() {
+                words = peek;
+            }
+            Ok(Command::Ban {
+                target: target.to_string(),
+                duration,
+                reason: rest_of(words),
+            })
+        }
+        "unban" => match (words.next(), words.next()) {
+            (Some(target), None) => Ok(Command::Unban(target.to_string())),
+            _ => Err(CommandError::Usage("/unban nick|ip")),
+        },
+        "help" => Ok(Command::Help),
+        "quit" => Ok(Command::Quit),
+        other => Err(CommandError::Unknown(other.to_string())),
+    }
+}
+
+// ==================== REGISTRY ====================
+
+#[derive(Debug)]
+pub enum ChatError {
+    InvalidNick(String),
+    NickInUse(String),
+    NoSuchNick(String),
+    InvalidRoom(String),
+    NotInRoom(String),
+    AlreadyInRoom(String),
+    // 계정이 있는 닉네임. 로그인해야 쓸 수 있다
+    NickReserved(String),
+    Banned(String),
+    PermissionDenied,
+    RateLimited,
+    MessageTooLong(usize),
+}
+
+impl fmt::Display for ChatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChatError::InvalidNick(nick) => write!(
+                f,
+                "invalid nickname '{}' (1-{} letters, digits, '_' or '-')",
+                nick, MAX_NAME_LEN
+            ),
+            ChatError::NickInUse(nick) => write!(f, "nickname '{}' is already in use", nick),
+            ChatError::NoSuchNick(nick) => write!(f, "no such nickname '{}'", nick),
+            ChatError::InvalidRoom(room) => write!(f, "invalid room name '{}'", room),
+            ChatError::NotInRoom(room) => write!(f, "you are not in #{}", room),
+            ChatError::AlreadyInRoom(room) => write!(f, "you are already in #{}", room),
+            ChatError::NickReserved(nick) => {
+                write!(f, "nickname '{}' is registered, reconnect and log in to use it", nick)
+            }
+            ChatError::Banned(ban) => write!(f, "that nickname is {}", ban),
+            ChatError::PermissionDenied => write!(f, "permission denied"),
+            ChatError::RateLimited => write!(f, "you are sending messages too fast, slow down"),
+            ChatError::MessageTooLong(max) => {
+                write!(f, "message is too long (max {} characters)", max)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ChatError {}
+
+fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+// "#Rust" -> "rust"
+pub fn normalize_room(room: &str) -> Result<String, ChatError> {
+    let name = room.strip_prefix('#').unwrap_or(room).to_ascii_lowercase();
+    if valid_name(&name) {
+        Ok(name)
+    } else {
+        Err(ChatError::InvalidRoom(room.to_string()))
     }
+}
 
-    let mut nickname = String::new();
+struct User {
+    // 화면에 보이는 원래 대소문자
+    nick: String,
+    addr: IpAddr,
+    role: Role,
+    inbox: Arc<Outbox<Event>>,
+    rooms: BTreeSet<String>,
+}
+
+struct Room {
+    // 순번은 history와 같이 붙는다. 구독자는 순번으로 중복과 빠진 구간을 안다
+    tx: broadcast::Sender<(u64, Event)>,
+    history: History<Event>,
+    members: BTreeSet<String>,
+}
 
-    match reader.read_line(&mut nickname).await {
-        Ok(0) => {
-            println!("[SERVER] Client disconnected before nickname: {}", addr);
+impl Room {
+    fn publish(&mut self, event: Event) {
+        let seq = self.history.push(event.clone());
+        let _ = self.tx.send((seq, event));
+    }
+}
+
+#[derive(Default)]
+struct Registry {
+    // key는 소문자 닉네임. 대소문자만 다른 중복도 막는다
+    users: HashMap<String, User>,
+    rooms: HashMap<String, Room>,
+}
+
+impl Registry {
+    fn user(&self, nick: &str) -> Result<&User, ChatError> {
+        self.users
+            .get(&nick.to_ascii_lowercase())
+            .ok_or_else(|| ChatError::NoSuchNick(nick.to_string()))
+    }
+
+    fn user_mut(&mut self, nick: &str) -> Result<&mut User, ChatError> {
+        self.users
+            .get_mut(&nick.to_ascii_lowercase())
+            .ok_or_else(|| ChatError::NoSuchNick(nick.to_string()))
+    }
+
+    // 같은 방에 있는 사람들(본인 포함)에게 한 번씩만 보낸다
+    fn notify_peers(&self, nick: &str, event: Event) {
+        let Ok(user) = self.user(nick) else {
             return;
+        };
+
+        let mut peers: BTreeSet<String> = BTreeSet::new();
+        peers.insert(nick.to_ascii_lowercase());
+        for room in &user.rooms {
+            if let Some(room) = self.rooms.get(room) {
+                peers.extend(room.members.iter().map(|m| m.to_ascii_lowercase()));
+            }
         }
-        Ok(_) => {}
-        Err(e) => {
-            println!("[SERVER] Failed to read nickname from {}: {}", addr, e);
+
+        for peer in peers {
+            if let Some(peer) = self.users.get(&peer) {
+                peer.inbox.push(event.clone());
+            }
+        }
+    }
+}
+
+// 닉네임과 방을 관리하는 공유 상태. 여러 front-end가 같은 Hub를 쓸 수 있다
+pub struct Hub {
+    registry: Mutex<Registry>,
+    config: ChatConfig,
+    // None이면 기록 없이 메모리에서만
+    store: Option<ChatStore>,
+    auth: Auth,
+}
+
+impl Hub {
+    pub fn new(config: ChatConfig, store: Option<ChatStore>, auth: Auth) -> Arc<Self> {
+        Arc::new(Self {
+            registry: Mutex::new(Registry::default()),
+            config,
+            store,
+            auth,
+        })
+    }
+
+    fn record(&self, room: &str, kind: EventKind, nick: &str, body: Option<&str>) {
+        if let Some(store) = &self.store {
+            store.record(StoredEvent::new(room, kind, nick, body));
+        }
+    }
+
+    // 방의 지난 메시지를 DB에서 읽어 outbox로 보낸다. 연결 루프가 DB를 기다리지 않도록 task로
+    pub fn replay(&self, room: &str, limit: i64, outbox: Arc<Outbox<Event>>) -> bool {
+        let Some(store) = self.store.clone() else {
+            return false;
+        };
+        let room = room.to_string();
+
+        tokio::spawn(async move {
+            match store.recent(&room, limit).await {
+                Ok(events) if events.is_empty() => {}
+                Ok(events) => {
+                    outbox.push(Event::Notice(format!(
+                        "last {} messages in #{}",
+                        events.len(),
+                        room
+                    )));
+                    for event in events {
+                        outbox.push(Event::History(event));
+                    }
+                }
+                Err(e) => {
+                    println!("[ERROR] Failed to load history for #{}: {}", room, e);
+                    outbox.push(Event::Notice("history is unavailable right now".into()));
+                }
+            }
+        });
+
+        true
+    }
+
+    pub fn config(&self) -> &ChatConfig {
+        &self.config
+    }
+
+    pub fn auth(&self) -> &Auth {
+        &self.auth
+    }
+
+    // auth.login이 돌려준 role로 등록한다
+    pub fn register(
+        &self,
+        nick: &str,
+        addr: IpAddr,
+        role: Role,
+        inbox: Arc<Outbox<Event>>,
+    ) -> Result<(), ChatError> {
+        if !valid_name(nick) {
+            return Err(ChatError::InvalidNick(nick.to_string()));
+        }
+
+        let mut registry = self.registry.lock().unwrap();
+        let key = nick.to_ascii_lowercase();
+
+        if registry.users.contains_key(&key) {
+            return Err(ChatError::NickInUse(nick.to_string()));
+        }
+
+        registry.users.insert(
+            key,
+            User {
+                nick: nick.to_string(),
+                addr,
+                role,
+                inbox,
+                rooms: BTreeSet::new(),
+            },
+        );
+
+        Ok(())
+    }
+
+    // 접속 종료. 들어가 있던 방에서 빼고 같은 방 사람들에게 알린다
+    pub fn unregister(&self, nick: &str) {
+        let mut registry = self.registry.lock().unwrap();
+
+        registry.notify_peers(nick, Event::Quit { nick: nick.to_string() });
+
+        let Some(user) = registry.users.remove(&nick.to_ascii_lowercase()) else {
             return;
+        };
+
+        for room in user.rooms {
+            self.record(&room, EventKind::Quit, &user.nick, None);
+            remove_member(&mut registry, &room, &user.nick);
+        }
+    }
+
+    pub fn rename(&self, old: &str, new: &str) -> Result<(), ChatError> {
+        if !valid_name(new) {
+            return Err(ChatError::InvalidNick(new.to_string()));
+        }
+
+        let mut registry = self.registry.lock().unwrap();
+        let old_key = old.to_ascii_lowercase();
+        let new_key = new.to_ascii_lowercase();
+
+        // 대소문자만 바꾸는 건 허용
+        if old_key != new_key && registry.users.contains_key(&new_key) {
+            return Err(ChatError::NickInUse(new.to_string()));
+        }
+        if old_key != new_key && self.auth.is_account(new) {
+            return Err(ChatError::NickReserved(new.to_string()));
+        }
+        if let Err(AuthError::Banned(ban)) = self.auth.check_ban(Some(new), registry.user(old)?.addr) {
+            return Err(ChatError::Banned(ban.to_string()));
+        }
+
+        let mut user = registry
+            .users
+            .remove(&old_key)
+            .ok_or_else(|| ChatError::NoSuchNick(old.to_string()))?;
+        let old_nick = std::mem::replace(&mut user.nick, new.to_string());
+
+        for name in &user.rooms {
+            if let Some(room) = registry.rooms.get_mut(name) {
+                room.members.remove(&old_nick);
+                room.members.insert(new.to_string());
+            }
+            self.record(name, EventKind::Nick, &old_nick, Some(new));
+        }
+
+        registry.users.insert(new_key, user);
+        registry.notify_peers(
+            new,
+            Event::Nick {
+                old: old_nick,
+                new: new.to_string(),
+            },
+        );
+
+        Ok(())
+    }
+
+    // 방이 없으면 만든다. 돌려받은 receiver로 방 이벤트를 구독하고,
+    // 같이 돌려주는 순번 이후의 이벤트부터 받으면 된다
+    pub fn join(
+        &self,
+        nick: &str,
+        room: &str,
+    ) -> Result<(broadcast::Receiver<(u64, Event)>, u64), ChatError> {
+        let room = normalize_room(room)?;
+        let mut registry = self.registry.lock().unwrap();
+
+        let user = registry.user_mut(nick)?;
+        if !user.rooms.insert(room.clone()) {
+            return Err(ChatError::AlreadyInRoom(room));
+        }
+        let nick = user.nick.clone();
+
+        let config = &self.config;
+        let entry = registry.rooms.entry(room.clone()).or_insert_with(|| Room {
+            tx: broadcast::channel(config.room_capacity).0,
+            history: History::new(config.history_len),
+            members: BTreeSet::new(),
+        });
+
+        // 입장 알림을 본인도 받도록 먼저 구독
+        let rx = entry.tx.subscribe();
+        let last_seq = entry.history.last_seq();
+        entry.members.insert(nick.clone());
+        self.record(&room, EventKind::Join, &nick, None);
+        entry.publish(Event::Join { room, nick });
+
+        Ok((rx, last_seq))
+    }
+
+    pub fn history_since(&self, room: &str, seq: u64) -> Vec<(u64, Event)> {
+        let registry = self.registry.lock().unwrap();
+
+        registry
+            .rooms
+            .get(room)
+            .map(|entry| entry.history.since(seq))
+            .unwrap_or_default()
+    }
+
+    pub fn part(&self, nick: &str, room: &str) -> Result<(), ChatError> {
+        let room = normalize_room(room)?;
+        let mut registry = self.registry.lock().unwrap();
+
+        let user = registry.user_mut(nick)?;
+        if !user.rooms.remove(&room) {
+            return Err(ChatError::NotInRoom(room));
+        }
+        let nick = user.nick.clone();
+
+        self.record(&room, EventKind::Part, &nick, None);
+        if let Some(entry) = registry.rooms.get_mut(&room) {
+            entry.publish(Event::Part {
+                room: room.clone(),
+                nick: nick.clone(),
+            });
+        }
+        remove_member(&mut registry, &room, &nick);
+
+        Ok(())
+    }
+
+    pub fn say(&self, nick: &str, room: &str, text: &str) -> Result<(), ChatError> {
+        let room = normalize_room(room)?;
+        let mut registry = self.registry.lock().unwrap();
+
+        let user = registry.user(nick)?;
+        if !user.rooms.contains(&room) {
+            return Err(ChatError::NotInRoom(room));
+        }
+        let from = user.nick.clone();
+
+        self.record(&room, EventKind::Message, &from, Some(text));
+        if let Some(entry) = registry.rooms.get_mut(&room) {
+            entry.publish(Event::Message {
+                room: room.clone(),
+                from,
+                text: text.to_string(),
+            });
+        }
+
+        Ok(())
+    }
+
+    pub fn private(&self, from: &str, to: &str, text: &str) -> Result<(), ChatError> {
+        let registry = self.registry.lock().unwrap();
+
+        let sender = registry.user(from)?;
+        let target = registry.user(to)?;
+
+        let event = Event::Private {
+            from: sender.nick.clone(),
+            to: target.nick.clone(),
+            text: text.to_string(),
+        };
+
+        target.inbox.push(event.clone());
+        // 보낸 사람 화면에도 남긴다
+        if !std::ptr::eq(sender, target) {
+            sender.inbox.push(event);
+        }
+
+        Ok(())
+    }
+
+    // 마지막 사람이 나간 방은 없어지므로, 이름만 맞으면 없는 방도 빈 목록
+    pub fn who(&self, room: &str) -> Result<Vec<String>, ChatError> {
+        let room = normalize_room(room)?;
+        let registry = self.registry.lock().unwrap();
+
+        Ok(registry
+            .rooms
+            .get(&room)
+            .map(|entry| entry.members.iter().cloned().collect())
+            .unwrap_or_default())
+    }
+
+    // 관리자 명령은 Hub에서 권한을 확인한다
+    fn require_admin(registry: &Registry, nick: &str) -> Result<String, ChatError> {
+        let user = registry.user(nick)?;
+        match user.role {
+            Role::Admin => Ok(user.nick.clone()),
+            Role::User => Err(ChatError::PermissionDenied),
+        }
+    }
+
+    // 같은 방 사람들에게 알리고 연결을 끊게 한다. 실제 정리는 그 연결이 close하면서
+    fn disconnect(registry: &Registry, user: &User, reason: &str) {
+        registry.notify_peers(&user.nick, Event::Notice(format!("{} was {}", user.nick, reason)));
+        user.inbox.kick(reason);
+    }
+
+    pub fn kick(&self, by: &str, nick: &str, reason: Option<&str>) -> Result<String, ChatError> {
+        let registry = self.registry.lock().unwrap();
+        let by = Self::require_admin(&registry, by)?;
+        let target = registry.user(nick)?;
+
+        let reason = match reason {
+            Some(reason) => format!("kicked by {} ({})", by, reason),
+            None => format!("kicked by {}", by),
+        };
+        println!("[INFO] {} {}", target.nick, reason);
+        Self::disconnect(&registry, target, &reason);
+
+        Ok(target.nick.clone())
+    }
+
+    // 닉네임이나 IP를 막고, 지금 접속해 있는 대상도 끊는다. 끊은 닉네임들을 같이 돌려준다
+    pub fn ban(
+        &self,
+        by: &str,
+        target: &str,
+        duration: Option<Duration>,
+        reason: Option<&str>,
+    ) -> Result<(Ban, Vec<String>), ChatError> {
+        let registry = self.registry.lock().unwrap();
+        let by = Self::require_admin(&registry, by)?;
+        let ban = Ban::new(BanTarget::parse(target), &by, duration, reason.unwrap_or(""));
+        let reason = ban.to_string();
+
+        let mut kicked = Vec::new();
+        for user in registry.users.values() {
+            // 같은 IP에서 접속한 관리자 본인은 남겨 둔다
+            if user.nick != by && ban.target.matches(Some(&user.nick), user.addr) {
+                Self::disconnect(&registry, user, &reason);
+                kicked.push(user.nick.clone());
+            }
+        }
+        drop(registry);
+
+        println!("[INFO] {} {}", ban.target, reason);
+        self.auth.ban(ban.clone());
+        Ok((ban, kicked))
+    }
+
+    pub fn unban(&self, by: &str, target: &str) -> Result<bool, ChatError> {
+        let by = Self::require_admin(&self.registry.lock().unwrap(), by)?;
+        let target = BanTarget::parse(target);
+
+        let removed = self.auth.unban(&target);
+        if removed {
+            println!("[INFO] {} unbanned by {}", target, by);
         }
+        Ok(removed)
     }
 
-    let nickname = nickname.trim().to_string();
+    pub fn role(&self, nick: &str) -> Option<Role> {
+        self.registry.lock().unwrap().user(nick).ok().map(|user| user.role)
+    }
+
+    // (방 이름, 인원) 목록
+    pub fn list(&self) -> Vec<(String, usize)> {
+        let registry = self.registry.lock().unwrap();
+
+        let mut rooms: Vec<(String, usize)> = registry
+            .rooms
+            .iter()
+            .map(|(name, entry)| (name.clone(), entry.members.len()))
+            .collect();
+        rooms.sort();
+        rooms
+    }
+}
+
+// 마지막 사람이 나가면 방도 없앤다
+fn remove_member(registry: &mut Registry, room: &str, nick: &str) {
+    let empty = match registry.rooms.get_mut(room) {
+        Some(entry) => {
+            entry.members.remove(nick);
+            entry.members.is_empty()
+        }
+        None => false,
+    };
+
+    if empty {
+        registry.rooms.remove(room);
+    }
+}
+
+// ==================== SESSION ====================
+
+// 연결 하나의 상태. 평문 프로토콜과 IRC, WebSocket front-end가 같이 쓴다
+pub struct Session {
+    nick: String,
+    current: Option<String>,
+    subscriptions: HashMap<String, JoinHandle<()>>,
+    inbox: Arc<Outbox<Event>>,
+    // 방에 들어갈 때 다시 보여 줄 지난 메시지 수
+    replay: i64,
+    // 방 메시지와 귓속말에만 건다
+    bucket: TokenBucket,
+    max_message_len: usize,
+}
+
+impl Session {
+    // hub.register가 끝난 닉네임으로 만든다
+    pub fn new(nick: String, inbox: Arc<Outbox<Event>>, config: &ChatConfig) -> Self {
+        Self {
+            nick,
+            current: None,
+            subscriptions: HashMap::new(),
+            inbox,
+            replay: DEFAULT_REPLAY,
+            bucket: TokenBucket::new(config.rate_burst, config.rate_per_sec),
+            max_message_len: config.max_message_len,
+        }
+    }
+
+    pub fn nick(&self) -> &str {
+        &self.nick
+    }
+
+    pub fn is_member(&self, room: &str) -> bool {
+        normalize_room(room).is_ok_and(|room| self.subscriptions.contains_key(&room))
+    }
+
+    pub fn join(&mut self, hub: &Arc<Hub>, room: &str) -> Result<(), ChatError> {
+        let name = normalize_room(room)?;
+        let (rx, last_seq) = hub.join(&self.nick, &name)?;
+
+        // lag이 나면 방 history로 빠진 메시지를 다시 채운다
+        let resync = hub.config().resync.then(|| {
+            let hub = hub.clone();
+            let name = name.clone();
+            move |seq| hub.history_since(&name, seq)
+        });
 
-    if nickname.is_empty() {
-        let _ = writer.write_all(b"Nickname cannot be empty. Bye.\n").await;
-        println!("[SERVER] Empty nickname from {}", addr);
+        let handle = tokio::spawn(slow_consumer::forward(
+            rx,
+            self.inbox.clone(),
+            last_seq,
+            resync,
+        ));
+
+        self.subscriptions.insert(name.clone(), handle);
+        hub.replay(&name, self.replay, self.inbox.clone());
+        self.current = Some(name);
+
+        Ok(())
+    }
+
+    pub fn part(&mut self, hub: &Hub, room: &str) -> Result<(), ChatError> {
+        let name = normalize_room(room)?;
+        hub.part(&self.nick, &name)?;
+
+        if let Some(handle) = self.subscriptions.remove(&name) {
+            handle.abort();
+        }
+
+        // 나간 방이 현재 방이면 남은 방 중 하나로 옮긴다
+        if self.current.as_deref() == Some(name.as_str()) {
+            self.current = self.subscriptions.keys().min().cloned();
+        }
+
+        Ok(())
+    }
+
+    fn check_message(&mut self, text: &str) -> Result<(), ChatError> {
+        if text.chars().count() > self.max_message_len {
+            return Err(ChatError::MessageTooLong(self.max_message_len));
+        }
+        if !self.bucket.try_take() {
+            return Err(ChatError::RateLimited);
+        }
+        Ok(())
+    }
+
+    pub fn say(&mut self, hub: &Hub, room: &str, text: &str) -> Result<(), ChatError> {
+        self.check_message(text)?;
+        hub.say(&self.nick, room, text)
+    }
+
+    pub fn private(&mut self, hub: &Hub, to: &str, text: &str) -> Result<(), ChatError> {
+        self.check_message(text)?;
+        hub.private(&self.nick, to, text)
+    }
+
+    // DB를 기다리는 명령은 task로 돌리고 결과는 inbox로 알린다
+    fn spawn_notice<F>(&self, task: F)
+    where
+        F: std::future::Future<Output = String> + Send + 'static,
+    {
+        let inbox = self.inbox.clone();
+        tokio::spawn(async move {
+            inbox.push(Event::Notice(task.await));
+        });
+    }
+
+    // 처리 결과로 클라이언트에게 바로 보여 줄 줄. None이면 이벤트로 충분
+    fn execute(&mut self, hub: &Arc<Hub>, command: Command) -> Result<Option<String>, ChatError> {
+        match command {
+            Command::Say(text) => {
+                if text.is_empty() {
+                    return Ok(None);
+                }
+                match self.current.clone() {
+                    Some(room) => self.say(hub, &room, &text)?,
+                    None => return Ok(Some("*** You are not in a room. Try /join #room".into())),
+                }
+                Ok(None)
+            }
+            Command::Join(room) => {
+                self.join(hub, &room)?;
+                Ok(None)
+            }
+            Command::Part(room) => {
+                let room = match room.or_else(|| self.current.clone()) {
+                    Some(room) => room,
+                    None => return Ok(Some("*** You are not in a room".into())),
+                };
+                self.part(hub, &room)?;
+                Ok(self
+                    .current
+                    .as_ref()
+                    .map(|room| format!("*** Now talking in #{}", room)))
+            }
+            Command::Msg { to, text } => {
+                self.private(hub, &to, &text)?;
+                Ok(None)
+            }
+            Command::Nick(new) => {
+                self.rename(hub, new)?;
+                Ok(None)
+            }
+            Command::Who(room) => {
+                let room = match room.or_else(|| self.current.clone()) {
+                    Some(room) => normalize_room(&room)?,
+                    None => return Ok(Some("*** You are not in a room".into())),
+                };
+                let members = hub.who(&room)?;
+                Ok(Some(format!("*** #{} ({}): {}", room, members.len(), members.join(", "))))
+            }
+            Command::List => {
+                let rooms = hub.list();
+                if rooms.is_empty() {
+                    return Ok(Some("*** No rooms".into()));
+                }
+                let rooms: Vec<String> = rooms
+                    .iter()
+                    .map(|(name, count)| format!("#{} ({})", name, count))
+                    .collect();
+                Ok(Some(format!("*** Rooms: {}", rooms.join(", "))))
+            }
+            Command::History(n) => {
+                let Some(room) = self.current.clone() else {
+                    return Ok(Some("*** You are not in a room".into()));
+                };
+                // 이후 /join에도 같은 수만큼 보여 준다
+                self.replay = n;
+                if hub.replay(&room, n, self.inbox.clone()) {
+                    Ok(None)
+                } else {
+                    Ok(Some("*** Chat history is not enabled on this server".into()))
+                }
+            }
+            Command::Register(password) => {
+                let hub = hub.clone();
+                let nick = self.nick.clone();
+                self.spawn_notice(async move {
+                    match hub.auth().create_account(&nick, &password).await {
+                        Ok(()) => format!("{} is now registered. Log in with this password next time", nick),
+                        Err(e) => e.to_string(),
+                    }
+                });
+                Ok(None)
+            }
+            Command::Token => {
+                let hub = hub.clone();
+                let nick = self.nick.clone();
+                self.spawn_notice(async move {
+                    match hub.auth().issue_token(&nick).await {
+                        Ok(token) => format!("your token: {} (use it instead of the password, shown only once)", token),
+                        Err(e) => e.to_string(),
+                    }
+                });
+                Ok(None)
+            }
+            Command::Kick { nick, reason } => {
+                let nick = hub.kick(&self.nick, &nick, reason.as_deref())?;
+                Ok(Some(format!("*** Kicked {}", nick)))
+            }
+            Command::Ban { target, duration, reason } => {
+                let (ban, kicked) = hub.ban(&self.nick, &target, duration, reason.as_deref())?;
+                let mut reply = format!("*** {} is now {}", ban.target, ban);
+                if !kicked.is_empty() {
+                    reply.push_str(&format!(", disconnected: {}", kicked.join(", ")));
+                }
+                Ok(Some(reply))
+            }
+            Command::Unban(target) => match hub.unban(&self.nick, &target)? {
+                true => Ok(Some(format!("*** Unbanned {}", target))),
+                false => Ok(Some(format!("*** {} is not banned", target))),
+            },
+            Command::Help => match hub.role(&self.nick) {
+                Some(Role::Admin) => Ok(Some(format!("*** {}\n*** {}", HELP, ADMIN_HELP))),
+                _ => Ok(Some(format!("*** {}", HELP))),
+            },
+            // 연결 루프에서 처리
+            Command::Quit => Ok(None),
+        }
+    }
+
+    pub fn rename(&mut self, hub: &Hub, new: String) -> Result<(), ChatError> {
+        hub.rename(&self.nick, &new)?;
+        self.nick = new;
+        Ok(())
+    }
+
+    pub fn close(self, hub: &Hub) {
+        for (_, handle) in self.subscriptions {
+            handle.abort();
+        }
+        hub.unregister(&self.nick);
+    }
+}
+
+// ==================== SERVER ====================
+
+// 쓰는 도중에 kick되면 막힌 write를 기다리지 않고 바로 끊는다
+async fn send_line<W>(writer: &mut W, outbox: &Outbox<Event>, line: &str) -> std::io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let data = format!("{}\n", line);
+
+    tokio::select! {
+        result = writer.write_all(data.as_bytes()) => result,
+        _ = outbox.kicked() => Err(std::io::Error::other(outbox.kick_reason())),
+    }
+}
+
+// 한 줄의 최대 바이트 수. 글자 수 제한을 UTF-8 최대 4바이트로 환산하고 명령 몫을 더한다
+fn max_line_bytes(config: &ChatConfig) -> usize {
+    config.max_message_len.saturating_mul(4).saturating_add(LINE_OVERHEAD)
+}
+
+// 한 줄을 묻고 앞뒤 공백을 뗀 답. 연결이 끊겼으면 None
+async fn prompt<R, W>(
+    reader: &mut LineReader<R>,
+    writer: &mut W,
+    addr: std::net::SocketAddr,
+    question: &str,
+) -> Option<String>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    if let Err(e) = writer.write_all(question.as_bytes()).await {
+        println!("[SERVER] Failed to prompt {}: {}", addr, e);
+        return None;
+    }
+
+    match reader.next_line().await {
+        Ok(None) => {
+            println!("[SERVER] Client disconnected before login: {}", addr);
+            None
+        }
+        Ok(Some(line)) => Some(line.trim().to_string()),
+        Err(e) => {
+            println!("[SERVER] Failed to read from {}: {}", addr, e);
+            None
+        }
+    }
+}
+
+// 평문 클라이언트 하나. gateway 테스트도 같은 Hub에 붙여 본다
+pub async fn handle_client<S>(socket: S, addr: std::net::SocketAddr, hub: Arc<Hub>)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    println!("[SERVER] Client connected: {}", addr);
+
+    let config = hub.config().clone();
+
+    let (reader, mut writer) = tokio::io::split(socket);
+    // 개행 없이 계속 보내도 메모리가 한 줄 상한 이상 늘지 않는다
+    let mut reader = LineReader::new(BufReader::new(reader), max_line_bytes(&config));
+    let (outbox, mut inbox) = Outbox::new(config.outbound_capacity, config.kick_threshold);
+
+    let ip = addr.ip();
+    if let Err(e) = hub.auth().check_ban(None, ip) {
+        println!("[WARN] Rejected banned address {}", addr);
+        let _ = writer.write_all(format!("*** {}\n", e).as_bytes()).await;
         return;
     }
 
+    // 사용 가능한 닉네임이 나올 때까지 다시 묻는다. 계정이 있는 닉네임이면 비밀번호(또는 토큰)도
+    let mut failures = 0;
+    let (nickname, role) = loop {
+        let Some(nickname) = prompt(&mut reader, &mut writer, addr, "Enter your nickname: ").await
+        else {
+            return;
+        };
+
+        let secret = if hub.auth().requires_secret(&nickname) {
+            match prompt(&mut reader, &mut writer, addr, "Password (or token): ").await {
+                Some(secret) => Some(secret),
+                None => return,
+            }
+        } else {
+            None
+        };
+
+        let error = match hub.auth().login(&nickname, secret.as_deref(), ip).await {
+            Ok(role) => match hub.register(&nickname, ip, role, outbox.clone()) {
+                Ok(()) => break (nickname, role),
+                Err(e) => e.to_string(),
+            },
+            Err(e @ AuthError::Banned(_)) => {
+                println!("[WARN] Rejected banned nickname {} from {}", nickname, addr);
+                let _ = writer.write_all(format!("*** {}\n", e).as_bytes()).await;
+                return;
+            }
+            Err(e @ AuthError::InvalidCredentials) => {
+                failures += 1;
+                println!("[WARN] Failed login for {} from {} ({}/{})", nickname, addr, failures, MAX_LOGIN_ATTEMPTS);
+                if failures >= MAX_LOGIN_ATTEMPTS {
+                    let _ = writer.write_all(b"*** Too many failed logins\n").await;
+                    return;
+                }
+                // 비밀번호 대입을 늦춘다
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                e.to_string()
+            }
+            Err(e) => e.to_string(),
+        };
+
+        if writer.write_all(format!("*** {}\n", error).as_bytes()).await.is_err() {
+            return;
+        }
+    };
+
     println!("[SERVER] {} set nickname: {}", addr, nickname);
 
-    let welcome_msg = format!("*** Welcome, {}!\n", nickname);
+    let mut welcome_msg = format!("*** Welcome, {}! Type /help for commands.\n", nickname);
+    if role == Role::Admin {
+        welcome_msg.push_str("*** You are logged in as an administrator\n");
+    }
     if let Err(e) = writer.write_all(welcome_msg.as_bytes()).await {
         println!("[SERVER] Failed to send welcome message to {}: {}", addr, e);
+        hub.unregister(&nickname);
         return;
     }
 
-    let join_msg = format!("*** {} joined the chat", nickname);
-    let _ = tx.send(join_msg);
+    let mut session = Session::new(nickname, outbox.clone(), &config);
 
-    // 이 클라이언트도 전체 채널 구독
-    let mut rx = tx.subscribe();
-
-    let mut line = String::new();
+    if let Err(e) = session.join(&hub, DEFAULT_ROOM) {
+        println!("[SERVER] Failed to join {} for {}: {}", DEFAULT_ROOM, addr, e);
+    }
 
     loop {
         tokio::select! {
-            // 클라이언트 입력 처리
-            result = reader.read_line(&mut line) => {
-                match result {
-                    Ok(0) => {
-                        println!("[SERVER] Client disconnected: {} ({})", addr, nickname);
-                        let leave_msg = format!("*** {} left the chat", nickname);
-                        let _ = tx.send(leave_msg);
-                        return;
-                    }
-                    Ok(_) => {
-                        let msg = line.trim().to_string();
-
-                        if !msg.is_empty() {
-                            if msg == "/quit" {
-                                let bye = b"*** Bye!\n";
-                                let _ = writer.write_all(bye).await;
+            // 쓰기를 먼저 처리해야 자기 입력이 몰릴 때도 자기 outbox가 밀리지 않는다
+            biased;
 
-                                let leave_msg = format!("*** {} left the chat", nickname);
-                                let _ = tx.send(leave_msg);
-
-                                println!("[SERVER] Client quit: {} ({})", addr, nickname);
-                                return;
-                            }
-
-                            let full_msg = format!("[{}] {}", nickname, msg);
-                            let _ = tx.send(full_msg);
-                        }
+            // outbox가 한계를 넘었거나 관리자가 끊었다. 이 클라이언트 때문에 다른 사람이 기다릴 일은 없다
+            _ = outbox.kicked() => {
+                let reason = outbox.kick_reason();
+                println!("[WARN] Disconnecting {} ({}): {}", addr, session.nick, reason);
+                let _ = tokio::time::timeout(
+                    Duration::from_millis(100),
+                    writer.write_all(format!("*** Disconnected: {}\n", reason).as_bytes()),
+                )
+                .await;
+                break;
+            }
 
-                        line.clear();
-                    }
-                    Err(e) => {
-                        println!("[SERVER] Read error from {} ({}): {}", addr, nickname, e);
-                        let leave_msg = format!("*** {} left due to error", nickname);
-                        let _ = tx.send(leave_msg);
-                        return;
+            // 방 이벤트와 개인 메시지 전달
+            Some(event) = inbox.recv() => {
+                // 그 사이 버려진 메시지가 있으면 먼저 알린다
+                let dropped = outbox.take_dropped();
+                if dropped > 0 {
+                    let notice = Event::Notice(format!("{} messages were dropped because you fell behind", dropped));
+                    if let Err(e) = send_line(&mut writer, &outbox, &notice.to_string()).await {
+                        println!("[SERVER] Write error to {} ({}): {}", addr, session.nick, e);
+                        break;
                     }
                 }
+
+                if let Err(e) = send_line(&mut writer, &outbox, &event.to_string()).await {
+                    println!("[SERVER] Write error to {} ({}): {}", addr, session.nick, e);
+                    break;
+                }
             }
 
-            // 브로드캐스트 메시지 전달
-            result = rx.recv() => {
+            // 클라이언트 입력 처리
+            result = reader.next_line() => {
                 match result {
-                    Ok(msg) => {
-                        if let Err(e) = writer.write_all(msg.as_bytes()).await {
-                            println!("[SERVER] Write error to {} ({}): {}", addr, nickname, e);
-                            return;
-                        }
+                    Ok(None) => {
+                        println!("[SERVER] Client disconnected: {} ({})", addr, session.nick);
+                        break;
+                    }
+                    Ok(Some(line)) => {
+                        // 잘린 줄을 명령으로 실행하지 않는다
+                        let parsed = if reader.truncated() {
+                            Err(CommandError::LineTooLong(max_line_bytes(&config)))
+                        } else {
+                            parse_command(&line)
+                        };
+
+                        let reply = match parsed {
+                            Ok(Command::Quit) => {
+                                let _ = send_line(&mut writer, &outbox, "*** Bye!").await;
+                                println!("[SERVER] Client quit: {} ({})", addr, session.nick);
+                                break;
+                            }
+                            Ok(command) => match session.execute(&hub, command) {
+                                Ok(reply) => reply,
+                                Err(e) => Some(format!("*** {}", e)),
+                            },
+                            Err(e) => Some(format!("*** {}", e)),
+                        };
 
-                        if let Err(e) = writer.write_all(b"\n").await {
-                            println!("[SERVER] Write error to {} ({}): {}", addr, nickname, e);
-                            return;
+                        if let Some(reply) = reply {
+                            if let Err(e) = send_line(&mut writer, &outbox, &reply).await {
+                                println!("[SERVER] Write error to {} ({}): {}", addr, session.nick, e);
+                                break;
+                            }
                         }
                     }
                     Err(e) => {
-                        println!("[SERVER] Broadcast receive error for {} ({}): {}", addr, nickname, e);
-                        return;
+                        println!("[SERVER] Read error from {} ({}): {}", addr, session.nick, e);
+                        break;
                     }
                 }
             }
         }
     }
+
+    session.close(&hub);
 }
 
 async fn tcp_chat_server() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let listener = TcpListener::bind("127.0.0.1:8080").await?;
-    println!("[SERVER] Nickname Chat Server listening on 127.0.0.1:8080");
 
-    let (tx, _) = broadcast::channel::<String>(100);
+    // DATABASE_URL이 있으면 채팅 기록과 계정을 Postgres에 둔다
+    let pool = connection::try_connection().await?;
+    let store = match pool.clone() {
+        Some(pool) => {
+            println!("[INFO] Chat history is stored in Postgres");
+            Some(ChatStore::open(pool).await?)
+        }
+        None => {
+            println!("[INFO] DATABASE_URL not set, chat history is kept in memory only");
+            None
+        }
+    };
+
+    let auth = Auth::open(pool).await?;
+    println!("[INFO] Authentication: {:?}", auth.mode());
+
+    let hub = Hub::new(ChatConfig::from_env(), store, auth);
+    let acceptor = tls::acceptor_from_env()?;
+
+    // 같은 Hub로 IRC 클라이언트도 받는다. TLS면 관례대로 6697
+    let irc_addr = if acceptor.is_some() { "127.0.0.1:6697" } else { "127.0.0.1:6667" };
+    let irc_hub = hub.clone();
+    let irc_acceptor = acceptor.clone();
+    tokio::spawn(async move {
+        if let Err(e) = irc::serve(irc_addr, irc_hub, irc_acceptor).await {
+            println!("[ERROR] IRC front-end stopped: {}", e);
+        }
+    });
+
+    // 브라우저는 http://127.0.0.1:8081 (index.html + /ws)
+    let ws_hub = hub.clone();
+    let ws_acceptor = acceptor.clone();
+    tokio::spawn(async move {
+        if let Err(e) = gateway::serve("127.0.0.1:8081", ws_hub, ws_acceptor).await {
+            println!("[ERROR] WebSocket gateway stopped: {}", e);
+        }
+    });
+
+    if let Some(acceptor) = acceptor {
+        println!("[SERVER] Nickname Chat Server (TLS) listening on 127.0.0.1:8080");
+        return tls::serve(listener, acceptor, move |socket, addr| {
+            handle_client(socket, addr, hub.clone())
+        })
+        .await;
+    }
+
+    println!("[SERVER] Nickname Chat Server listening on 127.0.0.1:8080");
 
     loop {
         let (socket, addr) = listener.accept().await?;
-        let tx_clone = tx.clone();
+        let hub = hub.clone();
 
         tokio::spawn(async move {
-            handle_client(socket, addr, tx_clone).await;
+            handle_client(socket, addr, hub).await;
         });
     }
 }
@@ -140,4 +1216,283 @@ async fn tcp_chat_server() -> Result<(), Box<dyn std::error::Error + Send + Sync
 #[tokio::main]
 pub async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     tcp_chat_server().await
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, DuplexStream, ReadHalf, WriteHalf};
+
+    struct TestClient {
+        reader: BufReader<ReadHalf<DuplexStream>>,
+        writer: WriteHalf<DuplexStream>,
+    }
+
+    impl TestClient {
+        fn connect(config: ChatConfig) -> Self {
+            Self::attach(Hub::new(config, None, Auth::disabled()))
+        }
+
+        // 같은 Hub에 여러 클라이언트를 붙인다
+        fn attach(hub: Arc<Hub>) -> Self {
+            let (client, server) = tokio::io::duplex(64 * 1024);
+            let addr = "127.0.0.1:4000".parse().unwrap();
+            tokio::spawn(handle_client(server, addr, hub));
+
+            let (reader, writer) = tokio::io::split(client);
+            Self {
+                reader: BufReader::new(reader),
+                writer,
+            }
+        }
+
+        async fn send(&mut self, data: &[u8]) {
+            self.writer.write_all(data).await.unwrap();
+        }
+
+        // prompt에는 개행이 없으니 찾는 문자열이 나올 때까지 바이트 단위로 읽는다
+        async fn expect(&mut self, needle: &str) {
+            let mut seen = Vec::new();
+            let result = tokio::time::timeout(Duration::from_secs(5), async {
+                while !String::from_utf8_lossy(&seen).contains(needle) {
+                    let byte = self.reader.read_u8().await.expect("connection closed");
+                    seen.push(byte);
+                }
+            })
+            .await;
+            assert!(result.is_ok(), "never saw {:?} in {:?}", needle, String::from_utf8_lossy(&seen));
+        }
+    }
+
+    fn hub() -> Arc<Hub> {
+        Hub::new(ChatConfig::default(), None, Auth::disabled())
+    }
+
+    fn localhost() -> IpAddr {
+        "127.0.0.1".parse().unwrap()
+    }
+
+    // inbox receiver는 돌려줘야 push가 실패하지 않는다
+    fn register(hub: &Hub, nick: &str) -> tokio::sync::mpsc::Receiver<Event> {
+        let (outbox, inbox) = Outbox::new(16, 1024);
+        hub.register(nick, localhost(), Role::User, outbox).unwrap();
+        inbox
+    }
+
+    async fn login(hub: &Arc<Hub>, nick: &str) -> TestClient {
+        let mut client = TestClient::attach(hub.clone());
+        client.expect("Enter your nickname: ").await;
+        client.send(format!("{}\n", nick).as_bytes()).await;
+        client.expect(&format!("Welcome, {}!", nick)).await;
+        client
+    }
+
+    fn small_config() -> ChatConfig {
+        ChatConfig {
+            max_message_len: 8,
+            ..ChatConfig::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn oversized_line_is_ignored_and_the_client_stays_connected() {
+        let mut client = TestClient::connect(small_config());
+        client.expect("Enter your nickname: ").await;
+        client.send(b"alice\n").await;
+        client.expect("Welcome, alice!").await;
+
+        let long = format!("/quit {}\n", "x".repeat(64 * 1024));
+        client.send(long.as_bytes()).await;
+        client.expect("*** line is too long").await;
+
+        client.send(b"/quit\n").await;
+        client.expect("*** Bye!").await;
+    }
+
+    #[tokio::test]
+    async fn oversized_nickname_is_cut_at_the_prompt() {
+        let mut client = TestClient::connect(small_config());
+        client.expect("Enter your nickname: ").await;
+        client.send(format!("{}\n", "a".repeat(64 * 1024)).as_bytes()).await;
+        client.expect("*** invalid nickname").await;
+        client.expect("Enter your nickname: ").await;
+
+        client.send(b"bob\n").await;
+        client.expect("Welcome, bob!").await;
+    }
+
+    #[test]
+    fn parse_command_reads_arguments() {
+        assert_eq!(parse_command("  hello there ").unwrap(), Command::Say("hello there".into()));
+        assert_eq!(parse_command("/JOIN #Rust").unwrap(), Command::Join("#Rust".into()));
+        assert_eq!(parse_command("/part").unwrap(), Command::Part(None));
+        assert_eq!(parse_command("/leave #rust").unwrap(), Command::Part(Some("#rust".into())));
+        assert_eq!(
+            parse_command("/msg bob  hi   there ").unwrap(),
+            Command::Msg {
+                to: "bob".into(),
+                text: "hi   there".into()
+            }
+        );
+        assert_eq!(parse_command("/who").unwrap(), Command::Who(None));
+        assert_eq!(parse_command("/list").unwrap(), Command::List);
+        assert_eq!(parse_command("/history").unwrap(), Command::History(DEFAULT_REPLAY));
+        assert_eq!(parse_command("/history 3").unwrap(), Command::History(3));
+        assert_eq!(
+            parse_command("/kick bob too loud").unwrap(),
+            Command::Kick {
+                nick: "bob".into(),
+                reason: Some("too loud".into())
+            }
+        );
+        assert_eq!(
+            parse_command("/ban 10.0.0.1 2h spam").unwrap(),
+            Command::Ban {
+                target: "10.0.0.1".into(),
+                duration: Some(Duration::from_secs(2 * 3600)),
+                reason: Some("spam".into())
+            }
+        );
+        // 기간처럼 읽히지 않으면 영구 ban이고 그 단어부터 사유
+        assert_eq!(
+            parse_command("/ban bob forever and ever").unwrap(),
+            Command::Ban {
+                target: "bob".into(),
+                duration: None,
+                reason: Some("forever and ever".into())
+            }
+        );
+        assert_eq!(parse_command("/quit").unwrap(), Command::Quit);
+    }
+
+    #[test]
+    fn parse_command_reports_usage_and_unknown_commands() {
+        for (line, usage) in [
+            ("/join", "/join #room"),
+            ("/join #a #b", "/join #room"),
+            ("/part #a #b", "/part [#room]"),
+            ("/msg bob", "/msg nick text"),
+            ("/msg bob   ", "/msg nick text"),
+            ("/nick", "/nick name"),
+            ("/who #a #b", "/who [#room]"),
+            ("/list all", "/list"),
+            ("/history 0", "/history [N]"),
+            ("/history many", "/history [N]"),
+            ("/token now", "/token"),
+            ("/kick", "/kick nick [reason]"),
+            ("/ban", "/ban nick|ip [30s|10m|2h|7d] [reason]"),
+            ("/unban", "/unban nick|ip"),
+        ] {
+            match parse_command(line) {
+                Err(CommandError::Usage(got)) => assert_eq!(got, usage, "line={:?}", line),
+                other => panic!("{:?} parsed as {:?}", line, other),
+            }
+        }
+
+        let err = parse_command("/dance").unwrap_err();
+        assert!(matches!(&err, CommandError::Unknown(cmd) if cmd == "dance"));
+        assert_eq!(err.to_string(), "unknown command /dance (try /help)");
+    }
+
+    #[test]
+    fn hub_tracks_membership_per_room() {
+        let hub = hub();
+        let _alice = register(&hub, "Alice");
+        let _bob = register(&hub, "bob");
+
+        hub.join("alice", "#Rust").unwrap();
+        hub.join("bob", "rust").unwrap();
+        hub.join("bob", "lobby").unwrap();
+        assert!(matches!(hub.join("bob", "#rust"), Err(ChatError::AlreadyInRoom(_))));
+
+        assert_eq!(hub.who("#RUST").unwrap(), ["Alice", "bob"]);
+        assert_eq!(hub.list(), [("lobby".to_string(), 1), ("rust".to_string(), 2)]);
+
+        hub.part("bob", "#lobby").unwrap();
+        assert!(matches!(hub.part("bob", "#lobby"), Err(ChatError::NotInRoom(_))));
+
+        // 마지막 사람이 나가 없어진 방과 한 번도 없던 방은 빈 목록
+        assert!(hub.who("#lobby").unwrap().is_empty());
+        assert!(hub.who("#nobody-here").unwrap().is_empty());
+        assert_eq!(hub.list(), [("rust".to_string(), 2)]);
+
+        assert!(matches!(hub.who("#not a room"), Err(ChatError::InvalidRoom(_))));
+        assert!(matches!(hub.join("ghost", "#rust"), Err(ChatError::NoSuchNick(_))));
+        assert!(matches!(hub.say("alice", "#lobby", "hi"), Err(ChatError::NotInRoom(_))));
+
+        hub.unregister("alice");
+        assert_eq!(hub.who("#rust").unwrap(), ["bob"]);
+    }
+
+    #[test]
+    fn hub_nicknames_are_unique_ignoring_case() {
+        let hub = hub();
+        let _alice = register(&hub, "alice");
+        let mut bob = register(&hub, "bob");
+
+        let (outbox, _inbox) = Outbox::new(16, 1024);
+        assert!(matches!(
+            hub.register("ALICE", localhost(), Role::User, outbox.clone()),
+            Err(ChatError::NickInUse(_))
+        ));
+        assert!(matches!(
+            hub.register("bad nick", localhost(), Role::User, outbox),
+            Err(ChatError::InvalidNick(_))
+        ));
+
+        assert!(matches!(hub.rename("bob", "Alice"), Err(ChatError::NickInUse(_))));
+        hub.rename("bob", "Bob").unwrap();
+        assert!(matches!(hub.private("alice", "carol", "hi"), Err(ChatError::NoSuchNick(_))));
+
+        hub.private("alice", "BOB", "hi").unwrap();
+        let mut seen = Vec::new();
+        while let Ok(event) = bob.try_recv() {
+            seen.push(event.to_string());
+        }
+        assert!(seen.contains(&"[PM alice -> Bob] hi".to_string()), "{:?}", seen);
+    }
+
+    #[tokio::test]
+    async fn clients_on_one_hub_see_each_other() {
+        let hub = hub();
+        let mut alice = login(&hub, "alice").await;
+        let mut bob = login(&hub, "bob").await;
+        alice.expect("*** bob joined #lobby").await;
+
+        bob.send(b"/who\n").await;
+        bob.expect("*** #lobby (2): alice, bob").await;
+
+        alice.send(b"/join #rust\n").await;
+        alice.expect("*** alice joined #rust").await;
+        bob.send(b"/list\n").await;
+        bob.expect("*** Rooms: #lobby (2), #rust (1)").await;
+
+        // 방 목록에 없는 방도 이름이 맞으면 빈 목록
+        bob.send(b"/who #empty\n").await;
+        bob.expect("*** #empty (0): ").await;
+
+        alice.send(b"/part\n").await;
+        alice.expect("*** Now talking in #lobby").await;
+        alice.send(b"hello\n").await;
+        bob.expect("[#lobby] <alice> hello").await;
+
+        bob.send(b"/msg carol hi\n").await;
+        bob.expect("*** no such nickname 'carol'").await;
+        bob.send(b"/msg alice psst\n").await;
+        alice.expect("[PM bob -> alice] psst").await;
+
+        bob.send(b"/nick ALICE\n").await;
+        bob.expect("*** nickname 'ALICE' is already in use").await;
+        bob.send(b"/nick robert\n").await;
+        alice.expect("*** bob is now known as robert").await;
+
+        bob.send(b"/join\n").await;
+        bob.expect("*** usage: /join #room").await;
+        bob.send(b"/dance\n").await;
+        bob.expect("*** unknown command /dance (try /help)").await;
+
+        bob.send(b"/quit\n").await;
+        bob.expect("*** Bye!").await;
+        alice.expect("*** robert left the chat").await;
+    }
+}
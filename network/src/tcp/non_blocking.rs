@@ -2,14 +2,101 @@ use mio::net::{TcpListener, TcpStream};
 use mio::{Events, Interest, Poll, Token};
 
 use std::collections::HashMap;
-use std::io::{Read, Write};
+use std::io::{ErrorKind, Read, Write};
 use std::net::SocketAddr;
 
 const SERVER: Token = Token(0);
+const READ_BUF_SIZE: usize = 4096;
 
-pub fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let addr: SocketAddr = "127.0.0.1:8080".parse()?;
+// 보낼 데이터가 이만큼 쌓이면 그 연결에서는 더 읽지 않는다
+const HIGH_WATERMARK: usize = 64 * 1024;
+// 읽기를 멈춘 연결은 이 아래로 비워지면 다시 읽는다
+const LOW_WATERMARK: usize = 16 * 1024;
+
+// ==================== HANDLER ====================
+
+// 연결 이벤트를 처리하는 콜백.
+// 응답은 out에 쌓아 두면 서버가 WRITABLE 이벤트에 맞춰 나눠 보낸다.
+pub trait Handler {
+    fn on_connect(&mut self, _token: Token, _addr: SocketAddr, _out: &mut Vec<u8>) {}
+
+    fn on_data(&mut self, token: Token, data: &[u8], out: &mut Vec<u8>);
+
+    fn on_disconnect(&mut self, _token: Token) {}
+}
+
+pub struct EchoHandler;
+
+impl Handler for EchoHandler {
+    fn on_data(&mut self, token: Token, data: &[u8], out: &mut Vec<u8>) {
+        println!(
+            "[SERVER] Received from {:?}: {}",
+            token,
+            String::from_utf8_lossy(data)
+        );
+        out.extend_from_slice(data);
+    }
+
+    fn on_disconnect(&mut self, token: Token) {
+        println!("[SERVER] Client disconnected: {:?}", token);
+    }
+}
+
+// ==================== SERVER ====================
+
+struct Connection {
+    stream: TcpStream,
+    // 아직 보내지 못한 데이터. out[written..]이 남은 부분
+    out: Vec<u8>,
+    written: usize,
+    interest: Interest,
+    // 상대가 write 쪽을 닫음. 남은 데이터를 다 보내면 정리
+    read_closed: bool,
+    // HIGH_WATERMARK를 넘어 읽기를 멈춘 상태
+    read_paused: bool,
+}
+
+impl Connection {
+    fn new(stream: TcpStream, out: Vec<u8>, interest: Interest) -> Self {
+        Self {
+            stream,
+            out,
+            written: 0,
+            interest,
+            read_closed: false,
+            read_paused: false,
+        }
+    }
+
+    fn has_pending(&self) -> bool {
+        self.written < self.out.len()
+    }
+
+    fn pending(&self) -> usize {
+        self.out.len() - self.written
+    }
+
+    // watermark에 맞춰 읽기 중단/재개를 정하고 필요한 interest를 돌려준다
+    fn next_interest(&mut self) -> Interest {
+        let pending = self.pending();
+
+        if self.read_paused {
+            if pending <= LOW_WATERMARK {
+                self.read_paused = false;
+            }
+        } else if pending >= HIGH_WATERMARK {
+            self.read_paused = true;
+        }
 
+        match (self.read_paused, self.has_pending()) {
+            (true, _) => Interest::WRITABLE,
+            (false, true) => Interest::READABLE | Interest::WRITABLE,
+            (false, false) => Interest::READABLE,
+        }
+    }
+}
+
+pub fn run<H: Handler>(addr: SocketAddr, handler: &mut H) -> Result<(), Box<dyn std::error::Error>> {
     let mut listener = TcpListener::bind(addr)?;
     let mut poll = Poll::new()?;
     let mut events = Events::with_capacity(128);
@@ -18,10 +105,10 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
     poll.registry()
         .register(&mut listener, SERVER, Interest::READABLE)?;
 
-    println!("[SERVER] epoll-style echo server listening on 127.0.0.1:8080");
+    println!("[SERVER] epoll-style server listening on {}", addr);
 
     let mut unique_token_id = 1usize;
-    let mut clients: HashMap<Token, TcpStream> = HashMap::new();
+    let mut connections: HashMap<Token, Connection> = HashMap::new();
 
     loop {
         // 이벤트가 생길 때까지 대기
@@ -38,15 +125,19 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
 
                                 println!("[SERVER] Client connected: {} -> token {:?}", addr, token);
 
-                                poll.registry().register(
-                                    &mut stream,
-                                    token,
-                                    Interest::READABLE,
-                                )?;
+                                let mut out = Vec::new();
+                                handler.on_connect(token, addr, &mut out);
 
-                                clients.insert(token, stream);
+                                let interest = if out.is_empty() {
+                                    Interest::READABLE
+                                } else {
+                                    Interest::READABLE | Interest::WRITABLE
+                                };
+                                poll.registry().register(&mut stream, token, interest)?;
+
+                                connections.insert(token, Connection::new(stream, out, interest));
                             }
-                            Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                            Err(ref e) if e.kind() == ErrorKind::WouldBlock => {
                                 break;
                             }
                             Err(e) => {
@@ -58,52 +149,227 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
                 }
 
                 token => {
+                    let Some(conn) = connections.get_mut(&token) else {
+                        continue;
+                    };
+
                     let mut disconnected = false;
 
-                    if let Some(stream) = clients.get_mut(&token) {
-                        let mut buffer = [0u8; 1024];
+                    if event.is_readable() && !conn.read_paused {
+                        if let Err(e) = drain_reads(token, conn, handler) {
+                            eprintln!("[SERVER] Read error for {:?}: {}", token, e);
+                            disconnected = true;
+                        }
+                    }
+
+                    // readable 처리 중 쌓인 응답도 바로 보내 본다
+                    if !disconnected && (event.is_writable() || conn.has_pending()) {
+                        if let Err(e) = flush(conn) {
+                            eprintln!("[SERVER] Write error for {:?}: {}", token, e);
+                            disconnected = true;
+                        }
+                    }
 
-                        match stream.read(&mut buffer) {
-                            Ok(0) => {
-                                println!("[SERVER] Client disconnected: {:?}", token);
-                                disconnected = true;
-                            }
-                            Ok(n) => {
-                                let received = &buffer[..n];
-                                println!(
-                                    "[SERVER] Received from {:?}: {}",
-                                    token,
-                                    String::from_utf8_lossy(received)
-                                );
-
-                                match stream.write_all(received) {
-                                    Ok(_) => {}
-                                    Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-                                        println!("[SERVER] Write would block for {:?}", token);
-                                    }
-                                    Err(e) => {
-                                        eprintln!("[SERVER] Write error for {:?}: {}", token, e);
-                                        disconnected = true;
-                                    }
+                    if conn.read_closed && !conn.has_pending() {
+                        disconnected = true;
+                    }
+
+                    if !disconnected {
+                        // 읽기를 재개할 때 READABLE을 다시 등록하면 커널에 남아 있던
+                        // 데이터에 대해 edge가 다시 올라온다
+                        let interest = conn.next_interest();
+
+                        if interest != conn.interest {
+                            match poll.registry().reregister(&mut conn.stream, token, interest) {
+                                Ok(()) => conn.interest = interest,
+                                Err(e) => {
+                                    eprintln!("[SERVER] Reregister error for {:?}: {}", token, e);
+                                    disconnected = true;
                                 }
                             }
-                            Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-                                // 지금 읽을 데이터 없음
-                            }
-                            Err(e) => {
-                                eprintln!("[SERVER] Read error for {:?}: {}", token, e);
-                                disconnected = true;
-                            }
                         }
                     }
 
                     if disconnected {
-                        if let Some(mut stream) = clients.remove(&token) {
-                            let _ = poll.registry().deregister(&mut stream);
+                        if let Some(mut conn) = connections.remove(&token) {
+                            let _ = poll.registry().deregister(&mut conn.stream);
                         }
+                        handler.on_disconnect(token);
                     }
                 }
             }
         }
     }
-}
\ No newline at end of file
+}
+
+// edge-triggered이므로 WouldBlock이 나올 때까지 읽는다.
+// 단, 보낼 데이터가 HIGH_WATERMARK를 넘으면 나머지는 커널 버퍼에 남겨 둔다.
+fn drain_reads<H: Handler>(
+    token: Token,
+    conn: &mut Connection,
+    handler: &mut H,
+) -> std::io::Result<()> {
+    let mut buffer = [0u8; READ_BUF_SIZE];
+
+    loop {
+        if conn.pending() >= HIGH_WATERMARK {
+            conn.read_paused = true;
+            return Ok(());
+        }
+
+        match conn.stream.read(&mut buffer) {
+            Ok(0) => {
+                conn.read_closed = true;
+                return Ok(());
+            }
+            Ok(n) => {
+                handler.on_data(token, &buffer[..n], &mut conn.out);
+            }
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => {
+                // 지금 읽을 데이터 없음
+                return Ok(());
+            }
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+fn flush(conn: &mut Connection) -> std::io::Result<()> {
+    while conn.has_pending() {
+        match conn.stream.write(&conn.out[conn.written..]) {
+            Ok(0) => return Err(ErrorKind::WriteZero.into()),
+            Ok(n) => conn.written += n,
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    // 다 보냈으면 버퍼 재사용
+    if !conn.has_pending() {
+        conn.out.clear();
+        conn.written = 0;
+    } else if conn.written >= conn.out.len() / 2 {
+        // 일부만 보낸 경우 앞쪽이 절반을 넘으면 당겨서 out이 계속 자라지 않게 한다
+        conn.out.drain(..conn.written);
+        conn.written = 0;
+    }
+
+    Ok(())
+}
+
+pub fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let addr: SocketAddr = "127.0.0.1:8080".parse()?;
+    run(addr, &mut EchoHandler)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::Duration;
+
+    // 출력 없이 받은 그대로 돌려주는 handler
+    struct SilentEcho;
+
+    impl Handler for SilentEcho {
+        fn on_data(&mut self, _token: Token, data: &[u8], out: &mut Vec<u8>) {
+            out.extend_from_slice(data);
+        }
+    }
+
+    fn pair() -> (std::net::TcpStream, Connection) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let peer = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        stream.set_nonblocking(true).unwrap();
+
+        let conn = Connection::new(TcpStream::from_std(stream), Vec::new(), Interest::READABLE);
+        (peer, conn)
+    }
+
+    fn pattern(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn reading_pauses_above_high_watermark_until_peer_catches_up() {
+        const TOTAL: usize = 1024 * 1024;
+        let (peer, mut conn) = pair();
+
+        // 상대는 다 보낸 뒤에야 echo를 읽기 시작한다
+        let mut writer = peer.try_clone().unwrap();
+        let mut reader = peer;
+        let sender = thread::spawn(move || writer.write_all(&pattern(TOTAL)).unwrap());
+        let receiver = thread::spawn(move || {
+            sender.join().unwrap();
+            let mut echoed = vec![0u8; TOTAL];
+            reader.read_exact(&mut echoed).unwrap();
+            echoed
+        });
+
+        let mut handler = SilentEcho;
+        let mut was_paused = false;
+        let mut received = 0;
+
+        while received < TOTAL {
+            if !conn.read_paused {
+                let before = conn.pending();
+                drain_reads(Token(1), &mut conn, &mut handler).unwrap();
+                received += conn.pending() - before;
+            }
+            assert!(conn.pending() < HIGH_WATERMARK + READ_BUF_SIZE);
+
+            // 아직 보내기 전이면 쓰기만 기다린다
+            if conn.read_paused {
+                was_paused = true;
+                assert_eq!(conn.next_interest(), Interest::WRITABLE);
+            }
+
+            flush(&mut conn).unwrap();
+            conn.next_interest();
+
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        while conn.has_pending() {
+            flush(&mut conn).unwrap();
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        assert!(was_paused);
+        assert!(!conn.read_closed);
+        assert_eq!(receiver.join().unwrap(), pattern(TOTAL));
+    }
+
+    #[test]
+    fn partial_writes_compact_the_out_buffer() {
+        const TOTAL: usize = 16 * 1024 * 1024;
+        let (mut peer, mut conn) = pair();
+        conn.out = pattern(TOTAL);
+
+        let receiver = thread::spawn(move || {
+            let mut echoed = vec![0u8; TOTAL];
+            peer.read_exact(&mut echoed).unwrap();
+            echoed
+        });
+
+        let mut compacted = false;
+        while conn.has_pending() {
+            flush(&mut conn).unwrap();
+
+            // 보낸 앞부분이 버퍼의 절반 이상 남아 있지 않다
+            assert!(conn.out.is_empty() || conn.written < conn.out.len() / 2);
+            if conn.has_pending() && conn.out.len() < TOTAL {
+                compacted = true;
+            }
+
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        assert!(compacted);
+        assert_eq!(conn.written, 0);
+        assert_eq!(receiver.join().unwrap(), pattern(TOTAL));
+    }
+}
@@ -0,0 +1,1134 @@
+use bytes::{Buf, BufMut};
+use futures_util::{SinkExt, StreamExt};
+use std::collections::HashMap;
+use std::fmt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_util::codec::{Decoder, Encoder, Framed};
+
+pub use bytes::{Bytes, BytesMut};
+
+// ==================== PACKET LAYOUT ====================
+//
+// [헤더 + 패킷타입 + 데이터] (network.md 참고)
+//
+// | length (u32) | type (u16) | flags (u8) | version (u8) | payload ... | [crc32c (u32)] |
+//
+// 모든 정수는 big-endian, length는 payload(+ crc trailer) 길이
+//
+// flags 하위 3비트는 codec이 사용한다
+//   FLAG_LZ4 / FLAG_ZSTD: payload가 압축됨 (원본 길이 u32 + 압축 데이터)
+//   FLAG_CRC32C: payload 뒤에 CRC32C trailer가 붙음 (헤더 8바이트 + 압축된 payload 기준)
+// 나머지 비트는 RawPacket.flags로 애플리케이션에 그대로 전달된다.
+
+pub const HEADER_LEN: usize = 8;
+pub const PROTOCOL_VERSION: u8 = 1;
+pub const DEFAULT_MAX_PAYLOAD: usize = 1024 * 1024;
+
+pub const FLAG_LZ4: u8 = 0x01;
+pub const FLAG_ZSTD: u8 = 0x02;
+pub const FLAG_CRC32C: u8 = 0x04;
+const CODEC_FLAGS: u8 = FLAG_LZ4 | FLAG_ZSTD | FLAG_CRC32C;
+
+const CRC_LEN: usize = 4;
+const ZSTD_LEVEL: i32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PacketHeader {
+    pub length: u32,
+    pub msg_type: u16,
+    pub flags: u8,
+    pub version: u8,
+}
+
+impl PacketHeader {
+    fn read(buf: &[u8]) -> Self {
+        Self {
+            length: u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]),
+            msg_type: u16::from_be_bytes([buf[4], buf[5]]),
+            flags: buf[6],
+            version: buf[7],
+        }
+    }
+
+    fn write(&self, buf: &mut BytesMut) {
+        buf.put_u32(self.length);
+        buf.put_u16(self.msg_type);
+        buf.put_u8(self.flags);
+        buf.put_u8(self.version);
+    }
+}
+
+// 타입이 아직 해석되지 않은 패킷
+#[derive(Debug, Clone)]
+pub struct RawPacket {
+    pub msg_type: u16,
+    pub flags: u8,
+    pub payload: Bytes,
+}
+
+impl RawPacket {
+    pub fn from_message<M: Message>(message: &M) -> Self {
+        let mut buf = BytesMut::new();
+        message.encode(&mut buf);
+
+        Self {
+            msg_type: M::TYPE_ID,
+            flags: 0,
+            payload: buf.freeze(),
+        }
+    }
+
+    pub fn decode<M: Message>(&self) -> Result<M, PacketError> {
+        if self.msg_type != M::TYPE_ID {
+            return Err(PacketError::UnexpectedType {
+                expected: M::TYPE_ID,
+                actual: self.msg_type,
+            });
+        }
+
+        let mut payload = self.payload.clone();
+        let message = M::decode(&mut payload)?;
+
+        if payload.has_remaining() {
+            return Err(PacketError::Malformed(format!(
+                "{} has {} trailing bytes",
+                M::NAME,
+                payload.remaining()
+            )));
+        }
+
+        Ok(message)
+    }
+}
+
+// ==================== ERRORS ====================
+
+#[derive(Debug)]
+pub enum PacketError {
+    Io(std::io::Error),
+    PayloadTooLarge { length: usize, max: usize },
+    UnsupportedVersion(u8),
+    UnknownType(u16),
+    // Registry에 같은 type id를 두 번 등록했다
+    DuplicateType {
+        msg_type: u16,
+        existing: &'static str,
+        added: &'static str,
+    },
+    UnexpectedType { expected: u16, actual: u16 },
+    Malformed(String),
+    ChecksumMismatch { expected: u32, actual: u32 },
+    // checksum을 협상했는데 CRC32C trailer 없이 온 frame
+    ChecksumMissing,
+    Compression(String),
+}
+
+impl fmt::Display for PacketError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PacketError::Io(e) => write!(f, "io error: {}", e),
+            PacketError::PayloadTooLarge { length, max } => {
+                write!(f, "payload too large: {} > {}", length, max)
+            }
+            PacketError::UnsupportedVersion(v) => write!(f, "unsupported protocol version: {}", v),
+            PacketError::UnknownType(t) => write!(f, "unknown message type: 0x{:04x}", t),
+            PacketError::DuplicateType {
+                msg_type,
+                existing,
+                added,
+            } => write!(
+                f,
+                "message type 0x{:04x} registered twice ({} and {})",
+                msg_type, existing, added
+            ),
+            PacketError::UnexpectedType { expected, actual } => write!(
+                f,
+                "unexpected message type: expected 0x{:04x}, got 0x{:04x}",
+                expected, actual
+            ),
+            PacketError::Malformed(msg) => write!(f, "malformed packet: {}", msg),
+            PacketError::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "checksum mismatch: expected 0x{:08x}, got 0x{:08x}",
+                expected, actual
+            ),
+            PacketError::ChecksumMissing => write!(f, "checksum required but frame has none"),
+            PacketError::Compression(msg) => write!(f, "compression error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for PacketError {}
+
+impl From<std::io::Error> for PacketError {
+    fn from(e: std::io::Error) -> Self {
+        PacketError::Io(e)
+    }
+}
+
+// ==================== CODEC ====================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Lz4,
+    Zstd,
+}
+
+// 압축 설정은 송신 쪽에만 쓰인다 (수신은 header flags로 해석).
+// checksum은 양방향: 켜져 있으면 보낼 때 붙이고, 받을 때 없는 frame은 거절한다.
+#[derive(Debug, Clone, Copy)]
+pub struct CodecOptions {
+    pub compression: Compression,
+    // 이보다 작은 payload는 압축하지 않는다
+    pub compress_threshold: usize,
+    pub checksum: bool,
+}
+
+impl Default for CodecOptions {
+    fn default() -> Self {
+        Self {
+            compression: Compression::None,
+            compress_threshold: 512,
+            checksum: false,
+        }
+    }
+}
+
+pub struct PacketCodec {
+    max_payload: usize,
+    options: CodecOptions,
+}
+
+impl PacketCodec {
+    pub fn new(max_payload: usize) -> Self {
+        Self {
+            max_payload,
+            options: CodecOptions::default(),
+        }
+    }
+
+    pub fn with_options(max_payload: usize, options: CodecOptions) -> Self {
+        Self {
+            max_payload,
+            options,
+        }
+    }
+
+    // handshake로 기능이 결정된 뒤 Framed::codec_mut()로 바꾼다
+    pub fn set_options(&mut self, options: CodecOptions) {
+        self.options = options;
+    }
+
+    fn compress(&self, payload: &[u8]) -> Result<Option<(u8, Vec<u8>)>, PacketError> {
+        if payload.len() < self.options.compress_threshold {
+            return Ok(None);
+        }
+
+        let (flag, compressed) = match self.options.compression {
+            Compression::None => return Ok(None),
+            Compression::Lz4 => (FLAG_LZ4, lz4_flex::block::compress(payload)),
+            Compression::Zstd => (
+                FLAG_ZSTD,
+                zstd::bulk::compress(payload, ZSTD_LEVEL)
+                    .map_err(|e| PacketError::Compression(e.to_string()))?,
+            ),
+        };
+
+        // 원본 길이 prefix 포함해서도 줄지 않으면 압축하지 않은 채로 보낸다
+        if compressed.len() + 4 >= payload.len() {
+            return Ok(None);
+        }
+
+        let mut out = Vec::with_capacity(4 + compressed.len());
+        out.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        out.extend_from_slice(&compressed);
+        Ok(Some((flag, out)))
+    }
+
+    fn decompress(&self, flags: u8, payload: Bytes) -> Result<Bytes, PacketError> {
+        if flags & (FLAG_LZ4 | FLAG_ZSTD) == 0 {
+            return Ok(payload);
+        }
+
+        if payload.len() < 4 {
+            return Err(PacketError::Malformed("compressed payload without size".into()));
+        }
+
+        // 압축 해제 크기도 max_payload로 제한 (압축 폭탄 방지)
+        let original = u32::from_be_bytes([payload[0], payload[1], payload[2], payload[3]]) as usize;
+        if original > self.max_payload {
+            return Err(PacketError::PayloadTooLarge {
+                length: original,
+                max: self.max_payload,
+            });
+        }
+
+        let data = &payload[4..];
+        let decompressed = if flags & FLAG_LZ4 != 0 {
+            lz4_flex::block::decompress(data, original)
+                .map_err(|e| PacketError::Compression(e.to_string()))?
+        } else {
+            zstd::bulk::decompress(data, original)
+                .map_err(|e| PacketError::Compression(e.to_string()))?
+        };
+
+        if decompressed.len() != original {
+            return Err(PacketError::Compression(format!(
+                "decompressed {} bytes, header said {}",
+                decompressed.len(),
+                original
+            )));
+        }
+
+        Ok(Bytes::from(decompressed))
+    }
+}
+
+impl Default for PacketCodec {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_PAYLOAD)
+    }
+}
+
+impl Decoder for PacketCodec {
+    type Item = RawPacket;
+    type Error = PacketError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<RawPacket>, PacketError> {
+        if src.len() < HEADER_LEN {
+            return Ok(None);
+        }
+
+        let header = PacketHeader::read(&src[..HEADER_LEN]);
+
+        if header.version != PROTOCOL_VERSION {
+            return Err(PacketError::UnsupportedVersion(header.version));
+        }
+
+        // 헤더만 보고 거절해야 거대한 length로 메모리를 잡아먹지 않는다.
+        // trailer 자리는 checksum을 협상했을 때만 더 봐준다
+        let length = header.length as usize;
+        let trailer = if self.options.checksum { CRC_LEN } else { 0 };
+        if length > self.max_payload + trailer {
+            return Err(PacketError::PayloadTooLarge {
+                length,
+                max: self.max_payload,
+            });
+        }
+
+        if src.len() < HEADER_LEN + length {
+            src.reserve(HEADER_LEN + length - src.len());
+            return Ok(None);
+        }
+
+        let has_crc = header.flags & FLAG_CRC32C != 0;
+        if self.options.checksum && !has_crc {
+            return Err(PacketError::ChecksumMissing);
+        }
+
+        // 압축을 풀기 전에 무결성부터 확인. type / flags가 뒤집혀도 잡히도록 헤더까지 포함한다.
+        if has_crc {
+            if length < CRC_LEN {
+                return Err(PacketError::Malformed("missing crc32c trailer".into()));
+            }
+
+            let covered = HEADER_LEN + length - CRC_LEN;
+            let trailer = &src[covered..HEADER_LEN + length];
+            let expected = u32::from_be_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+            let actual = crc32c::crc32c(&src[..covered]);
+            if expected != actual {
+                return Err(PacketError::ChecksumMismatch { expected, actual });
+            }
+        }
+
+        src.advance(HEADER_LEN);
+        let mut payload = src.split_to(length).freeze();
+        if has_crc {
+            payload.truncate(length - CRC_LEN);
+        }
+
+        let payload = self.decompress(header.flags, payload)?;
+
+        Ok(Some(RawPacket {
+            msg_type: header.msg_type,
+            flags: header.flags & !CODEC_FLAGS,
+            payload,
+        }))
+    }
+}
+
+impl Encoder<RawPacket> for PacketCodec {
+    type Error = PacketError;
+
+    fn encode(&mut self, packet: RawPacket, dst: &mut BytesMut) -> Result<(), PacketError> {
+        if packet.payload.len() > self.max_payload {
+            return Err(PacketError::PayloadTooLarge {
+                length: packet.payload.len(),
+                max: self.max_payload,
+            });
+        }
+
+        let mut flags = packet.flags & !CODEC_FLAGS;
+        let compressed = self.compress(&packet.payload)?;
+        let body: &[u8] = match &compressed {
+            Some((flag, data)) => {
+                flags |= flag;
+                data
+            }
+            None => &packet.payload,
+        };
+
+        if self.options.checksum {
+            flags |= FLAG_CRC32C;
+        }
+
+        let length = body.len() + if self.options.checksum { CRC_LEN } else { 0 };
+        dst.reserve(HEADER_LEN + length);
+
+        let start = dst.len();
+        PacketHeader {
+            length: length as u32,
+            msg_type: packet.msg_type,
+            flags,
+            version: PROTOCOL_VERSION,
+        }
+        .write(dst);
+        dst.extend_from_slice(body);
+        if self.options.checksum {
+            let crc = crc32c::crc32c(&dst[start..]);
+            dst.put_u32(crc);
+        }
+
+        Ok(())
+    }
+}
+
+// ==================== MESSAGES ====================
+
+// 패킷 타입 하나 = Rust struct 하나.
+// 직접 구현해도 되고 packet! 매크로로 정의해도 된다.
+pub trait Message: Sized {
+    const TYPE_ID: u16;
+    const NAME: &'static str;
+
+    fn encode(&self, buf: &mut BytesMut);
+    fn decode(buf: &mut Bytes) -> Result<Self, PacketError>;
+}
+
+// 필드 단위 직렬화
+pub trait Wire: Sized {
+    fn put(&self, buf: &mut BytesMut);
+    fn get(buf: &mut Bytes) -> Result<Self, PacketError>;
+}
+
+fn ensure(buf: &Bytes, n: usize) -> Result<(), PacketError> {
+    if buf.remaining() < n {
+        return Err(PacketError::Malformed(format!(
+            "need {} bytes, {} left",
+            n,
+            buf.remaining()
+        )));
+    }
+    Ok(())
+}
+
+macro_rules! impl_wire_num {
+    ($($ty:ty => $put:ident, $get:ident;)*) => {
+        $(
+            impl Wire for $ty {
+                fn put(&self, buf: &mut BytesMut) {
+                    buf.$put(*self);
+                }
+
+                fn get(buf: &mut Bytes) -> Result<Self, PacketError> {
+                    ensure(buf, std::mem::size_of::<$ty>())?;
+                    Ok(buf.$get())
+                }
+            }
+        )*
+    };
+}
+
+impl_wire_num! {
+    u8 => put_u8, get_u8;
+    u16 => put_u16, get_u16;
+    u32 => put_u32, get_u32;
+    u64 => put_u64, get_u64;
+    i8 => put_i8, get_i8;
+    i16 => put_i16, get_i16;
+    i32 => put_i32, get_i32;
+    i64 => put_i64, get_i64;
+    f32 => put_f32, get_f32;
+    f64 => put_f64, get_f64;
+}
+
+impl Wire for bool {
+    fn put(&self, buf: &mut BytesMut) {
+        buf.put_u8(*self as u8);
+    }
+
+    fn get(buf: &mut Bytes) -> Result<Self, PacketError> {
+        Ok(u8::get(buf)? != 0)
+    }
+}
+
+// 가변 길이 필드는 u32 길이 + 데이터
+impl Wire for Vec<u8> {
+    fn put(&self, buf: &mut BytesMut) {
+        buf.put_u32(self.len() as u32);
+        buf.extend_from_slice(self);
+    }
+
+    fn get(buf: &mut Bytes) -> Result<Self, PacketError> {
+        let len = u32::get(buf)? as usize;
+        ensure(buf, len)?;
+        Ok(buf.split_to(len).to_vec())
+    }
+}
+
+impl Wire for String {
+    fn put(&self, buf: &mut BytesMut) {
+        buf.put_u32(self.len() as u32);
+        buf.extend_from_slice(self.as_bytes());
+    }
+
+    fn get(buf: &mut Bytes) -> Result<Self, PacketError> {
+        let bytes = Vec::<u8>::get(buf)?;
+        String::from_utf8(bytes).map_err(|e| PacketError::Malformed(e.to_string()))
+    }
+}
+
+// struct 정의와 Message 구현을 함께 만든다.
+//
+// packet! {
+//     0x0001 => pub struct Login { pub user_id: u64, pub name: String }
+// }
+#[macro_export]
+macro_rules! packet {
+    ($(
+        $id:expr => $(#[$meta:meta])* $vis:vis struct $name:ident {
+            $($fvis:vis $field:ident : $ty:ty),* $(,)?
+        }
+    )*) => {
+        $(
+            $(#[$meta])*
+            $vis struct $name {
+                $($fvis $field: $ty),*
+            }
+
+            impl $crate::tcp::packet::Message for $name {
+                const TYPE_ID: u16 = $id;
+                const NAME: &'static str = stringify!($name);
+
+                #[allow(unused_variables)]
+                fn encode(&self, buf: &mut $crate::tcp::packet::BytesMut) {
+                    $($crate::tcp::packet::Wire::put(&self.$field, buf);)*
+                }
+
+                #[allow(unused_variables)]
+                fn decode(
+                    buf: &mut $crate::tcp::packet::Bytes,
+                ) -> Result<Self, $crate::tcp::packet::PacketError> {
+                    Ok(Self {
+                        $($field: $crate::tcp::packet::Wire::get(buf)?),*
+                    })
+                }
+            }
+        )*
+    };
+}
+
+// ==================== REGISTRY ====================
+
+type DispatchFn<C> = Box<dyn Fn(&mut C, &RawPacket) -> Result<(), PacketError> + Send + Sync>;
+
+// type id -> 디코더 + 핸들러. C는 핸들러가 공유하는 세션 상태
+pub struct Registry<C> {
+    handlers: HashMap<u16, (&'static str, DispatchFn<C>)>,
+}
+
+impl<C> Registry<C> {
+    pub fn new() -> Self {
+        Self {
+            handlers: HashMap::new(),
+        }
+    }
+
+    // 같은 type id를 두 번 등록하면 앞의 핸들러를 남기고 DuplicateType
+    pub fn on<M, F>(mut self, handler: F) -> Result<Self, PacketError>
+    where
+        M: Message + 'static,
+        F: Fn(&mut C, M) -> Result<(), PacketError> + Send + Sync + 'static,
+    {
+        if let Some((existing, _)) = self.handlers.get(&M::TYPE_ID) {
+            return Err(PacketError::DuplicateType {
+                msg_type: M::TYPE_ID,
+                existing,
+                added: M::NAME,
+            });
+        }
+
+        let dispatch: DispatchFn<C> = Box::new(move |ctx, packet| handler(ctx, packet.decode::<M>()?));
+        self.handlers.insert(M::TYPE_ID, (M::NAME, dispatch));
+        Ok(self)
+    }
+
+    pub fn name_of(&self, msg_type: u16) -> Option<&'static str> {
+        self.handlers.get(&msg_type).map(|(name, _)| *name)
+    }
+
+    pub fn dispatch(&self, ctx: &mut C, packet: &RawPacket) -> Result<(), PacketError> {
+        match self.handlers.get(&packet.msg_type) {
+            Some((_, handler)) => handler(ctx, packet),
+            None => Err(PacketError::UnknownType(packet.msg_type)),
+        }
+    }
+}
+
+impl<C> Default for Registry<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// ==================== EXAMPLE ====================
+
+packet! {
+    0x0001 => #[derive(Debug)] pub struct Login { pub user_id: u64, pub name: String }
+    0x0002 => #[derive(Debug)] pub struct LoginAck { pub session_id: u32 }
+    0x0010 => #[derive(Debug)] pub struct Move { pub x: f32, pub y: f32 }
+    0x0020 => #[derive(Debug)] pub struct Chat { pub text: String }
+}
+
+// 세션 상태: 핸들러가 보낼 패킷을 outbox에 넣는다
+struct Session {
+    addr: std::net::SocketAddr,
+    user: Option<String>,
+    outbox: Vec<RawPacket>,
+}
+
+fn build_registry() -> Result<Registry<Session>, PacketError> {
+    Registry::new()
+        .on(|s: &mut Session, msg: Login| {
+            println!("[SERVER] {} login: {:?}", s.addr, msg);
+            s.user = Some(msg.name);
+            s.outbox.push(RawPacket::from_message(&LoginAck {
+                session_id: msg.user_id as u32,
+            }));
+            Ok(())
+        })?
+        .on(|s: &mut Session, msg: Move| {
+            println!("[SERVER] {:?} moved to ({}, {})", s.user, msg.x, msg.y);
+            Ok(())
+        })?
+        .on(|s: &mut Session, msg: Chat| {
+            let text = format!("{}: {}", s.user.as_deref().unwrap_or("?"), msg.text);
+            s.outbox.push(RawPacket::from_message(&Chat { text }));
+            Ok(())
+        })
+}
+
+async fn handle_client(
+    socket: TcpStream,
+    addr: std::net::SocketAddr,
+    registry: std::sync::Arc<Registry<Session>>,
+) -> Result<(), PacketError> {
+    println!("[SERVER] Client connected: {}", addr);
+
+    let mut framed = Framed::new(socket, PacketCodec::default());
+    let mut session = Session {
+        addr,
+        user: None,
+        outbox: Vec::new(),
+    };
+
+    while let Some(packet) = framed.next().await {
+        let packet = packet?;
+
+        if let Err(e) = registry.dispatch(&mut session, &packet) {
+            println!("[SERVER] Dispatch error from {}: {}", addr, e);
+            continue;
+        }
+
+        for reply in session.outbox.drain(..) {
+            framed.send(reply).await?;
+        }
+    }
+
+    println!("[SERVER] Client disconnected: {}", addr);
+    Ok(())
+}
+
+async fn packet_server() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let listener = TcpListener::bind("127.0.0.1:8080").await?;
+    println!("[SERVER] Packet server listening on 127.0.0.1:8080");
+
+    let registry = std::sync::Arc::new(build_registry()?);
+
+    loop {
+        let (socket, addr) = listener.accept().await?;
+        let registry = registry.clone();
+
+        tokio::spawn(async move {
+            if let Err(e) = handle_client(socket, addr, registry).await {
+                println!("[SERVER] Error handling {}: {}", addr, e);
+            }
+        });
+    }
+}
+
+async fn packet_client() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let stream = TcpStream::connect("127.0.0.1:8080").await?;
+    let mut framed = Framed::new(stream, PacketCodec::default());
+    println!("[CLIENT] Connected to server");
+
+    framed
+        .send(RawPacket::from_message(&Login {
+            user_id: 42,
+            name: "rustacean".to_string(),
+        }))
+        .await?;
+    framed
+        .send(RawPacket::from_message(&Move { x: 1.5, y: -3.0 }))
+        .await?;
+    framed
+        .send(RawPacket::from_message(&Chat {
+            text: "hello packets".to_string(),
+        }))
+        .await?;
+
+    for _ in 0..2 {
+        let Some(packet) = framed.next().await else {
+            println!("[CLIENT] Server closed connection");
+            break;
+        };
+        let packet = packet?;
+
+        match packet.msg_type {
+            LoginAck::TYPE_ID => println!("[CLIENT] Received: {:?}", packet.decode::<LoginAck>()?),
+            Chat::TYPE_ID => println!("[CLIENT] Received: {:?}", packet.decode::<Chat>()?),
+            other => println!("[CLIENT] Unknown packet type 0x{:04x}", other),
+        }
+    }
+
+    Ok(())
+}
+
+#[tokio::main]
+pub async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    println!("Starting Typed Packet Protocol Example\n");
+
+    tokio::spawn(async {
+        if let Err(e) = packet_server().await {
+            println!("[SERVER] Fatal error: {}", e);
+        }
+    });
+
+    tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+
+    packet_client().await?;
+
+    tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+    use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+    // 쓰는 바이트 중 every번째마다 한 비트를 뒤집는 stream.
+    // tokio::io::duplex와 묶어서 checksum 검출을 메모리 안에서 확인한다
+    struct CorruptingStream<S> {
+        inner: S,
+        every: usize,
+        written: usize,
+    }
+
+    impl<S> CorruptingStream<S> {
+        fn new(inner: S, every: usize) -> Self {
+            Self {
+                inner,
+                every: every.max(1),
+                written: 0,
+            }
+        }
+    }
+
+    impl<S: AsyncRead + Unpin> AsyncRead for CorruptingStream<S> {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.inner).poll_read(cx, buf)
+        }
+    }
+
+    impl<S: AsyncWrite + Unpin> AsyncWrite for CorruptingStream<S> {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            let mut corrupted = buf.to_vec();
+            for (i, byte) in corrupted.iter_mut().enumerate() {
+                if (self.written + i + 1).is_multiple_of(self.every) {
+                    *byte ^= 0x10;
+                }
+            }
+
+            let result = Pin::new(&mut self.inner).poll_write(cx, &corrupted);
+            if let Poll::Ready(Ok(n)) = result {
+                self.written += n;
+            }
+            result
+        }
+
+        fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.inner).poll_flush(cx)
+        }
+
+        fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.inner).poll_shutdown(cx)
+        }
+    }
+
+    crate::packet! {
+        0x0100 => #[derive(Debug, PartialEq)] pub struct Everything {
+            pub a: u8, pub b: u16, pub c: u32, pub d: u64,
+            pub e: i8, pub f: i16, pub g: i32, pub h: i64,
+            pub x: f32, pub y: f64, pub flag: bool,
+            pub blob: Vec<u8>, pub name: String,
+        }
+        0x0101 => #[derive(Debug)] pub struct Ping {}
+        0x0020 => #[derive(Debug)] pub struct Shout { pub text: String }
+    }
+
+    fn everything() -> Everything {
+        Everything {
+            a: u8::MAX,
+            b: 0xbeef,
+            c: 0xdead_beef,
+            d: u64::MAX - 1,
+            e: -1,
+            f: i16::MIN,
+            g: -123_456,
+            h: i64::MIN,
+            x: 1.5,
+            y: -0.25,
+            flag: true,
+            blob: vec![0, 1, 2, 255],
+            name: "한글 name".to_string(),
+        }
+    }
+
+    fn frame_of(codec: &mut PacketCodec, packet: RawPacket) -> BytesMut {
+        let mut frame = BytesMut::new();
+        codec.encode(packet, &mut frame).unwrap();
+        frame
+    }
+
+    fn raw(msg_type: u16, payload: &[u8]) -> RawPacket {
+        RawPacket {
+            msg_type,
+            flags: 0,
+            payload: Bytes::copy_from_slice(payload),
+        }
+    }
+
+    // 핸들러가 받은 것을 문자열로 남기는 컨텍스트
+    fn log_registry() -> Registry<Vec<String>> {
+        Registry::new()
+            .on(|log: &mut Vec<String>, msg: Login| {
+                log.push(format!("login {} {}", msg.user_id, msg.name));
+                Ok(())
+            })
+            .unwrap()
+            .on(|log: &mut Vec<String>, msg: Chat| {
+                log.push(format!("chat {}", msg.text));
+                Ok(())
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn packet_macro_round_trips_every_wire_type() {
+        let mut codec = PacketCodec::default();
+        let mut frame = frame_of(&mut codec, RawPacket::from_message(&everything()));
+
+        let packet = codec.decode(&mut frame).unwrap().unwrap();
+        assert_eq!(packet.msg_type, Everything::TYPE_ID);
+        assert_eq!(Everything::NAME, "Everything");
+        assert_eq!(packet.decode::<Everything>().unwrap(), everything());
+
+        // 필드가 없는 메시지는 빈 payload
+        let empty = RawPacket::from_message(&Ping {});
+        assert!(empty.payload.is_empty());
+        empty.decode::<Ping>().unwrap();
+    }
+
+    #[test]
+    fn registry_dispatches_to_typed_handlers() {
+        let registry = log_registry();
+        let mut log = Vec::new();
+
+        let login = Login {
+            user_id: 42,
+            name: "ferris".to_string(),
+        };
+        registry
+            .dispatch(&mut log, &RawPacket::from_message(&login))
+            .unwrap();
+        registry
+            .dispatch(&mut log, &RawPacket::from_message(&Chat { text: "hi".into() }))
+            .unwrap();
+
+        assert_eq!(log, ["login 42 ferris", "chat hi"]);
+        assert_eq!(registry.name_of(Login::TYPE_ID), Some("Login"));
+        assert_eq!(registry.name_of(Move::TYPE_ID), None);
+    }
+
+    #[test]
+    fn registry_rejects_unknown_and_malformed_packets() {
+        let registry = log_registry();
+        let mut log = Vec::new();
+
+        assert!(matches!(
+            registry.dispatch(&mut log, &RawPacket::from_message(&Move { x: 0.0, y: 0.0 })),
+            Err(PacketError::UnknownType(0x0010))
+        ));
+
+        // Chat payload 뒤에 남는 바이트가 있으면 핸들러까지 가지 않는다
+        let mut payload = BytesMut::new();
+        Chat { text: "hi".into() }.encode(&mut payload);
+        payload.put_u8(0);
+        assert!(matches!(
+            registry.dispatch(&mut log, &raw(Chat::TYPE_ID, &payload)),
+            Err(PacketError::Malformed(_))
+        ));
+        assert!(log.is_empty());
+    }
+
+    #[test]
+    fn registry_refuses_duplicate_type_ids() {
+        let result = log_registry().on(|_: &mut Vec<String>, _: Shout| Ok(()));
+        match result {
+            Err(PacketError::DuplicateType {
+                msg_type,
+                existing,
+                added,
+            }) => {
+                assert_eq!(msg_type, 0x0020);
+                assert_eq!(existing, "Chat");
+                assert_eq!(added, "Shout");
+            }
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("duplicate type id was accepted"),
+        }
+
+        build_registry().unwrap();
+    }
+
+    #[test]
+    fn decode_rejects_bad_payloads() {
+        // 길이 prefix보다 짧은 문자열
+        assert!(matches!(
+            raw(Chat::TYPE_ID, &[0, 0, 0, 9, b'h', b'i']).decode::<Chat>(),
+            Err(PacketError::Malformed(_))
+        ));
+        // UTF-8이 아닌 문자열
+        assert!(matches!(
+            raw(Chat::TYPE_ID, &[0, 0, 0, 2, 0xff, 0xfe]).decode::<Chat>(),
+            Err(PacketError::Malformed(_))
+        ));
+        // 남는 바이트
+        assert!(matches!(
+            raw(LoginAck::TYPE_ID, &[0, 0, 0, 7, 0]).decode::<LoginAck>(),
+            Err(PacketError::Malformed(_))
+        ));
+        assert!(matches!(
+            raw(LoginAck::TYPE_ID, &[0, 0, 0, 7]).decode::<Chat>(),
+            Err(PacketError::UnexpectedType {
+                expected: 0x0020,
+                actual: 0x0002
+            })
+        ));
+    }
+
+    #[test]
+    fn codec_waits_for_whole_frames_and_checks_the_header() {
+        let mut codec = PacketCodec::default();
+        let frame = frame_of(&mut codec, RawPacket::from_message(&LoginAck { session_id: 7 }));
+
+        // 헤더 일부 / payload 일부만 온 경우는 더 기다린다
+        for cut in [HEADER_LEN - 1, frame.len() - 1] {
+            let mut partial = BytesMut::from(&frame[..cut]);
+            assert!(codec.decode(&mut partial).unwrap().is_none());
+            assert_eq!(partial.len(), cut);
+        }
+
+        let mut future = frame.clone();
+        future[7] = PROTOCOL_VERSION + 1;
+        assert!(matches!(
+            codec.decode(&mut future),
+            Err(PacketError::UnsupportedVersion(2))
+        ));
+
+        let mut small = PacketCodec::new(4);
+        assert!(matches!(
+            small.encode(raw(Chat::TYPE_ID, &[0; 5]), &mut BytesMut::new()),
+            Err(PacketError::PayloadTooLarge { length: 5, max: 4 })
+        ));
+        let mut oversized = frame_of(&mut codec, raw(Chat::TYPE_ID, &[0; 9]));
+        assert!(matches!(
+            small.decode(&mut oversized),
+            Err(PacketError::PayloadTooLarge { length: 9, max: 4 })
+        ));
+    }
+
+    fn checked(compression: Compression) -> CodecOptions {
+        CodecOptions {
+            compression,
+            compress_threshold: 64,
+            checksum: true,
+        }
+    }
+
+    fn bulk_state() -> Chat {
+        Chat {
+            text: "tile:0;".repeat(512),
+        }
+    }
+
+    // every번째 바이트마다 비트를 뒤집는 transport로 한 frame을 보내고 수신 결과를 돌려준다
+    async fn send_through(
+        every: usize,
+        options: CodecOptions,
+    ) -> Option<Result<RawPacket, PacketError>> {
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let transport = CorruptingStream::new(client, every);
+
+        let mut tx = Framed::new(transport, PacketCodec::with_options(DEFAULT_MAX_PAYLOAD, options));
+        let mut rx = Framed::new(server, PacketCodec::with_options(DEFAULT_MAX_PAYLOAD, options));
+
+        tx.send(RawPacket::from_message(&bulk_state())).await.unwrap();
+        drop(tx);
+        rx.next().await
+    }
+
+    #[tokio::test]
+    async fn clean_transport_round_trips_compressed_frames() {
+        for compression in [Compression::None, Compression::Lz4, Compression::Zstd] {
+            let packet = send_through(usize::MAX, checked(compression)).await.unwrap().unwrap();
+            assert_eq!(packet.decode::<Chat>().unwrap().text, bulk_state().text);
+        }
+    }
+
+    #[tokio::test]
+    async fn corrupting_transport_is_caught_by_crc() {
+        // 5: 헤더의 msg_type 바이트, 20 / 34: 압축된 payload
+        for every in [5, 20, 34] {
+            match send_through(every, checked(Compression::Lz4)).await {
+                Some(Err(PacketError::ChecksumMismatch { .. })) => {}
+                other => panic!(
+                    "corrupt_every={} decoded as {:?}",
+                    every,
+                    other.map(|r| r.map(|p| p.msg_type))
+                ),
+            }
+        }
+    }
+
+    #[test]
+    fn crc_covers_header_fields() {
+        let mut codec = PacketCodec::with_options(DEFAULT_MAX_PAYLOAD, checked(Compression::None));
+        let mut frame = BytesMut::new();
+        codec
+            .encode(RawPacket::from_message(&Move { x: 1.0, y: 2.0 }), &mut frame)
+            .unwrap();
+
+        // msg_type 하위 바이트 / 애플리케이션 flag 비트를 각각 뒤집는다
+        for (index, mask) in [(5, 0x01), (6, 0x80)] {
+            let mut corrupted = frame.clone();
+            corrupted[index] ^= mask;
+            assert!(matches!(
+                codec.decode(&mut corrupted),
+                Err(PacketError::ChecksumMismatch { .. })
+            ));
+        }
+
+        let packet = codec.decode(&mut frame).unwrap().unwrap();
+        assert_eq!(packet.msg_type, Move::TYPE_ID);
+        assert!(frame.is_empty());
+    }
+
+    #[test]
+    fn negotiated_checksum_rejects_frames_without_crc() {
+        let mut plain = PacketCodec::default();
+        let mut frame = BytesMut::new();
+        plain
+            .encode(RawPacket::from_message(&LoginAck { session_id: 7 }), &mut frame)
+            .unwrap();
+
+        let mut strict = PacketCodec::with_options(DEFAULT_MAX_PAYLOAD, checked(Compression::None));
+        assert!(matches!(
+            strict.decode(&mut frame.clone()),
+            Err(PacketError::ChecksumMissing)
+        ));
+
+        // checksum을 협상하지 않은 쪽은 그대로 받는다
+        let packet = plain.decode(&mut frame).unwrap().unwrap();
+        assert_eq!(packet.decode::<LoginAck>().unwrap().session_id, 7);
+    }
+
+    #[test]
+    fn unchecked_receiver_still_verifies_crc_when_present() {
+        let mut sender = PacketCodec::with_options(DEFAULT_MAX_PAYLOAD, checked(Compression::None));
+        let mut frame = BytesMut::new();
+        sender
+            .encode(RawPacket::from_message(&LoginAck { session_id: 7 }), &mut frame)
+            .unwrap();
+
+        let mut receiver = PacketCodec::default();
+        let mut corrupted = frame.clone();
+        corrupted[HEADER_LEN] ^= 0x01;
+        assert!(matches!(
+            receiver.decode(&mut corrupted),
+            Err(PacketError::ChecksumMismatch { .. })
+        ));
+
+        let packet = receiver.decode(&mut frame).unwrap().unwrap();
+        assert_eq!(packet.decode::<LoginAck>().unwrap().session_id, 7);
+    }
+
+    #[test]
+    fn crc_allowance_only_applies_when_checksum_is_negotiated() {
+        let no_compression = CodecOptions {
+            compression: Compression::None,
+            ..CodecOptions::default()
+        };
+        let payload = [0x5a; 16];
+
+        // max_payload + 4 바이트짜리 frame: checksum이 없으면 그냥 큰 payload다
+        let mut wide = PacketCodec::with_options(20, no_compression);
+        let mut frame = frame_of(&mut wide, raw(Chat::TYPE_ID, &[0x5a; 20]));
+        let mut plain = PacketCodec::with_options(16, no_compression);
+        assert!(matches!(
+            plain.decode(&mut frame),
+            Err(PacketError::PayloadTooLarge { length: 20, max: 16 })
+        ));
+
+        // 같은 길이라도 checksum을 협상했다면 payload 16 + crc 4
+        let mut strict = PacketCodec::with_options(16, checked(Compression::None));
+        let mut frame = frame_of(&mut strict, raw(Chat::TYPE_ID, &payload));
+        assert_eq!(frame.len(), HEADER_LEN + 20);
+        let packet = strict.decode(&mut frame).unwrap().unwrap();
+        assert_eq!(&packet.payload[..], &payload);
+    }
+}
@@ -0,0 +1,237 @@
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+use std::time::Duration;
+
+// libpcap 고전 포맷(.pcap) reader. tcpdump -w 로 뜬 파일을 그대로 읽는다.
+// magic으로 byte order와 timestamp 단위(usec/nsec)를 정하고, 레코드마다 캡처 시각과 프레임을 꺼낸다.
+// pcapng는 읽지 않는다 (editcap -F pcap 으로 바꿔서 넣는다).
+
+pub const LINKTYPE_ETHERNET: u32 = 1;
+
+const MAGIC_MICROS: u32 = 0xa1b2_c3d4;
+const MAGIC_NANOS: u32 = 0xa1b2_3c4d;
+const MAGIC_PCAPNG: u32 = 0x0a0d_0d0a;
+
+// 깨진 파일에서 엄청난 길이를 읽고 그만큼 할당하지 않도록
+const MAX_RECORD_LEN: u32 = 256 * 1024;
+
+#[derive(Debug, Clone)]
+pub struct PcapPacket {
+    // UNIX epoch 기준 캡처 시각
+    pub timestamp: Duration,
+    // 실제 길이. snaplen 때문에 data.len()보다 클 수 있다
+    pub orig_len: u32,
+    pub data: Vec<u8>,
+}
+
+pub struct PcapReader<R> {
+    reader: R,
+    swapped: bool,
+    nanos: bool,
+    link_type: u32,
+    // 마지막 레코드가 중간에 끊겨 있었다 (캡처 도중 복사한 파일 등)
+    truncated: bool,
+}
+
+impl PcapReader<BufReader<File>> {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> PcapReader<R> {
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut header = [0u8; 24];
+        reader.read_exact(&mut header)?;
+
+        let magic = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+
+        let (swapped, nanos) = match magic {
+            MAGIC_MICROS => (false, false),
+            MAGIC_NANOS => (false, true),
+            m if m.swap_bytes() == MAGIC_MICROS => (true, false),
+            m if m.swap_bytes() == MAGIC_NANOS => (true, true),
+            MAGIC_PCAPNG => {
+                return Err(invalid(
+                    "pcapng는 지원하지 않습니다 (editcap -F pcap 으로 변환)",
+                ))
+            }
+            _ => return Err(invalid("pcap 파일이 아닙니다")),
+        };
+
+        let mut pcap = Self {
+            reader,
+            swapped,
+            nanos,
+            link_type: 0,
+            truncated: false,
+        };
+        pcap.link_type = pcap.u32_at(&header, 20);
+
+        Ok(pcap)
+    }
+
+    pub fn link_type(&self) -> u32 {
+        self.link_type
+    }
+
+    pub fn truncated(&self) -> bool {
+        self.truncated
+    }
+
+    // 파일 끝이면 None. 마지막 레코드가 잘려 있으면 그 레코드만 버리고 None
+    pub fn next_packet(&mut self) -> io::Result<Option<PcapPacket>> {
+        let mut header = [0u8; 16];
+
+        match self.fill(&mut header)? {
+            0 => return Ok(None),
+            n if n < header.len() => {
+                self.truncated = true;
+                return Ok(None);
+            }
+            _ => {}
+        }
+
+        let secs = self.u32_at(&header, 0);
+        let fraction = self.u32_at(&header, 4);
+        let incl_len = self.u32_at(&header, 8);
+        let orig_len = self.u32_at(&header, 12);
+
+        if incl_len > MAX_RECORD_LEN {
+            return Err(invalid("레코드 길이가 비정상입니다"));
+        }
+
+        let mut data = vec![0u8; incl_len as usize];
+        if self.fill(&mut data)? < data.len() {
+            self.truncated = true;
+            return Ok(None);
+        }
+
+        let nanos = if self.nanos {
+            fraction
+        } else {
+            fraction.saturating_mul(1_000)
+        };
+
+        Ok(Some(PcapPacket {
+            timestamp: Duration::new(secs as u64, nanos.min(999_999_999)),
+            orig_len,
+            data,
+        }))
+    }
+
+    // buf를 채울 때까지 읽는다. 중간에 EOF면 거기까지 읽은 바이트 수
+    fn fill(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            match self.reader.read(&mut buf[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(filled)
+    }
+
+    fn u32_at(&self, buf: &[u8], at: usize) -> u32 {
+        let bytes = [buf[at], buf[at + 1], buf[at + 2], buf[at + 3]];
+        if self.swapped {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        }
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 24바이트 파일 헤더 + 레코드들. big_endian이면 모든 필드를 뒤집어 쓴다
+    fn pcap(magic: u32, big_endian: bool, records: &[(u32, u32, &[u8])]) -> Vec<u8> {
+        let word = |v: u32| if big_endian { v.to_be_bytes() } else { v.to_le_bytes() };
+        let half = |v: u16| if big_endian { v.to_be_bytes() } else { v.to_le_bytes() };
+
+        let mut out = Vec::new();
+        out.extend_from_slice(&word(magic));
+        out.extend_from_slice(&half(2));
+        out.extend_from_slice(&half(4));
+        out.extend_from_slice(&word(0));
+        out.extend_from_slice(&word(0));
+        out.extend_from_slice(&word(65535));
+        out.extend_from_slice(&word(LINKTYPE_ETHERNET));
+
+        for (secs, fraction, data) in records {
+            out.extend_from_slice(&word(*secs));
+            out.extend_from_slice(&word(*fraction));
+            out.extend_from_slice(&word(data.len() as u32));
+            out.extend_from_slice(&word(data.len() as u32 + 10));
+            out.extend_from_slice(data);
+        }
+        out
+    }
+
+    fn read_all(bytes: &[u8]) -> (Vec<PcapPacket>, bool) {
+        let mut reader = PcapReader::new(bytes).unwrap();
+        let mut packets = Vec::new();
+        while let Some(packet) = reader.next_packet().unwrap() {
+            packets.push(packet);
+        }
+        (packets, reader.truncated())
+    }
+
+    #[test]
+    fn reads_microsecond_little_endian_records() {
+        let bytes = pcap(MAGIC_MICROS, false, &[(1, 500_000, b"first"), (2, 7, b"second")]);
+        let (packets, truncated) = read_all(&bytes);
+
+        assert!(!truncated);
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[0].timestamp, Duration::new(1, 500_000_000));
+        assert_eq!(packets[0].data, b"first");
+        assert_eq!(packets[0].orig_len, 15);
+        assert_eq!(packets[1].timestamp, Duration::new(2, 7_000));
+    }
+
+    #[test]
+    fn reads_nanosecond_big_endian_records() {
+        let bytes = pcap(MAGIC_NANOS, true, &[(3, 123_456_789, b"frame")]);
+        let reader = PcapReader::new(&bytes[..]).unwrap();
+        assert_eq!(reader.link_type(), LINKTYPE_ETHERNET);
+
+        let (packets, _) = read_all(&bytes);
+        assert_eq!(packets[0].timestamp, Duration::new(3, 123_456_789));
+        assert_eq!(packets[0].data, b"frame");
+    }
+
+    #[test]
+    fn truncated_last_record_keeps_the_records_before_it() {
+        let bytes = pcap(MAGIC_MICROS, false, &[(1, 0, b"first"), (2, 0, b"second")]);
+
+        // 둘째 레코드의 data 중간, header 중간에서 끊는다
+        for cut in [bytes.len() - 3, bytes.len() - b"second".len() - 8] {
+            let (packets, truncated) = read_all(&bytes[..cut]);
+            assert!(truncated, "cut at {}", cut);
+            assert_eq!(packets.len(), 1);
+            assert_eq!(packets[0].data, b"first");
+        }
+    }
+
+    #[test]
+    fn rejects_pcapng_and_absurd_record_lengths() {
+        let mut bytes = pcap(MAGIC_PCAPNG, false, &[]);
+        assert!(PcapReader::new(&bytes[..]).is_err());
+
+        bytes = pcap(MAGIC_MICROS, false, &[(1, 0, b"x")]);
+        // incl_len을 한계보다 크게
+        bytes[24 + 8..24 + 12].copy_from_slice(&(MAX_RECORD_LEN + 1).to_le_bytes());
+        let mut reader = PcapReader::new(&bytes[..]).unwrap();
+        assert_eq!(reader.next_packet().unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
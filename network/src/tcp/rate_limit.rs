@@ -0,0 +1,88 @@
+use std::time::Instant;
+
+// 연결별 token bucket. capacity만큼 몰아서 보낼 수 있고, 초당 refill개씩 다시 찬다
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    capacity: f64,
+    refill_per_sec: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(capacity: u32, refill_per_sec: f64) -> Self {
+        Self {
+            capacity: capacity as f64,
+            refill_per_sec,
+            tokens: capacity as f64,
+            last: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        self.last = self.last.max(now);
+    }
+
+    // 토큰이 있으면 하나 쓰고 true
+    pub fn try_take(&mut self) -> bool {
+        self.try_take_at(Instant::now())
+    }
+
+    fn try_take_at(&mut self, now: Instant) -> bool {
+        self.refill(now);
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn burst_then_empty() {
+        let mut bucket = TokenBucket::new(3, 1.0);
+        let start = bucket.last;
+
+        for _ in 0..3 {
+            assert!(bucket.try_take_at(start));
+        }
+        assert!(!bucket.try_take_at(start));
+    }
+
+    #[test]
+    fn refills_at_the_configured_rate() {
+        let mut bucket = TokenBucket::new(2, 4.0);
+        let start = bucket.last;
+        assert!(bucket.try_take_at(start));
+        assert!(bucket.try_take_at(start));
+        assert!(!bucket.try_take_at(start));
+
+        // 초당 4개: 0.2초면 아직 0.8개, 0.25초에 한 개
+        assert!(!bucket.try_take_at(start + Duration::from_millis(200)));
+        assert!(bucket.try_take_at(start + Duration::from_millis(250)));
+        assert!(!bucket.try_take_at(start + Duration::from_millis(250)));
+    }
+
+    #[test]
+    fn idle_time_never_exceeds_capacity() {
+        let mut bucket = TokenBucket::new(2, 10.0);
+        let later = bucket.last + Duration::from_secs(60);
+
+        assert!(bucket.try_take_at(later));
+        assert!(bucket.try_take_at(later));
+        assert!(!bucket.try_take_at(later));
+
+        // 시계가 뒤로 가도 토큰이 생기거나 줄지 않는다
+        assert!(!bucket.try_take_at(later - Duration::from_secs(1)));
+        assert!(bucket.try_take_at(later + Duration::from_millis(100)));
+    }
+}
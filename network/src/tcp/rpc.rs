@@ -0,0 +1,871 @@
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use futures_util::{FutureExt, SinkExt, StreamExt};
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{mpsc, oneshot, OwnedSemaphorePermit, Semaphore};
+use tokio::task::{AbortHandle, JoinHandle};
+use tokio::time::timeout;
+use tokio_util::codec::{FramedRead, FramedWrite, LengthDelimitedCodec};
+
+// ==================== FRAME LAYOUT ====================
+//
+// custom_protocol과 같은 4바이트 big-endian length prefix 위에
+//
+// | kind (u8) | correlation id (u64) | body ... |
+//
+// 하나의 연결에 여러 요청을 동시에 보내고, 응답은 id로 짝을 맞춘다.
+
+const FRAME_HEADER_LEN: usize = 9;
+const MAX_FRAME_SIZE: usize = 1024 * 1024;
+const OUTBOUND_QUEUE: usize = 256;
+const DEFAULT_MAX_IN_FLIGHT: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FrameKind {
+    Request = 1,
+    Response = 2,
+    // 클라이언트가 더 이상 응답을 기다리지 않음
+    Cancel = 3,
+    // 서버 핸들러가 실패함. body는 UTF-8 에러 메시지
+    Error = 4,
+}
+
+impl FrameKind {
+    fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            1 => Some(FrameKind::Request),
+            2 => Some(FrameKind::Response),
+            3 => Some(FrameKind::Cancel),
+            4 => Some(FrameKind::Error),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct RpcFrame {
+    kind: FrameKind,
+    id: u64,
+    body: Bytes,
+}
+
+impl RpcFrame {
+    fn new(kind: FrameKind, id: u64, body: Bytes) -> Self {
+        Self { kind, id, body }
+    }
+
+    fn encode(&self) -> Bytes {
+        let mut buf = BytesMut::with_capacity(FRAME_HEADER_LEN + self.body.len());
+        buf.put_u8(self.kind as u8);
+        buf.put_u64(self.id);
+        buf.extend_from_slice(&self.body);
+        buf.freeze()
+    }
+
+    fn decode(mut buf: BytesMut) -> Result<Self, RpcError> {
+        if buf.len() < FRAME_HEADER_LEN {
+            return Err(RpcError::Malformed(format!("frame too short: {} bytes", buf.len())));
+        }
+
+        let kind = buf.get_u8();
+        let kind = FrameKind::from_u8(kind)
+            .ok_or_else(|| RpcError::Malformed(format!("unknown frame kind: {}", kind)))?;
+        let id = buf.get_u64();
+
+        Ok(Self {
+            kind,
+            id,
+            body: buf.freeze(),
+        })
+    }
+}
+
+fn codec() -> LengthDelimitedCodec {
+    LengthDelimitedCodec::builder()
+        .length_field_length(4)
+        .max_frame_length(MAX_FRAME_SIZE)
+        .new_codec()
+}
+
+// ==================== ERRORS ====================
+
+#[derive(Debug)]
+pub enum RpcError {
+    Timeout,
+    Disconnected,
+    Remote(String),
+    Malformed(String),
+    Io(std::io::Error),
+}
+
+impl fmt::Display for RpcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RpcError::Timeout => write!(f, "request timed out"),
+            RpcError::Disconnected => write!(f, "connection closed"),
+            RpcError::Remote(msg) => write!(f, "remote error: {}", msg),
+            RpcError::Malformed(msg) => write!(f, "malformed frame: {}", msg),
+            RpcError::Io(e) => write!(f, "io error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for RpcError {}
+
+impl From<std::io::Error> for RpcError {
+    fn from(e: std::io::Error) -> Self {
+        RpcError::Io(e)
+    }
+}
+
+// ==================== CLIENT ====================
+
+type Pending = Arc<Mutex<HashMap<u64, oneshot::Sender<Result<Bytes, RpcError>>>>>;
+
+pub struct RpcClient {
+    outbound: mpsc::Sender<RpcFrame>,
+    pending: Pending,
+    next_id: AtomicU64,
+    reader: JoinHandle<()>,
+}
+
+impl RpcClient {
+    pub async fn connect(addr: SocketAddr) -> Result<Self, RpcError> {
+        let stream = TcpStream::connect(addr).await?;
+        Ok(Self::from_stream(stream))
+    }
+
+    // 이미 연결된 stream 위에서 동작 (TLS, tokio::io::duplex 등)
+    pub fn from_stream<S>(stream: S) -> Self
+    where
+        S: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        let (read_half, write_half) = tokio::io::split(stream);
+
+        let (outbound, mut outbound_rx) = mpsc::channel::<RpcFrame>(OUTBOUND_QUEUE);
+        let pending: Pending = Arc::new(Mutex::new(HashMap::new()));
+
+        // writer: 여러 요청이 보낸 frame을 한 소켓에 순서대로 쓴다
+        tokio::spawn(async move {
+            let mut sink = FramedWrite::new(write_half, codec());
+            while let Some(frame) = outbound_rx.recv().await {
+                if let Err(e) = sink.send(frame.encode()).await {
+                    println!("[CLIENT] Write error: {}", e);
+                    break;
+                }
+            }
+        });
+
+        // reader: 응답을 id로 찾아 기다리는 future에 넘긴다
+        let reader_pending = pending.clone();
+        let reader = tokio::spawn(async move {
+            let mut frames = FramedRead::new(read_half, codec());
+
+            while let Some(frame) = frames.next().await {
+                let frame = match frame.map_err(RpcError::from).and_then(RpcFrame::decode) {
+                    Ok(frame) => frame,
+                    Err(e) => {
+                        println!("[CLIENT] Read error: {}", e);
+                        break;
+                    }
+                };
+
+                let result = match frame.kind {
+                    FrameKind::Response => Ok(frame.body),
+                    FrameKind::Error => Err(RpcError::Remote(
+                        String::from_utf8_lossy(&frame.body).into_owned(),
+                    )),
+                    kind => {
+                        println!("[CLIENT] Unexpected {:?} frame id={}", kind, frame.id);
+                        continue;
+                    }
+                };
+
+                // 이미 timeout/cancel된 요청의 늦은 응답은 버린다
+                if let Some(waiter) = reader_pending.lock().unwrap().remove(&frame.id) {
+                    let _ = waiter.send(result);
+                }
+            }
+
+            // 남은 요청은 모두 실패 처리
+            for (_, waiter) in reader_pending.lock().unwrap().drain() {
+                let _ = waiter.send(Err(RpcError::Disconnected));
+            }
+        });
+
+        Self {
+            outbound,
+            pending,
+            next_id: AtomicU64::new(1),
+            reader,
+        }
+    }
+
+    // 요청을 보내고 응답을 기다린다. 여러 call을 동시에 await해도 된다.
+    // timeout이 나거나 future가 drop되면 서버에 Cancel frame을 보낸다.
+    pub async fn call(&self, body: impl Into<Bytes>, deadline: Duration) -> Result<Bytes, RpcError> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(id, tx);
+
+        let mut guard = CancelGuard {
+            client: self,
+            id,
+            armed: true,
+        };
+
+        self.outbound
+            .send(RpcFrame::new(FrameKind::Request, id, body.into()))
+            .await
+            .map_err(|_| RpcError::Disconnected)?;
+
+        match timeout(deadline, rx).await {
+            Ok(Ok(result)) => {
+                guard.armed = false;
+                result
+            }
+            Ok(Err(_)) => {
+                guard.armed = false;
+                Err(RpcError::Disconnected)
+            }
+            Err(_) => Err(RpcError::Timeout),
+        }
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.pending.lock().unwrap().len()
+    }
+}
+
+impl Drop for RpcClient {
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
+}
+
+struct CancelGuard<'a> {
+    client: &'a RpcClient,
+    id: u64,
+    armed: bool,
+}
+
+impl Drop for CancelGuard<'_> {
+    fn drop(&mut self) {
+        if !self.armed {
+            return;
+        }
+
+        self.client.pending.lock().unwrap().remove(&self.id);
+
+        let cancel = RpcFrame::new(FrameKind::Cancel, self.id, Bytes::new());
+        match self.client.outbound.try_send(cancel) {
+            Ok(()) => {}
+            // drop 안에서는 await할 수 없으니 큐가 꽉 찼으면 task에서 자리가 날 때까지 기다린다
+            Err(TrySendError::Full(cancel)) => match tokio::runtime::Handle::try_current() {
+                Ok(runtime) => {
+                    let outbound = self.client.outbound.clone();
+                    runtime.spawn(async move {
+                        let _ = outbound.send(cancel).await;
+                    });
+                }
+                Err(_) => println!("[CLIENT] Dropped cancel for id={}: outbound queue full", self.id),
+            },
+            // writer가 이미 끝났으면 보낼 곳이 없다
+            Err(TrySendError::Closed(_)) => {}
+        }
+    }
+}
+
+// ==================== SERVER ====================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResponseOrder {
+    // 요청이 들어온 순서대로 응답 (앞 요청이 느리면 뒤 응답도 기다림)
+    Ordered,
+    // 끝나는 대로 응답
+    Unordered,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ServeOptions {
+    pub order: ResponseOrder,
+    // 한 연결에서 동시에 도는 핸들러 수. 다 차면 다음 frame을 읽지 않는다 (최소 1)
+    pub max_in_flight: usize,
+}
+
+impl Default for ServeOptions {
+    fn default() -> Self {
+        Self {
+            order: ResponseOrder::Unordered,
+            max_in_flight: DEFAULT_MAX_IN_FLIGHT,
+        }
+    }
+}
+
+type InFlight = Arc<Mutex<HashMap<u64, AbortHandle>>>;
+
+pub async fn serve_connection<S, H, Fut>(
+    socket: S,
+    addr: SocketAddr,
+    handler: Arc<H>,
+    options: ServeOptions,
+) -> Result<(), RpcError>
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+    H: Fn(Bytes) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<Bytes, String>> + Send + 'static,
+{
+    let (read_half, write_half) = tokio::io::split(socket);
+    let (out_tx, mut out_rx) = mpsc::channel::<RpcFrame>(OUTBOUND_QUEUE);
+
+    let writer = tokio::spawn(async move {
+        let mut sink = FramedWrite::new(write_half, codec());
+        while let Some(frame) = out_rx.recv().await {
+            if sink.send(frame.encode()).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    // 응답이 실제로 나갈 때까지 permit을 잡고 있으므로 sequencer 큐도 max_in_flight를 넘지 않는다
+    let max_in_flight = options.max_in_flight.max(1);
+    let limit = Arc::new(Semaphore::new(max_in_flight));
+
+    // Ordered 모드: 요청 순서대로 응답 수신기를 줄 세운다
+    let sequencer = match options.order {
+        ResponseOrder::Ordered => {
+            let (seq_tx, mut seq_rx) =
+                mpsc::channel::<(oneshot::Receiver<RpcFrame>, OwnedSemaphorePermit)>(max_in_flight);
+            let out_tx = out_tx.clone();
+            let task = tokio::spawn(async move {
+                while let Some((rx, _permit)) = seq_rx.recv().await {
+                    // 취소된 요청은 sender가 drop되어 Err -> 응답 없이 건너뜀
+                    if let Ok(frame) = rx.await {
+                        if out_tx.send(frame).await.is_err() {
+                            break;
+                        }
+                    }
+                }
+            });
+            Some((seq_tx, task))
+        }
+        ResponseOrder::Unordered => None,
+    };
+
+    let in_flight: InFlight = Arc::new(Mutex::new(HashMap::new()));
+    let mut frames = FramedRead::new(read_half, codec());
+
+    while let Some(frame) = frames.next().await {
+        let frame = match frame.map_err(RpcError::from).and_then(RpcFrame::decode) {
+            Ok(frame) => frame,
+            Err(e) => {
+                println!("[SERVER] Bad frame from {}: {}", addr, e);
+                break;
+            }
+        };
+
+        match frame.kind {
+            FrameKind::Request => {
+                let id = frame.id;
+                let permit = limit.clone().acquire_owned().await.expect("semaphore is never closed");
+
+                // 에러 응답도 Ordered 모드에서는 요청 순서를 지켜야 하므로 같은 경로로 보낸다
+                let reply_to = match &sequencer {
+                    Some((seq_tx, _)) => {
+                        let (tx, rx) = oneshot::channel();
+                        if seq_tx.send((rx, permit)).await.is_err() {
+                            break;
+                        }
+                        Reply::Ordered(tx)
+                    }
+                    None => Reply::Unordered(out_tx.clone(), permit),
+                };
+
+                let duplicate = in_flight.lock().unwrap().contains_key(&id);
+                if duplicate {
+                    let reply = RpcFrame::new(FrameKind::Error, id, Bytes::from_static(b"duplicate request id"));
+                    reply_to.send(reply).await;
+                    continue;
+                }
+
+                let future = handler(frame.body);
+                let done = in_flight.clone();
+
+                // lock을 잡은 채로 spawn + insert 해야 빨리 끝난 task가 먼저 remove하지 않는다
+                let mut tasks = in_flight.lock().unwrap();
+                let task = tokio::spawn(async move {
+                    let guard = InFlightGuard {
+                        in_flight: done,
+                        id,
+                        task: tokio::task::id(),
+                    };
+
+                    let reply = match AssertUnwindSafe(future).catch_unwind().await {
+                        Ok(Ok(body)) => RpcFrame::new(FrameKind::Response, id, body),
+                        Ok(Err(msg)) => RpcFrame::new(FrameKind::Error, id, Bytes::from(msg)),
+                        Err(_) => {
+                            println!("[SERVER] Handler for id={} panicked", id);
+                            RpcFrame::new(FrameKind::Error, id, Bytes::from_static(b"handler panicked"))
+                        }
+                    };
+
+                    drop(guard);
+                    reply_to.send(reply).await;
+                });
+
+                tasks.insert(id, task.abort_handle());
+            }
+            FrameKind::Cancel => {
+                if let Some(task) = in_flight.lock().unwrap().remove(&frame.id) {
+                    println!("[SERVER] {} cancelled request id={}", addr, frame.id);
+                    task.abort();
+                }
+            }
+            kind => {
+                println!("[SERVER] Unexpected {:?} frame from {}", kind, addr);
+            }
+        }
+    }
+
+    // 연결이 끊기면 진행 중인 요청도 정리
+    for (_, task) in in_flight.lock().unwrap().drain() {
+        task.abort();
+    }
+
+    drop(out_tx);
+    if let Some((seq_tx, task)) = sequencer {
+        drop(seq_tx);
+        let _ = task.await;
+    }
+    let _ = writer.await;
+
+    Ok(())
+}
+
+// 핸들러 task가 끝나거나 panic / abort로 drop될 때 in_flight 항목을 지운다.
+// Cancel 뒤 같은 id로 새 요청이 들어왔을 수 있으니 자기 task의 항목만 지운다.
+struct InFlightGuard {
+    in_flight: InFlight,
+    id: u64,
+    task: tokio::task::Id,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        let mut tasks = self.in_flight.lock().unwrap();
+        if tasks.get(&self.id).is_some_and(|handle| handle.id() == self.task) {
+            tasks.remove(&self.id);
+        }
+    }
+}
+
+// Unordered 모드는 응답을 writer에 넘길 때까지 permit을 들고 있는다.
+// Ordered 모드의 permit은 sequencer 큐에 함께 들어간다.
+enum Reply {
+    Ordered(oneshot::Sender<RpcFrame>),
+    Unordered(mpsc::Sender<RpcFrame>, OwnedSemaphorePermit),
+}
+
+impl Reply {
+    async fn send(self, frame: RpcFrame) {
+        match self {
+            Reply::Ordered(tx) => {
+                let _ = tx.send(frame);
+            }
+            Reply::Unordered(out_tx, _permit) => {
+                let _ = out_tx.send(frame).await;
+            }
+        }
+    }
+}
+
+// ==================== EXAMPLE ====================
+
+// "sleep <ms> <text>" 요청은 ms만큼 기다렸다가 text를 돌려준다
+async fn example_handler(body: Bytes) -> Result<Bytes, String> {
+    let text = String::from_utf8(body.to_vec()).map_err(|e| e.to_string())?;
+    let mut parts = text.splitn(3, ' ');
+
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some("sleep"), Some(ms), Some(reply)) => {
+            let ms: u64 = ms.parse().map_err(|_| format!("bad delay: {}", ms))?;
+            tokio::time::sleep(Duration::from_millis(ms)).await;
+            Ok(Bytes::from(reply.to_string()))
+        }
+        _ => Err(format!("unknown request: {}", text)),
+    }
+}
+
+async fn rpc_server(order: ResponseOrder) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let listener = TcpListener::bind("127.0.0.1:8080").await?;
+    println!("[SERVER] Multiplexed RPC server ({:?}) listening on 127.0.0.1:8080", order);
+
+    let handler = Arc::new(example_handler);
+
+    loop {
+        let (socket, addr) = listener.accept().await?;
+        let handler = handler.clone();
+
+        tokio::spawn(async move {
+            println!("[SERVER] Client connected: {}", addr);
+            let options = ServeOptions {
+                order,
+                ..ServeOptions::default()
+            };
+            if let Err(e) = serve_connection(socket, addr, handler, options).await {
+                println!("[SERVER] Error handling {}: {}", addr, e);
+            }
+            println!("[SERVER] Client disconnected: {}", addr);
+        });
+    }
+}
+
+async fn rpc_client() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let client = RpcClient::connect("127.0.0.1:8080".parse()?).await?;
+    println!("[CLIENT] Connected to server");
+
+    let deadline = Duration::from_secs(1);
+
+    // 한 연결에서 세 요청을 동시에 보낸다
+    let (slow, fast, too_slow) = tokio::join!(
+        client.call("sleep 300 slow", deadline),
+        client.call("sleep 50 fast", deadline),
+        client.call("sleep 5000 never", Duration::from_millis(200)),
+    );
+
+    for (name, result) in [("slow", slow), ("fast", fast), ("too_slow", too_slow)] {
+        match result {
+            Ok(body) => println!("[CLIENT] {} -> {}", name, String::from_utf8_lossy(&body)),
+            Err(e) => println!("[CLIENT] {} -> {}", name, e),
+        }
+    }
+
+    match client.call("bogus", deadline).await {
+        Ok(body) => println!("[CLIENT] bogus -> {}", String::from_utf8_lossy(&body)),
+        Err(e) => println!("[CLIENT] bogus -> {}", e),
+    }
+
+    println!("[CLIENT] in flight: {}", client.in_flight());
+    Ok(())
+}
+
+#[tokio::main]
+pub async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    println!("Starting Multiplexed RPC Example\n");
+
+    tokio::spawn(async {
+        if let Err(e) = rpc_server(ResponseOrder::Unordered).await {
+            println!("[SERVER] Fatal error: {}", e);
+        }
+    });
+
+    tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+
+    rpc_client().await?;
+
+    tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicBool;
+    use tokio::io::{duplex, DuplexStream, ReadHalf, WriteHalf};
+
+    // 서버 쪽 frame 순서를 그대로 보려고 RpcClient 대신 frame을 직접 주고받는 peer
+    struct RawPeer {
+        sink: FramedWrite<WriteHalf<DuplexStream>, LengthDelimitedCodec>,
+        frames: FramedRead<ReadHalf<DuplexStream>, LengthDelimitedCodec>,
+    }
+
+    impl RawPeer {
+        fn new(stream: DuplexStream) -> Self {
+            let (read_half, write_half) = tokio::io::split(stream);
+            Self {
+                sink: FramedWrite::new(write_half, codec()),
+                frames: FramedRead::new(read_half, codec()),
+            }
+        }
+
+        async fn send(&mut self, kind: FrameKind, id: u64, body: &'static str) {
+            let frame = RpcFrame::new(kind, id, Bytes::from_static(body.as_bytes()));
+            self.sink.send(frame.encode()).await.unwrap();
+        }
+
+        async fn recv(&mut self) -> Option<RpcFrame> {
+            let frame = timeout(Duration::from_secs(2), self.frames.next())
+                .await
+                .expect("no frame within 2s")?;
+            Some(RpcFrame::decode(frame.unwrap()).unwrap())
+        }
+
+        async fn expect(&mut self, kind: FrameKind, id: u64, body: &str) {
+            let frame = self.recv().await.expect("connection closed");
+            assert_eq!(
+                (frame.kind, frame.id, &frame.body[..]),
+                (kind, id, body.as_bytes()),
+                "body={:?}",
+                String::from_utf8_lossy(&frame.body)
+            );
+        }
+    }
+
+    fn addr() -> SocketAddr {
+        "127.0.0.1:9".parse().unwrap()
+    }
+
+    fn serve<H, Fut>(handler: H, options: ServeOptions) -> (DuplexStream, JoinHandle<()>)
+    where
+        H: Fn(Bytes) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Bytes, String>> + Send + 'static,
+    {
+        let (client, server) = duplex(64 * 1024);
+        let task = tokio::spawn(async move {
+            serve_connection(server, addr(), Arc::new(handler), options)
+                .await
+                .unwrap();
+        });
+        (client, task)
+    }
+
+    fn options(order: ResponseOrder) -> ServeOptions {
+        ServeOptions {
+            order,
+            ..ServeOptions::default()
+        }
+    }
+
+    // example_handler + "panic" 요청은 핸들러 안에서 panic
+    async fn test_handler(body: Bytes) -> Result<Bytes, String> {
+        if &body[..] == b"panic" {
+            panic!("handler blew up");
+        }
+        example_handler(body).await
+    }
+
+    // 핸들러 future가 drop되면(abort 포함) 표시한다
+    struct SetOnDrop(Arc<AtomicBool>);
+
+    impl Drop for SetOnDrop {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[tokio::test]
+    async fn unordered_server_replies_as_handlers_finish() {
+        let (client, server) = serve(test_handler, options(ResponseOrder::Unordered));
+        let mut peer = RawPeer::new(client);
+
+        peer.send(FrameKind::Request, 1, "sleep 150 slow").await;
+        peer.send(FrameKind::Request, 2, "sleep 0 fast").await;
+        peer.send(FrameKind::Request, 3, "bogus").await;
+
+        let mut replies = Vec::new();
+        for _ in 0..3 {
+            let frame = peer.recv().await.unwrap();
+            replies.push((frame.id, frame.kind));
+        }
+        assert_eq!(replies[2], (1, FrameKind::Response));
+        assert!(replies.contains(&(2, FrameKind::Response)));
+        assert!(replies.contains(&(3, FrameKind::Error)));
+
+        drop(peer);
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn ordered_server_keeps_request_order() {
+        let (client, server) = serve(test_handler, options(ResponseOrder::Ordered));
+        let mut peer = RawPeer::new(client);
+
+        peer.send(FrameKind::Request, 1, "sleep 150 slow").await;
+        peer.send(FrameKind::Request, 2, "sleep 0 fast").await;
+        peer.send(FrameKind::Request, 3, "bogus").await;
+
+        peer.expect(FrameKind::Response, 1, "slow").await;
+        peer.expect(FrameKind::Response, 2, "fast").await;
+        peer.expect(FrameKind::Error, 3, "unknown request: bogus").await;
+
+        drop(peer);
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn duplicate_id_error_follows_the_ordered_sequence() {
+        let (client, server) = serve(test_handler, options(ResponseOrder::Ordered));
+        let mut peer = RawPeer::new(client);
+
+        peer.send(FrameKind::Request, 1, "sleep 100 first").await;
+        peer.send(FrameKind::Request, 1, "sleep 0 again").await;
+        peer.send(FrameKind::Request, 2, "sleep 0 second").await;
+
+        peer.expect(FrameKind::Response, 1, "first").await;
+        peer.expect(FrameKind::Error, 1, "duplicate request id").await;
+        peer.expect(FrameKind::Response, 2, "second").await;
+
+        drop(peer);
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn panicking_handler_replies_with_error_and_frees_its_id() {
+        for order in [ResponseOrder::Unordered, ResponseOrder::Ordered] {
+            let (client, server) = serve(test_handler, options(order));
+            let mut peer = RawPeer::new(client);
+
+            peer.send(FrameKind::Request, 1, "panic").await;
+            peer.expect(FrameKind::Error, 1, "handler panicked").await;
+
+            // in_flight에서 빠졌으므로 같은 id를 다시 써도 duplicate가 아니다
+            peer.send(FrameKind::Request, 1, "sleep 0 ok").await;
+            peer.expect(FrameKind::Response, 1, "ok").await;
+
+            drop(peer);
+            server.await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn max_in_flight_holds_back_later_requests() {
+        let options = ServeOptions {
+            order: ResponseOrder::Unordered,
+            max_in_flight: 1,
+        };
+        let (client, server) = serve(test_handler, options);
+        let mut peer = RawPeer::new(client);
+
+        peer.send(FrameKind::Request, 1, "sleep 100 slow").await;
+        peer.send(FrameKind::Request, 2, "sleep 0 fast").await;
+
+        // 자리가 하나뿐이라 빠른 요청도 앞 요청이 끝난 뒤에 시작한다
+        peer.expect(FrameKind::Response, 1, "slow").await;
+        peer.expect(FrameKind::Response, 2, "fast").await;
+
+        drop(peer);
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn cancel_frame_aborts_the_handler() {
+        let dropped = Arc::new(AtomicBool::new(false));
+        let flag = dropped.clone();
+        let handler = move |body: Bytes| {
+            let guard = SetOnDrop(flag.clone());
+            async move {
+                let _guard = guard;
+                example_handler(body).await
+            }
+        };
+
+        let (client, server) = serve(handler, options(ResponseOrder::Ordered));
+        let mut peer = RawPeer::new(client);
+
+        peer.send(FrameKind::Request, 1, "sleep 5000 never").await;
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        peer.send(FrameKind::Cancel, 1, "").await;
+        peer.send(FrameKind::Request, 2, "sleep 0 after").await;
+
+        // 취소된 요청은 응답 없이 건너뛰고 다음 요청이 나온다
+        peer.expect(FrameKind::Response, 2, "after").await;
+        assert!(dropped.load(Ordering::SeqCst));
+
+        drop(peer);
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn client_matches_out_of_order_replies_by_id() {
+        let (client, server) = duplex(64 * 1024);
+        let client = RpcClient::from_stream(client);
+        let mut peer = RawPeer::new(server);
+
+        // 세 요청을 모두 받은 뒤 역순으로 body를 그대로 돌려준다
+        let fake_server = async {
+            let mut requests = Vec::new();
+            for _ in 0..3 {
+                requests.push(peer.recv().await.unwrap());
+            }
+            for request in requests.into_iter().rev() {
+                let reply = RpcFrame::new(FrameKind::Response, request.id, request.body);
+                peer.sink.send(reply.encode()).await.unwrap();
+            }
+        };
+
+        let deadline = Duration::from_secs(2);
+        let (a, b, c, ()) = tokio::join!(
+            client.call("a", deadline),
+            client.call("b", deadline),
+            client.call("c", deadline),
+            fake_server,
+        );
+        assert_eq!(&a.unwrap()[..], b"a");
+        assert_eq!(&b.unwrap()[..], b"b");
+        assert_eq!(&c.unwrap()[..], b"c");
+        assert_eq!(client.in_flight(), 0);
+    }
+
+    #[tokio::test]
+    async fn client_timeout_cancels_on_the_server() {
+        let dropped = Arc::new(AtomicBool::new(false));
+        let flag = dropped.clone();
+        let handler = move |body: Bytes| {
+            let guard = SetOnDrop(flag.clone());
+            async move {
+                let _guard = guard;
+                example_handler(body).await
+            }
+        };
+
+        let (client, server) = serve(handler, options(ResponseOrder::Unordered));
+        let client = RpcClient::from_stream(client);
+
+        let result = client.call("sleep 5000 never", Duration::from_millis(50)).await;
+        assert!(matches!(result, Err(RpcError::Timeout)));
+        assert_eq!(client.in_flight(), 0);
+
+        // Cancel frame이 서버에 닿으면 핸들러 future가 drop된다
+        timeout(Duration::from_secs(2), async {
+            while !dropped.load(Ordering::SeqCst) {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .expect("handler was not aborted");
+
+        let reply = client.call("sleep 0 fine", Duration::from_secs(2)).await.unwrap();
+        assert_eq!(&reply[..], b"fine");
+        assert!(matches!(
+            client.call("bogus", Duration::from_secs(2)).await,
+            Err(RpcError::Remote(msg)) if msg == "unknown request: bogus"
+        ));
+
+        drop(client);
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn client_fails_pending_calls_when_the_server_goes_away() {
+        let (client, server) = duplex(64 * 1024);
+        let client = RpcClient::from_stream(client);
+        let mut peer = RawPeer::new(server);
+
+        let (result, ()) = tokio::join!(client.call("hello", Duration::from_secs(2)), async {
+            peer.recv().await.unwrap();
+            drop(peer);
+        });
+        assert!(matches!(result, Err(RpcError::Disconnected)));
+        assert_eq!(client.in_flight(), 0);
+    }
+}
@@ -0,0 +1,783 @@
+use bytes::Bytes;
+use futures_util::{SinkExt, StreamExt};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::time::{MissedTickBehavior, timeout};
+use tokio_util::codec::Framed;
+
+use crate::packet;
+use crate::tcp::packet::{CodecOptions, Compression, Message, PacketCodec, PacketError, RawPacket};
+use crate::util::fill_random;
+
+// ==================== SESSION LAYER ====================
+//
+// packet.rs framing 위에 올라가는 연결 관리 계층
+//
+// 1. handshake: Hello(버전 범위, 기능 플래그, 재접속 토큰) -> Welcome / Reject
+// 2. heartbeat: 주기적 Ping/Pong, 일정 시간 아무것도 못 받으면 dead peer
+// 3. 재개: Data에 seq를 붙이고 Ack 전까지 보관. 같은 토큰으로 재접속하면
+//    상대가 받지 못한 Data를 다시 보낸다.
+
+pub const SESSION_VERSION_MIN: u8 = 1;
+pub const SESSION_VERSION_MAX: u8 = 1;
+
+// 기능 플래그 (양쪽이 모두 켠 것만 사용)
+pub const FEATURE_LZ4: u32 = 0x0001;
+pub const FEATURE_CHECKSUM: u32 = 0x0002;
+pub const FEATURE_ZSTD: u32 = 0x0004;
+
+// 이만큼 받을 때마다 heartbeat를 기다리지 않고 Ack
+const ACK_EVERY: u64 = 32;
+
+// 제어 메시지는 0xFF00 이상을 쓴다
+packet! {
+    0xFF01 => #[derive(Debug)] pub struct Hello {
+        pub min_version: u8,
+        pub max_version: u8,
+        pub features: u32,
+        // 0이면 새 세션
+        pub session_token: u64,
+        pub last_received: u64,
+    }
+    0xFF02 => #[derive(Debug)] pub struct Welcome {
+        pub version: u8,
+        pub features: u32,
+        pub session_token: u64,
+        pub last_received: u64,
+        pub resumed: bool,
+    }
+    0xFF03 => #[derive(Debug)] pub struct Reject { pub reason: String }
+    0xFF04 => #[derive(Debug)] pub struct Ping { pub nonce: u64 }
+    0xFF05 => #[derive(Debug)] pub struct Pong { pub nonce: u64 }
+    0xFF06 => #[derive(Debug)] pub struct Ack { pub seq: u64 }
+    0xFF07 => #[derive(Debug)] pub struct Data { pub seq: u64, pub payload: Vec<u8> }
+}
+
+#[derive(Debug, Clone)]
+pub struct SessionConfig {
+    pub features: u32,
+    pub handshake_timeout: Duration,
+    pub heartbeat_interval: Duration,
+    // 이 시간 동안 아무 frame도 받지 못하면 연결을 끊는다
+    pub dead_after: Duration,
+    // 끊긴 세션을 재개할 수 있는 시간 (서버)
+    pub resume_window: Duration,
+    // 서버가 보관하는 끊긴 세션 수 상한. 넘으면 가장 오래된 것부터 버린다
+    pub max_parked: usize,
+    // Ack 받지 못한 Data가 이만큼 쌓이면 더 보내지 않는다
+    pub max_unacked: usize,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            features: FEATURE_LZ4 | FEATURE_ZSTD | FEATURE_CHECKSUM,
+            handshake_timeout: Duration::from_secs(5),
+            heartbeat_interval: Duration::from_secs(5),
+            dead_after: Duration::from_secs(15),
+            resume_window: Duration::from_secs(60),
+            max_parked: 10_000,
+            max_unacked: 1024,
+        }
+    }
+}
+
+// ==================== ERRORS ====================
+
+#[derive(Debug)]
+pub enum SessionError {
+    Packet(PacketError),
+    Handshake(String),
+    Rejected(String),
+    // 재전송이 아닌데 seq가 건너뛰어짐 (중간 Data 유실)
+    SequenceGap { expected: u64, got: u64 },
+    Io(std::io::Error),
+    DeadPeer,
+    Closed,
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionError::Packet(e) => write!(f, "{}", e),
+            SessionError::Handshake(msg) => write!(f, "handshake failed: {}", msg),
+            SessionError::Rejected(reason) => write!(f, "rejected by peer: {}", reason),
+            SessionError::SequenceGap { expected, got } => {
+                write!(f, "sequence gap: expected {}, got {}", expected, got)
+            }
+            SessionError::Io(e) => write!(f, "{}", e),
+            SessionError::DeadPeer => write!(f, "peer stopped responding"),
+            SessionError::Closed => write!(f, "connection closed"),
+        }
+    }
+}
+
+impl std::error::Error for SessionError {}
+
+impl From<PacketError> for SessionError {
+    fn from(e: PacketError) -> Self {
+        SessionError::Packet(e)
+    }
+}
+
+impl From<std::io::Error> for SessionError {
+    fn from(e: std::io::Error) -> Self {
+        SessionError::Io(e)
+    }
+}
+
+// ==================== SESSION STATE ====================
+
+// 연결이 끊겨도 유지되는 세션 상태
+#[derive(Debug)]
+pub struct SessionState {
+    pub token: u64,
+    next_seq: u64,
+    // 상대에게서 순서대로 받은 마지막 seq
+    last_received: u64,
+    // 보냈지만 아직 Ack 받지 못한 Data
+    unacked: VecDeque<(u64, Bytes)>,
+}
+
+impl SessionState {
+    pub fn new(token: u64) -> Self {
+        Self {
+            token,
+            next_seq: 1,
+            last_received: 0,
+            unacked: VecDeque::new(),
+        }
+    }
+
+    pub fn unacked(&self) -> usize {
+        self.unacked.len()
+    }
+
+    fn push(&mut self, payload: Bytes) -> u64 {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.unacked.push_back((seq, payload));
+        seq
+    }
+
+    fn on_ack(&mut self, seq: u64) {
+        while matches!(self.unacked.front(), Some((s, _)) if *s <= seq) {
+            self.unacked.pop_front();
+        }
+    }
+
+    // 재전송된 Data는 false (이미 받음)
+    fn accept(&mut self, seq: u64) -> Result<bool, SessionError> {
+        if seq <= self.last_received {
+            return Ok(false);
+        }
+
+        if seq != self.last_received + 1 {
+            return Err(SessionError::SequenceGap {
+                expected: self.last_received + 1,
+                got: seq,
+            });
+        }
+
+        self.last_received = seq;
+        Ok(true)
+    }
+
+    // 상대가 peer_last_received까지 받았으니 그 이후를 다시 보낸다
+    async fn replay<S>(
+        &mut self,
+        framed: &mut Framed<S, PacketCodec>,
+        peer_last_received: u64,
+    ) -> Result<usize, SessionError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        self.on_ack(peer_last_received);
+
+        for (seq, payload) in &self.unacked {
+            let data = Data {
+                seq: *seq,
+                payload: payload.to_vec(),
+            };
+            framed.send(RawPacket::from_message(&data)).await?;
+        }
+
+        Ok(self.unacked.len())
+    }
+}
+
+fn new_token() -> std::io::Result<u64> {
+    let mut buf = [0u8; 8];
+    // 토큰이 곧 재접속 권한이므로 커널 난수를 쓴다
+    fill_random(&mut buf)?;
+    Ok(u64::from_ne_bytes(buf).max(1))
+}
+
+// 협상된 기능으로 송신 codec 설정을 만든다. 둘 다 되면 압축률이 좋은 zstd.
+pub fn codec_options(features: u32) -> CodecOptions {
+    let compression = if features & FEATURE_ZSTD != 0 {
+        Compression::Zstd
+    } else if features & FEATURE_LZ4 != 0 {
+        Compression::Lz4
+    } else {
+        Compression::None
+    };
+
+    CodecOptions {
+        compression,
+        checksum: features & FEATURE_CHECKSUM != 0,
+        ..CodecOptions::default()
+    }
+}
+
+fn negotiate_version(min: u8, max: u8) -> Option<u8> {
+    let version = max.min(SESSION_VERSION_MAX);
+    if version >= min.max(SESSION_VERSION_MIN) {
+        Some(version)
+    } else {
+        None
+    }
+}
+
+async fn next_packet<S>(framed: &mut Framed<S, PacketCodec>) -> Result<RawPacket, SessionError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    match framed.next().await {
+        Some(packet) => Ok(packet?),
+        None => Err(SessionError::Closed),
+    }
+}
+
+// ==================== HANDSHAKE ====================
+
+#[derive(Debug, Clone, Copy)]
+pub struct Negotiated {
+    pub version: u8,
+    pub features: u32,
+    pub resumed: bool,
+}
+
+// 서버에 보관된 끊긴 세션들
+pub struct SessionStore {
+    parked: Mutex<HashMap<u64, (SessionState, Instant)>>,
+    resume_window: Duration,
+    capacity: usize,
+}
+
+impl SessionStore {
+    pub fn new(resume_window: Duration, capacity: usize) -> Self {
+        Self {
+            parked: Mutex::new(HashMap::new()),
+            resume_window,
+            capacity,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.parked.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn take(&self, token: u64) -> Option<SessionState> {
+        let mut parked = self.parked.lock().unwrap();
+        parked.retain(|_, (_, since)| since.elapsed() < self.resume_window);
+        parked.remove(&token).map(|(state, _)| state)
+    }
+
+    // 만료된 세션을 먼저 정리하고, 그래도 가득 차 있으면 가장 오래된 세션을 버린다
+    fn park(&self, state: SessionState) {
+        let mut parked = self.parked.lock().unwrap();
+        parked.retain(|_, (_, since)| since.elapsed() < self.resume_window);
+
+        while parked.len() >= self.capacity.max(1) {
+            let Some(oldest) = parked
+                .iter()
+                .min_by_key(|(_, (_, since))| *since)
+                .map(|(token, _)| *token)
+            else {
+                break;
+            };
+            parked.remove(&oldest);
+            println!("[SESSION] store full, evicted session={:016x}", oldest);
+        }
+
+        parked.insert(state.token, (state, Instant::now()));
+    }
+}
+
+pub async fn server_handshake<S>(
+    framed: &mut Framed<S, PacketCodec>,
+    store: &SessionStore,
+    config: &SessionConfig,
+) -> Result<(SessionState, Negotiated), SessionError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let packet = timeout(config.handshake_timeout, next_packet(framed))
+        .await
+        .map_err(|_| SessionError::Handshake("timed out waiting for Hello".into()))??;
+    let hello = packet.decode::<Hello>()?;
+
+    let Some(version) = negotiate_version(hello.min_version, hello.max_version) else {
+        let reason = format!(
+            "no common version: client {}..={}, server {}..={}",
+            hello.min_version, hello.max_version, SESSION_VERSION_MIN, SESSION_VERSION_MAX
+        );
+        framed
+            .send(RawPacket::from_message(&Reject { reason: reason.clone() }))
+            .await?;
+        return Err(SessionError::Handshake(reason));
+    };
+
+    // 모르는 토큰이거나 만료됐으면 새 세션
+    let resumed_state = match hello.session_token {
+        0 => None,
+        token => store.take(token),
+    };
+    let resumed = resumed_state.is_some();
+    let mut state = match resumed_state {
+        Some(state) => state,
+        None => match new_token() {
+            Ok(token) => SessionState::new(token),
+            Err(e) => {
+                let reason = "server could not create a session".to_string();
+                framed.send(RawPacket::from_message(&Reject { reason })).await?;
+                return Err(SessionError::Io(e));
+            }
+        },
+    };
+
+    let negotiated = Negotiated {
+        version,
+        features: hello.features & config.features,
+        resumed,
+    };
+
+    framed
+        .send(RawPacket::from_message(&Welcome {
+            version,
+            features: negotiated.features,
+            session_token: state.token,
+            last_received: state.last_received,
+            resumed,
+        }))
+        .await?;
+
+    // handshake frame 이후부터 협상된 압축 / checksum 적용
+    framed.codec_mut().set_options(codec_options(negotiated.features));
+
+    if resumed {
+        state.replay(framed, hello.last_received).await?;
+    }
+
+    Ok((state, negotiated))
+}
+
+// state가 있으면 재개를 요청한다. 서버가 거절하면 state는 새 세션으로 바뀐다.
+pub async fn client_handshake<S>(
+    framed: &mut Framed<S, PacketCodec>,
+    state: &mut Option<SessionState>,
+    config: &SessionConfig,
+) -> Result<Negotiated, SessionError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (token, last_received) = match state {
+        Some(s) => (s.token, s.last_received),
+        None => (0, 0),
+    };
+
+    framed
+        .send(RawPacket::from_message(&Hello {
+            min_version: SESSION_VERSION_MIN,
+            max_version: SESSION_VERSION_MAX,
+            features: config.features,
+            session_token: token,
+            last_received,
+        }))
+        .await?;
+
+    let packet = timeout(config.handshake_timeout, next_packet(framed))
+        .await
+        .map_err(|_| SessionError::Handshake("timed out waiting for Welcome".into()))??;
+
+    if packet.msg_type == Reject::TYPE_ID {
+        return Err(SessionError::Rejected(packet.decode::<Reject>()?.reason));
+    }
+    let welcome = packet.decode::<Welcome>()?;
+
+    framed.codec_mut().set_options(codec_options(welcome.features));
+
+    match state {
+        Some(s) if welcome.resumed => {
+            s.replay(framed, welcome.last_received).await?;
+        }
+        _ => {
+            if let Some(old) = state.as_ref().filter(|s| !s.unacked.is_empty()) {
+                println!(
+                    "[CLIENT] Session could not be resumed, {} unacked messages dropped",
+                    old.unacked.len()
+                );
+            }
+            *state = Some(SessionState::new(welcome.session_token));
+        }
+    }
+
+    Ok(Negotiated {
+        version: welcome.version,
+        features: welcome.features,
+        resumed: welcome.resumed,
+    })
+}
+
+// ==================== DRIVER ====================
+
+// handshake 이후 연결이 끊길 때까지 Data / Ack / Ping / Pong을 처리한다.
+// outgoing이 닫히면 Ok(())로 끝난다.
+pub async fn drive<S>(
+    framed: &mut Framed<S, PacketCodec>,
+    state: &mut SessionState,
+    config: &SessionConfig,
+    outgoing: &mut mpsc::Receiver<Bytes>,
+    incoming: &mpsc::Sender<Bytes>,
+) -> Result<(), SessionError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut ticker = tokio::time::interval(config.heartbeat_interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    let started = Instant::now();
+    let mut last_seen = Instant::now();
+    let mut acked_up_to = state.last_received;
+
+    loop {
+        tokio::select! {
+            packet = next_packet(framed) => {
+                let packet = packet?;
+                last_seen = Instant::now();
+
+                match packet.msg_type {
+                    Data::TYPE_ID => {
+                        let data = packet.decode::<Data>()?;
+                        if state.accept(data.seq)? {
+                            incoming
+                                .send(Bytes::from(data.payload))
+                                .await
+                                .map_err(|_| SessionError::Closed)?;
+                        }
+
+                        if state.last_received - acked_up_to >= ACK_EVERY {
+                            framed.send(RawPacket::from_message(&Ack { seq: state.last_received })).await?;
+                            acked_up_to = state.last_received;
+                        }
+                    }
+                    Ack::TYPE_ID => state.on_ack(packet.decode::<Ack>()?.seq),
+                    Ping::TYPE_ID => {
+                        let ping = packet.decode::<Ping>()?;
+                        framed.send(RawPacket::from_message(&Pong { nonce: ping.nonce })).await?;
+                    }
+                    Pong::TYPE_ID => {
+                        let pong = packet.decode::<Pong>()?;
+                        let rtt = started.elapsed().saturating_sub(Duration::from_micros(pong.nonce));
+                        println!("[SESSION] token={:016x} rtt={:?}", state.token, rtt);
+                    }
+                    other => return Err(PacketError::UnknownType(other).into()),
+                }
+            }
+
+            payload = outgoing.recv(), if state.unacked.len() < config.max_unacked => {
+                let Some(payload) = payload else {
+                    return Ok(());
+                };
+
+                let seq = state.push(payload.clone());
+                let data = Data { seq, payload: payload.to_vec() };
+                framed.send(RawPacket::from_message(&data)).await?;
+            }
+
+            _ = ticker.tick() => {
+                if last_seen.elapsed() >= config.dead_after {
+                    return Err(SessionError::DeadPeer);
+                }
+
+                // nonce = 세션 시작 후 경과 시간(us), Pong으로 돌아오면 RTT 계산
+                let nonce = started.elapsed().as_micros() as u64;
+                framed.send(RawPacket::from_message(&Ping { nonce })).await?;
+
+                if state.last_received > acked_up_to {
+                    framed.send(RawPacket::from_message(&Ack { seq: state.last_received })).await?;
+                    acked_up_to = state.last_received;
+                }
+            }
+        }
+    }
+}
+
+// ==================== EXAMPLE ====================
+
+async fn handle_client(
+    socket: TcpStream,
+    addr: SocketAddr,
+    store: Arc<SessionStore>,
+    config: Arc<SessionConfig>,
+) -> Result<(), SessionError> {
+    let mut framed = Framed::new(socket, PacketCodec::default());
+    let (mut state, negotiated) = server_handshake(&mut framed, &store, &config).await?;
+
+    println!(
+        "[SERVER] {} session={:016x} version={} features=0x{:x} resumed={} unacked={}",
+        addr,
+        state.token,
+        negotiated.version,
+        negotiated.features,
+        negotiated.resumed,
+        state.unacked()
+    );
+
+    // 앱 로직: 받은 메시지를 echo
+    let (in_tx, mut in_rx) = mpsc::channel::<Bytes>(64);
+    let (out_tx, mut out_rx) = mpsc::channel::<Bytes>(64);
+    tokio::spawn(async move {
+        while let Some(msg) = in_rx.recv().await {
+            let reply = format!("echo: {}", String::from_utf8_lossy(&msg));
+            if out_tx.send(Bytes::from(reply)).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let result = drive(&mut framed, &mut state, &config, &mut out_rx, &in_tx).await;
+    println!(
+        "[SERVER] {} session={:016x} detached ({:?}), {} unacked kept",
+        addr,
+        state.token,
+        result.as_ref().err().map(|e| e.to_string()),
+        state.unacked()
+    );
+    store.park(state);
+
+    Ok(())
+}
+
+async fn session_server() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let listener = TcpListener::bind("127.0.0.1:8080").await?;
+    println!("[SERVER] Session server listening on 127.0.0.1:8080");
+
+    let config = Arc::new(SessionConfig::default());
+    let store = Arc::new(SessionStore::new(config.resume_window, config.max_parked));
+
+    loop {
+        let (socket, addr) = listener.accept().await?;
+        let store = store.clone();
+        let config = config.clone();
+
+        tokio::spawn(async move {
+            if let Err(e) = handle_client(socket, addr, store, config).await {
+                println!("[SERVER] Error handling {}: {}", addr, e);
+            }
+        });
+    }
+}
+
+async fn session_client() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let config = SessionConfig {
+        heartbeat_interval: Duration::from_millis(200),
+        ..SessionConfig::default()
+    };
+    let mut state: Option<SessionState> = None;
+
+    for round in ["first", "second"] {
+        let stream = TcpStream::connect("127.0.0.1:8080").await?;
+        let mut framed = Framed::new(stream, PacketCodec::default());
+
+        let negotiated = client_handshake(&mut framed, &mut state, &config).await?;
+        let Some(session) = state.as_mut() else {
+            return Err("handshake left no session".into());
+        };
+        println!(
+            "[CLIENT] {} connection: session={:016x} resumed={} features=0x{:x}",
+            round, session.token, negotiated.resumed, negotiated.features
+        );
+
+        let (out_tx, mut out_rx) = mpsc::channel::<Bytes>(16);
+        let (in_tx, mut in_rx) = mpsc::channel::<Bytes>(16);
+
+        tokio::spawn(async move {
+            for i in 0..3 {
+                let _ = out_tx.send(Bytes::from(format!("{} #{}", round, i))).await;
+            }
+            // 연결을 조금 유지하다가 끊는다 (out_tx drop -> drive 종료)
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        });
+
+        let printer = tokio::spawn(async move {
+            while let Some(msg) = in_rx.recv().await {
+                println!("[CLIENT] Received: {}", String::from_utf8_lossy(&msg));
+            }
+        });
+
+        if let Err(e) = drive(&mut framed, session, &config, &mut out_rx, &in_tx).await {
+            println!("[CLIENT] Connection ended: {}", e);
+        }
+        drop(in_tx);
+        let _ = printer.await;
+
+        println!("[CLIENT] Disconnected with {} unacked messages", session.unacked());
+    }
+
+    Ok(())
+}
+
+#[tokio::main]
+pub async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    println!("Starting Session Layer Example\n");
+
+    tokio::spawn(async {
+        if let Err(e) = session_server().await {
+            println!("[SERVER] Fatal error: {}", e);
+        }
+    });
+
+    tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+
+    session_client().await?;
+
+    tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn framed_pair() -> (
+        Framed<tokio::io::DuplexStream, PacketCodec>,
+        Framed<tokio::io::DuplexStream, PacketCodec>,
+    ) {
+        let (a, b) = tokio::io::duplex(64 * 1024);
+        (
+            Framed::new(a, PacketCodec::default()),
+            Framed::new(b, PacketCodec::default()),
+        )
+    }
+
+    #[test]
+    fn accept_skips_duplicates_and_reports_gaps() {
+        let mut state = SessionState::new(1);
+
+        assert!(state.accept(1).unwrap());
+        assert!(state.accept(2).unwrap());
+        assert!(!state.accept(2).unwrap());
+
+        match state.accept(5) {
+            Err(SessionError::SequenceGap { expected, got }) => {
+                assert_eq!((expected, got), (3, 5));
+            }
+            other => panic!("expected sequence gap, got {:?}", other),
+        }
+        assert_eq!(state.last_received, 2);
+    }
+
+    #[test]
+    fn new_tokens_are_random_and_non_zero() {
+        let a = new_token().unwrap();
+        let b = new_token().unwrap();
+        assert_ne!(a, 0);
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn park_evicts_oldest_when_full() {
+        let store = SessionStore::new(Duration::from_secs(60), 2);
+
+        store.park(SessionState::new(1));
+        store.park(SessionState::new(2));
+        store.park(SessionState::new(3));
+
+        assert_eq!(store.len(), 2);
+        assert!(store.take(1).is_none());
+        assert!(store.take(2).is_some());
+        assert!(store.take(3).is_some());
+        assert!(store.is_empty());
+    }
+
+    #[test]
+    fn park_drops_expired_sessions() {
+        let store = SessionStore::new(Duration::from_millis(20), 100);
+
+        store.park(SessionState::new(1));
+        std::thread::sleep(Duration::from_millis(30));
+        store.park(SessionState::new(2));
+
+        assert_eq!(store.len(), 1);
+        assert!(store.take(2).is_some());
+    }
+
+    #[tokio::test]
+    async fn resumed_session_replays_unacked_data() {
+        let config = SessionConfig::default();
+        let store = SessionStore::new(config.resume_window, config.max_parked);
+
+        // 첫 연결: 새 세션
+        let (mut server, mut client) = framed_pair();
+        let mut client_state = None;
+        let (server_result, client_result) = tokio::join!(
+            server_handshake(&mut server, &store, &config),
+            client_handshake(&mut client, &mut client_state, &config),
+        );
+        let (mut server_state, negotiated) = server_result.unwrap();
+        assert!(!negotiated.resumed);
+        assert!(!client_result.unwrap().resumed);
+        assert_eq!(client_state.as_ref().unwrap().token, server_state.token);
+
+        // 서버가 두 개를 보냈고 클라이언트는 첫 번째만 받은 채로 끊김
+        server_state.push(Bytes::from_static(b"first"));
+        server_state.push(Bytes::from_static(b"second"));
+        store.park(server_state);
+        client_state.as_mut().unwrap().last_received = 1;
+
+        let (mut server, mut client) = framed_pair();
+        let (server_result, client_result) = tokio::join!(
+            server_handshake(&mut server, &store, &config),
+            client_handshake(&mut client, &mut client_state, &config),
+        );
+        let (server_state, negotiated) = server_result.unwrap();
+        assert!(negotiated.resumed);
+        assert!(client_result.unwrap().resumed);
+        assert_eq!(server_state.unacked(), 1);
+
+        let data = next_packet(&mut client).await.unwrap().decode::<Data>().unwrap();
+        assert_eq!(data.seq, 2);
+        assert_eq!(data.payload, b"second");
+    }
+
+    #[tokio::test]
+    async fn unknown_token_starts_a_new_session() {
+        let config = SessionConfig::default();
+        let store = SessionStore::new(config.resume_window, config.max_parked);
+
+        let (mut server, mut client) = framed_pair();
+        let mut client_state = Some(SessionState::new(42));
+        let (server_result, client_result) = tokio::join!(
+            server_handshake(&mut server, &store, &config),
+            client_handshake(&mut client, &mut client_state, &config),
+        );
+
+        let (server_state, _) = server_result.unwrap();
+        assert!(!client_result.unwrap().resumed);
+        assert_ne!(server_state.token, 42);
+        assert_eq!(client_state.unwrap().token, server_state.token);
+    }
+}
@@ -0,0 +1,398 @@
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+use tokio::sync::{mpsc, Notify};
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+// 채팅 서버의 느린 클라이언트 정책.
+// 방 broadcast -> 클라이언트별 bounded outbox -> socket 순서로 흐르고,
+// outbox가 가득 차면 그 클라이언트 몫만 버린다. 다른 클라이언트는 영향을 받지 않는다.
+
+#[derive(Debug, Clone)]
+pub struct ChatConfig {
+    // 방 broadcast 채널 크기
+    pub room_capacity: usize,
+    // 방마다 남겨 두는 최근 메시지 수. lag 후 resync에 쓴다
+    pub history_len: usize,
+    // 클라이언트별 outbound queue 크기
+    pub outbound_capacity: usize,
+    // 아직 알리지 못한 drop이 이보다 많아지면 연결을 끊는다
+    pub kick_threshold: u64,
+    // broadcast lag이 나면 history로 빠진 메시지를 다시 채운다
+    pub resync: bool,
+    // 메시지 한 개의 최대 글자 수
+    pub max_message_len: usize,
+    // 연결별 token bucket. rate_burst개까지 몰아서 보내고 초당 rate_per_sec개씩 다시 찬다
+    pub rate_burst: u32,
+    pub rate_per_sec: f64,
+}
+
+impl Default for ChatConfig {
+    fn default() -> Self {
+        Self {
+            room_capacity: 100,
+            history_len: 50,
+            outbound_capacity: 256,
+            kick_threshold: 1000,
+            resync: true,
+            max_message_len: 512,
+            rate_burst: 10,
+            rate_per_sec: 2.0,
+        }
+    }
+}
+
+impl ChatConfig {
+    // CHAT_ROOM_CAPACITY, CHAT_HISTORY, CHAT_OUTBOUND, CHAT_KICK_THRESHOLD, CHAT_RESYNC=0,
+    // CHAT_MAX_MESSAGE, CHAT_RATE_BURST, CHAT_RATE 로 덮어쓴다
+    pub fn from_env() -> Self {
+        fn var<T: std::str::FromStr>(name: &str, default: T) -> T {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default)
+        }
+
+        let default = Self::default();
+        Self {
+            // broadcast::channel은 0이면 panic
+            room_capacity: var("CHAT_ROOM_CAPACITY", default.room_capacity).max(1),
+            history_len: var("CHAT_HISTORY", default.history_len),
+            outbound_capacity: var("CHAT_OUTBOUND", default.outbound_capacity).max(1),
+            kick_threshold: var("CHAT_KICK_THRESHOLD", default.kick_threshold),
+            resync: std::env::var("CHAT_RESYNC").as_deref() != Ok("0"),
+            max_message_len: var("CHAT_MAX_MESSAGE", default.max_message_len).max(1),
+            rate_burst: var("CHAT_RATE_BURST", default.rate_burst).max(1),
+            rate_per_sec: var("CHAT_RATE", default.rate_per_sec),
+        }
+    }
+}
+
+// ==================== HISTORY ====================
+
+// 순번을 붙인 최근 메시지 ring buffer
+pub struct History<T> {
+    next_seq: u64,
+    entries: VecDeque<(u64, T)>,
+    capacity: usize,
+}
+
+impl<T: Clone> History<T> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            next_seq: 1,
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    // 붙인 순번을 돌려준다
+    pub fn push(&mut self, item: T) -> u64 {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+
+        if self.capacity > 0 {
+            if self.entries.len() == self.capacity {
+                self.entries.pop_front();
+            }
+            self.entries.push_back((seq, item));
+        }
+
+        seq
+    }
+
+    // 지금까지 붙인 마지막 순번. 아직 없으면 0
+    pub fn last_seq(&self) -> u64 {
+        self.next_seq - 1
+    }
+
+    // seq 이후 남아 있는 메시지들
+    pub fn since(&self, seq: u64) -> Vec<(u64, T)> {
+        self.entries
+            .iter()
+            .filter(|(s, _)| *s > seq)
+            .cloned()
+            .collect()
+    }
+}
+
+// ==================== OUTBOX ====================
+
+// 클라이언트 하나의 outbound queue. push는 절대 기다리지 않는다
+pub struct Outbox<T> {
+    tx: mpsc::Sender<T>,
+    // 아직 클라이언트에게 알리지 않은 drop 수
+    dropped: AtomicU64,
+    kick_threshold: u64,
+    kicked: AtomicBool,
+    // 관리자 /kick처럼 이유가 따로 있는 경우
+    kick_reason: Mutex<Option<String>>,
+    notify: Notify,
+}
+
+impl<T> Outbox<T> {
+    pub fn new(capacity: usize, kick_threshold: u64) -> (Arc<Self>, mpsc::Receiver<T>) {
+        let (tx, rx) = mpsc::channel(capacity);
+
+        let outbox = Arc::new(Self {
+            tx,
+            dropped: AtomicU64::new(0),
+            kick_threshold,
+            kicked: AtomicBool::new(false),
+            kick_reason: Mutex::new(None),
+            notify: Notify::new(),
+        });
+
+        (outbox, rx)
+    }
+
+    // 자리가 없으면 버리고 false. receiver가 닫혔을 때도 false
+    pub fn push(&self, item: T) -> bool {
+        match self.tx.try_send(item) {
+            Ok(()) => true,
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                self.add_dropped(1);
+                false
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => false,
+        }
+    }
+
+    // broadcast lag처럼 outbox에 들어오기 전에 빠진 것도 같이 센다
+    pub fn add_dropped(&self, n: u64) {
+        let dropped = self.dropped.fetch_add(n, Ordering::Relaxed) + n;
+
+        if dropped > self.kick_threshold && !self.kicked.swap(true, Ordering::Relaxed) {
+            // notify_one은 permit을 남겨 두므로 아직 기다리는 중이 아니어도 놓치지 않는다
+            self.notify.notify_one();
+        }
+    }
+
+    // 밀린 것과 상관없이 연결을 끊게 한다
+    pub fn kick(&self, reason: &str) {
+        *self.kick_reason.lock().unwrap() = Some(reason.to_string());
+        if !self.kicked.swap(true, Ordering::Relaxed) {
+            self.notify.notify_one();
+        }
+    }
+
+    pub fn kick_reason(&self) -> String {
+        self.kick_reason
+            .lock()
+            .unwrap()
+            .clone()
+            .unwrap_or_else(|| "too slow to keep up".to_string())
+    }
+
+    // 클라이언트에게 알릴 drop 수를 가져가고 0으로 되돌린다
+    pub fn take_dropped(&self) -> u64 {
+        self.dropped.swap(0, Ordering::Relaxed)
+    }
+
+    pub fn is_kicked(&self) -> bool {
+        self.kicked.load(Ordering::Relaxed)
+    }
+
+    // kick될 때까지 기다린다. 연결 루프의 select!에서 쓴다
+    pub async fn kicked(&self) {
+        if self.is_kicked() {
+            return;
+        }
+        self.notify.notified().await;
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.tx.is_closed()
+    }
+}
+
+// 순번이 붙은 broadcast를 outbox로 옮긴다. lag이 나면 resync 함수로 빠진 구간을 채우고,
+// 채우지 못한 만큼은 drop으로 센다. last_seq 이하는 이미 받은 것이라 건너뛴다
+pub async fn forward<T, F>(
+    mut rx: tokio::sync::broadcast::Receiver<(u64, T)>,
+    outbox: Arc<Outbox<T>>,
+    mut last_seq: u64,
+    resync: Option<F>,
+) where
+    T: Clone,
+    F: Fn(u64) -> Vec<(u64, T)>,
+{
+    use tokio::sync::broadcast::error::RecvError;
+
+    loop {
+        let (seq, item) = match rx.recv().await {
+            Ok(entry) => entry,
+            Err(RecvError::Lagged(n)) => {
+                let replay = match &resync {
+                    Some(resync) => resync(last_seq),
+                    None => Vec::new(),
+                };
+
+                match replay.first() {
+                    // history에도 없는 앞부분
+                    Some((first, _)) => outbox.add_dropped(first.saturating_sub(last_seq + 1)),
+                    None => outbox.add_dropped(n),
+                }
+
+                for (seq, item) in replay {
+                    last_seq = seq;
+                    outbox.push(item);
+                }
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+
+        if seq <= last_seq {
+            continue;
+        }
+        last_seq = seq;
+
+        outbox.push(item);
+        if outbox.is_closed() || outbox.is_kicked() {
+            return;
+        }
+    }
+}
+
+// ==================== LINE READER ====================
+
+// 한 줄을 최대 max바이트까지만 버퍼에 담는 reader.
+// 넘치는 부분은 개행까지 읽어서 버리고, 잘린 줄은 UTF-8 글자 경계에서 자른다.
+// 상태를 전부 self에 두므로 select! 안에서 취소돼도 읽던 줄을 잃지 않는다.
+pub struct LineReader<R> {
+    reader: R,
+    buf: Vec<u8>,
+    max: usize,
+    // max를 넘겨서 개행까지 나머지를 버리는 중
+    discarding: bool,
+    // 마지막으로 돌려준 줄이 잘렸는지
+    truncated: bool,
+}
+
+impl<R: AsyncBufRead + Unpin> LineReader<R> {
+    pub fn new(reader: R, max: usize) -> Self {
+        Self {
+            reader,
+            buf: Vec::new(),
+            max,
+            discarding: false,
+            truncated: false,
+        }
+    }
+
+    pub fn truncated(&self) -> bool {
+        self.truncated
+    }
+
+    // 줄 경계에서만 호출할 것. 아직 다 못 읽은 줄은 버려진다
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    // 개행("\n", "\r\n")을 뗀 한 줄. 연결이 닫혔으면 None
+    pub async fn next_line(&mut self) -> std::io::Result<Option<String>> {
+        loop {
+            let available = self.reader.fill_buf().await?;
+            if available.is_empty() {
+                if self.buf.is_empty() && !self.discarding {
+                    return Ok(None);
+                }
+                return self.take_line().map(Some);
+            }
+
+            let newline = available.iter().position(|&b| b == b'\n');
+            let end = newline.map_or(available.len(), |i| i + 1);
+
+            if !self.discarding {
+                let room = self.max.saturating_sub(self.buf.len());
+                let content = newline.unwrap_or(available.len());
+                self.buf.extend_from_slice(&available[..content.min(room)]);
+                if content > room {
+                    self.discarding = true;
+                }
+            }
+
+            self.reader.consume(end);
+            if newline.is_some() {
+                return self.take_line().map(Some);
+            }
+        }
+    }
+
+    fn take_line(&mut self) -> std::io::Result<String> {
+        let mut bytes = std::mem::take(&mut self.buf);
+        let truncated = std::mem::replace(&mut self.discarding, false);
+        self.truncated = truncated;
+
+        if bytes.last() == Some(&b'\r') && !truncated {
+            bytes.pop();
+        }
+
+        // 잘리면서 끝에 남은 글자 조각은 버린다
+        if truncated {
+            if let Err(e) = std::str::from_utf8(&bytes) {
+                if e.error_len().is_none() {
+                    bytes.truncate(e.valid_up_to());
+                }
+            }
+        }
+
+        String::from_utf8(bytes).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::BufReader;
+
+    // BufReader 크기를 작게 잡아 줄이 여러 번의 fill_buf에 걸쳐 들어오게 한다
+    fn reader(input: &[u8], max: usize) -> LineReader<BufReader<&[u8]>> {
+        LineReader::new(BufReader::with_capacity(4, input), max)
+    }
+
+    async fn lines(input: &[u8], max: usize) -> Vec<String> {
+        let mut reader = reader(input, max);
+        let mut lines = Vec::new();
+        while let Some(line) = reader.next_line().await.unwrap() {
+            lines.push(line);
+        }
+        lines
+    }
+
+    #[tokio::test]
+    async fn splits_lines_and_strips_terminators() {
+        assert_eq!(
+            lines(b"first\r\nsecond\n\nlast", 64).await,
+            ["first", "second", "", "last"]
+        );
+    }
+
+    #[tokio::test]
+    async fn long_lines_are_cut_and_the_rest_discarded() {
+        let input = format!("{}\r\nnext\r\n", "x".repeat(100));
+        assert_eq!(lines(input.as_bytes(), 10).await, ["x".repeat(10), "next".to_string()]);
+
+        let mut reader = reader(input.as_bytes(), 10);
+        reader.next_line().await.unwrap();
+        assert!(reader.truncated());
+        reader.next_line().await.unwrap();
+        assert!(!reader.truncated());
+    }
+
+    #[tokio::test]
+    async fn cut_lands_on_a_char_boundary() {
+        // "가"는 3바이트. 4바이트에서 자르면 두 번째 글자는 통째로 빠진다
+        let input = "가나다\nok\n".as_bytes();
+        assert_eq!(lines(input, 4).await, ["가", "ok"]);
+    }
+
+    #[tokio::test]
+    async fn invalid_utf8_is_an_error() {
+        let mut reader = reader(b"\xff\xfe\n", 64);
+        let err = reader.next_line().await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+}
@@ -3,49 +3,625 @@ use pnet::packet::{
     ethernet::{EtherTypes, EthernetPacket},
     ip::IpNextHeaderProtocols,
     ipv4::Ipv4Packet,
-    tcp::TcpPacket,
-    Packet,
+    ipv6::Ipv6Packet,
+    tcp::{TcpFlags, TcpPacket},
 };
 use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-struct FlowKey {
-    src_ip: String,
-    src_port: u16,
-    dst_ip: String,
-    dst_port: u16,
+// TCP 한 방향의 byte stream을 순서대로 다시 맞춘다.
+// SYN의 ISN을 기준으로 seq를 64bit stream offset으로 펼쳐서 u32 wraparound와 4GB 넘는 stream을 같이 처리하고,
+// 겹치는 segment는 정책대로 잘라 낸다. 끝내 채워지지 않는 구간은 Gap으로 알리고 건너뛴다.
+
+// ==================== CONFIG ====================
+
+// 이미 받은 바이트와 다른 내용으로 겹치는 segment가 오면 어느 쪽을 믿을지.
+// OS마다 다르게 처리하므로 분석 대상에 맞춰 고른다
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OverlapPolicy {
+    // 먼저 받은 바이트를 유지 (Windows, BSD 계열)
+    First,
+    // 나중에 받은 바이트로 덮어쓴다 (Linux 일부, Solaris)
+    Last,
+}
+
+#[derive(Debug, Clone)]
+pub struct ReassemblyConfig {
+    pub overlap: OverlapPolicy,
+    // 한 방향에서 순서를 기다리며 들고 있을 최대 바이트. 넘치면 hole을 건너뛴다
+    pub max_flow_buffer: usize,
+    // 모든 방향을 합친 최대 바이트. 넘치면 가장 많이 들고 있는 방향부터 비운다
+    pub max_total_buffer: usize,
+    // 동시에 추적하는 방향 수. 넘치면 가장 오래 조용했던 방향을 닫는다
+    pub max_streams: usize,
+    pub idle_timeout: Duration,
+    // SYN을 못 본 stream도 처음 본 data segment부터 시작한다. 캡처를 중간부터 시작했을 때만 쓴다
+    pub allow_midstream: bool,
+}
+
+impl Default for ReassemblyConfig {
+    fn default() -> Self {
+        Self {
+            overlap: OverlapPolicy::First,
+            max_flow_buffer: 1024 * 1024,
+            max_total_buffer: 64 * 1024 * 1024,
+            max_streams: 100_000,
+            idle_timeout: Duration::from_secs(120),
+            allow_midstream: false,
+        }
+    }
+}
+
+// ==================== TYPES ====================
+
+// 한 방향. 반대 방향은 reverse()
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FlowKey {
+    pub src: SocketAddr,
+    pub dst: SocketAddr,
+}
+
+impl FlowKey {
+    pub fn reverse(&self) -> Self {
+        Self {
+            src: self.dst,
+            dst: self.src,
+        }
+    }
+}
+
+impl fmt::Display for FlowKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} -> {}", self.src, self.dst)
+    }
+}
+
+// 캡처에서 꺼낸 segment 하나. timestamp는 캡처 시각(UNIX epoch 기준)이라 pcap을 읽을 때도 그대로 쓴다
+#[derive(Debug, Clone, Copy)]
+pub struct TcpSegment<'a> {
+    pub key: FlowKey,
+    pub seq: u32,
+    pub flags: u8,
+    pub payload: &'a [u8],
+    pub timestamp: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CloseReason {
+    Fin,
+    Reset,
+    Timeout,
+    // max_streams를 넘어서 밀려남
+    Evicted,
+    // 같은 4-tuple로 ISN이 다른 SYN이 왔다 (port 재사용)
+    Reused,
+    // finish()로 캡처가 끝남
+    Shutdown,
+}
+
+impl fmt::Display for CloseReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self {
+            CloseReason::Fin => "fin",
+            CloseReason::Reset => "reset",
+            CloseReason::Timeout => "timeout",
+            CloseReason::Evicted => "evicted",
+            CloseReason::Reused => "reused",
+            CloseReason::Shutdown => "shutdown",
+        };
+        write!(f, "{}", reason)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct StreamStats {
+    pub segments: u64,
+    // 순서대로 내보낸 바이트
+    pub delivered: u64,
+    // 채워지지 않아 건너뛴 바이트
+    pub gap_bytes: u64,
+    pub gaps: u64,
+    // 이미 내보낸 구간을 다시 받은 바이트
+    pub retransmitted: u64,
+    // 아직 버퍼에 있는 구간과 겹친 바이트, 그중 내용이 달랐던 바이트
+    pub overlapped: u64,
+    pub conflicting: u64,
+    pub out_of_order: u64,
+}
+
+// 엔진이 내보내는 결과. offset은 SYN 다음 첫 바이트가 0
+#[derive(Debug, Clone)]
+pub enum StreamEvent {
+    Data {
+        key: FlowKey,
+        offset: u64,
+        data: Vec<u8>,
+        timestamp: Duration,
+    },
+    // offset부터 len바이트는 끝내 받지 못했다
+    Gap {
+        key: FlowKey,
+        offset: u64,
+        len: u64,
+    },
+    Closed {
+        key: FlowKey,
+        reason: CloseReason,
+        stats: StreamStats,
+    },
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ReassemblerStats {
+    pub segments: u64,
+    // SYN을 못 봐서 버린 segment
+    pub unanchored: u64,
+    pub opened: u64,
+    pub closed: u64,
+    // 버퍼 한계 때문에 강제로 hole을 건너뛴 횟수
+    pub forced_flushes: u64,
+}
+
+// ==================== HALF STREAM ====================
+
+struct Pending {
+    data: Vec<u8>,
+    timestamp: Duration,
+}
+
+struct HalfStream {
+    isn: u32,
+    // 다음에 내보낼 바이트의 offset
+    next_offset: u64,
+    // 서로 겹치지 않는 구간만 둔다. 모두 next_offset 이후
+    pending: BTreeMap<u64, Pending>,
+    buffered: usize,
+    // FIN이 차지하는 offset. 여기까지 내보내면 닫힌다
+    fin_offset: Option<u64>,
+    last_seen: Duration,
+    stats: StreamStats,
+}
+
+impl HalfStream {
+    fn new(isn: u32, timestamp: Duration) -> Self {
+        Self {
+            isn,
+            next_offset: 0,
+            pending: BTreeMap::new(),
+            buffered: 0,
+            fin_offset: None,
+            last_seen: timestamp,
+            stats: StreamStats::default(),
+        }
+    }
+
+    // seq를 next_offset 근처의 64bit offset으로 펼친다. 2^31 이상 차이는 뒤쪽으로 본다
+    fn offset_of(&self, seq: u32) -> i64 {
+        let relative = seq.wrapping_sub(self.isn.wrapping_add(1));
+        let delta = relative.wrapping_sub(self.next_offset as u32) as i32;
+        self.next_offset as i64 + delta as i64
+    }
+
+    fn insert(&mut self, offset: i64, data: &[u8], timestamp: Duration, policy: OverlapPolicy) {
+        // 이미 내보낸 앞부분은 다시 쓸 수 없다
+        let skip = (self.next_offset as i64 - offset).clamp(0, data.len() as i64) as usize;
+        self.stats.retransmitted += skip as u64;
+
+        let data = &data[skip..];
+        if data.is_empty() {
+            return;
+        }
+
+        // offset이 음수여도 skip을 더하면 next_offset 이상이 된다. 더한 뒤에 u64로 바꾼다
+        let start = (offset + skip as i64) as u64;
+        if start > self.next_offset {
+            self.stats.out_of_order += 1;
+        }
+
+        match policy {
+            OverlapPolicy::First => self.insert_first(start, data, timestamp),
+            OverlapPolicy::Last => self.insert_last(start, data, timestamp),
+        }
+    }
+
+    // [start, end)와 겹치는 기존 구간들의 시작 offset. 오름차순
+    fn overlapping(&self, start: u64, end: u64) -> Vec<u64> {
+        let mut keys: Vec<u64> = self
+            .pending
+            .range(..end)
+            .rev()
+            .take_while(|(s, p)| *s + p.data.len() as u64 > start)
+            .map(|(s, _)| *s)
+            .collect();
+        keys.reverse();
+        keys
+    }
+
+    fn count_overlap(&mut self, old_start: u64, old: &[u8], new_start: u64, new: &[u8]) {
+        let from = old_start.max(new_start);
+        let to = (old_start + old.len() as u64).min(new_start + new.len() as u64);
+        if to <= from {
+            return;
+        }
+
+        let old = &old[(from - old_start) as usize..(to - old_start) as usize];
+        let new = &new[(from - new_start) as usize..(to - new_start) as usize];
+        self.stats.overlapped += old.len() as u64;
+        self.stats.conflicting += old.iter().zip(new).filter(|(a, b)| a != b).count() as u64;
+    }
+
+    fn put(&mut self, start: u64, data: Vec<u8>, timestamp: Duration) {
+        self.buffered += data.len();
+        self.pending.insert(start, Pending { data, timestamp });
+    }
+
+    fn take(&mut self, start: u64) -> Option<Pending> {
+        let pending = self.pending.remove(&start)?;
+        self.buffered -= pending.data.len();
+        Some(pending)
+    }
+
+    // 기존 구간 사이의 빈 곳만 채운다
+    fn insert_first(&mut self, start: u64, data: &[u8], timestamp: Duration) {
+        let end = start + data.len() as u64;
+        let mut pos = start;
+
+        for key in self.overlapping(start, end) {
+            let old = self.pending[&key].data.clone();
+            self.count_overlap(key, &old, start, data);
+
+            if key > pos {
+                let piece = data[(pos - start) as usize..(key - start) as usize].to_vec();
+                self.put(pos, piece, timestamp);
+            }
+            pos = pos.max(key + old.len() as u64);
+        }
+
+        if pos < end {
+            self.put(pos, data[(pos - start) as usize..].to_vec(), timestamp);
+        }
+    }
+
+    // 겹치는 기존 구간을 잘라 내고 새 구간을 통째로 넣는다
+    fn insert_last(&mut self, start: u64, data: &[u8], timestamp: Duration) {
+        let end = start + data.len() as u64;
+
+        for key in self.overlapping(start, end) {
+            let Some(old) = self.take(key) else {
+                continue;
+            };
+            self.count_overlap(key, &old.data, start, data);
+
+            let old_end = key + old.data.len() as u64;
+            if key < start {
+                self.put(key, old.data[..(start - key) as usize].to_vec(), old.timestamp);
+            }
+            if old_end > end {
+                self.put(end, old.data[(end - key) as usize..].to_vec(), old.timestamp);
+            }
+        }
+
+        self.put(start, data.to_vec(), timestamp);
+    }
+
+    // 이어지는 만큼 내보낸다
+    fn drain(&mut self, key: FlowKey, events: &mut Vec<StreamEvent>) {
+        while let Some(pending) = self.take(self.next_offset) {
+            let offset = self.next_offset;
+            self.next_offset += pending.data.len() as u64;
+            self.stats.delivered += pending.data.len() as u64;
+
+            events.push(StreamEvent::Data {
+                key,
+                offset,
+                data: pending.data,
+                timestamp: pending.timestamp,
+            });
+        }
+    }
+
+    // 첫 hole을 Gap으로 알리고 건너뛴다. 건너뛸 게 없으면 false
+    fn skip_gap(&mut self, key: FlowKey, events: &mut Vec<StreamEvent>) -> bool {
+        let Some(&start) = self.pending.keys().next() else {
+            return false;
+        };
+
+        let len = start - self.next_offset;
+        if len > 0 {
+            events.push(StreamEvent::Gap {
+                key,
+                offset: self.next_offset,
+                len,
+            });
+            self.stats.gaps += 1;
+            self.stats.gap_bytes += len;
+            self.next_offset = start;
+        }
+
+        self.drain(key, events);
+        true
+    }
+
+    fn is_finished(&self) -> bool {
+        self.fin_offset.is_some_and(|fin| self.next_offset >= fin)
+    }
 }
 
-#[derive(Debug)]
-struct TcpStream {
-    next_seq: u32,
-    buffer: BTreeMap<u32, Vec<u8>>, // seq -> payload
+// ==================== REASSEMBLER ====================
+
+pub struct Reassembler {
+    config: ReassemblyConfig,
+    streams: HashMap<FlowKey, HalfStream>,
+    total_buffered: usize,
+    stats: ReassemblerStats,
 }
 
-impl TcpStream {
-    fn new(seq: u32) -> Self {
+impl Reassembler {
+    pub fn new(config: ReassemblyConfig) -> Self {
         Self {
-            next_seq: seq,
-            buffer: BTreeMap::new(),
+            config,
+            streams: HashMap::new(),
+            total_buffered: 0,
+            stats: ReassemblerStats::default(),
+        }
+    }
+
+    pub fn stats(&self) -> &ReassemblerStats {
+        &self.stats
+    }
+
+    pub fn stream_count(&self) -> usize {
+        self.streams.len()
+    }
+
+    pub fn buffered_bytes(&self) -> usize {
+        self.total_buffered
+    }
+
+    // 아직 열려 있는 방향인지
+    pub fn is_tracking(&self, key: &FlowKey) -> bool {
+        self.streams.contains_key(key)
+    }
+
+    pub fn process(&mut self, segment: &TcpSegment<'_>, events: &mut Vec<StreamEvent>) {
+        self.stats.segments += 1;
+        let key = segment.key;
+
+        // RST는 양방향을 같이 닫는다
+        if segment.flags & TcpFlags::RST != 0 {
+            self.close(key, CloseReason::Reset, events);
+            self.close(key.reverse(), CloseReason::Reset, events);
+            return;
         }
+
+        // SYN은 seq 하나를 차지한다. TFO면 data는 그 다음부터
+        let syn = segment.flags & TcpFlags::SYN != 0;
+        let data_seq = if syn { segment.seq.wrapping_add(1) } else { segment.seq };
+
+        if syn {
+            match self.streams.get(&key) {
+                // SYN 재전송
+                Some(stream) if stream.isn == segment.seq => {}
+                Some(_) => {
+                    self.close(key, CloseReason::Reused, events);
+                    self.open(key, segment.seq, segment.timestamp, events);
+                }
+                None => self.open(key, segment.seq, segment.timestamp, events),
+            }
+        } else if !self.streams.contains_key(&key) {
+            if !self.config.allow_midstream || segment.payload.is_empty() {
+                self.stats.unanchored += 1;
+                return;
+            }
+            // 이 segment의 첫 바이트를 offset 0으로
+            self.open(key, segment.seq.wrapping_sub(1), segment.timestamp, events);
+        }
+
+        let policy = self.config.overlap;
+        let max_flow_buffer = self.config.max_flow_buffer;
+        let Some(stream) = self.streams.get_mut(&key) else {
+            return;
+        };
+
+        stream.stats.segments += 1;
+        stream.last_seen = stream.last_seen.max(segment.timestamp);
+
+        let before = stream.buffered;
+        let offset = stream.offset_of(data_seq);
+        if !segment.payload.is_empty() {
+            stream.insert(offset, segment.payload, segment.timestamp, policy);
+        }
+
+        if segment.flags & TcpFlags::FIN != 0 {
+            let fin = (offset + segment.payload.len() as i64).max(0) as u64;
+            stream.fin_offset = Some(stream.fin_offset.map_or(fin, |old| old.min(fin)));
+        }
+
+        stream.drain(key, events);
+
+        // 이 방향 하나가 너무 많이 들고 있으면 hole을 포기한다
+        let mut forced = 0;
+        while stream.buffered > max_flow_buffer && stream.skip_gap(key, events) {
+            forced += 1;
+        }
+        self.stats.forced_flushes += forced;
+
+        let finished = stream.is_finished();
+        let after = stream.buffered;
+        self.total_buffered = self.total_buffered + after - before;
+
+        if finished {
+            self.close(key, CloseReason::Fin, events);
+        }
+
+        self.enforce_total(events);
+    }
+
+    // 전체 한계를 넘으면 가장 많이 들고 있는 방향부터 hole을 건너뛴다
+    fn enforce_total(&mut self, events: &mut Vec<StreamEvent>) {
+        while self.total_buffered > self.config.max_total_buffer {
+            let Some((&key, _)) = self.streams.iter().max_by_key(|(_, s)| s.buffered) else {
+                return;
+            };
+            let Some(stream) = self.streams.get_mut(&key) else {
+                return;
+            };
+
+            let before = stream.buffered;
+            if !stream.skip_gap(key, events) {
+                return;
+            }
+            self.total_buffered -= before - stream.buffered;
+            self.stats.forced_flushes += 1;
+
+            if stream.is_finished() {
+                self.close(key, CloseReason::Fin, events);
+            }
+        }
+    }
+
+    fn open(&mut self, key: FlowKey, isn: u32, timestamp: Duration, events: &mut Vec<StreamEvent>) {
+        if self.streams.len() >= self.config.max_streams {
+            let oldest = self
+                .streams
+                .iter()
+                .min_by_key(|(_, s)| s.last_seen)
+                .map(|(k, _)| *k);
+            if let Some(oldest) = oldest {
+                self.close(oldest, CloseReason::Evicted, events);
+            }
+        }
+
+        self.streams.insert(key, HalfStream::new(isn, timestamp));
+        self.stats.opened += 1;
     }
 
-    fn push(&mut self, seq: u32, payload: &[u8]) {
-        if payload.is_empty() {
+    // 남은 구간을 hole을 건너뛰며 모두 내보내고 닫는다
+    fn close(&mut self, key: FlowKey, reason: CloseReason, events: &mut Vec<StreamEvent>) {
+        let Some(mut stream) = self.streams.remove(&key) else {
             return;
+        };
+
+        let before = stream.buffered;
+        while stream.skip_gap(key, events) {}
+        self.total_buffered -= before;
+        self.stats.closed += 1;
+
+        events.push(StreamEvent::Closed {
+            key,
+            reason,
+            stats: stream.stats,
+        });
+    }
+
+    // now까지 idle_timeout 동안 조용했던 방향을 닫는다
+    pub fn expire(&mut self, now: Duration, events: &mut Vec<StreamEvent>) {
+        let timeout = self.config.idle_timeout;
+        let idle: Vec<FlowKey> = self
+            .streams
+            .iter()
+            .filter(|(_, s)| now.saturating_sub(s.last_seen) >= timeout)
+            .map(|(k, _)| *k)
+            .collect();
+
+        for key in idle {
+            self.close(key, CloseReason::Timeout, events);
+        }
+    }
+
+    // 캡처가 끝났다. 남은 방향을 모두 닫는다
+    pub fn finish(&mut self, events: &mut Vec<StreamEvent>) {
+        let keys: Vec<FlowKey> = self.streams.keys().copied().collect();
+        for key in keys {
+            self.close(key, CloseReason::Shutdown, events);
+        }
+    }
+}
+
+// ==================== CAPTURE ====================
+
+// Ethernet frame에서 TCP segment를 꺼낸다. IPv4, IPv6 모두
+pub fn parse_segment(frame: &[u8], timestamp: Duration) -> Option<TcpSegment<'_>> {
+    let eth = EthernetPacket::new(frame)?;
+    // payload()가 frame을 빌리는 packet을 돌려주므로 offset으로 다시 자른다
+    let ip = &frame[EthernetPacket::minimum_packet_size()..];
+
+    let (src, dst, tcp) = match eth.get_ethertype() {
+        EtherTypes::Ipv4 => {
+            let ipv4 = Ipv4Packet::new(ip)?;
+            if ipv4.get_next_level_protocol() != IpNextHeaderProtocols::Tcp {
+                return None;
+            }
+            let header = ipv4.get_header_length() as usize * 4;
+            let total = (ipv4.get_total_length() as usize).min(ip.len());
+            let tcp = ip.get(header..total)?;
+            (
+                IpAddr::V4(ipv4.get_source()),
+                IpAddr::V4(ipv4.get_destination()),
+                tcp,
+            )
         }
+        // 확장 헤더는 따라가지 않는다
+        EtherTypes::Ipv6 => {
+            let ipv6 = Ipv6Packet::new(ip)?;
+            if ipv6.get_next_header() != IpNextHeaderProtocols::Tcp {
+                return None;
+            }
+            let end = (40 + ipv6.get_payload_length() as usize).min(ip.len());
+            let tcp = ip.get(40..end)?;
+            (
+                IpAddr::V6(ipv6.get_source()),
+                IpAddr::V6(ipv6.get_destination()),
+                tcp,
+            )
+        }
+        _ => return None,
+    };
+
+    let header = TcpPacket::new(tcp)?;
+    let offset = header.get_data_offset() as usize * 4;
 
-        self.buffer.insert(seq, payload.to_vec());
+    Some(TcpSegment {
+        key: FlowKey {
+            src: SocketAddr::new(src, header.get_source()),
+            dst: SocketAddr::new(dst, header.get_destination()),
+        },
+        seq: header.get_sequence(),
+        flags: header.get_flags(),
+        payload: tcp.get(offset..)?,
+        timestamp,
+    })
+}
 
-        // 가능한 만큼 이어붙여 출력
-        while let Some(data) = self.buffer.remove(&self.next_seq) {
-            print!("{}", String::from_utf8_lossy(&data));
-            self.next_seq += data.len() as u32;
+fn print_events(events: &mut Vec<StreamEvent>) {
+    for event in events.drain(..) {
+        match event {
+            StreamEvent::Data { key, data, .. } => {
+                println!("[DATA] {} ({} bytes)", key, data.len());
+                print!("{}", String::from_utf8_lossy(&data));
+            }
+            StreamEvent::Gap { key, offset, len } => {
+                println!("[WARN] {} missing {} bytes at offset {}", key, len, offset);
+            }
+            StreamEvent::Closed { key, reason, stats } => {
+                println!(
+                    "[INFO] {} closed ({}) delivered={} gaps={} retransmitted={} overlapped={} conflicting={}",
+                    key,
+                    reason,
+                    stats.delivered,
+                    stats.gap_bytes,
+                    stats.retransmitted,
+                    stats.overlapped,
+                    stats.conflicting
+                );
+            }
         }
     }
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+pub fn main() -> Result<(), Box<dyn std::error::Error>> {
     let interface_name = "en0";
 
     let interfaces = datalink::interfaces();
@@ -57,48 +633,169 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     println!("[INFO] listening on interface: {}", interface.name);
 
-    let mut config = datalink::Config::default();
-    config.read_timeout = None;
+    // timeout 검사를 위해 조용할 때도 한 번씩 깨어난다
+    let config = datalink::Config {
+        read_timeout: Some(Duration::from_secs(1)),
+        ..Default::default()
+    };
 
     let (_, mut rx) = match datalink::channel(&interface, config)? {
         Ethernet(tx, rx) => (tx, rx),
         _ => return Err("unsupported channel".into()),
     };
 
-    let mut streams: HashMap<FlowKey, TcpStream> = HashMap::new();
+    let mut reassembler = Reassembler::new(ReassemblyConfig::default());
+    let mut events = Vec::new();
+    let mut last_expire = Instant::now();
 
     loop {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+
         match rx.next() {
             Ok(packet) => {
-                if let Some(eth) = EthernetPacket::new(packet) {
-                    if eth.get_ethertype() == EtherTypes::Ipv4 {
-                        if let Some(ipv4) = Ipv4Packet::new(eth.payload()) {
-                            if ipv4.get_next_level_protocol() == IpNextHeaderProtocols::Tcp {
-                                if let Some(tcp) = TcpPacket::new(ipv4.payload()) {
-                                    let payload = tcp.payload();
-                                    let seq = tcp.get_sequence();
-
-                                    let key = FlowKey {
-                                        src_ip: ipv4.get_source().to_string(),
-                                        src_port: tcp.get_source(),
-                                        dst_ip: ipv4.get_destination().to_string(),
-                                        dst_port: tcp.get_destination(),
-                                    };
-
-                                    let stream = streams
-                                        .entry(key)
-                                        .or_insert_with(|| TcpStream::new(seq));
-
-                                    stream.push(seq, payload);
-                                }
-                            }
-                        }
-                    }
+                if let Some(segment) = parse_segment(packet, now) {
+                    reassembler.process(&segment, &mut events);
                 }
             }
+            Err(e) if e.kind() == std::io::ErrorKind::TimedOut => {}
             Err(e) => {
                 eprintln!("read error: {}", e);
             }
         }
+
+        if last_expire.elapsed() >= Duration::from_secs(1) {
+            reassembler.expire(now, &mut events);
+            last_expire = Instant::now();
+        }
+
+        print_events(&mut events);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key() -> FlowKey {
+        FlowKey {
+            src: "10.0.0.1:40000".parse().unwrap(),
+            dst: "10.0.0.2:80".parse().unwrap(),
+        }
     }
-}
\ No newline at end of file
+
+    fn segment(seq: u32, flags: u8, payload: &[u8]) -> TcpSegment<'_> {
+        TcpSegment {
+            key: key(),
+            seq,
+            flags,
+            payload,
+            timestamp: Duration::from_secs(1),
+        }
+    }
+
+    // 내보낸 Data를 이어 붙이고 Closed의 통계를 돌려준다
+    fn run(policy: OverlapPolicy, segments: &[TcpSegment<'_>]) -> (Vec<u8>, StreamStats) {
+        let mut reassembler = Reassembler::new(ReassemblyConfig {
+            overlap: policy,
+            ..ReassemblyConfig::default()
+        });
+        let mut events = Vec::new();
+        for segment in segments {
+            reassembler.process(segment, &mut events);
+        }
+        reassembler.finish(&mut events);
+
+        let mut stream = Vec::new();
+        let mut stats = None;
+        for event in events {
+            match event {
+                StreamEvent::Data { offset, data, .. } => {
+                    assert_eq!(offset, stream.len() as u64);
+                    stream.extend_from_slice(&data);
+                }
+                StreamEvent::Gap { offset, len, .. } => panic!("unexpected gap {}+{}", offset, len),
+                StreamEvent::Closed { stats: s, .. } => stats = Some(s),
+            }
+        }
+        (stream, stats.expect("stream was never closed"))
+    }
+
+    #[test]
+    fn retransmit_starting_before_the_first_byte_is_trimmed() {
+        // SYN이 차지한 seq부터 시작하는 재전송. offset이 -1이다
+        let isn = 1000;
+        let (stream, stats) = run(
+            OverlapPolicy::First,
+            &[segment(isn, TcpFlags::SYN, b""), segment(isn, 0, b"xab")],
+        );
+        assert_eq!(stream, b"ab");
+        assert_eq!(stats.retransmitted, 1);
+    }
+
+    #[test]
+    fn sequence_numbers_wrap_around() {
+        let isn = u32::MAX - 2;
+        // 첫 segment가 u32 끝을 넘어가고, 둘째가 먼저 도착한다
+        let (stream, stats) = run(
+            OverlapPolicy::First,
+            &[
+                segment(isn, TcpFlags::SYN, b""),
+                segment(1, 0, b"def"),
+                segment(u32::MAX - 1, 0, b"abc"),
+                segment(4, 0, b"ghi"),
+            ],
+        );
+        assert_eq!(stream, b"abcdefghi");
+        assert_eq!(stats.out_of_order, 1);
+    }
+
+    #[test]
+    fn offsets_keep_growing_past_four_gigabytes() {
+        let mut stream = HalfStream::new(0, Duration::ZERO);
+        stream.next_offset = (1u64 << 32) + 10;
+        // 펼친 offset은 u32로 잘리지 않는다
+        assert_eq!(stream.offset_of(11), (1i64 << 32) + 10);
+        assert_eq!(stream.offset_of(1), (1i64 << 32));
+    }
+
+    // 0..8 중 4..8을 먼저 "XXXX"로, 그 뒤 2..8을 "cdefgh"로, 마지막에 0..2를 받는다
+    fn conflicting_segments() -> Vec<TcpSegment<'static>> {
+        vec![
+            segment(0, TcpFlags::SYN, b""),
+            segment(5, 0, b"XXXX"),
+            segment(3, 0, b"cdefgh"),
+            segment(1, 0, b"ab"),
+        ]
+    }
+
+    #[test]
+    fn first_policy_keeps_bytes_received_first() {
+        let (stream, stats) = run(OverlapPolicy::First, &conflicting_segments());
+        assert_eq!(stream, b"abcdXXXX");
+        assert_eq!(stats.overlapped, 4);
+        assert_eq!(stats.conflicting, 4);
+    }
+
+    #[test]
+    fn last_policy_overwrites_with_bytes_received_last() {
+        let (stream, stats) = run(OverlapPolicy::Last, &conflicting_segments());
+        assert_eq!(stream, b"abcdefgh");
+        assert_eq!(stats.overlapped, 4);
+        assert_eq!(stats.conflicting, 4);
+    }
+
+    #[test]
+    fn last_policy_keeps_the_uncovered_ends_of_old_segments() {
+        let (stream, stats) = run(
+            OverlapPolicy::Last,
+            &[
+                segment(0, TcpFlags::SYN, b""),
+                segment(3, 0, b"cdefgh"),
+                segment(5, 0, b"XY"),
+                segment(1, 0, b"ab"),
+            ],
+        );
+        assert_eq!(stream, b"abcdXYgh");
+        assert_eq!(stats.conflicting, 2);
+    }
+}
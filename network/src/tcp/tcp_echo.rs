@@ -1,8 +1,14 @@
 
-use tokio::io::{AsyncReadExt,AsyncWriteExt};
+use tokio::io::{AsyncRead,AsyncReadExt,AsyncWrite,AsyncWriteExt};
 use tokio::net::{TcpListener,TcpStream};
 
-async fn handle_client(mut socket:TcpStream,addr:std::net::SocketAddr){
+use super::tls;
+
+// 평문 TcpStream과 TLS 스트림 둘 다 받는다
+async fn handle_client<S>(mut socket:S,addr:std::net::SocketAddr)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
     println!("[SERVER] Client connected: {}", addr);
     
     let mut buffer = [0u8;1024];
@@ -67,6 +73,74 @@ async fn tcp_client() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     Ok(())
 }
 
+// ==================== TLS ====================
+
+// 기본 인증서는 localhost, SNI가 echo.local이면 별도 인증서. 클라이언트 인증서 필수 (mTLS)
+async fn tcp_echo_tls_server(acceptor: tokio_rustls::TlsAcceptor) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let listener = TcpListener::bind("127.0.0.1:8443").await?;
+
+    println!("[SERVER] TLS echo server listening on 127.0.0.1:8443");
+
+    tls::serve(listener, acceptor, handle_client).await
+}
+
+async fn tcp_tls_client(connector: &tokio_rustls::TlsConnector, server_name: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut stream = tls::connect(connector, "127.0.0.1:8443", server_name).await?;
+
+    println!("[CLIENT] TLS connected as {}", server_name);
+
+    let message = format!("Hello TLS Echo Server via {}!", server_name);
+    stream.write_all(message.as_bytes()).await?;
+
+    let mut buffer = [0u8; 1024];
+    let n = stream.read(&mut buffer).await?;
+
+    println!("[CLIENT] Received Echo: {}", String::from_utf8_lossy(&buffer[..n]));
+
+    // close_notify를 보내야 서버가 잘린 연결로 보지 않는다
+    stream.shutdown().await?;
+
+    Ok(())
+}
+
+#[tokio::main]
+pub async fn tls_main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    println!("Starting TLS Echo Example\n");
+
+    let ca = tls::LocalCa::new("network local CA")?;
+
+    let acceptor = tls::ServerTls::new()
+        .identity(ca.issue_server(&["localhost"])?)
+        .sni("echo.local", ca.issue_server(&["echo.local"])?)
+        .client_auth(tls::ClientAuth::Required(ca.roots()?))
+        .build()?;
+
+    tokio::spawn(async move {
+        if let Err(e) = tcp_echo_tls_server(acceptor).await {
+            println!("[SERVER] Fatal error: {}", e);
+        }
+    });
+
+    tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+
+    let connector = tls::ClientTls::new()
+        .root(ca.cert())?
+        .identity(ca.issue_client("echo-client")?)
+        .build()?;
+
+    tcp_tls_client(&connector, "localhost").await?;
+    tcp_tls_client(&connector, "echo.local").await?;
+
+    // 클라이언트 인증서가 없으면 핸드셰이크에서 거절된다
+    let anonymous = tls::ClientTls::new().root(ca.cert())?.build()?;
+    if let Err(e) = tcp_tls_client(&anonymous, "localhost").await {
+        println!("[CLIENT] Rejected without client certificate: {}", e);
+    }
+
+    tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+
+    Ok(())
+}
 
 #[tokio::main]
 
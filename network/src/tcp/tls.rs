@@ -0,0 +1,801 @@
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, ServerName};
+use rustls::server::{ClientHello, ResolvesServerCert, WebPkiClientVerifier};
+use rustls::sign::CertifiedKey;
+use rustls::{ClientConfig, RootCertStore, ServerConfig};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::timeout;
+use tokio_rustls::{client, server, TlsAcceptor, TlsConnector};
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+// tcp/ 아래 서버들이 같이 쓰는 TLS 계층.
+// 핸드셰이크만 여기서 끝내고, 나머지는 평문 TcpStream과 똑같이 AsyncRead/AsyncWrite로 다룬다.
+
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// quinn/tungstenite가 aws-lc-rs도 같이 켜기 때문에 기본 provider 자동 선택에 기대지 않고 ring을 명시한다
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(rustls::crypto::ring::default_provider())
+}
+
+// ==================== CERTIFICATES ====================
+
+pub fn load_certs(
+    path: impl AsRef<Path>,
+) -> Result<Vec<CertificateDer<'static>>, Box<dyn std::error::Error + Send + Sync>> {
+    let path = path.as_ref();
+    let certs = CertificateDer::pem_file_iter(path)?.collect::<Result<Vec<_>, _>>()?;
+
+    if certs.is_empty() {
+        return Err(format!("no certificates in {}", path.display()).into());
+    }
+
+    Ok(certs)
+}
+
+// PKCS#1, PKCS#8, SEC1 중 파일에 먼저 나오는 키를 쓴다
+pub fn load_private_key(
+    path: impl AsRef<Path>,
+) -> Result<PrivateKeyDer<'static>, Box<dyn std::error::Error + Send + Sync>> {
+    let path = path.as_ref();
+    PrivateKeyDer::from_pem_file(path)
+        .map_err(|e| format!("failed to load private key from {}: {}", path.display(), e).into())
+}
+
+pub fn load_roots(
+    path: impl AsRef<Path>,
+) -> Result<RootCertStore, Box<dyn std::error::Error + Send + Sync>> {
+    let mut roots = RootCertStore::empty();
+    for cert in load_certs(path)? {
+        roots.add(cert)?;
+    }
+    Ok(roots)
+}
+
+// 인증서 체인 + 개인키
+pub struct Identity {
+    pub cert_chain: Vec<CertificateDer<'static>>,
+    pub key: PrivateKeyDer<'static>,
+}
+
+impl Clone for Identity {
+    fn clone(&self) -> Self {
+        Self {
+            cert_chain: self.cert_chain.clone(),
+            key: self.key.clone_key(),
+        }
+    }
+}
+
+impl Identity {
+    pub fn from_pem_files(
+        cert_path: impl AsRef<Path>,
+        key_path: impl AsRef<Path>,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Self {
+            cert_chain: load_certs(cert_path)?,
+            key: load_private_key(key_path)?,
+        })
+    }
+
+    // 로컬 테스트용 self-signed 인증서. 클라이언트는 cert_chain[0]을 root로 넣어야 한다
+    pub fn self_signed(names: &[&str]) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let names: Vec<String> = names.iter().map(|n| n.to_string()).collect();
+        let cert = rcgen::generate_simple_self_signed(names)?;
+
+        Ok(Self {
+            cert_chain: vec![cert.cert.der().clone()],
+            key: PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(cert.signing_key.serialize_der())),
+        })
+    }
+
+    fn certified_key(
+        &self,
+        provider: &CryptoProvider,
+    ) -> Result<Arc<CertifiedKey>, Box<dyn std::error::Error + Send + Sync>> {
+        let signing_key = provider.key_provider.load_private_key(self.key.clone_key())?;
+        Ok(Arc::new(CertifiedKey::new(self.cert_chain.clone(), signing_key)))
+    }
+}
+
+// mTLS 테스트용 로컬 CA. 서버/클라이언트 인증서를 같은 root로 발급한다
+pub struct LocalCa {
+    cert: CertificateDer<'static>,
+    issuer: rcgen::Issuer<'static, rcgen::KeyPair>,
+}
+
+impl LocalCa {
+    pub fn new(common_name: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let mut params = rcgen::CertificateParams::new(Vec::<String>::new())?;
+        params
+            .distinguished_name
+            .push(rcgen::DnType::CommonName, common_name);
+        params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        params.key_usages = vec![
+            rcgen::KeyUsagePurpose::KeyCertSign,
+            rcgen::KeyUsagePurpose::CrlSign,
+            rcgen::KeyUsagePurpose::DigitalSignature,
+        ];
+
+        let key = rcgen::KeyPair::generate()?;
+        let cert = params.self_signed(&key)?;
+
+        Ok(Self {
+            cert: cert.der().clone(),
+            issuer: rcgen::Issuer::new(params, key),
+        })
+    }
+
+    pub fn cert(&self) -> CertificateDer<'static> {
+        self.cert.clone()
+    }
+
+    pub fn roots(&self) -> Result<RootCertStore, Box<dyn std::error::Error + Send + Sync>> {
+        let mut roots = RootCertStore::empty();
+        roots.add(self.cert())?;
+        Ok(roots)
+    }
+
+    pub fn issue_server(
+        &self,
+        names: &[&str],
+    ) -> Result<Identity, Box<dyn std::error::Error + Send + Sync>> {
+        let names: Vec<String> = names.iter().map(|n| n.to_string()).collect();
+        let mut params = rcgen::CertificateParams::new(names.clone())?;
+        params
+            .distinguished_name
+            .push(rcgen::DnType::CommonName, names.first().cloned().unwrap_or_default());
+        params.extended_key_usages = vec![rcgen::ExtendedKeyUsagePurpose::ServerAuth];
+        self.issue(params)
+    }
+
+    pub fn issue_client(
+        &self,
+        common_name: &str,
+    ) -> Result<Identity, Box<dyn std::error::Error + Send + Sync>> {
+        let mut params = rcgen::CertificateParams::new(Vec::<String>::new())?;
+        params
+            .distinguished_name
+            .push(rcgen::DnType::CommonName, common_name);
+        params.extended_key_usages = vec![rcgen::ExtendedKeyUsagePurpose::ClientAuth];
+        self.issue(params)
+    }
+
+    fn issue(
+        &self,
+        params: rcgen::CertificateParams,
+    ) -> Result<Identity, Box<dyn std::error::Error + Send + Sync>> {
+        let key = rcgen::KeyPair::generate()?;
+        let cert = params.signed_by(&key, &self.issuer)?;
+
+        Ok(Identity {
+            // 체인에 CA도 붙여 두면 상대가 중간 인증서 없이 검증할 수 있다
+            cert_chain: vec![cert.der().clone(), self.cert()],
+            key: PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key.serialize_der())),
+        })
+    }
+}
+
+// ==================== SERVER ====================
+
+pub enum ClientAuth {
+    None,
+    // 인증서가 있으면 검증하고, 없으면 익명으로 통과
+    Optional(RootCertStore),
+    Required(RootCertStore),
+}
+
+// SNI로 인증서를 고른다. 맞는 이름이 없거나 SNI가 없으면 기본 인증서
+#[derive(Debug)]
+struct SniResolver {
+    by_name: HashMap<String, Arc<CertifiedKey>>,
+    default: Option<Arc<CertifiedKey>>,
+}
+
+impl SniResolver {
+    fn lookup(&self, name: &str) -> Option<Arc<CertifiedKey>> {
+        let name = name.to_ascii_lowercase();
+        if let Some(key) = self.by_name.get(&name) {
+            return Some(key.clone());
+        }
+
+        // api.example.com -> *.example.com
+        let (_, parent) = name.split_once('.')?;
+        self.by_name.get(&format!("*.{}", parent)).cloned()
+    }
+}
+
+impl ResolvesServerCert for SniResolver {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        client_hello
+            .server_name()
+            .and_then(|name| self.lookup(name))
+            .or_else(|| self.default.clone())
+    }
+}
+
+pub struct ServerTls {
+    default: Option<Identity>,
+    sni: Vec<(String, Identity)>,
+    client_auth: ClientAuth,
+    alpn: Vec<Vec<u8>>,
+}
+
+impl Default for ServerTls {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ServerTls {
+    pub fn new() -> Self {
+        Self {
+            default: None,
+            sni: Vec::new(),
+            client_auth: ClientAuth::None,
+            alpn: Vec::new(),
+        }
+    }
+
+    pub fn identity(mut self, identity: Identity) -> Self {
+        self.default = Some(identity);
+        self
+    }
+
+    // "*.example.com" 같은 wildcard 이름도 된다
+    pub fn sni(mut self, name: &str, identity: Identity) -> Self {
+        self.sni.push((name.to_ascii_lowercase(), identity));
+        self
+    }
+
+    pub fn client_auth(mut self, client_auth: ClientAuth) -> Self {
+        self.client_auth = client_auth;
+        self
+    }
+
+    pub fn alpn(mut self, protocol: &[u8]) -> Self {
+        self.alpn.push(protocol.to_vec());
+        self
+    }
+
+    pub fn build_config(self) -> Result<ServerConfig, Box<dyn std::error::Error + Send + Sync>> {
+        if self.default.is_none() && self.sni.is_empty() {
+            return Err("server TLS needs at least one certificate".into());
+        }
+
+        let provider = provider();
+
+        let mut by_name = HashMap::new();
+        for (name, identity) in &self.sni {
+            by_name.insert(name.clone(), identity.certified_key(&provider)?);
+        }
+
+        let default = match &self.default {
+            Some(identity) => Some(identity.certified_key(&provider)?),
+            None => None,
+        };
+
+        let builder = ServerConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()?;
+
+        let builder = match self.client_auth {
+            ClientAuth::None => builder.with_no_client_auth(),
+            ClientAuth::Optional(roots) => {
+                let verifier =
+                    WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider.clone())
+                        .allow_unauthenticated()
+                        .build()?;
+                builder.with_client_cert_verifier(verifier)
+            }
+            ClientAuth::Required(roots) => {
+                let verifier =
+                    WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider.clone())
+                        .build()?;
+                builder.with_client_cert_verifier(verifier)
+            }
+        };
+
+        let mut config = builder.with_cert_resolver(Arc::new(SniResolver { by_name, default }));
+        config.alpn_protocols = self.alpn;
+
+        Ok(config)
+    }
+
+    pub fn build(self) -> Result<TlsAcceptor, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(TlsAcceptor::from(Arc::new(self.build_config()?)))
+    }
+}
+
+// 환경 변수로 서버 TLS를 켠다. 아무것도 없으면 None (평문)
+//   TLS_CERT, TLS_KEY      기본 인증서 PEM
+//   TLS_SNI                "host=cert.pem,key.pem;host2=cert2.pem,key2.pem"
+//   TLS_CLIENT_CA          있으면 이 CA로 클라이언트 인증서 요구 (mTLS)
+//   TLS_CLIENT_OPTIONAL    1이면 클라이언트 인증서는 선택
+//   TLS_SELF_SIGNED        "localhost,chat.local" 이름으로 self-signed 생성 (로컬 테스트)
+pub fn acceptor_from_env() -> Result<Option<TlsAcceptor>, Box<dyn std::error::Error + Send + Sync>>
+{
+    let mut tls = ServerTls::new();
+    let mut enabled = false;
+
+    if let (Ok(cert), Ok(key)) = (std::env::var("TLS_CERT"), std::env::var("TLS_KEY")) {
+        tls = tls.identity(Identity::from_pem_files(cert, key)?);
+        enabled = true;
+    } else if let Ok(names) = std::env::var("TLS_SELF_SIGNED") {
+        let names: Vec<&str> = names.split(',').map(str::trim).collect();
+        tls = tls.identity(Identity::self_signed(&names)?);
+        println!("[INFO] Generated self-signed certificate for {:?}", names);
+        enabled = true;
+    }
+
+    if let Ok(entries) = std::env::var("TLS_SNI") {
+        for entry in entries.split(';').filter(|e| !e.trim().is_empty()) {
+            let (name, files) = entry
+                .split_once('=')
+                .ok_or_else(|| format!("invalid TLS_SNI entry: {}", entry))?;
+            let (cert, key) = files
+                .split_once(',')
+                .ok_or_else(|| format!("invalid TLS_SNI entry: {}", entry))?;
+            tls = tls.sni(name.trim(), Identity::from_pem_files(cert.trim(), key.trim())?);
+            enabled = true;
+        }
+    }
+
+    if !enabled {
+        return Ok(None);
+    }
+
+    if let Ok(ca) = std::env::var("TLS_CLIENT_CA") {
+        let roots = load_roots(ca)?;
+        tls = if std::env::var("TLS_CLIENT_OPTIONAL").as_deref() == Ok("1") {
+            tls.client_auth(ClientAuth::Optional(roots))
+        } else {
+            tls.client_auth(ClientAuth::Required(roots))
+        };
+    }
+
+    Ok(Some(tls.build()?))
+}
+
+pub async fn accept(
+    acceptor: &TlsAcceptor,
+    socket: TcpStream,
+    addr: SocketAddr,
+) -> std::io::Result<server::TlsStream<TcpStream>> {
+    let stream = match timeout(HANDSHAKE_TIMEOUT, acceptor.accept(socket)).await {
+        Ok(result) => result?,
+        Err(_) => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                "TLS handshake timed out",
+            ))
+        }
+    };
+
+    let (_, conn) = stream.get_ref();
+    println!(
+        "[SERVER] TLS handshake with {}: sni={} version={:?} client_certs={}",
+        addr,
+        conn.server_name().unwrap_or("-"),
+        conn.protocol_version(),
+        conn.peer_certificates().map_or(0, |certs| certs.len())
+    );
+
+    Ok(stream)
+}
+
+// accept loop. 핸드셰이크는 연결마다 task 안에서 해서 느린 클라이언트가 accept를 막지 않게 한다
+pub async fn serve<F, Fut>(
+    listener: TcpListener,
+    acceptor: TlsAcceptor,
+    handler: F,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>>
+where
+    F: Fn(server::TlsStream<TcpStream>, SocketAddr) -> Fut + Clone + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    loop {
+        let (socket, addr) = listener.accept().await?;
+        let acceptor = acceptor.clone();
+        let handler = handler.clone();
+
+        tokio::spawn(async move {
+            match accept(&acceptor, socket, addr).await {
+                Ok(stream) => handler(stream, addr).await,
+                Err(e) => println!("[SERVER] TLS handshake failed for {}: {}", addr, e),
+            }
+        });
+    }
+}
+
+// ==================== CLIENT ====================
+
+pub struct ClientTls {
+    roots: RootCertStore,
+    identity: Option<Identity>,
+    alpn: Vec<Vec<u8>>,
+}
+
+impl Default for ClientTls {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ClientTls {
+    pub fn new() -> Self {
+        Self {
+            roots: RootCertStore::empty(),
+            identity: None,
+            alpn: Vec::new(),
+        }
+    }
+
+    pub fn root(mut self, cert: CertificateDer<'static>) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        self.roots.add(cert)?;
+        Ok(self)
+    }
+
+    pub fn roots(mut self, roots: RootCertStore) -> Self {
+        self.roots.extend(roots.roots);
+        self
+    }
+
+    // mTLS 서버에 보낼 클라이언트 인증서
+    pub fn identity(mut self, identity: Identity) -> Self {
+        self.identity = Some(identity);
+        self
+    }
+
+    pub fn alpn(mut self, protocol: &[u8]) -> Self {
+        self.alpn.push(protocol.to_vec());
+        self
+    }
+
+    pub fn build(self) -> Result<TlsConnector, Box<dyn std::error::Error + Send + Sync>> {
+        let builder = ClientConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()?
+            .with_root_certificates(self.roots);
+
+        let mut config = match self.identity {
+            Some(identity) => builder.with_client_auth_cert(identity.cert_chain, identity.key)?,
+            None => builder.with_no_client_auth(),
+        };
+        config.alpn_protocols = self.alpn;
+
+        Ok(TlsConnector::from(Arc::new(config)))
+    }
+}
+
+pub async fn connect(
+    connector: &TlsConnector,
+    addr: &str,
+    server_name: &str,
+) -> Result<client::TlsStream<TcpStream>, Box<dyn std::error::Error + Send + Sync>> {
+    let server_name = ServerName::try_from(server_name.to_string())?;
+    let socket = TcpStream::connect(addr).await?;
+
+    match timeout(HANDSHAKE_TIMEOUT, connector.connect(server_name, socket)).await {
+        Ok(result) => Ok(result?),
+        Err(_) => Err("TLS handshake timed out".into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::Engine;
+    use std::path::PathBuf;
+    use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt, DuplexStream};
+
+    type Handshake = (
+        std::io::Result<server::TlsStream<DuplexStream>>,
+        std::io::Result<client::TlsStream<DuplexStream>>,
+    );
+
+    // 메모리 안에서 양쪽 핸드셰이크를 동시에 돌린다
+    async fn handshake(acceptor: &TlsAcceptor, connector: &TlsConnector, name: &str) -> Handshake {
+        let (client_io, server_io) = duplex(64 * 1024);
+        let name = ServerName::try_from(name.to_string()).unwrap();
+
+        let (server, client) = tokio::join!(
+            acceptor.accept(server_io),
+            connector.connect(name, client_io)
+        );
+        (server, client)
+    }
+
+    // 서버가 보낸 leaf 인증서
+    fn served_leaf(client: &client::TlsStream<DuplexStream>) -> CertificateDer<'static> {
+        let (_, conn) = client.get_ref();
+        conn.peer_certificates().unwrap()[0].clone().into_owned()
+    }
+
+    fn client_certs(server: &server::TlsStream<DuplexStream>) -> usize {
+        let (_, conn) = server.get_ref();
+        conn.peer_certificates().map_or(0, |certs| certs.len())
+    }
+
+    fn pem(label: &str, der: &[u8]) -> String {
+        let body = base64::engine::general_purpose::STANDARD.encode(der);
+        let mut out = format!("-----BEGIN {}-----\n", label);
+        for line in body.as_bytes().chunks(64) {
+            out.push_str(std::str::from_utf8(line).unwrap());
+            out.push('\n');
+        }
+        out.push_str(&format!("-----END {}-----\n", label));
+        out
+    }
+
+    // 테스트마다 겹치지 않는 임시 디렉터리
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("tls-test-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // identity를 cert.pem / key.pem으로 쓴다
+    fn write_identity(dir: &Path, name: &str, identity: &Identity) -> (PathBuf, PathBuf) {
+        let cert_path = dir.join(format!("{}.crt", name));
+        let key_path = dir.join(format!("{}.key", name));
+        let chain: String = identity.cert_chain.iter().map(|c| pem("CERTIFICATE", c)).collect();
+        std::fs::write(&cert_path, chain).unwrap();
+        std::fs::write(&key_path, pem("PRIVATE KEY", identity.key.secret_der())).unwrap();
+        (cert_path, key_path)
+    }
+
+    #[tokio::test]
+    async fn sni_picks_exact_then_wildcard_then_default() {
+        let ca = LocalCa::new("sni test ca").unwrap();
+        let exact = ca.issue_server(&["api.example.test"]).unwrap();
+        let wildcard = ca.issue_server(&["*.example.test"]).unwrap();
+        let fallback = ca.issue_server(&["default.test", "other.test"]).unwrap();
+
+        let acceptor = ServerTls::new()
+            .sni("API.example.test", exact.clone())
+            .sni("*.example.test", wildcard.clone())
+            .identity(fallback.clone())
+            .build()
+            .unwrap();
+        let connector = ClientTls::new().roots(ca.roots().unwrap()).build().unwrap();
+
+        for (name, expected) in [
+            ("api.example.test", &exact),
+            ("www.example.test", &wildcard),
+            ("other.test", &fallback),
+        ] {
+            let (server, client) = handshake(&acceptor, &connector, name).await;
+            server.unwrap();
+            assert_eq!(served_leaf(&client.unwrap()), expected.cert_chain[0], "sni={}", name);
+        }
+    }
+
+    #[test]
+    fn sni_lookup_matches_one_label_wildcards_only() {
+        let provider = provider();
+        let ca = LocalCa::new("sni lookup ca").unwrap();
+        let key = ca
+            .issue_server(&["*.example.test"])
+            .unwrap()
+            .certified_key(&provider)
+            .unwrap();
+
+        let resolver = SniResolver {
+            by_name: HashMap::from([("*.example.test".to_string(), key)]),
+            default: None,
+        };
+
+        assert!(resolver.lookup("WWW.Example.Test").is_some());
+        assert!(resolver.lookup("a.b.example.test").is_none());
+        assert!(resolver.lookup("example.test").is_none());
+        assert!(resolver.lookup("localhost").is_none());
+    }
+
+    #[tokio::test]
+    async fn sni_only_server_without_default_rejects_unknown_names() {
+        let ca = LocalCa::new("no default ca").unwrap();
+        let acceptor = ServerTls::new()
+            .sni("known.test", ca.issue_server(&["known.test"]).unwrap())
+            .build()
+            .unwrap();
+        let connector = ClientTls::new().roots(ca.roots().unwrap()).build().unwrap();
+
+        let (server, client) = handshake(&acceptor, &connector, "unknown.test").await;
+        assert!(server.is_err());
+        assert!(client.is_err());
+
+        assert!(ServerTls::new().build().is_err());
+    }
+
+    #[tokio::test]
+    async fn required_client_auth_needs_a_cert_from_the_trusted_ca() {
+        let ca = LocalCa::new("mtls ca").unwrap();
+        let stranger = LocalCa::new("other ca").unwrap();
+
+        let acceptor = ServerTls::new()
+            .identity(ca.issue_server(&["mtls.test"]).unwrap())
+            .client_auth(ClientAuth::Required(ca.roots().unwrap()))
+            .build()
+            .unwrap();
+        let client = |identity: Option<Identity>| {
+            let tls = ClientTls::new().roots(ca.roots().unwrap());
+            match identity {
+                Some(identity) => tls.identity(identity),
+                None => tls,
+            }
+            .build()
+            .unwrap()
+        };
+
+        let trusted = client(Some(ca.issue_client("alice").unwrap()));
+        let (server, client_side) = handshake(&acceptor, &trusted, "mtls.test").await;
+        assert_eq!(client_certs(&server.unwrap()), 2);
+        client_side.unwrap();
+
+        for connector in [client(None), client(Some(stranger.issue_client("mallory").unwrap()))] {
+            let (server, _) = handshake(&acceptor, &connector, "mtls.test").await;
+            assert!(server.is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn optional_client_auth_allows_anonymous_but_checks_presented_certs() {
+        let ca = LocalCa::new("optional ca").unwrap();
+        let stranger = LocalCa::new("other ca").unwrap();
+
+        let acceptor = ServerTls::new()
+            .identity(ca.issue_server(&["opt.test"]).unwrap())
+            .client_auth(ClientAuth::Optional(ca.roots().unwrap()))
+            .build()
+            .unwrap();
+        let roots = || ClientTls::new().roots(ca.roots().unwrap());
+
+        let (server, client) = handshake(&acceptor, &roots().build().unwrap(), "opt.test").await;
+        assert_eq!(client_certs(&server.unwrap()), 0);
+        client.unwrap();
+
+        let trusted = roots().identity(ca.issue_client("bob").unwrap()).build().unwrap();
+        let (server, _) = handshake(&acceptor, &trusted, "opt.test").await;
+        assert_eq!(client_certs(&server.unwrap()), 2);
+
+        let untrusted = roots()
+            .identity(stranger.issue_client("mallory").unwrap())
+            .build()
+            .unwrap();
+        let (server, _) = handshake(&acceptor, &untrusted, "opt.test").await;
+        assert!(server.is_err());
+    }
+
+    #[test]
+    fn pem_loading_reports_bad_files() {
+        let dir = temp_dir("pem");
+        let ca = LocalCa::new("pem ca").unwrap();
+        let (cert_path, key_path) = write_identity(&dir, "server", &ca.issue_server(&["pem.test"]).unwrap());
+
+        let identity = Identity::from_pem_files(&cert_path, &key_path).unwrap();
+        assert_eq!(identity.cert_chain.len(), 2);
+        assert_eq!(load_roots(&cert_path).unwrap().len(), 2);
+
+        assert!(load_certs(dir.join("missing.crt")).is_err());
+
+        // 키만 있는 파일에는 인증서가 없다
+        let err = load_certs(&key_path).unwrap_err().to_string();
+        assert!(err.starts_with("no certificates in"), "{}", err);
+
+        let err = load_private_key(&cert_path).unwrap_err().to_string();
+        assert!(err.starts_with("failed to load private key"), "{}", err);
+
+        let garbage = dir.join("garbage.pem");
+        std::fs::write(&garbage, "-----BEGIN CERTIFICATE-----\n!!!\n-----END CERTIFICATE-----\n").unwrap();
+        assert!(load_certs(&garbage).is_err());
+        assert!(Identity::from_pem_files(&garbage, &key_path).is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    // TLS_* 환경 변수는 프로세스 전체가 공유하므로 한 테스트 안에서 순서대로 확인한다
+    #[tokio::test]
+    async fn acceptor_from_env_reads_tls_variables() {
+        const VARS: [&str; 6] = [
+            "TLS_CERT",
+            "TLS_KEY",
+            "TLS_SNI",
+            "TLS_CLIENT_CA",
+            "TLS_CLIENT_OPTIONAL",
+            "TLS_SELF_SIGNED",
+        ];
+        let reset = || VARS.iter().for_each(|var| std::env::remove_var(var));
+
+        let dir = temp_dir("env");
+        let ca = LocalCa::new("env ca").unwrap();
+        let (cert, key) = write_identity(&dir, "default", &ca.issue_server(&["env.test"]).unwrap());
+        let sni = ca.issue_server(&["sni.test"]).unwrap();
+        let (sni_cert, sni_key) = write_identity(&dir, "sni", &sni);
+        let ca_path = dir.join("ca.crt");
+        std::fs::write(&ca_path, pem("CERTIFICATE", &ca.cert())).unwrap();
+
+        reset();
+        assert!(acceptor_from_env().unwrap().is_none());
+
+        // 기본 인증서 + SNI 항목
+        std::env::set_var("TLS_CERT", &cert);
+        std::env::set_var("TLS_KEY", &key);
+        std::env::set_var(
+            "TLS_SNI",
+            format!(" sni.test = {} , {} ;", sni_cert.display(), sni_key.display()),
+        );
+        let acceptor = acceptor_from_env().unwrap().unwrap();
+        let connector = ClientTls::new().roots(ca.roots().unwrap()).build().unwrap();
+        let (server, client) = handshake(&acceptor, &connector, "sni.test").await;
+        server.unwrap();
+        assert_eq!(served_leaf(&client.unwrap()), sni.cert_chain[0]);
+        let (server, _) = handshake(&acceptor, &connector, "env.test").await;
+        server.unwrap();
+
+        // TLS_CLIENT_CA만 있으면 클라이언트 인증서 필수, TLS_CLIENT_OPTIONAL=1이면 선택
+        std::env::set_var("TLS_CLIENT_CA", &ca_path);
+        let acceptor = acceptor_from_env().unwrap().unwrap();
+        let (server, _) = handshake(&acceptor, &connector, "env.test").await;
+        assert!(server.is_err());
+
+        std::env::set_var("TLS_CLIENT_OPTIONAL", "1");
+        let acceptor = acceptor_from_env().unwrap().unwrap();
+        let (server, _) = handshake(&acceptor, &connector, "env.test").await;
+        assert_eq!(client_certs(&server.unwrap()), 0);
+
+        std::env::set_var("TLS_SNI", "sni.test");
+        assert!(acceptor_from_env().is_err());
+
+        // 인증서 없이 self-signed만
+        reset();
+        std::env::set_var("TLS_SELF_SIGNED", "localhost, chat.local");
+        assert!(acceptor_from_env().unwrap().is_some());
+
+        reset();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn serve_and_connect_over_loopback() {
+        let ca = LocalCa::new("loopback ca").unwrap();
+        let acceptor = ServerTls::new()
+            .identity(ca.issue_server(&["localhost"]).unwrap())
+            .alpn(b"echo")
+            .build()
+            .unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(serve(listener, acceptor, |mut stream, _addr| async move {
+            let mut buf = [0u8; 5];
+            stream.read_exact(&mut buf).await.unwrap();
+            stream.write_all(&buf).await.unwrap();
+            stream.shutdown().await.unwrap();
+        }));
+
+        let connector = ClientTls::new()
+            .root(ca.cert())
+            .unwrap()
+            .alpn(b"echo")
+            .build()
+            .unwrap();
+        let mut stream = connect(&connector, &addr.to_string(), "localhost").await.unwrap();
+        assert_eq!(stream.get_ref().1.alpn_protocol(), Some(&b"echo"[..]));
+
+        stream.write_all(b"hello").await.unwrap();
+        let mut reply = Vec::new();
+        stream.read_to_end(&mut reply).await.unwrap();
+        assert_eq!(reply, b"hello");
+
+        // 이름이 맞지 않으면 클라이언트가 거절한다
+        assert!(connect(&connector, &addr.to_string(), "elsewhere").await.is_err());
+
+        server.abort();
+    }
+}
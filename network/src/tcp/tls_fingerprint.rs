@@ -0,0 +1,804 @@
+use crate::tcp::conversation::{
+    capture_live, read_pcap, Conversation, ConversationHandler, ConversationTracker, Direction,
+};
+use crate::tcp::stream_reassembly::ReassemblyConfig;
+use md5::{Digest, Md5};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::time::Duration;
+
+// 443이 암호화돼 있어도 handshake 첫 메시지는 평문이다.
+// 재조립된 byte stream에서 TLS record를 이어 붙여 ClientHello / ServerHello를 꺼내고
+// SNI, ALPN, 버전, cipher suite, extension을 뽑아 JA3 / JA3S / JA4 fingerprint를 만든다.
+// hello가 여러 segment, 여러 record에 걸쳐 와도 된다 (post-quantum key share는 MTU를 넘는다).
+
+// ==================== CONFIG ====================
+
+const CONTENT_HANDSHAKE: u8 = 22;
+const HANDSHAKE_CLIENT_HELLO: u8 = 1;
+const HANDSHAKE_SERVER_HELLO: u8 = 2;
+
+const EXT_SERVER_NAME: u16 = 0x0000;
+const EXT_SUPPORTED_GROUPS: u16 = 0x000a;
+const EXT_EC_POINT_FORMATS: u16 = 0x000b;
+const EXT_SIGNATURE_ALGORITHMS: u16 = 0x000d;
+const EXT_ALPN: u16 = 0x0010;
+const EXT_SUPPORTED_VERSIONS: u16 = 0x002b;
+
+// record 하나는 2^14 + 암호화 여유분을 넘지 않는다
+const MAX_RECORD: usize = 16384 + 2048;
+// hello를 찾기 전에 이만큼 쌓이면 포기
+const MAX_HANDSHAKE: usize = 64 * 1024;
+
+// ==================== HELLO ====================
+
+#[derive(Debug, Clone, Default)]
+pub struct ClientHello {
+    pub legacy_version: u16,
+    pub cipher_suites: Vec<u16>,
+    // 등장 순서 그대로 (GREASE 포함)
+    pub extensions: Vec<u16>,
+    pub sni: Option<String>,
+    pub alpn: Vec<String>,
+    pub supported_versions: Vec<u16>,
+    pub groups: Vec<u16>,
+    pub ec_point_formats: Vec<u8>,
+    pub signature_algorithms: Vec<u16>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ServerHello {
+    pub legacy_version: u16,
+    pub cipher_suite: u16,
+    pub extensions: Vec<u16>,
+    // TLS 1.3이면 supported_versions에 실제 버전이 들어 있다
+    pub selected_version: Option<u16>,
+    pub alpn: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Hello {
+    Client(ClientHello),
+    Server(ServerHello),
+}
+
+impl ClientHello {
+    // 가장 높은 제안 버전. supported_versions가 없으면 legacy_version
+    pub fn max_version(&self) -> u16 {
+        self.supported_versions
+            .iter()
+            .copied()
+            .filter(|v| !is_grease(*v))
+            .max()
+            .unwrap_or(self.legacy_version)
+    }
+
+    // SSLVersion,Ciphers,Extensions,EllipticCurves,EllipticCurvePointFormats (GREASE 제외)
+    pub fn ja3_string(&self) -> String {
+        format!(
+            "{},{},{},{},{}",
+            self.legacy_version,
+            join_decimal(&self.cipher_suites),
+            join_decimal(&self.extensions),
+            join_decimal(&self.groups),
+            self.ec_point_formats
+                .iter()
+                .map(|f| f.to_string())
+                .collect::<Vec<_>>()
+                .join("-"),
+        )
+    }
+
+    pub fn ja3(&self) -> String {
+        hex(&Md5::digest(self.ja3_string()))
+    }
+
+    // t13d1516h2_<cipher hash>_<extension hash>
+    pub fn ja4(&self) -> String {
+        let ciphers: Vec<u16> = self
+            .cipher_suites
+            .iter()
+            .copied()
+            .filter(|c| !is_grease(*c))
+            .collect();
+        let extensions: Vec<u16> = self
+            .extensions
+            .iter()
+            .copied()
+            .filter(|e| !is_grease(*e))
+            .collect();
+
+        let sni = if self.sni.is_some() { 'd' } else { 'i' };
+
+        let a = format!(
+            "t{}{}{:02}{:02}{}",
+            ja4_version(self.max_version()),
+            sni,
+            ciphers.len().min(99),
+            extensions.len().min(99),
+            ja4_alpn(self.alpn.first().map(String::as_str)),
+        );
+
+        let mut sorted_ciphers = ciphers;
+        sorted_ciphers.sort_unstable();
+
+        // SNI와 ALPN은 a 부분에 이미 반영돼 있어서 뺀다
+        let mut sorted_extensions: Vec<u16> = extensions
+            .into_iter()
+            .filter(|e| *e != EXT_SERVER_NAME && *e != EXT_ALPN)
+            .collect();
+        sorted_extensions.sort_unstable();
+
+        let b = truncated_sha256(&join_hex(&sorted_ciphers));
+
+        let c = if sorted_extensions.is_empty() {
+            truncated_sha256("")
+        } else {
+            let mut input = join_hex(&sorted_extensions);
+            if !self.signature_algorithms.is_empty() {
+                input.push('_');
+                input.push_str(&join_hex(&self.signature_algorithms));
+            }
+            truncated_sha256(&input)
+        };
+
+        format!("{}_{}_{}", a, b, c)
+    }
+}
+
+impl ServerHello {
+    pub fn version(&self) -> u16 {
+        self.selected_version.unwrap_or(self.legacy_version)
+    }
+
+    // SSLVersion,Cipher,Extensions
+    pub fn ja3s_string(&self) -> String {
+        format!(
+            "{},{},{}",
+            self.legacy_version,
+            self.cipher_suite,
+            join_decimal(&self.extensions)
+        )
+    }
+
+    pub fn ja3s(&self) -> String {
+        hex(&Md5::digest(self.ja3s_string()))
+    }
+}
+
+// 0x?a?a 형태의 GREASE 값 (RFC 8701)
+fn is_grease(value: u16) -> bool {
+    value & 0x0f0f == 0x0a0a && value >> 8 == value & 0xff
+}
+
+fn join_decimal(values: &[u16]) -> String {
+    values
+        .iter()
+        .filter(|v| !is_grease(**v))
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+fn join_hex(values: &[u16]) -> String {
+    values
+        .iter()
+        .map(|v| format!("{:04x}", v))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut out, b| {
+        let _ = write!(out, "{:02x}", b);
+        out
+    })
+}
+
+// 입력이 없으면 hash 대신 0으로 채운다
+fn truncated_sha256(input: &str) -> String {
+    if input.is_empty() {
+        return "0".repeat(12);
+    }
+    hex(&Sha256::digest(input))[..12].to_string()
+}
+
+fn ja4_version(version: u16) -> &'static str {
+    match version {
+        0x0304 => "13",
+        0x0303 => "12",
+        0x0302 => "11",
+        0x0301 => "10",
+        0x0300 => "s3",
+        0x0002 => "s2",
+        0xfeff => "d1",
+        0xfefd => "d2",
+        0xfefc => "d3",
+        _ => "00",
+    }
+}
+
+// 첫 ALPN의 처음과 마지막 글자. 영숫자가 아니면 hex 표현의 처음과 마지막 글자
+fn ja4_alpn(alpn: Option<&str>) -> String {
+    let Some(alpn) = alpn.filter(|a| !a.is_empty()) else {
+        return "00".to_string();
+    };
+
+    let bytes = alpn.as_bytes();
+    let first = bytes[0];
+    let last = bytes[bytes.len() - 1];
+
+    if first.is_ascii_alphanumeric() && last.is_ascii_alphanumeric() {
+        format!("{}{}", first as char, last as char)
+    } else {
+        let encoded = hex(bytes);
+        let chars: Vec<char> = encoded.chars().collect();
+        format!("{}{}", chars[0], chars[chars.len() - 1])
+    }
+}
+
+pub fn version_name(version: u16) -> String {
+    match version {
+        0x0304 => "TLS1.3".to_string(),
+        0x0303 => "TLS1.2".to_string(),
+        0x0302 => "TLS1.1".to_string(),
+        0x0301 => "TLS1.0".to_string(),
+        0x0300 => "SSL3.0".to_string(),
+        other => format!("0x{:04x}", other),
+    }
+}
+
+// ==================== PARSER ====================
+
+// 길이 검사를 매번 하지 않도록 Option으로 읽는 cursor
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    fn bytes(&mut self, n: usize) -> Option<&'a [u8]> {
+        let end = self.pos.checked_add(n)?;
+        let slice = self.data.get(self.pos..end)?;
+        self.pos = end;
+        Some(slice)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.bytes(1).map(|b| b[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.bytes(2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    // 1바이트 길이 + 내용
+    fn vec8(&mut self) -> Option<Reader<'a>> {
+        let len = self.u8()? as usize;
+        self.bytes(len).map(Reader::new)
+    }
+
+    // 2바이트 길이 + 내용
+    fn vec16(&mut self) -> Option<Reader<'a>> {
+        let len = self.u16()? as usize;
+        self.bytes(len).map(Reader::new)
+    }
+
+    fn u16_list(mut self) -> Option<Vec<u16>> {
+        let mut values = Vec::new();
+        while !self.is_empty() {
+            values.push(self.u16()?);
+        }
+        Some(values)
+    }
+}
+
+fn parse_client_hello(body: &[u8]) -> Option<ClientHello> {
+    let mut r = Reader::new(body);
+    let mut hello = ClientHello {
+        legacy_version: r.u16()?,
+        ..Default::default()
+    };
+
+    r.bytes(32)?; // random
+    r.vec8()?; // session id
+    hello.cipher_suites = r.vec16()?.u16_list()?;
+    r.vec8()?; // compression methods
+
+    // extension이 없는 오래된 hello도 있다
+    if r.is_empty() {
+        return Some(hello);
+    }
+
+    let mut extensions = r.vec16()?;
+    while !extensions.is_empty() {
+        let kind = extensions.u16()?;
+        let mut data = extensions.vec16()?;
+        hello.extensions.push(kind);
+
+        match kind {
+            EXT_SERVER_NAME => {
+                let mut names = data.vec16()?;
+                while !names.is_empty() {
+                    let name_type = names.u8()?;
+                    let name = names.vec16()?;
+                    if name_type == 0 {
+                        hello.sni = Some(String::from_utf8_lossy(name.data).into_owned());
+                    }
+                }
+            }
+            EXT_ALPN => {
+                let mut protocols = data.vec16()?;
+                while !protocols.is_empty() {
+                    let protocol = protocols.vec8()?;
+                    hello
+                        .alpn
+                        .push(String::from_utf8_lossy(protocol.data).into_owned());
+                }
+            }
+            EXT_SUPPORTED_VERSIONS => {
+                hello.supported_versions = data.vec8()?.u16_list()?;
+            }
+            EXT_SUPPORTED_GROUPS => {
+                hello.groups = data.vec16()?.u16_list()?;
+            }
+            EXT_EC_POINT_FORMATS => {
+                hello.ec_point_formats = data.vec8()?.data.to_vec();
+            }
+            EXT_SIGNATURE_ALGORITHMS => {
+                hello.signature_algorithms = data.vec16()?.u16_list()?;
+            }
+            _ => {}
+        }
+    }
+
+    Some(hello)
+}
+
+fn parse_server_hello(body: &[u8]) -> Option<ServerHello> {
+    let mut r = Reader::new(body);
+    let mut hello = ServerHello {
+        legacy_version: r.u16()?,
+        ..Default::default()
+    };
+
+    r.bytes(32)?; // random
+    r.vec8()?; // session id
+    hello.cipher_suite = r.u16()?;
+    r.u8()?; // compression method
+
+    if r.is_empty() {
+        return Some(hello);
+    }
+
+    let mut extensions = r.vec16()?;
+    while !extensions.is_empty() {
+        let kind = extensions.u16()?;
+        let mut data = extensions.vec16()?;
+        hello.extensions.push(kind);
+
+        match kind {
+            EXT_SUPPORTED_VERSIONS => {
+                hello.selected_version = Some(data.u16()?);
+            }
+            EXT_ALPN => {
+                let mut protocols = data.vec16()?;
+                let protocol = protocols.vec8()?;
+                hello.alpn = Some(String::from_utf8_lossy(protocol.data).into_owned());
+            }
+            _ => {}
+        }
+    }
+
+    Some(hello)
+}
+
+// 한 방향의 stream에서 record를 벗기고 handshake 메시지를 이어 붙인다.
+// 첫 hello를 찾거나 TLS가 아니라고 판단하면 그 뒤로는 보지 않는다
+struct HandshakeReader {
+    records: Vec<u8>,
+    handshake: Vec<u8>,
+    done: bool,
+}
+
+impl HandshakeReader {
+    fn new() -> Self {
+        Self {
+            records: Vec::new(),
+            handshake: Vec::new(),
+            done: false,
+        }
+    }
+
+    fn finish(&mut self) {
+        self.done = true;
+        self.records = Vec::new();
+        self.handshake = Vec::new();
+    }
+
+    fn push(&mut self, data: &[u8]) -> Option<Hello> {
+        if self.done {
+            return None;
+        }
+
+        self.records.extend_from_slice(data);
+
+        while self.records.len() >= 5 {
+            let content_type = self.records[0];
+            let major = self.records[1];
+            let length = u16::from_be_bytes([self.records[3], self.records[4]]) as usize;
+
+            // hello 전에 다른 record가 오거나 record 모양이 아니면 TLS가 아니다
+            if content_type != CONTENT_HANDSHAKE || major != 3 || length > MAX_RECORD {
+                self.finish();
+                return None;
+            }
+
+            if self.records.len() < 5 + length {
+                return None;
+            }
+
+            let fragment: Vec<u8> = self.records.drain(..5 + length).skip(5).collect();
+            self.handshake.extend_from_slice(&fragment);
+
+            if self.handshake.len() > MAX_HANDSHAKE {
+                self.finish();
+                return None;
+            }
+
+            while self.handshake.len() >= 4 {
+                let kind = self.handshake[0];
+                let length = u32::from_be_bytes([
+                    0,
+                    self.handshake[1],
+                    self.handshake[2],
+                    self.handshake[3],
+                ]) as usize;
+
+                if self.handshake.len() < 4 + length {
+                    break;
+                }
+
+                let message: Vec<u8> = self.handshake.drain(..4 + length).skip(4).collect();
+
+                let hello = match kind {
+                    HANDSHAKE_CLIENT_HELLO => parse_client_hello(&message).map(Hello::Client),
+                    HANDSHAKE_SERVER_HELLO => parse_server_hello(&message).map(Hello::Server),
+                    _ => continue,
+                };
+
+                self.finish();
+                return hello;
+            }
+        }
+
+        None
+    }
+}
+
+// ==================== INSPECTOR ====================
+
+// conversation마다 양방향 hello를 찾아 sink로 넘긴다
+pub struct TlsInspector<F> {
+    // 두 방향 모두 끝났거나 TLS가 아니면 더 보지 않는다
+    flows: HashMap<u64, [HandshakeReader; 2]>,
+    sink: F,
+}
+
+impl<F: FnMut(&Conversation, &Hello)> TlsInspector<F> {
+    pub fn new(sink: F) -> Self {
+        Self {
+            flows: HashMap::new(),
+            sink,
+        }
+    }
+}
+
+impl<F: FnMut(&Conversation, &Hello)> ConversationHandler for TlsInspector<F> {
+    fn on_data(
+        &mut self,
+        conversation: &Conversation,
+        direction: Direction,
+        data: &[u8],
+        _timestamp: Duration,
+    ) {
+        let readers = self
+            .flows
+            .entry(conversation.id)
+            .or_insert_with(|| [HandshakeReader::new(), HandshakeReader::new()]);
+
+        let reader = match direction {
+            Direction::ClientToServer => &mut readers[0],
+            Direction::ServerToClient => &mut readers[1],
+        };
+
+        if let Some(hello) = reader.push(data) {
+            (self.sink)(conversation, &hello);
+        }
+    }
+
+    fn on_gap(
+        &mut self,
+        conversation: &Conversation,
+        direction: Direction,
+        _offset: u64,
+        _len: u64,
+    ) {
+        // 빈 구간 뒤로는 record 경계를 믿을 수 없다
+        if let Some(readers) = self.flows.get_mut(&conversation.id) {
+            match direction {
+                Direction::ClientToServer => readers[0].finish(),
+                Direction::ServerToClient => readers[1].finish(),
+            }
+        }
+    }
+
+    fn on_close(&mut self, conversation: &Conversation) {
+        self.flows.remove(&conversation.id);
+    }
+}
+
+// 기본 출력 형식
+pub fn print_hello(conversation: &Conversation, hello: &Hello) {
+    match hello {
+        Hello::Client(hello) => {
+            let versions: Vec<String> = hello
+                .supported_versions
+                .iter()
+                .filter(|v| !is_grease(**v))
+                .map(|v| version_name(*v))
+                .collect();
+
+            println!(
+                "[TLS] {} -> {} ClientHello sni={} alpn={} versions={} ciphers={} exts={}",
+                conversation.client,
+                conversation.server,
+                hello.sni.as_deref().unwrap_or("-"),
+                if hello.alpn.is_empty() {
+                    "-".to_string()
+                } else {
+                    hello.alpn.join(",")
+                },
+                if versions.is_empty() {
+                    version_name(hello.legacy_version)
+                } else {
+                    versions.join(",")
+                },
+                hello.cipher_suites.len(),
+                hello.extensions.len(),
+            );
+            println!("      ja3={} ja4={}", hello.ja3(), hello.ja4());
+        }
+        Hello::Server(hello) => {
+            println!(
+                "[TLS] {} <- {} ServerHello version={} cipher=0x{:04x} alpn={} ja3s={}",
+                conversation.client,
+                conversation.server,
+                version_name(hello.version()),
+                hello.cipher_suite,
+                hello.alpn.as_deref().unwrap_or("-"),
+                hello.ja3s(),
+            );
+        }
+    }
+}
+
+// ==================== MAIN ====================
+
+// 인자로 pcap 파일을 주면 그 파일을, 없으면 en0을 캡처한다
+pub fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let inspector = TlsInspector::new(print_hello);
+    let mut tracker = ConversationTracker::new(ReassemblyConfig::default(), inspector);
+
+    match std::env::args().nth(1) {
+        Some(path) => read_pcap(&path, &mut tracker),
+        None => capture_live("en0", &mut tracker),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GREASE_A: u16 = 0x0a0a;
+    const GREASE_B: u16 = 0x1a1a;
+
+    fn u16s(values: &[u16]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_be_bytes()).collect()
+    }
+
+    fn with_len16(body: &[u8]) -> Vec<u8> {
+        let mut out = (body.len() as u16).to_be_bytes().to_vec();
+        out.extend_from_slice(body);
+        out
+    }
+
+    fn with_len8(body: &[u8]) -> Vec<u8> {
+        let mut out = vec![body.len() as u8];
+        out.extend_from_slice(body);
+        out
+    }
+
+    fn sni(name: &str) -> Vec<u8> {
+        let mut entry = vec![0u8];
+        entry.extend(with_len16(name.as_bytes()));
+        with_len16(&entry)
+    }
+
+    fn alpn(protocols: &[&str]) -> Vec<u8> {
+        let list: Vec<u8> = protocols.iter().flat_map(|p| with_len8(p.as_bytes())).collect();
+        with_len16(&list)
+    }
+
+    // handshake header + record header까지 씌운 ClientHello
+    fn client_hello(version: u16, ciphers: &[u16], extensions: &[(u16, Vec<u8>)]) -> Vec<u8> {
+        let mut body = version.to_be_bytes().to_vec();
+        body.extend([0x11; 32]); // random
+        body.extend(with_len8(&[0x22; 32])); // session id
+        body.extend(with_len16(&u16s(ciphers)));
+        body.extend(with_len8(&[0])); // null compression
+
+        let mut exts = Vec::new();
+        for (kind, data) in extensions {
+            exts.extend(kind.to_be_bytes());
+            exts.extend(with_len16(data));
+        }
+        body.extend(with_len16(&exts));
+
+        let mut handshake = vec![HANDSHAKE_CLIENT_HELLO];
+        handshake.extend(&(body.len() as u32).to_be_bytes()[1..]);
+        handshake.extend(body);
+
+        let mut record = vec![CONTENT_HANDSHAKE, 3, 1];
+        record.extend(with_len16(&handshake));
+        record
+    }
+
+    fn parse(record: &[u8]) -> ClientHello {
+        match HandshakeReader::new().push(record) {
+            Some(Hello::Client(hello)) => hello,
+            other => panic!("expected ClientHello, got {:?}", other),
+        }
+    }
+
+    // JA3 README의 예시: 769,47-53-5-10-49161-49162-49171-49172-50-56-19-4,0-10-11,23-24-25,0
+    fn ja3_readme_hello() -> Vec<u8> {
+        client_hello(
+            0x0301,
+            &[47, 53, 5, 10, 49161, 49162, 49171, 49172, 50, 56, 19, 4],
+            &[
+                (EXT_SERVER_NAME, sni("example.com")),
+                (EXT_SUPPORTED_GROUPS, with_len16(&u16s(&[23, 24, 25]))),
+                (EXT_EC_POINT_FORMATS, with_len8(&[0])),
+            ],
+        )
+    }
+
+    // JA4 문서의 Chrome 예시와 같은 cipher / extension / signature algorithm 구성. GREASE가 섞여 있다
+    fn chrome_hello() -> Vec<u8> {
+        let ciphers = [
+            GREASE_A, 0x1301, 0x1302, 0x1303, 0xc02b, 0xc02f, 0xc02c, 0xc030, 0xcca9, 0xcca8,
+            0xc013, 0xc014, 0x009c, 0x009d, 0x002f, 0x0035,
+        ];
+        let sig_algs = [0x0403, 0x0804, 0x0401, 0x0503, 0x0805, 0x0501, 0x0806, 0x0601];
+
+        client_hello(
+            0x0303,
+            &ciphers,
+            &[
+                (GREASE_A, Vec::new()),
+                (EXT_SERVER_NAME, sni("www.google.com")),
+                (0x0017, Vec::new()),
+                (0xff01, vec![0]),
+                (EXT_SUPPORTED_GROUPS, with_len16(&u16s(&[GREASE_B, 29, 23, 24]))),
+                (EXT_EC_POINT_FORMATS, with_len8(&[0])),
+                (0x0023, Vec::new()),
+                (EXT_ALPN, alpn(&["h2", "http/1.1"])),
+                (0x0005, vec![1, 0, 0, 0, 0]),
+                (EXT_SIGNATURE_ALGORITHMS, with_len16(&u16s(&sig_algs))),
+                (0x0012, Vec::new()),
+                (0x0033, with_len16(&[])),
+                (0x002d, with_len8(&[1])),
+                (EXT_SUPPORTED_VERSIONS, with_len8(&u16s(&[GREASE_B, 0x0304, 0x0303]))),
+                (0x001b, vec![2, 0, 2]),
+                (0x4469, vec![0, 3, 2, b'h', b'2']),
+                (0x0015, vec![0; 8]),
+                (GREASE_B, vec![0]),
+            ],
+        )
+    }
+
+    #[test]
+    fn ja3_matches_the_reference_example() {
+        let hello = parse(&ja3_readme_hello());
+        assert_eq!(hello.sni.as_deref(), Some("example.com"));
+        assert_eq!(
+            hello.ja3_string(),
+            "769,47-53-5-10-49161-49162-49171-49172-50-56-19-4,0-10-11,23-24-25,0"
+        );
+        assert_eq!(hello.ja3(), "ada70206e40642a3e4461f35503241d5");
+    }
+
+    #[test]
+    fn ja4_matches_the_reference_chrome_example() {
+        let hello = parse(&chrome_hello());
+        assert_eq!(hello.sni.as_deref(), Some("www.google.com"));
+        assert_eq!(hello.alpn, ["h2", "http/1.1"]);
+        assert_eq!(hello.max_version(), 0x0304);
+        assert_eq!(hello.ja4(), "t13d1516h2_8daaf6152771_e5627efa2ab1");
+    }
+
+    #[test]
+    fn ja3_drops_grease_values() {
+        let hello = parse(&chrome_hello());
+        assert_eq!(
+            hello.ja3_string(),
+            "771,4865-4866-4867-49195-49199-49196-49200-52393-52392-49171-49172-156-157-47-53,\
+             0-23-65281-10-11-35-16-5-13-18-51-45-43-27-17513-21,29-23-24,0"
+        );
+    }
+
+    #[test]
+    fn ja4_without_sni_alpn_or_extensions() {
+        let hello = parse(&client_hello(0x0303, &[0x002f, 0x0035], &[]));
+        assert_eq!(hello.ja4(), "t12i020000_f54dd463d39b_000000000000");
+        assert_eq!(ja4_alpn(Some("\u{1}x\u{2}")), "02");
+    }
+
+    #[test]
+    fn hello_split_across_records_and_segments_is_reassembled() {
+        let record = chrome_hello();
+        let handshake = &record[5..];
+
+        // handshake 메시지를 record 두 개로 나누고, 그걸 다시 3바이트씩 흘려 넣는다
+        let (first, second) = handshake.split_at(100);
+        let mut stream = Vec::new();
+        for part in [first, second] {
+            stream.extend([CONTENT_HANDSHAKE, 3, 1]);
+            stream.extend(with_len16(part));
+        }
+
+        let mut reader = HandshakeReader::new();
+        let mut found = None;
+        for chunk in stream.chunks(3) {
+            if let Some(hello) = reader.push(chunk) {
+                found = Some(hello);
+            }
+        }
+
+        match found {
+            Some(Hello::Client(hello)) => assert_eq!(hello.ja4(), "t13d1516h2_8daaf6152771_e5627efa2ab1"),
+            other => panic!("expected ClientHello, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn non_tls_stream_is_given_up() {
+        let mut reader = HandshakeReader::new();
+        assert!(reader.push(b"GET / HTTP/1.1\r\n\r\n").is_none());
+        assert!(reader.done);
+        assert!(reader.push(&chrome_hello()).is_none());
+    }
+
+    #[test]
+    fn ja3s_uses_legacy_version_cipher_and_extensions() {
+        let hello = ServerHello {
+            legacy_version: 0x0303,
+            cipher_suite: 0x1301,
+            extensions: vec![0x002b, 0x0033],
+            selected_version: Some(0x0304),
+            alpn: None,
+        };
+        assert_eq!(hello.version(), 0x0304);
+        assert_eq!(hello.ja3s_string(), "771,4865,43-51");
+    }
+}
@@ -1,3 +1,5 @@
+use crate::udp::dns_message::DnsMessage;
+use crate::udp::dns_stats::{print_dns_stats, DnsTracker};
 use pnet::datalink::{self, Channel::Ethernet};
 use pnet::packet::{
     ethernet::{EtherTypes, EthernetPacket},
@@ -6,61 +8,10 @@ use pnet::packet::{
     udp::UdpPacket,
     Packet,
 };
+use std::net::SocketAddr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-fn read_u16_be(data: &[u8], offset: usize) -> Option<u16> {
-    if offset + 1 >= data.len() {
-        return None;
-    }
-    Some(u16::from_be_bytes([data[offset], data[offset + 1]]))
-}
-
-fn parse_dns_name(data: &[u8], mut offset: usize) -> Option<(String, usize)> {
-    let mut labels = Vec::new();
-
-    loop {
-        if offset >= data.len() {
-            return None;
-        }
-
-        let len = data[offset] as usize;
-        offset += 1;
-
-        if len == 0 {
-            break;
-        }
-
-        // 압축 포인터는 이번 단계에서는 처리하지 않음
-        if len & 0b1100_0000 != 0 {
-            return None;
-        }
-
-        if offset + len > data.len() {
-            return None;
-        }
-
-        let label = std::str::from_utf8(&data[offset..offset + len]).ok()?;
-        labels.push(label.to_string());
-        offset += len;
-    }
-
-    Some((labels.join("."), offset))
-}
-
-fn dns_type_to_string(qtype: u16) -> &'static str {
-    match qtype {
-        1 => "A",
-        2 => "NS",
-        5 => "CNAME",
-        6 => "SOA",
-        12 => "PTR",
-        15 => "MX",
-        16 => "TXT",
-        28 => "AAAA",
-        _ => "OTHER",
-    }
-}
-
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+pub fn main() -> Result<(), Box<dyn std::error::Error>> {
     let interface_name = "en0";
 
     let interfaces = datalink::interfaces();
@@ -72,17 +23,26 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     println!("[INFO] listening on interface: {}", interface.name);
 
-    let mut config = datalink::Config::default();
-    config.read_timeout = None;
+    // 조용할 때도 timeout 검사와 통계 출력이 돌도록 1초마다 깨어난다
+    let config = datalink::Config {
+        read_timeout: Some(Duration::from_secs(1)),
+        ..Default::default()
+    };
 
     let (_, mut rx) = match datalink::channel(&interface, config)? {
         Ethernet(tx, rx) => (tx, rx),
         _ => return Err("지원되지 않는 channel type".into()),
     };
 
+    let mut tracker = DnsTracker::new();
+    let mut last_report = Instant::now();
+
     loop {
         match rx.next() {
             Ok(packet) => {
+                // 읽기가 돌아온 뒤에 찍어야 패킷을 기다린 시간이 응답 시간에 섞이지 않는다
+                let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+
                 // 1. raw bytes -> Ethernet
                 if let Some(eth) = EthernetPacket::new(packet) {
                     // 2. Ethernet 안에 IPv4만 처리
@@ -98,82 +58,41 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
                                     // 6. DNS는 보통 53 포트
                                     if src_port == 53 || dst_port == 53 {
-                                        let payload = udp.payload();
-
-                                        // DNS header는 최소 12바이트
-                                        if payload.len() < 12 {
-                                            continue;
-                                        }
-
-                                        let tx_id = match read_u16_be(payload, 0) {
-                                            Some(v) => v,
-                                            None => continue,
-                                        };
-                                        let flags = match read_u16_be(payload, 2) {
-                                            Some(v) => v,
-                                            None => continue,
-                                        };
-                                        let qdcount = match read_u16_be(payload, 4) {
-                                            Some(v) => v,
-                                            None => continue,
-                                        };
-
-                                        let qr = (flags & 0x8000) != 0;
-                                        let kind = if qr { "RESPONSE" } else { "QUERY" };
+                                        let endpoints = format!(
+                                            "{}:{} -> {}:{}",
+                                            ipv4.get_source(),
+                                            src_port,
+                                            ipv4.get_destination(),
+                                            dst_port
+                                        );
 
-                                        // 질문이 없으면 생략
-                                        if qdcount == 0 {
-                                            println!(
-                                                "[DNS] {} {}:{} -> {}:{} tx_id=0x{:04x} qdcount=0",
-                                                kind,
-                                                ipv4.get_source(),
-                                                src_port,
-                                                ipv4.get_destination(),
-                                                dst_port,
-                                                tx_id
-                                            );
-                                            continue;
-                                        }
+                                        // 응답은 거의 다 압축 포인터를 쓴다. section 전체를 타입별로 해석
+                                        match DnsMessage::parse(udp.payload()) {
+                                            Ok(message) => {
+                                                println!("[DNS] {} {}", endpoints, message);
 
-                                        // 첫 질문은 DNS header(12바이트) 바로 뒤에 시작
-                                        let (domain, next_offset) = match parse_dns_name(payload, 12) {
-                                            Some(v) => v,
-                                            None => {
-                                                println!(
-                                                    "[DNS] {} {}:{} -> {}:{} tx_id=0x{:04x} name_parse_failed",
-                                                    kind,
-                                                    ipv4.get_source(),
+                                                // 7. query와 response를 짝지어 응답 시간을 잰다
+                                                let src = SocketAddr::new(
+                                                    ipv4.get_source().into(),
                                                     src_port,
-                                                    ipv4.get_destination(),
+                                                );
+                                                let dst = SocketAddr::new(
+                                                    ipv4.get_destination().into(),
                                                     dst_port,
-                                                    tx_id
                                                 );
-                                                continue;
+                                                if let Some(resolution) =
+                                                    tracker.process(src, dst, &message, now)
+                                                {
+                                                    println!(
+                                                        "  resolved: {}",
+                                                        resolution.summary()
+                                                    );
+                                                }
                                             }
-                                        };
-
-                                        let qtype = match read_u16_be(payload, next_offset) {
-                                            Some(v) => v,
-                                            None => continue,
-                                        };
-
-                                        let qclass = match read_u16_be(payload, next_offset + 2) {
-                                            Some(v) => v,
-                                            None => continue,
-                                        };
-
-                                        println!(
-                                            "[DNS] {} {}:{} -> {}:{} tx_id=0x{:04x} domain={} type={} class={}",
-                                            kind,
-                                            ipv4.get_source(),
-                                            src_port,
-                                            ipv4.get_destination(),
-                                            dst_port,
-                                            tx_id,
-                                            domain,
-                                            dns_type_to_string(qtype),
-                                            qclass
-                                        );
+                                            Err(e) => {
+                                                println!("[DNS] {} parse_failed: {}", endpoints, e)
+                                            }
+                                        }
                                     }
                                 }
                             }
@@ -181,9 +100,17 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     }
                 }
             }
+            Err(e) if e.kind() == std::io::ErrorKind::TimedOut => {}
             Err(e) => {
                 eprintln!("[ERROR] failed to read packet: {}", e);
             }
         }
+
+        // 5초마다 통계 출력
+        if last_report.elapsed() >= Duration::from_secs(5) {
+            tracker.expire(SystemTime::now().duration_since(UNIX_EPOCH)?);
+            print_dns_stats(&tracker);
+            last_report = Instant::now();
+        }
     }
-}
\ No newline at end of file
+}
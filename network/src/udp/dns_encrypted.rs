@@ -0,0 +1,520 @@
+use crate::tcp::tls::{self, load_certs, Identity, LocalCa, ServerTls};
+use crate::udp::dns_message::{DnsMessage, TYPE_OPT};
+use crate::udp::dns_server::{
+    handle_stream, serve_tcp, serve_udp, DnsServer, DnsServerConfig, Transport,
+};
+use crate::udp::dns_transport::DNS_MESSAGE;
+use axum::body::Bytes;
+use axum::extract::connect_info::Connected;
+use axum::extract::{ConnectInfo, Query, State};
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use rustls::pki_types::CertificateDer;
+use std::collections::HashMap;
+use std::future::IntoFuture;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::sync::mpsc;
+use tokio_rustls::server;
+
+// DNS 메시지를 암호화된 채널로 주고받는다.
+// 1. DoT (RFC 7858): TLS 위에 TCP와 똑같은 2바이트 길이 prefix. 포트 853, ALPN "dot"
+// 2. DoH (RFC 8484): HTTPS로 /dns-query에 GET ?dns=<base64url> 또는 POST application/dns-message
+// 서버 쪽은 받은 메시지를 dns_server::DnsServer::handle에 그대로 넘기고,
+// 클라이언트 쪽(EncryptedClients)은 dns_transport에 있어서 dns_resolver의 Nameserver::Tls / Nameserver::Https가 그것으로 묻는다.
+// TLS는 tcp::tls(rustls)로 하고, DoH는 axum(서버)과 reqwest(클라이언트)로 한다.
+
+// ==================== CONFIG ====================
+
+pub const DOH_PATH: &str = "/dns-query";
+// DNS 메시지는 2바이트 길이로 나타낼 수 있는 크기를 넘지 않는다
+const MAX_MESSAGE: usize = u16::MAX as usize;
+// 핸드셰이크가 끝났지만 axum이 아직 가져가지 않은 DoH 연결
+const DOH_BACKLOG: usize = 64;
+
+#[derive(Debug, Clone)]
+pub struct EncryptedConfig {
+    // 평문 UDP/TCP listen 주소와 zone, upstream
+    pub server: DnsServerConfig,
+    // 853/443은 root 권한이 필요해서 로컬 기본값은 높은 포트. None이면 띄우지 않는다
+    pub dot_listen: Option<SocketAddr>,
+    pub doh_listen: Option<SocketAddr>,
+    // 인증서 파일을 주지 않았을 때 로컬 CA로 발급할 이름
+    pub names: Vec<String>,
+}
+
+impl Default for EncryptedConfig {
+    fn default() -> Self {
+        Self {
+            server: DnsServerConfig::default(),
+            dot_listen: Some(SocketAddr::from(([127, 0, 0, 1], 8853))),
+            doh_listen: Some(SocketAddr::from(([127, 0, 0, 1], 8443))),
+            names: vec!["localhost".to_string(), "127.0.0.1".to_string()],
+        }
+    }
+}
+
+impl EncryptedConfig {
+    // DnsServerConfig::from_env에 더해
+    //   DOT_LISTEN      127.0.0.1:8853, "off"면 끈다
+    //   DOH_LISTEN      127.0.0.1:8443, "off"면 끈다
+    //   DNS_TLS_NAMES   "localhost,127.0.0.1" 로컬 CA로 발급할 이름
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let mut config = Self {
+            server: DnsServerConfig::from_env()?,
+            ..Self::default()
+        };
+
+        if let Ok(listen) = std::env::var("DOT_LISTEN") {
+            config.dot_listen = parse_listen(&listen)?;
+        }
+        if let Ok(listen) = std::env::var("DOH_LISTEN") {
+            config.doh_listen = parse_listen(&listen)?;
+        }
+        if let Ok(names) = std::env::var("DNS_TLS_NAMES") {
+            config.names = names
+                .split(',')
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(str::to_string)
+                .collect();
+        }
+
+        Ok(config)
+    }
+}
+
+fn parse_listen(value: &str) -> Result<Option<SocketAddr>, std::net::AddrParseError> {
+    match value {
+        "off" | "" => Ok(None),
+        value => value.parse().map(Some),
+    }
+}
+
+// 서버 인증서와 client가 믿어야 할 root.
+// DNS_TLS_CERT/DNS_TLS_KEY가 있으면 그 인증서와 DNS_TLS_CA, 없으면 로컬 CA를 만들어 names로 발급한다
+pub fn server_identity(
+    names: &[String],
+) -> Result<(Identity, Vec<CertificateDer<'static>>), Box<dyn std::error::Error + Send + Sync>> {
+    if let (Ok(cert), Ok(key)) = (std::env::var("DNS_TLS_CERT"), std::env::var("DNS_TLS_KEY")) {
+        let roots = match std::env::var("DNS_TLS_CA") {
+            Ok(ca) => load_certs(ca)?,
+            Err(_) => Vec::new(),
+        };
+        return Ok((Identity::from_pem_files(cert, key)?, roots));
+    }
+
+    let names: Vec<&str> = names.iter().map(String::as_str).collect();
+    let ca = LocalCa::new("dns local ca")?;
+    let identity = ca.issue_server(&names)?;
+    println!("[INFO] Issued certificate for {:?} from a local CA", names);
+
+    Ok((identity, vec![ca.cert()]))
+}
+
+// ==================== DoT SERVER ====================
+
+// 연결마다 핸드셰이크를 끝내고 평문 TCP와 같은 loop를 돌린다.
+// tls::serve 대신 직접 accept해서 serve_tcp와 같은 연결 자리를 핸드셰이크 전부터 잡는다
+pub async fn serve_dot(
+    server: Arc<DnsServer>,
+    listener: TcpListener,
+    identity: Identity,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let acceptor = ServerTls::new().identity(identity).alpn(b"dot").build()?;
+
+    loop {
+        let permit = server.connection_permit().await;
+        let (socket, peer) = listener.accept().await?;
+        let acceptor = acceptor.clone();
+        let server = server.clone();
+
+        tokio::spawn(async move {
+            let _permit = permit;
+            let stream = match tls::accept(&acceptor, socket, peer).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    println!("[SERVER] TLS handshake failed for {}: {}", peer, e);
+                    return;
+                }
+            };
+            if let Err(e) = handle_stream(server, stream, peer, Transport::Tls).await {
+                println!("[WARN] tls {} closed: {}", peer, e);
+            }
+        });
+    }
+}
+
+// ==================== DoH SERVER ====================
+
+// 핸드셰이크가 끝난 연결만 axum에 넘긴다. 핸드셰이크는 tls::serve가 연결마다 task에서 한다
+struct TlsListener {
+    local_addr: SocketAddr,
+    accepted: mpsc::Receiver<(server::TlsStream<TcpStream>, SocketAddr)>,
+}
+
+impl axum::serve::Listener for TlsListener {
+    type Io = server::TlsStream<TcpStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self.accepted.recv().await {
+            Some(accepted) => accepted,
+            // accept loop가 끝났으면 serve_doh가 먼저 에러로 끝난다
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        Ok(self.local_addr)
+    }
+}
+
+// DoH handler가 로그에 남길 client 주소
+#[derive(Debug, Clone, Copy)]
+struct DohPeer(SocketAddr);
+
+impl Connected<axum::serve::IncomingStream<'_, TlsListener>> for DohPeer {
+    fn connect_info(stream: axum::serve::IncomingStream<'_, TlsListener>) -> Self {
+        DohPeer(*stream.remote_addr())
+    }
+}
+
+pub fn doh_router(server: Arc<DnsServer>) -> Router {
+    Router::new()
+        .route(DOH_PATH, get(doh_get).post(doh_post))
+        .with_state(server)
+}
+
+pub async fn serve_doh(
+    server: Arc<DnsServer>,
+    listener: TcpListener,
+    identity: Identity,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let acceptor = ServerTls::new()
+        .identity(identity)
+        .alpn(b"h2")
+        .alpn(b"http/1.1")
+        .build()?;
+
+    let local_addr = listener.local_addr()?;
+    let (tx, rx) = mpsc::channel(DOH_BACKLOG);
+
+    let handshakes = tls::serve(listener, acceptor, move |stream, peer| {
+        let tx = tx.clone();
+        async move {
+            let _ = tx.send((stream, peer)).await;
+        }
+    });
+
+    let app = doh_router(server).into_make_service_with_connect_info::<DohPeer>();
+    let http = axum::serve(
+        TlsListener {
+            local_addr,
+            accepted: rx,
+        },
+        app,
+    )
+    .into_future();
+
+    tokio::select! {
+        result = handshakes => result,
+        result = http => Ok(result?),
+    }
+}
+
+// GET /dns-query?dns=<base64url, padding 없음>
+async fn doh_get(
+    State(server): State<Arc<DnsServer>>,
+    ConnectInfo(DohPeer(peer)): ConnectInfo<DohPeer>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Response {
+    let Some(encoded) = params.get("dns") else {
+        return (StatusCode::BAD_REQUEST, "missing dns parameter").into_response();
+    };
+
+    // padding은 빼고 보내야 하지만 붙여 와도 받아 준다
+    let Ok(data) = URL_SAFE_NO_PAD.decode(encoded.trim_end_matches('=')) else {
+        return (StatusCode::BAD_REQUEST, "dns parameter is not base64url").into_response();
+    };
+
+    answer_doh(&server, &data, peer).await
+}
+
+// POST /dns-query, body가 DNS 메시지 그대로
+async fn doh_post(
+    State(server): State<Arc<DnsServer>>,
+    ConnectInfo(DohPeer(peer)): ConnectInfo<DohPeer>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+
+    if !content_type.eq_ignore_ascii_case(DNS_MESSAGE) {
+        return (
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "content-type must be application/dns-message",
+        )
+            .into_response();
+    }
+
+    answer_doh(&server, &body, peer).await
+}
+
+async fn answer_doh(server: &DnsServer, data: &[u8], peer: SocketAddr) -> Response {
+    if data.len() > MAX_MESSAGE {
+        return StatusCode::PAYLOAD_TOO_LARGE.into_response();
+    }
+
+    // 응답 메시지를 보냈거나 해석할 수 없어 FORMERR도 못 만든 경우
+    let Some(reply) = server.handle(data, peer, Transport::Https).await else {
+        return (StatusCode::BAD_REQUEST, "not a dns query").into_response();
+    };
+
+    let max_age = freshness(&reply);
+    let mut response = ([(header::CONTENT_TYPE, DNS_MESSAGE)], reply).into_response();
+
+    if let Some(max_age) = max_age {
+        if let Ok(value) = HeaderValue::from_str(&format!("max-age={}", max_age)) {
+            response.headers_mut().insert(header::CACHE_CONTROL, value);
+        }
+    }
+
+    response
+}
+
+// RFC 8484 5.1: HTTP cache가 record의 TTL보다 오래 들고 있지 않도록 가장 작은 TTL
+fn freshness(reply: &[u8]) -> Option<u32> {
+    let message = DnsMessage::parse(reply).ok()?;
+
+    message
+        .answers
+        .iter()
+        .chain(&message.authority)
+        .filter(|record| record.rtype != TYPE_OPT)
+        .map(|record| record.ttl)
+        .min()
+}
+
+// ==================== MAIN ====================
+
+// 평문 UDP/TCP, DoT, DoH listener. serve로 넘기기 전에 다 bind해 두므로
+// bind가 끝난 뒤에는 serve가 아직 돌기 전이라도 client가 연결할 수 있다 (포트 0이면 local_addr로 실제 포트를 본다)
+pub struct Listeners {
+    pub udp: UdpSocket,
+    pub tcp: TcpListener,
+    pub dot: Option<TcpListener>,
+    pub doh: Option<TcpListener>,
+}
+
+impl Listeners {
+    pub async fn bind(config: &EncryptedConfig) -> io::Result<Self> {
+        let udp = UdpSocket::bind(config.server.listen).await?;
+        // 포트 0이어도 TCP는 UDP와 같은 포트를 쓴다
+        let tcp = TcpListener::bind(udp.local_addr()?).await?;
+
+        let dot = match config.dot_listen {
+            Some(listen) => Some(TcpListener::bind(listen).await?),
+            None => None,
+        };
+        let doh = match config.doh_listen {
+            Some(listen) => Some(TcpListener::bind(listen).await?),
+            None => None,
+        };
+
+        Ok(Self { udp, tcp, dot, doh })
+    }
+}
+
+// 평문 UDP/TCP와 DoT, DoH를 같은 DnsServer로 띄운다
+pub async fn serve(
+    server: Arc<DnsServer>,
+    listeners: Listeners,
+    identity: Identity,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let Listeners { udp, tcp, dot, doh } = listeners;
+    println!(
+        "[SERVER] DNS server listening on {} (udp, tcp)",
+        udp.local_addr()?
+    );
+
+    let mut tasks = tokio::task::JoinSet::new();
+
+    let plain = server.clone();
+    tasks.spawn(async move {
+        tokio::try_join!(serve_udp(plain.clone(), udp), serve_tcp(plain, tcp))?;
+        Ok::<_, Box<dyn std::error::Error + Send + Sync>>(())
+    });
+
+    if let Some(listener) = dot {
+        println!("[SERVER] DoT listening on {}", listener.local_addr()?);
+        tasks.spawn(serve_dot(server.clone(), listener, identity.clone()));
+    }
+
+    if let Some(listener) = doh {
+        println!(
+            "[SERVER] DoH listening on https://{}{}",
+            listener.local_addr()?,
+            DOH_PATH
+        );
+        tasks.spawn(serve_doh(server.clone(), listener, identity));
+    }
+
+    // 하나라도 끝나면 (에러) 전체를 끝낸다
+    match tasks.join_next().await {
+        Some(result) => result?,
+        None => Ok(()),
+    }
+}
+
+// 로컬 CA로 발급한 인증서로 평문(127.0.0.1:5353), DoT(127.0.0.1:8853), DoH(127.0.0.1:8443)를 띄운다.
+// kdig +tls @127.0.0.1 -p 8853 www.example.test
+// curl --cacert ca.pem -H 'accept: application/dns-message' 'https://localhost:8443/dns-query?dns=...'
+// resolver 쪽: DNS_NAMESERVER=tls://127.0.0.1:8853#localhost 로 udp::dns_resolver::main
+#[tokio::main]
+pub async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let config = EncryptedConfig::from_env()?;
+    let (identity, _roots) = server_identity(&config.names)?;
+    let server = Arc::new(DnsServer::from_config(&config.server)?);
+
+    let listeners = Listeners::bind(&config).await?;
+    serve(server, listeners, identity).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::udp::dns_message::TYPE_A;
+    use crate::udp::dns_resolver::{Nameserver, Resolver, ResolverConfig};
+    use crate::udp::dns_transport::DohMethod;
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    // 모든 포트를 0으로 bind하고 DoT 주소, DoH URL, client가 믿을 root를 돌려준다
+    async fn start() -> (SocketAddr, String, Vec<CertificateDer<'static>>) {
+        let config = EncryptedConfig {
+            server: DnsServerConfig {
+                listen: SocketAddr::from(([127, 0, 0, 1], 0)),
+                zone_files: vec![PathBuf::from(concat!(
+                    env!("CARGO_MANIFEST_DIR"),
+                    "/zones/example.test.zone"
+                ))],
+                upstream: None,
+            },
+            dot_listen: Some(SocketAddr::from(([127, 0, 0, 1], 0))),
+            doh_listen: Some(SocketAddr::from(([127, 0, 0, 1], 0))),
+            ..EncryptedConfig::default()
+        };
+
+        let (identity, roots) = server_identity(&config.names).unwrap();
+        let server = Arc::new(DnsServer::from_config(&config.server).unwrap());
+        let listeners = Listeners::bind(&config).await.unwrap();
+
+        let dot = listeners.dot.as_ref().unwrap().local_addr().unwrap();
+        let doh = listeners.doh.as_ref().unwrap().local_addr().unwrap();
+        tokio::spawn(serve(server, listeners, identity));
+
+        (dot, format!("https://{}{}", doh, DOH_PATH), roots)
+    }
+
+    // serve가 spawn만 되고 아직 돌지 않았어도 listener는 bind돼 있어서 바로 물을 수 있다
+    async fn resolve_www(
+        nameserver: Nameserver,
+        roots: Vec<CertificateDer<'static>>,
+    ) -> Vec<IpAddr> {
+        let resolver = Resolver::new(ResolverConfig {
+            tls_roots: roots,
+            timeout: Duration::from_secs(5),
+            attempts: 1,
+            ..ResolverConfig::with_nameservers(vec![nameserver])
+        })
+        .unwrap();
+
+        resolver
+            .resolve("www.example.test", TYPE_A)
+            .await
+            .unwrap()
+            .ips()
+    }
+
+    fn www() -> Vec<IpAddr> {
+        vec![IpAddr::V4(Ipv4Addr::new(127, 0, 0, 20))]
+    }
+
+    #[tokio::test]
+    async fn dot_answers_from_the_zone() {
+        let (dot, _, roots) = start().await;
+        let nameserver = Nameserver::Tls {
+            addr: dot,
+            server_name: "localhost".to_string(),
+        };
+        assert_eq!(resolve_www(nameserver, roots).await, www());
+    }
+
+    #[tokio::test]
+    async fn doh_get_answers_from_the_zone() {
+        let (_, url, roots) = start().await;
+        let nameserver = Nameserver::Https {
+            url,
+            method: DohMethod::Get,
+        };
+        assert_eq!(resolve_www(nameserver, roots).await, www());
+    }
+
+    #[tokio::test]
+    async fn doh_post_answers_from_the_zone() {
+        let (_, url, roots) = start().await;
+        let nameserver = Nameserver::Https {
+            url,
+            method: DohMethod::Post,
+        };
+        assert_eq!(resolve_www(nameserver, roots).await, www());
+    }
+
+    #[tokio::test]
+    async fn doh_sets_max_age_and_rejects_bad_requests() {
+        let (_, url, roots) = start().await;
+        let client = reqwest::Client::builder()
+            .add_root_certificate(reqwest::Certificate::from_der(&roots[0]).unwrap())
+            .build()
+            .unwrap();
+
+        // HTTP cache는 가장 짧은 TTL(api의 CNAME 60초)보다 오래 들고 있으면 안 된다
+        let query = DnsMessage::query(0, "api.example.test", TYPE_A)
+            .to_bytes()
+            .unwrap();
+        let response = client
+            .get(&url)
+            .query(&[("dns", URL_SAFE_NO_PAD.encode(&query))])
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CACHE_CONTROL], "max-age=60");
+
+        let missing = client.get(&url).send().await.unwrap();
+        assert_eq!(missing.status(), StatusCode::BAD_REQUEST);
+
+        let not_base64 = client.get(format!("{}?dns=%%%", url)).send().await.unwrap();
+        assert_eq!(not_base64.status(), StatusCode::BAD_REQUEST);
+
+        let wrong_type = client
+            .post(&url)
+            .header(header::CONTENT_TYPE, "text/plain")
+            .body(query)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(wrong_type.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+}
@@ -0,0 +1,1172 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use crate::util::fill_random;
+
+// DNS wire format (RFC 1035) 해석과 생성. 압축 포인터를 따라가고 네 section의 record를 모두 타입별로 꺼낸다.
+// 캡처 도구(dns.rs, integration.rs)와 dns_server가 같이 쓴다.
+
+// ==================== CONSTANTS ====================
+
+pub const TYPE_A: u16 = 1;
+pub const TYPE_NS: u16 = 2;
+pub const TYPE_CNAME: u16 = 5;
+pub const TYPE_SOA: u16 = 6;
+pub const TYPE_PTR: u16 = 12;
+pub const TYPE_MX: u16 = 15;
+pub const TYPE_TXT: u16 = 16;
+pub const TYPE_AAAA: u16 = 28;
+pub const TYPE_SRV: u16 = 33;
+pub const TYPE_OPT: u16 = 41;
+pub const TYPE_CAA: u16 = 257;
+
+pub const CLASS_IN: u16 = 1;
+
+pub const FLAG_QR: u16 = 0x8000;
+pub const FLAG_AA: u16 = 0x0400;
+pub const FLAG_TC: u16 = 0x0200;
+pub const FLAG_RD: u16 = 0x0100;
+pub const FLAG_RA: u16 = 0x0080;
+
+pub const RCODE_NOERROR: u16 = 0;
+pub const RCODE_FORMERR: u16 = 1;
+pub const RCODE_SERVFAIL: u16 = 2;
+pub const RCODE_NXDOMAIN: u16 = 3;
+pub const RCODE_NOTIMP: u16 = 4;
+pub const RCODE_REFUSED: u16 = 5;
+pub const RCODE_BADVERS: u16 = 16;
+
+const HEADER_LEN: usize = 12;
+// 이름 전체는 255바이트, label 하나는 63바이트까지
+const MAX_NAME_LEN: usize = 255;
+// 정상 메시지에서 포인터가 이렇게 길게 이어질 일은 없다
+const MAX_POINTER_JUMPS: usize = 64;
+
+pub fn type_name(rtype: u16) -> String {
+    let name = match rtype {
+        TYPE_A => "A",
+        TYPE_NS => "NS",
+        TYPE_CNAME => "CNAME",
+        TYPE_SOA => "SOA",
+        TYPE_PTR => "PTR",
+        TYPE_MX => "MX",
+        TYPE_TXT => "TXT",
+        TYPE_AAAA => "AAAA",
+        TYPE_SRV => "SRV",
+        TYPE_OPT => "OPT",
+        TYPE_CAA => "CAA",
+        255 => "ANY",
+        other => return format!("TYPE{}", other),
+    };
+    name.to_string()
+}
+
+pub fn class_name(class: u16) -> String {
+    match class {
+        CLASS_IN => "IN".to_string(),
+        3 => "CH".to_string(),
+        255 => "ANY".to_string(),
+        other => format!("CLASS{}", other),
+    }
+}
+
+pub fn rcode_name(rcode: u16) -> String {
+    let name = match rcode {
+        RCODE_NOERROR => "NOERROR",
+        RCODE_FORMERR => "FORMERR",
+        RCODE_SERVFAIL => "SERVFAIL",
+        RCODE_NXDOMAIN => "NXDOMAIN",
+        RCODE_NOTIMP => "NOTIMP",
+        RCODE_REFUSED => "REFUSED",
+        RCODE_BADVERS => "BADVERS",
+        other => return format!("RCODE{}", other),
+    };
+    name.to_string()
+}
+
+// ==================== ERROR ====================
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DnsError {
+    // 길이 필드가 메시지 끝을 넘는다
+    Truncated,
+    // 앞쪽이 아닌 곳을 가리키거나 너무 많이 이어지는 포인터
+    BadPointer,
+    // 0x40, 0x80으로 시작하는 (폐기된) label 형식
+    BadLabel,
+    NameTooLong,
+    // 텍스트 이름의 escape가 잘못됐거나 빈 label, 63바이트 넘는 label이 있다
+    BadName(String),
+    // 64KB를 넘어서 wire format으로 만들 수 없다
+    TooLarge,
+    // rdata 길이가 타입과 맞지 않는다
+    BadRdata(u16),
+}
+
+impl fmt::Display for DnsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DnsError::Truncated => write!(f, "message truncated"),
+            DnsError::BadPointer => write!(f, "bad compression pointer"),
+            DnsError::BadLabel => write!(f, "unsupported label type"),
+            DnsError::NameTooLong => write!(f, "name longer than 255 bytes"),
+            DnsError::BadName(name) => write!(f, "invalid name: {}", name),
+            DnsError::TooLarge => write!(f, "message larger than 65535 bytes"),
+            DnsError::BadRdata(rtype) => write!(f, "malformed {} rdata", type_name(*rtype)),
+        }
+    }
+}
+
+impl std::error::Error for DnsError {}
+
+// ==================== TYPES ====================
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Question {
+    pub name: String,
+    pub qtype: u16,
+    pub qclass: u16,
+}
+
+impl fmt::Display for Question {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {}",
+            self.name,
+            class_name(self.qclass),
+            type_name(self.qtype)
+        )
+    }
+}
+
+// OPT pseudo-record (RFC 6891). class와 ttl 자리에 다른 값이 들어 있다
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edns {
+    pub udp_payload_size: u16,
+    // header rcode의 상위 8bit
+    pub extended_rcode: u8,
+    pub version: u8,
+    pub dnssec_ok: bool,
+    pub options: Vec<(u16, Vec<u8>)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RData {
+    A(Ipv4Addr),
+    Aaaa(Ipv6Addr),
+    Cname(String),
+    Ns(String),
+    Ptr(String),
+    Mx {
+        preference: u16,
+        exchange: String,
+    },
+    // character-string 여러 개
+    Txt(Vec<Vec<u8>>),
+    Soa {
+        mname: String,
+        rname: String,
+        serial: u32,
+        refresh: u32,
+        retry: u32,
+        expire: u32,
+        minimum: u32,
+    },
+    Srv {
+        priority: u16,
+        weight: u16,
+        port: u16,
+        target: String,
+    },
+    Caa {
+        flags: u8,
+        tag: String,
+        value: Vec<u8>,
+    },
+    Opt(Edns),
+    Unknown(Vec<u8>),
+}
+
+impl fmt::Display for RData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RData::A(ip) => write!(f, "{}", ip),
+            RData::Aaaa(ip) => write!(f, "{}", ip),
+            RData::Cname(name) | RData::Ns(name) | RData::Ptr(name) => write!(f, "{}", name),
+            RData::Mx {
+                preference,
+                exchange,
+            } => write!(f, "{} {}", preference, exchange),
+            RData::Txt(strings) => {
+                let quoted: Vec<String> = strings
+                    .iter()
+                    .map(|s| format!("{:?}", String::from_utf8_lossy(s)))
+                    .collect();
+                write!(f, "{}", quoted.join(" "))
+            }
+            RData::Soa {
+                mname,
+                rname,
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum,
+            } => write!(
+                f,
+                "{} {} {} {} {} {} {}",
+                mname, rname, serial, refresh, retry, expire, minimum
+            ),
+            RData::Srv {
+                priority,
+                weight,
+                port,
+                target,
+            } => write!(f, "{} {} {} {}", priority, weight, port, target),
+            RData::Caa { flags, tag, value } => {
+                write!(f, "{} {} {:?}", flags, tag, String::from_utf8_lossy(value))
+            }
+            RData::Opt(edns) => write!(
+                f,
+                "udp={} version={} do={} options={}",
+                edns.udp_payload_size,
+                edns.version,
+                edns.dnssec_ok,
+                edns.options.len()
+            ),
+            RData::Unknown(data) => write!(f, "\\# {}", data.len()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub name: String,
+    pub rtype: u16,
+    pub class: u16,
+    pub ttl: u32,
+    pub data: RData,
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let RData::Opt(_) = self.data {
+            return write!(f, "OPT {}", self.data);
+        }
+
+        write!(
+            f,
+            "{} {} {} {} {}",
+            self.name,
+            self.ttl,
+            class_name(self.class),
+            type_name(self.rtype),
+            self.data
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DnsMessage {
+    pub id: u16,
+    // QR | Opcode | AA | TC | RD | RA | Z | AD | CD | RCODE
+    pub flags: u16,
+    pub questions: Vec<Question>,
+    pub answers: Vec<Record>,
+    pub authority: Vec<Record>,
+    pub additional: Vec<Record>,
+}
+
+impl DnsMessage {
+    // RD를 켠 질문 하나짜리 query
+    pub fn query(id: u16, name: &str, qtype: u16) -> Self {
+        Self {
+            id,
+            flags: FLAG_RD,
+            questions: vec![Question {
+                name: name.to_string(),
+                qtype,
+                qclass: CLASS_IN,
+            }],
+            answers: Vec::new(),
+            authority: Vec::new(),
+            additional: Vec::new(),
+        }
+    }
+
+    // id, opcode, RD, question을 그대로 가져온 빈 응답
+    pub fn reply(&self) -> Self {
+        Self {
+            id: self.id,
+            flags: FLAG_QR | (self.flags & (0x7800 | FLAG_RD)),
+            questions: self.questions.clone(),
+            answers: Vec::new(),
+            authority: Vec::new(),
+            additional: Vec::new(),
+        }
+    }
+
+    pub fn is_response(&self) -> bool {
+        self.flags & FLAG_QR != 0
+    }
+
+    pub fn opcode(&self) -> u16 {
+        (self.flags >> 11) & 0x0f
+    }
+
+    pub fn authoritative(&self) -> bool {
+        self.flags & FLAG_AA != 0
+    }
+
+    pub fn truncated(&self) -> bool {
+        self.flags & FLAG_TC != 0
+    }
+
+    pub fn recursion_desired(&self) -> bool {
+        self.flags & FLAG_RD != 0
+    }
+
+    pub fn recursion_available(&self) -> bool {
+        self.flags & FLAG_RA != 0
+    }
+
+    pub fn set_flag(&mut self, flag: u16, on: bool) {
+        if on {
+            self.flags |= flag;
+        } else {
+            self.flags &= !flag;
+        }
+    }
+
+    // EDNS가 있으면 확장 rcode까지 합친 12bit 값
+    pub fn rcode(&self) -> u16 {
+        let low = self.flags & 0x000f;
+        match self.edns() {
+            Some(edns) => ((edns.extended_rcode as u16) << 4) | low,
+            None => low,
+        }
+    }
+
+    pub fn edns(&self) -> Option<&Edns> {
+        self.additional
+            .iter()
+            .find_map(|record| match &record.data {
+                RData::Opt(edns) => Some(edns),
+                _ => None,
+            })
+    }
+
+    // OPT record를 하나만 남기고 바꾼다. None이면 뺀다
+    pub fn set_edns(&mut self, edns: Option<Edns>) {
+        self.additional.retain(|record| record.rtype != TYPE_OPT);
+
+        if let Some(edns) = edns {
+            self.additional.push(Record {
+                name: ".".to_string(),
+                rtype: TYPE_OPT,
+                class: edns.udp_payload_size,
+                ttl: opt_ttl(&edns),
+                data: RData::Opt(edns),
+            });
+        }
+    }
+
+    // 하위 4bit는 header에, 나머지는 OPT의 extended rcode에 넣는다. OPT는 먼저 붙여 둬야 한다
+    pub fn set_rcode(&mut self, rcode: u16) {
+        self.flags = (self.flags & !0x000f) | (rcode & 0x000f);
+
+        for record in &mut self.additional {
+            if let RData::Opt(edns) = &mut record.data {
+                edns.extended_rcode = (rcode >> 4) as u8;
+                record.ttl = opt_ttl(edns);
+            }
+        }
+    }
+
+    // wire format. 이름은 앞에서 이미 쓴 suffix를 가리키도록 압축한다
+    pub fn to_bytes(&self) -> Result<Vec<u8>, DnsError> {
+        let mut w = Writer::default();
+
+        w.u16(self.id);
+        w.u16(self.flags);
+        for count in [
+            self.questions.len(),
+            self.answers.len(),
+            self.authority.len(),
+            self.additional.len(),
+        ] {
+            w.u16(u16::try_from(count).map_err(|_| DnsError::TooLarge)?);
+        }
+
+        for question in &self.questions {
+            w.name(&question.name, true)?;
+            w.u16(question.qtype);
+            w.u16(question.qclass);
+        }
+
+        for record in self
+            .answers
+            .iter()
+            .chain(&self.authority)
+            .chain(&self.additional)
+        {
+            w.record(record)?;
+        }
+
+        if w.buf.len() > u16::MAX as usize {
+            return Err(DnsError::TooLarge);
+        }
+
+        Ok(w.buf)
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self, DnsError> {
+        if data.len() < HEADER_LEN {
+            return Err(DnsError::Truncated);
+        }
+
+        let id = read_u16(data, 0)?;
+        let flags = read_u16(data, 2)?;
+        let qdcount = read_u16(data, 4)?;
+        let ancount = read_u16(data, 6)?;
+        let nscount = read_u16(data, 8)?;
+        let arcount = read_u16(data, 10)?;
+
+        let mut offset = HEADER_LEN;
+
+        // count는 믿지 않고 실제로 읽힌 만큼만 미리 잡는다
+        let mut questions = Vec::with_capacity(qdcount.min(16) as usize);
+        for _ in 0..qdcount {
+            let (name, next) = read_name(data, offset)?;
+            questions.push(Question {
+                name,
+                qtype: read_u16(data, next)?,
+                qclass: read_u16(data, next + 2)?,
+            });
+            offset = next + 4;
+        }
+
+        let mut sections = [Vec::new(), Vec::new(), Vec::new()];
+        for (section, count) in sections.iter_mut().zip([ancount, nscount, arcount]) {
+            for _ in 0..count {
+                let (record, next) = read_record(data, offset)?;
+                section.push(record);
+                offset = next;
+            }
+        }
+
+        let [answers, authority, additional] = sections;
+
+        Ok(Self {
+            id,
+            flags,
+            questions,
+            answers,
+            authority,
+            additional,
+        })
+    }
+
+    // 캡처 도구용 한 줄 요약
+    pub fn summary(&self) -> String {
+        format!(
+            "{} tx_id=0x{:04x} rcode={} qd={} an={} ns={} ar={}{}",
+            if self.is_response() {
+                "RESPONSE"
+            } else {
+                "QUERY"
+            },
+            self.id,
+            rcode_name(self.rcode()),
+            self.questions.len(),
+            self.answers.len(),
+            self.authority.len(),
+            self.additional.len(),
+            if self.truncated() { " TC" } else { "" },
+        )
+    }
+}
+
+// 요약 한 줄 뒤에 section별 record를 한 줄씩
+impl fmt::Display for DnsMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.summary())?;
+
+        for question in &self.questions {
+            write!(f, "\n  question: {}", question)?;
+        }
+
+        let sections = [
+            ("answer", &self.answers),
+            ("authority", &self.authority),
+            ("additional", &self.additional),
+        ];
+        for (label, records) in sections {
+            for record in records {
+                write!(f, "\n  {}: {}", label, record)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// ==================== NAMES ====================
+
+// 텍스트 이름을 label 바이트열로. read_name이 만든 "\." 와 "\DDD" escape를 되돌린다. "."과 ""은 root
+pub fn name_labels(name: &str) -> Result<Vec<Vec<u8>>, DnsError> {
+    if name.is_empty() || name == "." {
+        return Ok(Vec::new());
+    }
+
+    let bad = || DnsError::BadName(name.to_string());
+
+    let mut labels = Vec::new();
+    let mut label = Vec::new();
+    let mut bytes = name.bytes();
+
+    while let Some(b) = bytes.next() {
+        match b {
+            // 빈 label은 안 된다. 끝의 '.' 하나는 여기까지 오지 않는다
+            b'.' if label.is_empty() => return Err(bad()),
+            b'.' => labels.push(std::mem::take(&mut label)),
+            b'\\' => {
+                let first = bytes.next().ok_or_else(bad)?;
+                if first.is_ascii_digit() {
+                    let mut value = (first - b'0') as u32;
+                    for _ in 0..2 {
+                        let digit = bytes.next().filter(u8::is_ascii_digit).ok_or_else(bad)?;
+                        value = value * 10 + (digit - b'0') as u32;
+                    }
+                    label.push(u8::try_from(value).map_err(|_| bad())?);
+                } else {
+                    label.push(first);
+                }
+            }
+            _ => label.push(b),
+        }
+
+        if label.len() > 63 {
+            return Err(bad());
+        }
+    }
+
+    if !label.is_empty() {
+        labels.push(label);
+    }
+
+    let wire_len: usize = labels.iter().map(|label| label.len() + 1).sum::<usize>() + 1;
+    if wire_len > MAX_NAME_LEN {
+        return Err(DnsError::NameTooLong);
+    }
+
+    Ok(labels)
+}
+
+// 비교와 key용. 소문자로 바꾸고 끝의 '.'을 떼며, root는 "."
+pub fn normalize_name(name: &str) -> String {
+    let trimmed = name.trim_end_matches('.');
+    if trimmed.is_empty() {
+        ".".to_string()
+    } else {
+        trimmed.to_ascii_lowercase()
+    }
+}
+
+// ==================== ENCODE ====================
+
+// transaction id와 source port. 위조 응답을 막는 값이라 예측할 수 없도록 커널 난수를 쓴다
+pub fn random_id() -> std::io::Result<u16> {
+    let mut buf = [0u8; 2];
+    fill_random(&mut buf)?;
+    Ok(u16::from_ne_bytes(buf))
+}
+
+// OPT의 ttl 자리: extended rcode | version | DO
+fn opt_ttl(edns: &Edns) -> u32 {
+    (edns.extended_rcode as u32) << 24
+        | (edns.version as u32) << 16
+        | if edns.dnssec_ok { 0x8000 } else { 0 }
+}
+
+#[derive(Default)]
+struct Writer {
+    buf: Vec<u8>,
+    // 소문자로 맞춘 suffix → 처음 쓴 위치
+    names: HashMap<Vec<Vec<u8>>, u16>,
+}
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.buf.push(value);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.buf.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.buf.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn name(&mut self, name: &str, compress: bool) -> Result<(), DnsError> {
+        let labels = name_labels(name)?;
+
+        for i in 0..labels.len() {
+            let suffix: Vec<Vec<u8>> = labels[i..]
+                .iter()
+                .map(|label| label.to_ascii_lowercase())
+                .collect();
+
+            if compress {
+                if let Some(&offset) = self.names.get(&suffix) {
+                    self.u16(0xc000 | offset);
+                    return Ok(());
+                }
+            }
+
+            // 포인터는 14bit라 그 뒤 위치는 기억해도 가리킬 수 없다
+            if self.buf.len() < 0x4000 {
+                self.names.entry(suffix).or_insert(self.buf.len() as u16);
+            }
+
+            self.u8(labels[i].len() as u8);
+            self.buf.extend_from_slice(&labels[i]);
+        }
+
+        self.u8(0);
+        Ok(())
+    }
+
+    fn record(&mut self, record: &Record) -> Result<(), DnsError> {
+        self.name(&record.name, true)?;
+        self.u16(record.rtype);
+
+        match &record.data {
+            // OPT는 class와 ttl 자리에 EDNS 값을 넣는다
+            RData::Opt(edns) => {
+                self.u16(edns.udp_payload_size);
+                self.u32(opt_ttl(edns));
+            }
+            _ => {
+                self.u16(record.class);
+                self.u32(record.ttl);
+            }
+        }
+
+        // rdlength는 rdata를 다 쓰고 채운다
+        let length_at = self.buf.len();
+        self.u16(0);
+
+        match &record.data {
+            RData::A(ip) => self.buf.extend_from_slice(&ip.octets()),
+            RData::Aaaa(ip) => self.buf.extend_from_slice(&ip.octets()),
+            RData::Cname(name) | RData::Ns(name) | RData::Ptr(name) => self.name(name, true)?,
+            RData::Mx {
+                preference,
+                exchange,
+            } => {
+                self.u16(*preference);
+                self.name(exchange, true)?;
+            }
+            RData::Txt(strings) => {
+                for s in strings {
+                    let len = u8::try_from(s.len()).map_err(|_| DnsError::BadRdata(TYPE_TXT))?;
+                    self.u8(len);
+                    self.buf.extend_from_slice(s);
+                }
+            }
+            RData::Soa {
+                mname,
+                rname,
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum,
+            } => {
+                self.name(mname, true)?;
+                self.name(rname, true)?;
+                for value in [serial, refresh, retry, expire, minimum] {
+                    self.u32(*value);
+                }
+            }
+            RData::Srv {
+                priority,
+                weight,
+                port,
+                target,
+            } => {
+                self.u16(*priority);
+                self.u16(*weight);
+                self.u16(*port);
+                // RFC 2782: SRV target은 압축하지 않는다
+                self.name(target, false)?;
+            }
+            RData::Caa { flags, tag, value } => {
+                let len = u8::try_from(tag.len()).map_err(|_| DnsError::BadRdata(TYPE_CAA))?;
+                self.u8(*flags);
+                self.u8(len);
+                self.buf.extend_from_slice(tag.as_bytes());
+                self.buf.extend_from_slice(value);
+            }
+            RData::Opt(edns) => {
+                for (code, value) in &edns.options {
+                    let len = u16::try_from(value.len()).map_err(|_| DnsError::TooLarge)?;
+                    self.u16(*code);
+                    self.u16(len);
+                    self.buf.extend_from_slice(value);
+                }
+            }
+            RData::Unknown(data) => self.buf.extend_from_slice(data),
+        }
+
+        let rdlength = self.buf.len() - length_at - 2;
+        let rdlength = u16::try_from(rdlength).map_err(|_| DnsError::TooLarge)?;
+        self.buf[length_at..length_at + 2].copy_from_slice(&rdlength.to_be_bytes());
+
+        Ok(())
+    }
+}
+
+// ==================== WIRE ====================
+
+fn read_u8(data: &[u8], offset: usize) -> Result<u8, DnsError> {
+    data.get(offset).copied().ok_or(DnsError::Truncated)
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16, DnsError> {
+    match data.get(offset..offset + 2) {
+        Some(b) => Ok(u16::from_be_bytes([b[0], b[1]])),
+        None => Err(DnsError::Truncated),
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, DnsError> {
+    match data.get(offset..offset + 4) {
+        Some(b) => Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]])),
+        None => Err(DnsError::Truncated),
+    }
+}
+
+// 압축 포인터를 따라가며 이름을 읽는다. 돌려주는 offset은 원래 위치에서 이름이 끝난 다음
+pub fn read_name(data: &[u8], mut offset: usize) -> Result<(String, usize), DnsError> {
+    let mut name = String::new();
+    let mut wire_len = 0usize;
+    // 첫 포인터 다음 위치. 이름 뒤를 읽을 곳은 여기다
+    let mut resume = None;
+    let mut jumps = 0;
+
+    loop {
+        let len = read_u8(data, offset)? as usize;
+
+        match len & 0xc0 {
+            0x00 if len == 0 => {
+                offset += 1;
+                break;
+            }
+            0x00 => {
+                let label = data
+                    .get(offset + 1..offset + 1 + len)
+                    .ok_or(DnsError::Truncated)?;
+
+                wire_len += len + 1;
+                if wire_len > MAX_NAME_LEN {
+                    return Err(DnsError::NameTooLong);
+                }
+
+                if !name.is_empty() {
+                    name.push('.');
+                }
+                push_label(&mut name, label);
+                offset += len + 1;
+            }
+            0xc0 => {
+                let target = ((len & 0x3f) << 8) | read_u8(data, offset + 1)? as usize;
+
+                // 항상 앞쪽만 가리키게 해서 순환을 막고, 그래도 너무 길면 끊는다
+                jumps += 1;
+                if target >= offset || jumps > MAX_POINTER_JUMPS {
+                    return Err(DnsError::BadPointer);
+                }
+
+                resume.get_or_insert(offset + 2);
+                offset = target;
+            }
+            _ => return Err(DnsError::BadLabel),
+        }
+    }
+
+    if name.is_empty() {
+        name.push('.');
+    }
+
+    Ok((name, resume.unwrap_or(offset)))
+}
+
+// '.'이나 출력할 수 없는 바이트는 dig처럼 escape한다
+fn push_label(name: &mut String, label: &[u8]) {
+    for &b in label {
+        match b {
+            b'.' | b'\\' => {
+                name.push('\\');
+                name.push(b as char);
+            }
+            0x21..=0x7e => name.push(b as char),
+            _ => name.push_str(&format!("\\{:03}", b)),
+        }
+    }
+}
+
+fn read_record(data: &[u8], offset: usize) -> Result<(Record, usize), DnsError> {
+    let (name, offset) = read_name(data, offset)?;
+    let rtype = read_u16(data, offset)?;
+    let class = read_u16(data, offset + 2)?;
+    let ttl = read_u32(data, offset + 4)?;
+    let rdlength = read_u16(data, offset + 8)? as usize;
+
+    let start = offset + 10;
+    let end = start + rdlength;
+    if end > data.len() {
+        return Err(DnsError::Truncated);
+    }
+
+    let rdata = match rtype {
+        TYPE_OPT => RData::Opt(read_edns(data, start, end, class, ttl)?),
+        _ => read_rdata(data, rtype, start, end)?,
+    };
+
+    Ok((
+        Record {
+            name,
+            rtype,
+            class,
+            ttl,
+            data: rdata,
+        },
+        end,
+    ))
+}
+
+// rdata 안의 이름도 메시지 전체를 기준으로 포인터를 따라가야 해서 data 전체와 범위를 받는다
+fn read_rdata(data: &[u8], rtype: u16, start: usize, end: usize) -> Result<RData, DnsError> {
+    let rdata = &data[start..end];
+    let bad = || DnsError::BadRdata(rtype);
+
+    // 이름을 읽되 rdata 범위를 넘어가면 안 된다
+    let name_at = |offset: usize| -> Result<(String, usize), DnsError> {
+        let (name, next) = read_name(data, offset)?;
+        if next > end {
+            return Err(bad());
+        }
+        Ok((name, next))
+    };
+
+    let parsed = match rtype {
+        TYPE_A => {
+            let octets: [u8; 4] = rdata.try_into().map_err(|_| bad())?;
+            RData::A(Ipv4Addr::from(octets))
+        }
+        TYPE_AAAA => {
+            let octets: [u8; 16] = rdata.try_into().map_err(|_| bad())?;
+            RData::Aaaa(Ipv6Addr::from(octets))
+        }
+        TYPE_CNAME | TYPE_NS | TYPE_PTR => {
+            let (name, _) = name_at(start)?;
+            match rtype {
+                TYPE_CNAME => RData::Cname(name),
+                TYPE_NS => RData::Ns(name),
+                _ => RData::Ptr(name),
+            }
+        }
+        TYPE_MX => {
+            if rdata.len() < 3 {
+                return Err(bad());
+            }
+            RData::Mx {
+                preference: read_u16(data, start)?,
+                exchange: name_at(start + 2)?.0,
+            }
+        }
+        TYPE_TXT => {
+            let mut strings = Vec::new();
+            let mut i = 0;
+            while i < rdata.len() {
+                let len = rdata[i] as usize;
+                let s = rdata.get(i + 1..i + 1 + len).ok_or_else(bad)?;
+                strings.push(s.to_vec());
+                i += 1 + len;
+            }
+            RData::Txt(strings)
+        }
+        TYPE_SOA => {
+            let (mname, next) = name_at(start)?;
+            let (rname, next) = name_at(next)?;
+            if next + 20 != end {
+                return Err(bad());
+            }
+            RData::Soa {
+                mname,
+                rname,
+                serial: read_u32(data, next)?,
+                refresh: read_u32(data, next + 4)?,
+                retry: read_u32(data, next + 8)?,
+                expire: read_u32(data, next + 12)?,
+                minimum: read_u32(data, next + 16)?,
+            }
+        }
+        TYPE_SRV => {
+            if rdata.len() < 7 {
+                return Err(bad());
+            }
+            RData::Srv {
+                priority: read_u16(data, start)?,
+                weight: read_u16(data, start + 2)?,
+                port: read_u16(data, start + 4)?,
+                // RFC 2782는 압축을 금지하지만 실제로는 쓰는 서버가 있다
+                target: name_at(start + 6)?.0,
+            }
+        }
+        TYPE_CAA => {
+            if rdata.len() < 2 {
+                return Err(bad());
+            }
+            let tag_len = rdata[1] as usize;
+            let tag = rdata.get(2..2 + tag_len).ok_or_else(bad)?;
+            RData::Caa {
+                flags: rdata[0],
+                tag: String::from_utf8_lossy(tag).into_owned(),
+                value: rdata[2 + tag_len..].to_vec(),
+            }
+        }
+        _ => RData::Unknown(rdata.to_vec()),
+    };
+
+    Ok(parsed)
+}
+
+fn read_edns(
+    data: &[u8],
+    start: usize,
+    end: usize,
+    class: u16,
+    ttl: u32,
+) -> Result<Edns, DnsError> {
+    let mut options = Vec::new();
+    let mut i = start;
+
+    while i < end {
+        let code = read_u16(data, i)?;
+        let len = read_u16(data, i + 2)? as usize;
+        let value = data
+            .get(i + 4..i + 4 + len)
+            .filter(|_| i + 4 + len <= end)
+            .ok_or(DnsError::BadRdata(TYPE_OPT))?;
+        options.push((code, value.to_vec()));
+        i += 4 + len;
+    }
+
+    Ok(Edns {
+        udp_payload_size: class,
+        extended_rcode: (ttl >> 24) as u8,
+        version: (ttl >> 16) as u8,
+        dnssec_ok: ttl & 0x8000 != 0,
+        options,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(name: &str, rtype: u16, ttl: u32, data: RData) -> Record {
+        Record {
+            name: name.to_string(),
+            rtype,
+            class: CLASS_IN,
+            ttl,
+            data,
+        }
+    }
+
+    // example.com A 질문에 CNAME + A로 답한 응답. 이름은 모두 포인터로 압축돼 있다
+    fn compressed_response() -> Vec<u8> {
+        let mut data = vec![0x12, 0x34, 0x81, 0x80, 0, 1, 0, 2, 0, 0, 0, 0];
+        // 12: question
+        data.extend(b"\x07example\x03com\x00");
+        data.extend([0, 1, 0, 1]);
+        // 29: example.com CNAME www.example.com
+        data.extend([0xc0, 12, 0, 5, 0, 1, 0, 0, 1, 44, 0, 6]);
+        // 41: "www" + example.com 포인터
+        data.extend(b"\x03www\xc0\x0c");
+        // www.example.com (41을 가리킨다) A 93.184.216.34
+        data.extend([0xc0, 41, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 93, 184, 216, 34]);
+        data
+    }
+
+    #[test]
+    fn follows_compression_pointers_in_owner_names_and_rdata() {
+        let message = DnsMessage::parse(&compressed_response()).unwrap();
+
+        assert_eq!(message.id, 0x1234);
+        assert!(message.is_response());
+        assert!(message.recursion_available());
+        assert_eq!(message.questions[0].name, "example.com");
+        assert_eq!(
+            message.answers,
+            [
+                record("example.com", TYPE_CNAME, 300, RData::Cname("www.example.com".to_string())),
+                record("www.example.com", TYPE_A, 60, RData::A(Ipv4Addr::new(93, 184, 216, 34))),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_pointer_loops_and_forward_pointers() {
+        let mut data = vec![0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+        // 자기 자신을 가리키는 포인터
+        data.extend([0xc0, 12, 0, 1, 0, 1]);
+        assert_eq!(DnsMessage::parse(&data), Err(DnsError::BadPointer));
+
+        // 뒤쪽을 가리키는 포인터 (둘이 서로 가리키는 순환의 시작)
+        data.truncate(12);
+        data.extend([0xc0, 14, 0xc0, 12, 0, 1, 0, 1]);
+        assert_eq!(DnsMessage::parse(&data), Err(DnsError::BadPointer));
+    }
+
+    #[test]
+    fn rejects_truncated_messages_and_bad_labels() {
+        let data = compressed_response();
+        for cut in [5, 20, 30, data.len() - 1] {
+            assert_eq!(DnsMessage::parse(&data[..cut]), Err(DnsError::Truncated), "cut at {}", cut);
+        }
+
+        let mut bad = data[..12].to_vec();
+        bad.extend([0x40, 0, 0, 1, 0, 1]);
+        assert_eq!(DnsMessage::parse(&bad), Err(DnsError::BadLabel));
+    }
+
+    #[test]
+    fn every_record_type_round_trips_through_all_sections() {
+        let mut message = DnsMessage::query(7, "example.com", TYPE_A).reply();
+        message.answers = vec![
+            record("example.com", TYPE_A, 1, RData::A(Ipv4Addr::new(192, 0, 2, 1))),
+            record("example.com", TYPE_AAAA, 2, RData::Aaaa("2001:db8::1".parse().unwrap())),
+            record("alias.example.com", TYPE_CNAME, 3, RData::Cname("example.com".to_string())),
+            record(
+                "example.com",
+                TYPE_MX,
+                4,
+                RData::Mx {
+                    preference: 10,
+                    exchange: "mail.example.com".to_string(),
+                },
+            ),
+            record("example.com", TYPE_TXT, 5, RData::Txt(vec![b"v=spf1 -all".to_vec(), Vec::new()])),
+            record(
+                "_sip._tcp.example.com",
+                TYPE_SRV,
+                6,
+                RData::Srv {
+                    priority: 1,
+                    weight: 2,
+                    port: 5060,
+                    target: "sip.example.com".to_string(),
+                },
+            ),
+            record(
+                "example.com",
+                TYPE_CAA,
+                7,
+                RData::Caa {
+                    flags: 0,
+                    tag: "issue".to_string(),
+                    value: b"letsencrypt.org".to_vec(),
+                },
+            ),
+            record("1.2.0.192.in-addr.arpa", TYPE_PTR, 8, RData::Ptr("example.com".to_string())),
+            record("example.com", 99, 9, RData::Unknown(vec![1, 2, 3])),
+        ];
+        message.authority = vec![
+            record("example.com", TYPE_NS, 10, RData::Ns("ns1.example.com".to_string())),
+            record(
+                "example.com",
+                TYPE_SOA,
+                11,
+                RData::Soa {
+                    mname: "ns1.example.com".to_string(),
+                    rname: "hostmaster.example.com".to_string(),
+                    serial: 2024010101,
+                    refresh: 7200,
+                    retry: 3600,
+                    expire: 1209600,
+                    minimum: 300,
+                },
+            ),
+        ];
+        message.additional = vec![record("ns1.example.com", TYPE_A, 12, RData::A(Ipv4Addr::new(192, 0, 2, 53)))];
+        message.set_edns(Some(Edns {
+            udp_payload_size: 1232,
+            extended_rcode: 0,
+            version: 0,
+            dnssec_ok: true,
+            options: vec![(10, vec![0xaa; 8])],
+        }));
+
+        let bytes = message.to_bytes().unwrap();
+        assert_eq!(DnsMessage::parse(&bytes).unwrap(), message);
+    }
+
+    #[test]
+    fn repeated_suffixes_are_written_as_pointers() {
+        let mut message = DnsMessage::query(1, "example.com", TYPE_A).reply();
+        message.answers = vec![record("www.example.com", TYPE_A, 1, RData::A(Ipv4Addr::LOCALHOST))];
+
+        let bytes = message.to_bytes().unwrap();
+        // question 이름은 그대로, answer는 "www" + question을 가리키는 포인터
+        assert_eq!(&bytes[29..35], b"\x03www\xc0\x0c");
+        assert_eq!(bytes.len(), 29 + 6 + 10 + 4);
+    }
+
+    #[test]
+    fn escaped_labels_survive_a_round_trip() {
+        let name = "a\\.b.x\\032y.example";
+        assert_eq!(
+            name_labels(name).unwrap(),
+            [b"a.b".to_vec(), b"x y".to_vec(), b"example".to_vec()]
+        );
+
+        let bytes = DnsMessage::query(1, name, TYPE_TXT).to_bytes().unwrap();
+        assert_eq!(DnsMessage::parse(&bytes).unwrap().questions[0].name, name);
+
+        assert!(matches!(name_labels("a..b"), Err(DnsError::BadName(_))));
+        assert!(matches!(name_labels(&"x".repeat(64)), Err(DnsError::BadName(_))));
+        assert_eq!(name_labels(&["a"; 128].join(".")), Err(DnsError::NameTooLong));
+    }
+
+    #[test]
+    fn extended_rcode_is_split_between_header_and_opt() {
+        let mut message = DnsMessage::query(1, "example.com", TYPE_A).reply();
+        message.set_edns(Some(Edns {
+            udp_payload_size: 1232,
+            extended_rcode: 0,
+            version: 0,
+            dnssec_ok: false,
+            options: Vec::new(),
+        }));
+        message.set_rcode(RCODE_BADVERS);
+
+        let parsed = DnsMessage::parse(&message.to_bytes().unwrap()).unwrap();
+        assert_eq!(parsed.flags & 0x000f, 0);
+        assert_eq!(parsed.rcode(), RCODE_BADVERS);
+    }
+
+    #[test]
+    fn random_ids_come_from_the_kernel() {
+        let ids: Vec<u16> = (0..16).map(|_| random_id().unwrap()).collect();
+        // 16개가 전부 같을 확률은 2^-240
+        assert!(ids.iter().any(|id| *id != ids[0]));
+    }
+}
@@ -0,0 +1,703 @@
+use crate::tcp::tls::load_certs;
+use crate::udp::dns_message::{
+    normalize_name, type_name, DnsMessage, Question, RData, Record, CLASS_IN, RCODE_NOERROR,
+    RCODE_NXDOMAIN, TYPE_A, TYPE_AAAA, TYPE_CNAME,
+};
+use crate::udp::dns_transport::{
+    ask_udp, parse_nameserver, DnsCache, DohMethod, EncryptedClients, ResolveError, DOT_PORT,
+};
+use rustls::pki_types::CertificateDer;
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// dns_message로 query를 만들어 nameserver에 묻는 stub resolver.
+// 1. nameserver는 /etc/resolv.conf (nameserver, search, options timeout/attempts/ndots/rotate) 또는 직접 준 목록
+// 2. query마다 id와 UDP source port를 새로 뽑고, 응답은 보낸 서버/id/question이 모두 맞을 때만 받는다
+// 3. 잘린 응답(TC)은 같은 서버에 TCP로 다시 묻고, CNAME 체인을 따라가며, 결과는 TTL만큼 cache한다
+// 4. nameserver는 평문 UDP 말고도 DoT(tls://)와 DoH(https://)로 줄 수 있다
+// 서버 하나에 묻는 부분(UDP/TCP, DoT/DoH, cache)은 dns_server와 같이 쓰도록 dns_transport에 있다.
+
+// ==================== CONFIG ====================
+
+const RESOLV_CONF: &str = "/etc/resolv.conf";
+// CNAME이 이보다 길게 이어지면 loop로 본다
+const MAX_CNAME_CHAIN: usize = 8;
+// resolv.conf가 nameserver를 3개까지만 보는 것과 맞춘다
+const MAX_NAMESERVERS: usize = 3;
+
+// query를 보낼 곳과 방법
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Nameserver {
+    // UDP로 묻고 잘리면 TCP
+    Udp(SocketAddr),
+    // DNS over TLS. server_name으로 인증서를 확인한다
+    Tls {
+        addr: SocketAddr,
+        server_name: String,
+    },
+    // DNS over HTTPS. https://dns.example/dns-query
+    Https {
+        url: String,
+        method: DohMethod,
+    },
+}
+
+impl From<SocketAddr> for Nameserver {
+    fn from(addr: SocketAddr) -> Self {
+        Nameserver::Udp(addr)
+    }
+}
+
+impl fmt::Display for Nameserver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Nameserver::Udp(addr) => write!(f, "{}", addr),
+            Nameserver::Tls { addr, server_name } => write!(f, "tls://{}#{}", addr, server_name),
+            Nameserver::Https { url, method } => write!(f, "{} ({})", url, method),
+        }
+    }
+}
+
+impl Nameserver {
+    // 1.1.1.1, 1.1.1.1:53            -> UDP
+    // tls://1.1.1.1#cloudflare-dns.com -> DoT, 포트를 빼면 853, 이름을 빼면 IP로 인증서를 확인한다
+    // https://host/dns-query          -> DoH GET
+    pub fn parse(value: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        if value.starts_with("https://") {
+            return Ok(Nameserver::Https {
+                url: value.to_string(),
+                method: DohMethod::Get,
+            });
+        }
+
+        let Some(rest) = value.strip_prefix("tls://") else {
+            return Ok(Nameserver::Udp(parse_nameserver(value)?));
+        };
+
+        let (addr, server_name) = match rest.split_once('#') {
+            Some((addr, name)) => (addr, Some(name)),
+            None => (rest, None),
+        };
+        let addr = match addr.parse::<SocketAddr>() {
+            Ok(addr) => addr,
+            Err(_) => SocketAddr::new(addr.parse::<IpAddr>()?, DOT_PORT),
+        };
+
+        Ok(Nameserver::Tls {
+            addr,
+            server_name: server_name
+                .map(str::to_string)
+                .unwrap_or_else(|| addr.ip().to_string()),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ResolverConfig {
+    pub nameservers: Vec<Nameserver>,
+    // 점이 ndots개보다 적은 이름은 search domain을 먼저 붙여 본다
+    pub search: Vec<String>,
+    pub ndots: usize,
+    // 한 서버에 한 번 묻고 기다리는 시간
+    pub timeout: Duration,
+    // nameserver 목록 전체를 도는 횟수
+    pub attempts: usize,
+    // 첫 서버를 query마다 돌려가며 고른다
+    pub rotate: bool,
+    // DoT/DoH 서버 인증서를 확인할 root. DoH(reqwest)는 시스템 root에 이것을 더한다
+    pub tls_roots: Vec<CertificateDer<'static>>,
+}
+
+impl Default for ResolverConfig {
+    fn default() -> Self {
+        Self {
+            nameservers: vec![Nameserver::Udp(SocketAddr::from(([127, 0, 0, 1], 53)))],
+            search: Vec::new(),
+            ndots: 1,
+            timeout: Duration::from_secs(5),
+            attempts: 2,
+            rotate: false,
+            tls_roots: Vec::new(),
+        }
+    }
+}
+
+impl ResolverConfig {
+    pub fn with_nameservers(nameservers: Vec<Nameserver>) -> Self {
+        Self {
+            nameservers,
+            ..Self::default()
+        }
+    }
+
+    // resolv.conf가 없거나 nameserver가 없으면 127.0.0.1:53 (glibc와 같다)
+    pub fn system() -> Self {
+        match std::fs::read_to_string(RESOLV_CONF) {
+            Ok(text) => Self::parse_resolv_conf(&text),
+            Err(e) => {
+                println!("[WARN] failed to read {}: {}", RESOLV_CONF, e);
+                Self::default()
+            }
+        }
+    }
+
+    pub fn from_resolv_conf(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::parse_resolv_conf(&std::fs::read_to_string(path)?))
+    }
+
+    // 모르는 줄과 잘못된 값은 무시한다
+    pub fn parse_resolv_conf(text: &str) -> Self {
+        let mut config = Self::default();
+        let mut nameservers = Vec::new();
+
+        for line in text.lines() {
+            let line = line.split(['#', ';']).next().unwrap_or_default();
+            let mut words = line.split_whitespace();
+
+            match words.next() {
+                Some("nameserver") => {
+                    // fe80::1%eth0 같은 scope는 떼고 읽는다
+                    let ip = words.next().and_then(|ip| ip.split('%').next());
+                    if let Some(ip) = ip.and_then(|ip| ip.parse::<IpAddr>().ok()) {
+                        if nameservers.len() < MAX_NAMESERVERS {
+                            nameservers.push(Nameserver::Udp(SocketAddr::new(ip, 53)));
+                        }
+                    }
+                }
+                // 둘 다 나오면 마지막 것이 이긴다
+                Some("search") => config.search = words.map(str::to_string).collect(),
+                Some("domain") => config.search = words.take(1).map(str::to_string).collect(),
+                Some("options") => {
+                    for option in words {
+                        let (key, value) = option.split_once(':').unwrap_or((option, ""));
+                        match key {
+                            "ndots" => config.ndots = value.parse().unwrap_or(config.ndots).min(15),
+                            "timeout" => {
+                                if let Ok(secs) = value.parse::<u64>() {
+                                    config.timeout = Duration::from_secs(secs.clamp(1, 30));
+                                }
+                            }
+                            "attempts" => {
+                                config.attempts =
+                                    value.parse().unwrap_or(config.attempts).clamp(1, 5)
+                            }
+                            "rotate" => config.rotate = true,
+                            _ => {}
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if !nameservers.is_empty() {
+            config.nameservers = nameservers;
+        }
+
+        config
+    }
+
+    // DNS_NAMESERVER (쉼표로 여러 개, Nameserver::parse 형식)가 있으면 그것을, 없으면 resolv.conf
+    // DNS_TLS_CA (PEM 파일)는 DoT/DoH 서버 인증서를 확인할 root에 더한다
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let mut config = Self::system();
+
+        if let Ok(value) = std::env::var("DNS_NAMESERVER") {
+            let mut nameservers = Vec::new();
+            for server in value.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+                nameservers.push(Nameserver::parse(server)?);
+            }
+            config.nameservers = nameservers;
+        }
+
+        if let Ok(path) = std::env::var("DNS_TLS_CA") {
+            config.tls_roots = load_certs(path)?;
+        }
+
+        Ok(config)
+    }
+}
+
+// ==================== RESOLVER ====================
+
+#[derive(Debug, Clone)]
+pub struct Answer {
+    // CNAME을 다 따라간 끝 이름
+    pub canonical: String,
+    // 물은 이름부터 canonical 직전까지
+    pub aliases: Vec<String>,
+    pub records: Vec<Record>,
+    // 체인 전체에서 가장 짧은 TTL
+    pub ttl: u32,
+}
+
+impl Answer {
+    pub fn ips(&self) -> Vec<IpAddr> {
+        self.records
+            .iter()
+            .filter_map(|record| match record.data {
+                RData::A(ip) => Some(IpAddr::V4(ip)),
+                RData::Aaaa(ip) => Some(IpAddr::V6(ip)),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+pub struct Resolver {
+    config: ResolverConfig,
+    encrypted: EncryptedClients,
+    cache: Mutex<DnsCache>,
+    // rotate일 때 다음에 먼저 물을 서버
+    next_server: AtomicUsize,
+}
+
+impl Resolver {
+    // DoT/DoH client를 tls_roots로 만들어 두기 때문에 root가 잘못됐으면 실패한다
+    pub fn new(config: ResolverConfig) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Self {
+            encrypted: EncryptedClients::new(&config.tls_roots)?,
+            config,
+            cache: Mutex::new(DnsCache::default()),
+            next_server: AtomicUsize::new(0),
+        })
+    }
+
+    pub fn system() -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Self::new(ResolverConfig::system())
+    }
+
+    pub fn config(&self) -> &ResolverConfig {
+        &self.config
+    }
+
+    pub fn cache_stats(&self) -> (usize, u64, u64) {
+        let cache = self.cache.lock().unwrap();
+        (cache.len(), cache.hits, cache.misses)
+    }
+
+    // 이름 하나를 그대로 묻는다 (search, CNAME 처리 없음). NXDOMAIN도 응답으로 돌려준다
+    pub async fn query(&self, name: &str, qtype: u16) -> Result<DnsMessage, ResolveError> {
+        let question = Question {
+            name: name.trim_end_matches('.').to_string(),
+            qtype,
+            qclass: CLASS_IN,
+        };
+
+        let cached = self.cache.lock().unwrap().get(&question, Instant::now());
+        if let Some(cached) = cached {
+            return Ok(cached);
+        }
+
+        let servers = &self.config.nameservers;
+        if servers.is_empty() {
+            return Err(ResolveError::NoNameservers);
+        }
+
+        let first = if self.config.rotate {
+            self.next_server.fetch_add(1, Ordering::Relaxed) % servers.len()
+        } else {
+            0
+        };
+
+        let mut last_error = ResolveError::Timeout;
+
+        // 서버 하나가 답하지 않거나 SERVFAIL이면 다음 서버로
+        for _ in 0..self.config.attempts.max(1) {
+            for i in 0..servers.len() {
+                let server = &servers[(first + i) % servers.len()];
+
+                match self.ask_server(server, &question).await {
+                    Ok(response) => match response.rcode() {
+                        RCODE_NOERROR | RCODE_NXDOMAIN => {
+                            self.cache
+                                .lock()
+                                .unwrap()
+                                .insert(&question, &response, Instant::now());
+                            return Ok(response);
+                        }
+                        rcode => last_error = ResolveError::Rcode(rcode),
+                    },
+                    Err(e) => last_error = e,
+                }
+            }
+        }
+
+        Err(last_error)
+    }
+
+    // 서버 하나에 그 서버의 transport로 한 번 묻는다
+    async fn ask_server(
+        &self,
+        server: &Nameserver,
+        question: &Question,
+    ) -> Result<DnsMessage, ResolveError> {
+        let wait = self.config.timeout;
+
+        match server {
+            Nameserver::Udp(addr) => ask_udp(*addr, question, wait).await,
+            Nameserver::Tls { addr, server_name } => {
+                self.encrypted
+                    .ask_tls(*addr, server_name, question, wait)
+                    .await
+            }
+            Nameserver::Https { url, method } => {
+                self.encrypted.ask_https(url, *method, question, wait).await
+            }
+        }
+    }
+
+    // search domain을 붙여 볼 이름들 (resolv.conf의 ndots 규칙)
+    fn candidates(&self, name: &str) -> Vec<String> {
+        if let Some(absolute) = name.strip_suffix('.') {
+            return vec![absolute.to_string()];
+        }
+
+        let searched = self
+            .config
+            .search
+            .iter()
+            .map(|domain| format!("{}.{}", name, domain.trim_end_matches('.')));
+
+        if name.matches('.').count() >= self.config.ndots {
+            std::iter::once(name.to_string()).chain(searched).collect()
+        } else {
+            searched.chain(std::iter::once(name.to_string())).collect()
+        }
+    }
+
+    // search domain을 붙여 보고, CNAME을 따라가서 물은 타입의 record를 돌려준다
+    pub async fn resolve(&self, name: &str, qtype: u16) -> Result<Answer, ResolveError> {
+        let mut last_error = ResolveError::NxDomain(name.to_string());
+
+        for candidate in self.candidates(name) {
+            match self.resolve_exact(&candidate, qtype).await {
+                Ok(answer) => return Ok(answer),
+                // 이 이름에 없으면 다음 후보
+                Err(e @ (ResolveError::NxDomain(_) | ResolveError::NoRecords(_))) => last_error = e,
+                Err(e) => return Err(e),
+            }
+        }
+
+        Err(last_error)
+    }
+
+    async fn resolve_exact(&self, name: &str, qtype: u16) -> Result<Answer, ResolveError> {
+        let mut current = name.trim_end_matches('.').to_string();
+        let mut aliases: Vec<String> = Vec::new();
+        let mut ttl = u32::MAX;
+
+        loop {
+            let response = self.query(&current, qtype).await?;
+            let asked = current.clone();
+
+            // 응답 안에 있는 체인을 따라간다. 체인 밖의 이름에 대한 record는 믿지 않는다
+            loop {
+                let key = normalize_name(&current);
+                let owned_by = |record: &&Record| {
+                    record.class == CLASS_IN && normalize_name(&record.name) == key
+                };
+
+                let records: Vec<Record> = response
+                    .answers
+                    .iter()
+                    .filter(owned_by)
+                    .filter(|record| record.rtype == qtype)
+                    .cloned()
+                    .collect();
+
+                if !records.is_empty() {
+                    let ttl = records.iter().map(|r| r.ttl).fold(ttl, u32::min);
+                    return Ok(Answer {
+                        canonical: current,
+                        aliases,
+                        records,
+                        ttl,
+                    });
+                }
+
+                let Some(cname) = response
+                    .answers
+                    .iter()
+                    .filter(owned_by)
+                    .find(|record| record.rtype == TYPE_CNAME)
+                else {
+                    break;
+                };
+                let RData::Cname(target) = &cname.data else {
+                    break;
+                };
+
+                let target = target.clone();
+                let looped = aliases
+                    .iter()
+                    .chain(std::iter::once(&current))
+                    .any(|alias| normalize_name(alias) == normalize_name(&target));
+                if looped || aliases.len() >= MAX_CNAME_CHAIN {
+                    return Err(ResolveError::CnameLoop(name.to_string()));
+                }
+
+                ttl = ttl.min(cname.ttl);
+                aliases.push(std::mem::replace(&mut current, target));
+            }
+
+            // NXDOMAIN은 체인 끝 이름에 대한 것
+            if response.rcode() == RCODE_NXDOMAIN {
+                return Err(ResolveError::NxDomain(current));
+            }
+
+            // 체인이 이 응답 밖으로 이어지면 끝 이름을 다시 묻는다
+            if current == asked {
+                return Err(ResolveError::NoRecords(current));
+            }
+        }
+    }
+
+    // 주소 literal이면 그대로, 아니면 A와 AAAA를 같이 묻는다
+    pub async fn lookup_ip(&self, host: &str) -> Result<Vec<IpAddr>, ResolveError> {
+        if let Ok(ip) = host.parse::<IpAddr>() {
+            return Ok(vec![ip]);
+        }
+
+        let (v4, v6) = tokio::join!(self.resolve(host, TYPE_A), self.resolve(host, TYPE_AAAA));
+
+        let mut ips = Vec::new();
+        if let Ok(answer) = &v4 {
+            ips.extend(answer.ips());
+        }
+        if let Ok(answer) = &v6 {
+            ips.extend(answer.ips());
+        }
+
+        // 둘 다 실패했을 때만 에러. IPv4 쪽 이유를 보여 준다
+        match (ips.is_empty(), v4) {
+            (false, _) => Ok(ips),
+            (true, Err(e)) => Err(e),
+            (true, Ok(_)) => Err(ResolveError::NoRecords(host.to_string())),
+        }
+    }
+
+    // TcpStream::connect 등에 바로 넘길 주소
+    pub async fn lookup_socket_addrs(
+        &self,
+        host: &str,
+        port: u16,
+    ) -> Result<Vec<SocketAddr>, ResolveError> {
+        Ok(self
+            .lookup_ip(host)
+            .await?
+            .into_iter()
+            .map(|ip| SocketAddr::new(ip, port))
+            .collect())
+    }
+}
+
+// cargo run -- www.example.com [AAAA]
+// DNS_NAMESERVER=127.0.0.1:5353 이면 resolv.conf 대신 그 서버에 묻는다 (udp::dns_server와 같이 써 볼 수 있다)
+// DoT/DoH: DNS_NAMESERVER=tls://127.0.0.1:8853#localhost 또는 https://localhost:8443/dns-query, DNS_TLS_CA=ca.pem
+#[tokio::main]
+pub async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let config = ResolverConfig::from_env()?;
+    let nameservers: Vec<String> = config.nameservers.iter().map(|s| s.to_string()).collect();
+    println!(
+        "[INFO] nameservers: {:?} search: {:?} ndots={} timeout={:?} attempts={}",
+        nameservers, config.search, config.ndots, config.timeout, config.attempts
+    );
+
+    let resolver = Resolver::new(config)?;
+
+    let mut args = std::env::args().skip(1);
+    let name = args.next().unwrap_or_else(|| "example.com".to_string());
+    let qtype = match args.next().as_deref() {
+        Some("AAAA") | Some("aaaa") => TYPE_AAAA,
+        Some("CNAME") | Some("cname") => TYPE_CNAME,
+        Some(_) | None => TYPE_A,
+    };
+
+    // 두 번째는 cache에서 나와야 한다
+    for _ in 0..2 {
+        let started = Instant::now();
+        match resolver.resolve(&name, qtype).await {
+            Ok(answer) => {
+                println!(
+                    "[DNS] {} {} -> {} (aliases {:?}, ttl {}s, {:?})",
+                    name,
+                    type_name(qtype),
+                    answer.canonical,
+                    answer.aliases,
+                    answer.ttl,
+                    started.elapsed()
+                );
+                for record in &answer.records {
+                    println!("  {}", record);
+                }
+            }
+            Err(e) => println!("[DNS] {} {} -> {}", name, type_name(qtype), e),
+        }
+    }
+
+    match resolver.lookup_ip(&name).await {
+        Ok(ips) => println!("[DNS] lookup_ip {} -> {:?}", name, ips),
+        Err(e) => println!("[DNS] lookup_ip {} -> {}", name, e),
+    }
+
+    let (entries, hits, misses) = resolver.cache_stats();
+    println!(
+        "[INFO] cache entries={} hits={} misses={}",
+        entries, hits, misses
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::udp::dns_message::RCODE_REFUSED;
+    use crate::udp::dns_server::{serve_tcp, serve_udp, DnsServer, Zone};
+    use std::net::Ipv4Addr;
+    use std::sync::Arc;
+    use tokio::net::{TcpListener, UdpSocket};
+
+    const LOOP_ZONE: &str = "\
+$ORIGIN loop.test.
+$TTL 60
+@         IN SOA   ns hostmaster 1 3600 600 604800 30
+a         IN CNAME b
+b         IN CNAME a
+dangling  IN CNAME gone
+";
+
+    fn zone(file: &str) -> Zone {
+        Zone::load(format!("{}/zones/{}", env!("CARGO_MANIFEST_DIR"), file)).unwrap()
+    }
+
+    async fn start(server: DnsServer) -> SocketAddr {
+        let server = Arc::new(server);
+        let udp = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = udp.local_addr().unwrap();
+        let tcp = TcpListener::bind(addr).await.unwrap();
+
+        tokio::spawn(serve_udp(server.clone(), udp));
+        tokio::spawn(serve_tcp(server, tcp));
+        addr
+    }
+
+    fn resolver(nameserver: SocketAddr) -> Resolver {
+        Resolver::new(ResolverConfig {
+            timeout: Duration::from_secs(2),
+            attempts: 1,
+            ..ResolverConfig::with_nameservers(vec![nameserver.into()])
+        })
+        .unwrap()
+    }
+
+    fn v4(a: u8, b: u8, c: u8, d: u8) -> IpAddr {
+        IpAddr::V4(Ipv4Addr::new(a, b, c, d))
+    }
+
+    #[tokio::test]
+    async fn cname_chain_in_one_response() {
+        let server = start(DnsServer::new(vec![zone("example.test.zone")], None)).await;
+        let resolver = resolver(server);
+
+        let answer = resolver
+            .resolve("alias.example.test", TYPE_A)
+            .await
+            .unwrap();
+
+        assert_eq!(answer.canonical, "www.example.test");
+        assert_eq!(
+            answer.aliases,
+            vec!["alias.example.test", "api.example.test"]
+        );
+        assert_eq!(answer.ips(), vec![v4(127, 0, 0, 20)]);
+        // api의 CNAME TTL(60)이 체인에서 가장 짧다
+        assert_eq!(answer.ttl, 60);
+    }
+
+    #[tokio::test]
+    async fn cname_leaving_the_response_is_asked_again() {
+        let upstream = start(DnsServer::new(vec![zone("upstream.test.zone")], None)).await;
+        let server = start(DnsServer::new(
+            vec![zone("example.test.zone")],
+            Some(upstream),
+        ))
+        .await;
+        let resolver = resolver(server);
+
+        // example.test는 CNAME까지만 답하고 www.upstream.test는 따로 물어야 한다
+        let answer = resolver
+            .resolve("external.example.test", TYPE_A)
+            .await
+            .unwrap();
+
+        assert_eq!(answer.canonical, "www.upstream.test");
+        assert_eq!(answer.aliases, vec!["external.example.test"]);
+        assert_eq!(answer.ips(), vec![v4(127, 0, 1, 1)]);
+        assert_eq!(resolver.cache_stats(), (2, 0, 2));
+    }
+
+    #[tokio::test]
+    async fn cname_loop_and_dangling_cname_are_errors() {
+        let zone = Zone::parse(LOOP_ZONE, "loop.test").unwrap();
+        let resolver = resolver(start(DnsServer::new(vec![zone], None)).await);
+
+        let looped = resolver.resolve("a.loop.test", TYPE_A).await;
+        assert!(matches!(looped, Err(ResolveError::CnameLoop(name)) if name == "a.loop.test"));
+
+        // NXDOMAIN은 체인 끝 이름에 대한 것
+        let dangling = resolver.resolve("dangling.loop.test", TYPE_A).await;
+        assert!(matches!(dangling, Err(ResolveError::NxDomain(name)) if name == "gone.loop.test"));
+    }
+
+    #[tokio::test]
+    async fn search_domain_is_tried_and_the_answer_is_cached() {
+        let server = start(DnsServer::new(vec![zone("example.test.zone")], None)).await;
+        let resolver = Resolver::new(ResolverConfig {
+            search: vec!["example.test".to_string()],
+            ..ResolverConfig::with_nameservers(vec![server.into()])
+        })
+        .unwrap();
+
+        for _ in 0..2 {
+            let answer = resolver.resolve("www", TYPE_A).await.unwrap();
+            assert_eq!(answer.canonical, "www.example.test");
+        }
+
+        // 두 번째는 nameserver에 묻지 않는다
+        assert_eq!(resolver.cache_stats(), (1, 1, 1));
+
+        // search를 붙인 이름이 없으면 그대로의 이름도 묻는다. zone 밖이라 REFUSED
+        let missing = resolver.resolve("nope", TYPE_A).await;
+        assert!(matches!(missing, Err(ResolveError::Rcode(RCODE_REFUSED))));
+    }
+
+    #[test]
+    fn resolv_conf_options_are_read() {
+        let config = ResolverConfig::parse_resolv_conf(
+            "nameserver 10.0.0.1\n\
+             nameserver fe80::1%eth0\n\
+             search corp.example example.test # comment\n\
+             options ndots:2 timeout:3 attempts:9 rotate\n",
+        );
+
+        assert_eq!(
+            config.nameservers,
+            vec![
+                Nameserver::Udp("10.0.0.1:53".parse().unwrap()),
+                Nameserver::Udp("[fe80::1]:53".parse().unwrap()),
+            ]
+        );
+        assert_eq!(config.search, vec!["corp.example", "example.test"]);
+        assert_eq!(config.ndots, 2);
+        assert_eq!(config.timeout, Duration::from_secs(3));
+        assert_eq!(config.attempts, 5);
+        assert!(config.rotate);
+    }
+}
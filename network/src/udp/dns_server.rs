@@ -0,0 +1,1483 @@
+use crate::udp::dns_message::{
+    name_labels, normalize_name, rcode_name, type_name, DnsMessage, Edns, Question, RData, Record,
+    CLASS_IN, FLAG_AA, FLAG_RA, FLAG_TC, RCODE_BADVERS, RCODE_FORMERR, RCODE_NOTIMP,
+    RCODE_NXDOMAIN, RCODE_REFUSED, RCODE_SERVFAIL, TYPE_A, TYPE_AAAA, TYPE_CAA, TYPE_CNAME,
+    TYPE_MX, TYPE_NS, TYPE_OPT, TYPE_PTR, TYPE_SOA, TYPE_SRV, TYPE_TXT,
+};
+use crate::udp::dns_transport::{exchange, parse_nameserver, DnsCache, EDNS_PAYLOAD};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, UdpSocket};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::timeout;
+
+// dns_message 위에 올린 작은 DNS 서버.
+// 1. zone 파일(RFC 1035 master file)로 읽은 zone은 직접 답한다 (AA, CNAME 추적, NXDOMAIN/NODATA + SOA, 위임)
+// 2. 그 밖의 이름은 RD가 켜져 있으면 upstream으로 넘기고 (dns_transport::exchange), 답을 TTL만큼 cache한다
+// 3. UDP는 client가 EDNS0로 알린 크기(없으면 512)를 넘으면 TC를 켜서 TCP로 다시 묻게 하고,
+//    TCP는 2바이트 길이 prefix로 한 연결에서 여러 query를 받는다.
+// upstream도 이 서버로 띄울 수 있어서 loopback에서 두 개를 띄우면 forwarding까지 확인할 수 있다.
+// DoT/DoH front-end는 dns_encrypted에 있고 같은 DnsServer::handle을 부른다.
+
+// ==================== CONFIG ====================
+
+// EDNS가 없는 client에게 보내는 최대 크기
+const CLASSIC_UDP_PAYLOAD: usize = 512;
+const TYPE_ANY: u16 = 255;
+// CNAME이 이보다 길게 이어지면 더 따라가지 않는다
+const MAX_CNAME_CHAIN: usize = 8;
+// 아무 query도 보내지 않는 TCP 연결은 이만큼 기다리고 끊는다
+const TCP_IDLE: Duration = Duration::from_secs(10);
+const UPSTREAM_TIMEOUT: Duration = Duration::from_secs(2);
+const UPSTREAM_ATTEMPTS: usize = 2;
+// 동시에 처리하는 UDP query 수. 다 차면 recv를 멈추고, 밀린 query는 커널 버퍼에서 기다리다 버려진다
+const MAX_UDP_INFLIGHT: usize = 256;
+// 동시에 열어 두는 TCP/DoT 연결 수. 두 front-end가 같은 DnsServer의 자리를 나눠 쓴다
+const MAX_STREAM_CONNECTIONS: usize = 512;
+
+#[derive(Debug, Clone)]
+pub struct DnsServerConfig {
+    pub listen: SocketAddr,
+    pub zone_files: Vec<PathBuf>,
+    // 없으면 zone 밖의 이름은 REFUSED
+    pub upstream: Option<SocketAddr>,
+}
+
+impl Default for DnsServerConfig {
+    fn default() -> Self {
+        Self {
+            listen: SocketAddr::from(([127, 0, 0, 1], 5353)),
+            zone_files: vec![PathBuf::from("zones/example.test.zone")],
+            upstream: None,
+        }
+    }
+}
+
+impl DnsServerConfig {
+    // DNS_LISTEN (127.0.0.1:5353), DNS_ZONE (쉼표로 여러 개), DNS_UPSTREAM (ip 또는 ip:port) 로 덮어쓴다
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let mut config = Self::default();
+
+        if let Ok(listen) = std::env::var("DNS_LISTEN") {
+            config.listen = listen.parse()?;
+        }
+
+        if let Ok(zones) = std::env::var("DNS_ZONE") {
+            config.zone_files = zones
+                .split(',')
+                .map(str::trim)
+                .filter(|path| !path.is_empty())
+                .map(PathBuf::from)
+                .collect();
+        }
+
+        if let Ok(upstream) = std::env::var("DNS_UPSTREAM") {
+            config.upstream = Some(parse_nameserver(&upstream)?);
+        }
+
+        Ok(config)
+    }
+}
+
+// ==================== ZONE FILE ====================
+
+#[derive(Debug)]
+pub struct ZoneError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ZoneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "zone line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ZoneError {}
+
+#[derive(Debug)]
+struct Token {
+    text: String,
+    // 따옴표로 감싼 문자열 (TXT, CAA value)
+    quoted: bool,
+}
+
+// 괄호로 여러 줄에 걸친 것까지 합친 한 항목
+struct Entry {
+    line: usize,
+    // 줄이 공백으로 시작하면 앞 record의 owner를 그대로 쓴다
+    inherit_owner: bool,
+    tokens: Vec<Token>,
+}
+
+fn zone_error(line: usize, message: impl Into<String>) -> ZoneError {
+    ZoneError {
+        line,
+        message: message.into(),
+    }
+}
+
+// 주석(;)을 지우고, 따옴표 문자열을 한 token으로, 괄호 안의 줄바꿈을 이어 붙인다
+fn tokenize(text: &str) -> Result<Vec<Entry>, ZoneError> {
+    let mut entries = Vec::new();
+    let mut current: Option<Entry> = None;
+    let mut depth = 0usize;
+
+    for (index, line) in text.lines().enumerate() {
+        let line_no = index + 1;
+        let entry = current.get_or_insert_with(|| Entry {
+            line: line_no,
+            inherit_owner: line.starts_with([' ', '\t']),
+            tokens: Vec::new(),
+        });
+
+        let mut chars = line.chars();
+        let mut word = String::new();
+        let mut in_quote = false;
+
+        let flush = |word: &mut String, tokens: &mut Vec<Token>| {
+            if !word.is_empty() {
+                tokens.push(Token {
+                    text: std::mem::take(word),
+                    quoted: false,
+                });
+            }
+        };
+
+        while let Some(c) = chars.next() {
+            if in_quote {
+                match c {
+                    '\\' => {
+                        word.push(c);
+                        word.extend(chars.next());
+                    }
+                    '"' => {
+                        entry.tokens.push(Token {
+                            text: std::mem::take(&mut word),
+                            quoted: true,
+                        });
+                        in_quote = false;
+                    }
+                    _ => word.push(c),
+                }
+                continue;
+            }
+
+            match c {
+                ';' => break,
+                '"' => {
+                    flush(&mut word, &mut entry.tokens);
+                    in_quote = true;
+                }
+                '(' => {
+                    flush(&mut word, &mut entry.tokens);
+                    depth += 1;
+                }
+                ')' => {
+                    flush(&mut word, &mut entry.tokens);
+                    depth = depth
+                        .checked_sub(1)
+                        .ok_or_else(|| zone_error(line_no, "unbalanced ')'"))?;
+                }
+                '\\' => {
+                    word.push(c);
+                    word.extend(chars.next());
+                }
+                c if c.is_whitespace() => flush(&mut word, &mut entry.tokens),
+                _ => word.push(c),
+            }
+        }
+
+        if in_quote {
+            return Err(zone_error(line_no, "unterminated string"));
+        }
+        flush(&mut word, &mut entry.tokens);
+
+        // 괄호가 열려 있으면 다음 줄도 같은 항목
+        if depth == 0 {
+            if let Some(entry) = current.take() {
+                if !entry.tokens.is_empty() {
+                    entries.push(entry);
+                }
+            }
+        }
+    }
+
+    if depth > 0 {
+        let line = current.map(|entry| entry.line).unwrap_or(0);
+        return Err(zone_error(line, "unbalanced '('"));
+    }
+
+    Ok(entries)
+}
+
+// "\." 와 "\DDD" 를 푼 바이트
+fn unescape(text: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len());
+    let mut bytes = text.bytes();
+
+    while let Some(b) = bytes.next() {
+        if b != b'\\' {
+            out.push(b);
+            continue;
+        }
+
+        let first = bytes.next()?;
+        if first.is_ascii_digit() {
+            let mut value = (first - b'0') as u32;
+            for _ in 0..2 {
+                let digit = bytes.next().filter(u8::is_ascii_digit)?;
+                value = value * 10 + (digit - b'0') as u32;
+            }
+            out.push(u8::try_from(value).ok()?);
+        } else {
+            out.push(first);
+        }
+    }
+
+    Some(out)
+}
+
+// 1h30m 같은 단위도 받는다
+fn parse_ttl(text: &str) -> Option<u32> {
+    if let Ok(value) = text.parse::<u32>() {
+        return Some(value);
+    }
+
+    let mut total: u32 = 0;
+    let mut number = String::new();
+
+    for c in text.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+
+        let unit = match c.to_ascii_lowercase() {
+            's' => 1,
+            'm' => 60,
+            'h' => 3_600,
+            'd' => 86_400,
+            'w' => 604_800,
+            _ => return None,
+        };
+        let value: u32 = std::mem::take(&mut number).parse().ok()?;
+        total = total.checked_add(value.checked_mul(unit)?)?;
+    }
+
+    if !number.is_empty() {
+        return None;
+    }
+    Some(total)
+}
+
+fn parse_rdata(
+    rtype: &str,
+    args: &[&Token],
+    origin: &str,
+    line: usize,
+) -> Result<RData, ZoneError> {
+    let field = |i: usize| {
+        args.get(i)
+            .map(|token| token.text.as_str())
+            .ok_or_else(|| zone_error(line, format!("{} record needs more fields", rtype)))
+    };
+    let invalid = |i: usize| {
+        zone_error(
+            line,
+            format!("invalid {} field: {}", rtype, field(i).unwrap_or("")),
+        )
+    };
+    let u16_at = |i: usize| field(i)?.parse::<u16>().map_err(|_| invalid(i));
+    let ttl_at = |i: usize| parse_ttl(field(i)?).ok_or_else(|| invalid(i));
+    let name_at = |i: usize| absolute_name(field(i)?, origin, line);
+    let expect = |count: usize| {
+        if args.len() == count {
+            Ok(())
+        } else {
+            Err(zone_error(
+                line,
+                format!(
+                    "{} record takes {} fields, got {}",
+                    rtype,
+                    count,
+                    args.len()
+                ),
+            ))
+        }
+    };
+
+    let data = match rtype {
+        "A" => {
+            expect(1)?;
+            RData::A(field(0)?.parse().map_err(|_| invalid(0))?)
+        }
+        "AAAA" => {
+            expect(1)?;
+            RData::Aaaa(field(0)?.parse().map_err(|_| invalid(0))?)
+        }
+        "NS" => {
+            expect(1)?;
+            RData::Ns(name_at(0)?)
+        }
+        "CNAME" => {
+            expect(1)?;
+            RData::Cname(name_at(0)?)
+        }
+        "PTR" => {
+            expect(1)?;
+            RData::Ptr(name_at(0)?)
+        }
+        "MX" => {
+            expect(2)?;
+            RData::Mx {
+                preference: u16_at(0)?,
+                exchange: name_at(1)?,
+            }
+        }
+        "TXT" => {
+            if args.is_empty() {
+                return Err(zone_error(line, "TXT record needs a string"));
+            }
+            let mut strings = Vec::with_capacity(args.len());
+            for (i, token) in args.iter().enumerate() {
+                // character-string 하나는 255바이트까지
+                match unescape(&token.text) {
+                    Some(bytes) if bytes.len() <= 255 => strings.push(bytes),
+                    _ => return Err(invalid(i)),
+                }
+            }
+            RData::Txt(strings)
+        }
+        "SOA" => {
+            expect(7)?;
+            RData::Soa {
+                mname: name_at(0)?,
+                rname: name_at(1)?,
+                serial: field(2)?.parse().map_err(|_| invalid(2))?,
+                refresh: ttl_at(3)?,
+                retry: ttl_at(4)?,
+                expire: ttl_at(5)?,
+                minimum: ttl_at(6)?,
+            }
+        }
+        "SRV" => {
+            expect(4)?;
+            RData::Srv {
+                priority: u16_at(0)?,
+                weight: u16_at(1)?,
+                port: u16_at(2)?,
+                target: name_at(3)?,
+            }
+        }
+        "CAA" => {
+            expect(3)?;
+            RData::Caa {
+                flags: field(0)?.parse().map_err(|_| invalid(0))?,
+                tag: field(1)?.to_string(),
+                value: unescape(field(2)?).ok_or_else(|| invalid(2))?,
+            }
+        }
+        other => {
+            return Err(zone_error(
+                line,
+                format!("unsupported record type {}", other),
+            ))
+        }
+    };
+
+    Ok(data)
+}
+
+// 끝의 '.'이 escape되지 않았으면 절대 이름
+fn is_absolute(name: &str) -> bool {
+    match name.strip_suffix('.') {
+        Some(body) => body.bytes().rev().take_while(|&b| b == b'\\').count() % 2 == 0,
+        None => false,
+    }
+}
+
+// '@'와 상대 이름을 origin 기준으로 풀어서 끝의 '.' 없는 표기로
+fn absolute_name(name: &str, origin: &str, line: usize) -> Result<String, ZoneError> {
+    let absolute = if name == "@" {
+        origin.to_string()
+    } else if is_absolute(name) {
+        match &name[..name.len() - 1] {
+            "" => ".".to_string(),
+            trimmed => trimmed.to_string(),
+        }
+    } else if origin == "." {
+        name.to_string()
+    } else {
+        format!("{}.{}", name, origin)
+    };
+
+    name_labels(&absolute).map_err(|e| zone_error(line, e.to_string()))?;
+    Ok(absolute)
+}
+
+// 첫 label을 떼어낸 부모 이름. root면 None
+fn parent_name(name: &str) -> Option<&str> {
+    if name == "." {
+        return None;
+    }
+
+    let bytes = name.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'.' => return Some(&name[i + 1..]),
+            _ => i += 1,
+        }
+    }
+    Some(".")
+}
+
+// name이 parent와 같거나 그 아래인지. 둘 다 normalize_name을 거친 값
+fn is_subdomain(name: &str, parent: &str) -> bool {
+    let mut current = Some(name);
+    while let Some(candidate) = current {
+        if candidate == parent {
+            return true;
+        }
+        current = parent_name(candidate);
+    }
+    false
+}
+
+// ==================== ZONE ====================
+
+// 한 이름에서 찾은 결과
+#[derive(Debug)]
+enum Lookup {
+    Answer(Vec<Record>),
+    // 다른 타입을 물었는데 CNAME이 있다. target을 이어서 찾는다
+    Cname(Record),
+    // apex 아래의 NS. 그 아래는 이 zone이 답하지 않는다
+    Referral(Vec<Record>),
+    NoData,
+    NxDomain,
+}
+
+#[derive(Debug)]
+pub struct Zone {
+    // 표기 그대로 (끝의 '.' 없음)
+    origin: String,
+    // normalize_name(origin)
+    key: String,
+    soa: Record,
+    // normalize_name(owner) → record
+    records: HashMap<String, Vec<Record>>,
+    // record가 있는 이름과 그 조상(origin까지). empty non-terminal 판단용
+    nodes: HashSet<String>,
+}
+
+impl Zone {
+    // 파일 이름이 example.test.zone 이면 $ORIGIN이 없을 때 example.test 로 본다
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)?;
+
+        let file_name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default();
+        let origin = file_name.strip_suffix(".zone").unwrap_or(file_name);
+
+        Ok(Self::parse(&text, origin)?)
+    }
+
+    pub fn parse(text: &str, origin: &str) -> Result<Self, ZoneError> {
+        let mut origin = absolute_name(origin.trim_end_matches('.'), ".", 0)?;
+        // 파일 맨 위의 $ORIGIN이 zone의 이름이 된다
+        let mut zone_origin: Option<String> = None;
+        let mut default_ttl: Option<u32> = None;
+        let mut last_owner: Option<String> = None;
+        let mut last_ttl: Option<u32> = None;
+        let mut records = Vec::new();
+
+        for entry in tokenize(text)? {
+            let line = entry.line;
+            let mut tokens = entry.tokens.iter().peekable();
+
+            // 1. directive
+            let first = &entry.tokens[0];
+            if !entry.inherit_owner && !first.quoted && first.text.starts_with('$') {
+                let directive = entry.tokens[0].text.to_ascii_uppercase();
+                let argument = entry
+                    .tokens
+                    .get(1)
+                    .ok_or_else(|| zone_error(line, format!("{} needs an argument", directive)))?;
+
+                match directive.as_str() {
+                    "$ORIGIN" => origin = absolute_name(&argument.text, &origin, line)?,
+                    "$TTL" => {
+                        default_ttl = Some(
+                            parse_ttl(&argument.text)
+                                .ok_or_else(|| zone_error(line, "invalid $TTL"))?,
+                        )
+                    }
+                    other => return Err(zone_error(line, format!("unsupported {}", other))),
+                }
+                continue;
+            }
+
+            zone_origin.get_or_insert_with(|| origin.clone());
+
+            // 2. owner
+            let owner = if entry.inherit_owner {
+                last_owner
+                    .clone()
+                    .ok_or_else(|| zone_error(line, "no previous owner"))?
+            } else {
+                let token = tokens
+                    .next()
+                    .map(|token| token.text.as_str())
+                    .unwrap_or("@");
+                absolute_name(token, &origin, line)?
+            };
+
+            // 3. ttl과 class는 순서 상관없이 생략 가능
+            let mut ttl = None;
+            let mut class = CLASS_IN;
+            while let Some(token) = tokens.peek() {
+                if token.quoted {
+                    break;
+                } else if token.text.starts_with(|c: char| c.is_ascii_digit()) {
+                    ttl = Some(
+                        parse_ttl(&token.text).ok_or_else(|| zone_error(line, "invalid ttl"))?,
+                    );
+                } else if token.text.eq_ignore_ascii_case("IN") {
+                    class = CLASS_IN;
+                } else if ["CH", "HS", "CS"]
+                    .iter()
+                    .any(|c| token.text.eq_ignore_ascii_case(c))
+                {
+                    return Err(zone_error(line, "only class IN is supported"));
+                } else {
+                    break;
+                }
+                tokens.next();
+            }
+
+            let rtype = tokens
+                .next()
+                .ok_or_else(|| zone_error(line, "missing record type"))?
+                .text
+                .to_ascii_uppercase();
+            let rdata: Vec<&Token> = tokens.collect();
+
+            let data = parse_rdata(&rtype, &rdata, &origin, line)?;
+            let rtype = match &data {
+                RData::A(_) => TYPE_A,
+                RData::Aaaa(_) => TYPE_AAAA,
+                RData::Ns(_) => TYPE_NS,
+                RData::Cname(_) => TYPE_CNAME,
+                RData::Ptr(_) => TYPE_PTR,
+                RData::Mx { .. } => TYPE_MX,
+                RData::Txt(_) => TYPE_TXT,
+                RData::Soa { .. } => TYPE_SOA,
+                RData::Srv { .. } => TYPE_SRV,
+                RData::Caa { .. } => TYPE_CAA,
+                RData::Opt(_) | RData::Unknown(_) => unreachable!("parse_rdata만 만든다"),
+            };
+
+            // TTL이 없으면 $TTL, 그것도 없으면 앞 record 것
+            let ttl = match ttl.or(default_ttl).or(last_ttl) {
+                Some(ttl) => ttl,
+                None => match &data {
+                    RData::Soa { minimum, .. } => *minimum,
+                    _ => return Err(zone_error(line, "no TTL and no $TTL")),
+                },
+            };
+
+            last_owner = Some(owner.clone());
+            last_ttl = Some(ttl);
+            records.push((
+                line,
+                Record {
+                    name: owner,
+                    rtype,
+                    class,
+                    ttl,
+                    data,
+                },
+            ));
+        }
+
+        let origin = zone_origin.unwrap_or(origin);
+        Self::build(origin, records)
+    }
+
+    fn build(origin: String, records: Vec<(usize, Record)>) -> Result<Self, ZoneError> {
+        let key = normalize_name(&origin);
+        let mut soa = None;
+        let mut by_name: HashMap<String, Vec<Record>> = HashMap::new();
+        let mut nodes = HashSet::new();
+        nodes.insert(key.clone());
+
+        for (line, record) in records {
+            let owner = normalize_name(&record.name);
+
+            if !is_subdomain(&owner, &key) {
+                return Err(zone_error(
+                    line,
+                    format!("{} is outside of zone {}", record.name, origin),
+                ));
+            }
+
+            if record.rtype == TYPE_SOA {
+                if owner != key || soa.is_some() {
+                    return Err(zone_error(line, "exactly one SOA is allowed, at the apex"));
+                }
+                soa = Some(record.clone());
+            }
+
+            // CNAME은 다른 data와 같은 이름에 있을 수 없다 (RFC 1034 3.6.2)
+            let existing = by_name.entry(owner.clone()).or_default();
+            let has_cname = existing.iter().any(|r| r.rtype == TYPE_CNAME);
+            if !existing.is_empty() && (has_cname || record.rtype == TYPE_CNAME) {
+                return Err(zone_error(
+                    line,
+                    format!("CNAME and other data at {}", record.name),
+                ));
+            }
+            existing.push(record);
+
+            let mut node = Some(owner.as_str());
+            while let Some(name) = node {
+                if !nodes.insert(name.to_string()) {
+                    break;
+                }
+                node = parent_name(name);
+            }
+        }
+
+        let soa = soa.ok_or_else(|| zone_error(0, format!("zone {} has no SOA", origin)))?;
+
+        Ok(Self {
+            origin,
+            key,
+            soa,
+            records: by_name,
+            nodes,
+        })
+    }
+
+    pub fn origin(&self) -> &str {
+        &self.origin
+    }
+
+    pub fn record_count(&self) -> usize {
+        self.records.values().map(Vec::len).sum()
+    }
+
+    // NXDOMAIN/NODATA에 붙이는 SOA. TTL은 SOA minimum을 넘지 않는다 (RFC 2308)
+    fn negative_soa(&self) -> Record {
+        let mut soa = self.soa.clone();
+        if let RData::Soa { minimum, .. } = soa.data {
+            soa.ttl = soa.ttl.min(minimum);
+        }
+        soa
+    }
+
+    fn lookup(&self, qname: &str, qtype: u16) -> Lookup {
+        let key = normalize_name(qname);
+
+        // 1. apex와 qname 사이에 NS가 있으면 그 아래는 위임된 곳
+        let mut cut = None;
+        let mut node = Some(key.as_str());
+        while let Some(name) = node {
+            if name == self.key {
+                break;
+            }
+            if let Some(records) = self.records.get(name) {
+                if records.iter().any(|r| r.rtype == TYPE_NS) {
+                    // 가장 위의 cut이 이긴다
+                    cut = Some(records);
+                }
+            }
+            node = parent_name(name);
+        }
+
+        if let Some(records) = cut {
+            let ns = records
+                .iter()
+                .filter(|r| r.rtype == TYPE_NS)
+                .cloned()
+                .collect();
+            return Lookup::Referral(ns);
+        }
+
+        // 2. 정확히 그 이름
+        if let Some(records) = self.records.get(&key) {
+            return Self::select(records, qtype, None);
+        }
+
+        // 3. record는 없지만 아래에 이름이 있는 empty non-terminal
+        if self.nodes.contains(&key) {
+            return Lookup::NoData;
+        }
+
+        // 4. 가장 가까운 조상의 wildcard (RFC 4592)
+        let mut encloser = parent_name(&key);
+        while let Some(name) = encloser {
+            if self.nodes.contains(name) {
+                let wildcard = format!("*.{}", name);
+                if let Some(records) = self.records.get(&wildcard) {
+                    return Self::select(records, qtype, Some(qname));
+                }
+                break;
+            }
+            encloser = parent_name(name);
+        }
+
+        Lookup::NxDomain
+    }
+
+    // 한 이름의 record 중 물은 타입. wildcard로 찾았으면 owner를 qname으로 바꾼다
+    fn select(records: &[Record], qtype: u16, owner: Option<&str>) -> Lookup {
+        let rename = |record: &Record| {
+            let mut record = record.clone();
+            if let Some(owner) = owner {
+                record.name = owner.to_string();
+            }
+            record
+        };
+
+        let matching: Vec<Record> = records
+            .iter()
+            .filter(|r| r.rtype == qtype || qtype == TYPE_ANY)
+            .map(rename)
+            .collect();
+
+        if !matching.is_empty() {
+            return Lookup::Answer(matching);
+        }
+
+        match records.iter().find(|r| r.rtype == TYPE_CNAME) {
+            Some(cname) => Lookup::Cname(rename(cname)),
+            None => Lookup::NoData,
+        }
+    }
+}
+
+// ==================== SERVER ====================
+
+fn server_edns() -> Edns {
+    Edns {
+        udp_payload_size: EDNS_PAYLOAD,
+        extended_rcode: 0,
+        version: 0,
+        dnssec_ok: false,
+        options: Vec::new(),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    Udp,
+    Tcp,
+    // DNS over TLS
+    Tls,
+    // DNS over HTTPS
+    Https,
+}
+
+impl fmt::Display for Transport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Transport::Udp => write!(f, "udp"),
+            Transport::Tcp => write!(f, "tcp"),
+            Transport::Tls => write!(f, "tls"),
+            Transport::Https => write!(f, "https"),
+        }
+    }
+}
+
+pub struct DnsServer {
+    zones: Vec<Zone>,
+    upstream: Option<SocketAddr>,
+    cache: Mutex<DnsCache>,
+    connections: Arc<Semaphore>,
+}
+
+impl DnsServer {
+    pub fn new(zones: Vec<Zone>, upstream: Option<SocketAddr>) -> Self {
+        Self {
+            zones,
+            upstream,
+            cache: Mutex::new(DnsCache::default()),
+            connections: Arc::new(Semaphore::new(MAX_STREAM_CONNECTIONS)),
+        }
+    }
+
+    pub fn max_connections(mut self, limit: usize) -> Self {
+        self.connections = Arc::new(Semaphore::new(limit));
+        self
+    }
+
+    // 연결 하나가 끝날 때까지 들고 있는 자리. 다 차면 accept를 미룬다
+    pub async fn connection_permit(&self) -> OwnedSemaphorePermit {
+        self.connections
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("semaphore is never closed")
+    }
+
+    pub fn from_config(
+        config: &DnsServerConfig,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let mut zones = Vec::new();
+
+        for path in &config.zone_files {
+            let zone = Zone::load(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            println!(
+                "[INFO] loaded zone {} ({} records) from {}",
+                zone.origin(),
+                zone.record_count(),
+                path.display()
+            );
+            zones.push(zone);
+        }
+
+        Ok(Self::new(zones, config.upstream))
+    }
+
+    pub fn cache_stats(&self) -> (usize, u64, u64) {
+        let cache = self.cache.lock().unwrap();
+        (cache.len(), cache.hits, cache.misses)
+    }
+
+    // 이름을 담당하는 zone 중 가장 깊은 것
+    fn find_zone(&self, name: &str) -> Option<&Zone> {
+        let key = normalize_name(name);
+        self.zones
+            .iter()
+            .filter(|zone| is_subdomain(&key, &zone.key))
+            .max_by_key(|zone| zone.key.len())
+    }
+
+    // query 하나에 대한 응답 bytes. 응답하지 않을 것이면 None
+    pub async fn handle(
+        &self,
+        data: &[u8],
+        peer: SocketAddr,
+        transport: Transport,
+    ) -> Option<Vec<u8>> {
+        let query = match DnsMessage::parse(data) {
+            Ok(query) => query,
+            Err(e) => {
+                println!("[WARN] {} {} malformed query: {}", transport, peer, e);
+                return format_error(data);
+            }
+        };
+
+        if query.is_response() {
+            return None;
+        }
+
+        let mut response = query.reply();
+        response.set_flag(FLAG_RA, self.upstream.is_some());
+
+        // EDNS로 물으면 EDNS로 답한다. 광고한 크기는 512 아래로 내려가지 않는다
+        let client_edns = query.edns().cloned();
+        if client_edns.is_some() {
+            response.set_edns(Some(server_edns()));
+        }
+
+        // 크기 제한은 UDP에만 있다
+        let limit = match (transport, &client_edns) {
+            (Transport::Udp, Some(edns)) => {
+                (edns.udp_payload_size as usize).clamp(CLASSIC_UDP_PAYLOAD, EDNS_PAYLOAD as usize)
+            }
+            (Transport::Udp, None) => CLASSIC_UDP_PAYLOAD,
+            (Transport::Tcp | Transport::Tls | Transport::Https, _) => u16::MAX as usize,
+        };
+
+        let source = self
+            .answer(&query, client_edns.as_ref(), &mut response)
+            .await;
+
+        let bytes = encode(&response, limit)?;
+
+        if let Some(question) = query.questions.first() {
+            println!(
+                "[DNS] {} {} {} {} -> {} an={} ({}{})",
+                transport,
+                peer,
+                question.name,
+                type_name(question.qtype),
+                rcode_name(response.rcode()),
+                response.answers.len(),
+                source,
+                if truncated_flag(&bytes) { ", TC" } else { "" },
+            );
+        }
+
+        Some(bytes)
+    }
+
+    // response에 rcode와 section을 채우고 어디서 답했는지 돌려준다
+    async fn answer(
+        &self,
+        query: &DnsMessage,
+        client_edns: Option<&Edns>,
+        response: &mut DnsMessage,
+    ) -> &'static str {
+        if client_edns.is_some_and(|edns| edns.version > 0) {
+            response.set_rcode(RCODE_BADVERS);
+            return "badvers";
+        }
+
+        if query.opcode() != 0 {
+            response.set_rcode(RCODE_NOTIMP);
+            return "notimp";
+        }
+
+        let question = match query.questions.as_slice() {
+            [question] => question,
+            _ => {
+                response.set_rcode(RCODE_FORMERR);
+                return "formerr";
+            }
+        };
+
+        if question.qclass != CLASS_IN {
+            response.set_rcode(RCODE_REFUSED);
+            return "refused";
+        }
+
+        if self.find_zone(&question.name).is_some() {
+            self.authoritative(question, response);
+            return "zone";
+        }
+
+        let upstream = match self.upstream {
+            Some(upstream) if query.recursion_desired() => upstream,
+            _ => {
+                response.set_rcode(RCODE_REFUSED);
+                return "refused";
+            }
+        };
+
+        let cached = self.cache.lock().unwrap().get(question, Instant::now());
+        if let Some(cached) = cached {
+            copy_answer(&cached, response);
+            return "cache";
+        }
+
+        match exchange(upstream, question, UPSTREAM_TIMEOUT, UPSTREAM_ATTEMPTS).await {
+            Ok(upstream_response) => {
+                self.cache
+                    .lock()
+                    .unwrap()
+                    .insert(question, &upstream_response, Instant::now());
+                copy_answer(&upstream_response, response);
+                "upstream"
+            }
+            Err(e) => {
+                println!("[WARN] forward {} failed: {}", question.name, e);
+                response.set_rcode(RCODE_SERVFAIL);
+                "upstream failed"
+            }
+        }
+    }
+
+    // 우리 zone에서 답한다. CNAME이 다른 zone으로 가면 거기서 이어서 찾는다
+    fn authoritative(&self, question: &Question, response: &mut DnsMessage) {
+        response.set_flag(FLAG_AA, true);
+
+        let mut name = question.name.clone();
+
+        for _ in 0..=MAX_CNAME_CHAIN {
+            // 밖으로 나간 CNAME은 client(resolver)가 이어서 찾는다
+            let Some(zone) = self.find_zone(&name) else {
+                break;
+            };
+
+            match zone.lookup(&name, question.qtype) {
+                Lookup::Answer(records) => {
+                    response.answers.extend(records);
+                    break;
+                }
+                Lookup::Cname(record) => {
+                    if let RData::Cname(target) = &record.data {
+                        name = target.clone();
+                    }
+                    response.answers.push(record);
+                }
+                Lookup::Referral(ns) => {
+                    // 위임 응답은 우리가 권한을 가진 답이 아니다
+                    if response.answers.is_empty() {
+                        response.set_flag(FLAG_AA, false);
+                    }
+                    response.authority.extend(ns);
+                    break;
+                }
+                Lookup::NoData => {
+                    response.authority.push(zone.negative_soa());
+                    break;
+                }
+                Lookup::NxDomain => {
+                    response.set_rcode(RCODE_NXDOMAIN);
+                    response.authority.push(zone.negative_soa());
+                    break;
+                }
+            }
+        }
+
+        self.add_additional(response);
+    }
+
+    // NS, MX, SRV target의 주소를 우리 zone에 있으면 additional에 붙인다
+    fn add_additional(&self, response: &mut DnsMessage) {
+        let targets: Vec<String> = response
+            .answers
+            .iter()
+            .chain(&response.authority)
+            .filter_map(|record| match &record.data {
+                RData::Ns(name) => Some(name.clone()),
+                RData::Mx { exchange, .. } => Some(exchange.clone()),
+                RData::Srv { target, .. } => Some(target.clone()),
+                _ => None,
+            })
+            .collect();
+
+        for target in targets {
+            let Some(zone) = self.find_zone(&target) else {
+                continue;
+            };
+            let Some(records) = zone.records.get(&normalize_name(&target)) else {
+                continue;
+            };
+
+            for record in records {
+                if record.rtype != TYPE_A && record.rtype != TYPE_AAAA {
+                    continue;
+                }
+                if response.answers.contains(record) || response.additional.contains(record) {
+                    continue;
+                }
+                response.additional.push(record.clone());
+            }
+        }
+    }
+}
+
+// upstream 응답의 rcode와 section을 가져온다. OPT는 우리 것을 쓴다
+fn copy_answer(from: &DnsMessage, response: &mut DnsMessage) {
+    response.answers = from.answers.clone();
+    response.authority = from.authority.clone();
+    response.additional.extend(
+        from.additional
+            .iter()
+            .filter(|r| r.rtype != TYPE_OPT)
+            .cloned(),
+    );
+    response.set_rcode(from.rcode());
+}
+
+fn truncated_flag(bytes: &[u8]) -> bool {
+    bytes.len() >= 4 && u16::from_be_bytes([bytes[2], bytes[3]]) & FLAG_TC != 0
+}
+
+// 다 싣지 못하면 question(과 OPT)만 남기고 TC를 켜서 TCP로 다시 묻게 한다
+fn encode(response: &DnsMessage, limit: usize) -> Option<Vec<u8>> {
+    let bytes = match response.to_bytes() {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            println!("[ERROR] failed to encode response: {}", e);
+            let mut failed = response.clone();
+            failed.answers.clear();
+            failed.authority.clear();
+            failed.additional.retain(|r| r.rtype == TYPE_OPT);
+            failed.set_rcode(RCODE_SERVFAIL);
+            return failed.to_bytes().ok();
+        }
+    };
+
+    if bytes.len() <= limit {
+        return Some(bytes);
+    }
+
+    let mut truncated = response.clone();
+    truncated.answers.clear();
+    truncated.authority.clear();
+    truncated.additional.retain(|r| r.rtype == TYPE_OPT);
+    truncated.set_flag(FLAG_TC, true);
+    truncated.to_bytes().ok()
+}
+
+// question조차 읽지 못한 query. header만 살려서 FORMERR로 답한다
+fn format_error(data: &[u8]) -> Option<Vec<u8>> {
+    if data.len() < 12 {
+        return None;
+    }
+
+    let id = u16::from_be_bytes([data[0], data[1]]);
+    let flags = u16::from_be_bytes([data[2], data[3]]);
+    if flags & 0x8000 != 0 {
+        return None;
+    }
+
+    let mut response = DnsMessage {
+        id,
+        flags,
+        questions: Vec::new(),
+        answers: Vec::new(),
+        authority: Vec::new(),
+        additional: Vec::new(),
+    }
+    .reply();
+    response.set_rcode(RCODE_FORMERR);
+    response.to_bytes().ok()
+}
+
+// ==================== TRANSPORT ====================
+
+pub async fn serve_udp(server: Arc<DnsServer>, socket: UdpSocket) -> io::Result<()> {
+    let socket = Arc::new(socket);
+    let inflight = Arc::new(Semaphore::new(MAX_UDP_INFLIGHT));
+    let mut buf = vec![0u8; 65535];
+
+    loop {
+        // upstream이 느려도 task가 끝없이 쌓이지 않도록 자리가 날 때까지 다음 query를 읽지 않는다
+        let permit = inflight
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("semaphore is never closed");
+
+        let (n, peer) = match socket.recv_from(&mut buf).await {
+            Ok(received) => received,
+            Err(e) => {
+                println!("[WARN] udp recv failed: {}", e);
+                continue;
+            }
+        };
+
+        // upstream을 기다리는 동안 다른 query를 막지 않도록 query마다 task
+        let data = buf[..n].to_vec();
+        let server = server.clone();
+        let socket = socket.clone();
+
+        tokio::spawn(async move {
+            let _permit = permit;
+            if let Some(reply) = server.handle(&data, peer, Transport::Udp).await {
+                if let Err(e) = socket.send_to(&reply, peer).await {
+                    println!("[WARN] udp send to {} failed: {}", peer, e);
+                }
+            }
+        });
+    }
+}
+
+pub async fn serve_tcp(server: Arc<DnsServer>, listener: TcpListener) -> io::Result<()> {
+    loop {
+        // 연결이 끝없이 쌓이지 않도록 자리가 날 때까지 accept하지 않는다. 밀린 연결은 listen backlog에서 기다린다
+        let permit = server.connection_permit().await;
+        let (stream, peer) = listener.accept().await?;
+        let server = server.clone();
+
+        tokio::spawn(async move {
+            let _permit = permit;
+            if let Err(e) = handle_stream(server, stream, peer, Transport::Tcp).await {
+                println!("[WARN] tcp {} closed: {}", peer, e);
+            }
+        });
+    }
+}
+
+// 한 연결에서 길이 prefix가 붙은 query를 차례로 받는다. TCP와 DoT가 같이 쓴다
+pub async fn handle_stream<S>(
+    server: Arc<DnsServer>,
+    mut stream: S,
+    peer: SocketAddr,
+    transport: Transport,
+) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    loop {
+        let len = match timeout(TCP_IDLE, stream.read_u16()).await {
+            // idle timeout이나 client가 닫은 것은 정상 종료
+            Err(_) => return Ok(()),
+            Ok(Err(e)) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Ok(result) => result?,
+        };
+
+        let mut data = vec![0u8; len as usize];
+        timeout(TCP_IDLE, stream.read_exact(&mut data))
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "query body timed out"))??;
+
+        let Some(reply) = server.handle(&data, peer, transport).await else {
+            continue;
+        };
+
+        let mut frame = Vec::with_capacity(reply.len() + 2);
+        frame.extend_from_slice(&(reply.len() as u16).to_be_bytes());
+        frame.extend_from_slice(&reply);
+        stream.write_all(&frame).await?;
+    }
+}
+
+pub async fn serve(
+    config: DnsServerConfig,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let server = Arc::new(DnsServer::from_config(&config)?);
+
+    let udp = UdpSocket::bind(config.listen).await?;
+    let tcp = TcpListener::bind(config.listen).await?;
+
+    println!(
+        "[SERVER] DNS server listening on {} (udp, tcp)",
+        config.listen
+    );
+    match config.upstream {
+        Some(upstream) => println!("[INFO] forwarding other names to {}", upstream),
+        None => println!("[INFO] no upstream, other names are REFUSED"),
+    }
+
+    tokio::try_join!(serve_udp(server.clone(), udp), serve_tcp(server, tcp))?;
+    Ok(())
+}
+
+// dig @127.0.0.1 -p 5353 www.example.test
+// upstream 흉내: DNS_LISTEN=127.0.0.1:5354 DNS_ZONE=zones/upstream.test.zone 로 하나 더 띄우고
+// DNS_UPSTREAM=127.0.0.1:5354 를 주면 example.test 밖의 이름은 그쪽으로 간다
+#[tokio::main]
+pub async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let config = DnsServerConfig::from_env()?;
+    serve(config).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::udp::dns_message::{FLAG_RD, RCODE_NOERROR};
+    use crate::udp::dns_transport::exchange_stream;
+    use std::net::{IpAddr, Ipv4Addr};
+    use tokio::net::TcpStream;
+
+    fn example_zone() -> Zone {
+        Zone::load(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/zones/example.test.zone"
+        ))
+        .unwrap()
+    }
+
+    fn upstream_zone() -> Zone {
+        Zone::load(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/zones/upstream.test.zone"
+        ))
+        .unwrap()
+    }
+
+    // loopback의 같은 port에 UDP와 TCP를 띄운다
+    async fn start(server: DnsServer) -> (SocketAddr, Arc<DnsServer>) {
+        let server = Arc::new(server);
+        let udp = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = udp.local_addr().unwrap();
+        let tcp = TcpListener::bind(addr).await.unwrap();
+
+        tokio::spawn(serve_udp(server.clone(), udp));
+        tokio::spawn(serve_tcp(server.clone(), tcp));
+        (addr, server)
+    }
+
+    async fn ask_udp(server: SocketAddr, query: &DnsMessage) -> DnsMessage {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        socket.connect(server).await.unwrap();
+        socket.send(&query.to_bytes().unwrap()).await.unwrap();
+
+        let mut buf = vec![0u8; 65535];
+        let n = timeout(Duration::from_secs(5), socket.recv(&mut buf))
+            .await
+            .expect("server did not answer")
+            .unwrap();
+        DnsMessage::parse(&buf[..n]).unwrap()
+    }
+
+    async fn ask_tcp(server: SocketAddr, query: &DnsMessage) -> DnsMessage {
+        let mut stream = TcpStream::connect(server).await.unwrap();
+        let data = exchange_stream(&mut stream, &query.to_bytes().unwrap())
+            .await
+            .unwrap();
+        DnsMessage::parse(&data).unwrap()
+    }
+
+    fn addresses(response: &DnsMessage) -> Vec<IpAddr> {
+        response
+            .answers
+            .iter()
+            .filter_map(|record| match record.data {
+                RData::A(ip) => Some(IpAddr::V4(ip)),
+                RData::Aaaa(ip) => Some(IpAddr::V6(ip)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn zone_answer_follows_cnames() {
+        let (addr, _) = start(DnsServer::new(vec![example_zone()], None)).await;
+
+        let response = ask_udp(addr, &DnsMessage::query(1, "Alias.Example.Test", TYPE_A)).await;
+
+        assert_eq!(response.id, 1);
+        assert_eq!(response.rcode(), RCODE_NOERROR);
+        assert!(response.authoritative());
+        assert!(!response.recursion_available());
+
+        let types: Vec<u16> = response.answers.iter().map(|r| r.rtype).collect();
+        assert_eq!(types, vec![TYPE_CNAME, TYPE_CNAME, TYPE_A]);
+        assert_eq!(
+            addresses(&response),
+            vec![IpAddr::V4(Ipv4Addr::new(127, 0, 0, 20))]
+        );
+    }
+
+    #[tokio::test]
+    async fn missing_name_is_nxdomain_with_soa() {
+        let (addr, _) = start(DnsServer::new(vec![example_zone()], None)).await;
+
+        let response = ask_udp(addr, &DnsMessage::query(2, "nope.example.test", TYPE_A)).await;
+
+        assert_eq!(response.rcode(), RCODE_NXDOMAIN);
+        assert!(response.authoritative());
+        assert!(response.answers.is_empty());
+        assert_eq!(response.authority.len(), 1);
+
+        // negative TTL은 SOA minimum(60)을 넘지 않는다
+        let soa = &response.authority[0];
+        assert_eq!(soa.rtype, TYPE_SOA);
+        assert_eq!(soa.name, "example.test");
+        assert_eq!(soa.ttl, 60);
+
+        // 이름은 있지만 타입이 없으면 NOERROR + SOA
+        let nodata = ask_udp(addr, &DnsMessage::query(3, "mail.example.test", TYPE_TXT)).await;
+        assert_eq!(nodata.rcode(), RCODE_NOERROR);
+        assert!(nodata.answers.is_empty());
+        assert_eq!(nodata.authority[0].rtype, TYPE_SOA);
+    }
+
+    #[tokio::test]
+    async fn other_names_are_forwarded_and_cached() {
+        let (upstream, _) = start(DnsServer::new(vec![upstream_zone()], None)).await;
+        let (addr, server) = start(DnsServer::new(vec![example_zone()], Some(upstream))).await;
+
+        let query = DnsMessage::query(4, "www.upstream.test", TYPE_A);
+        let response = ask_udp(addr, &query).await;
+
+        assert_eq!(response.rcode(), RCODE_NOERROR);
+        assert!(response.recursion_available());
+        assert!(!response.authoritative());
+        assert_eq!(
+            addresses(&response),
+            vec![IpAddr::V4(Ipv4Addr::new(127, 0, 1, 1))]
+        );
+
+        // 두 번째는 upstream에 가지 않는다
+        let again = ask_udp(addr, &query).await;
+        assert_eq!(addresses(&again), addresses(&response));
+        let (entries, hits, misses) = server.cache_stats();
+        assert_eq!((entries, hits, misses), (1, 1, 1));
+
+        // RD가 없으면 넘기지 않는다
+        let mut no_recursion = DnsMessage::query(5, "www.upstream.test", TYPE_A);
+        no_recursion.set_flag(FLAG_RD, false);
+        let refused = ask_udp(addr, &no_recursion).await;
+        assert_eq!(refused.rcode(), RCODE_REFUSED);
+    }
+
+    #[tokio::test]
+    async fn upstream_failure_is_servfail() {
+        // 아무도 듣지 않는 port
+        let closed = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let upstream = closed.local_addr().unwrap();
+        drop(closed);
+
+        let (addr, _) = start(DnsServer::new(Vec::new(), Some(upstream))).await;
+        let response = ask_udp(addr, &DnsMessage::query(6, "www.upstream.test", TYPE_A)).await;
+        assert_eq!(response.rcode(), RCODE_SERVFAIL);
+    }
+
+    #[tokio::test]
+    async fn oversized_udp_answer_is_truncated_and_retried_over_tcp() {
+        let (addr, _) = start(DnsServer::new(vec![example_zone()], None)).await;
+        let query = DnsMessage::query(7, "big.example.test", TYPE_TXT);
+
+        // EDNS가 없으면 512바이트까지라 question만 남기고 TC
+        let truncated = ask_udp(addr, &query).await;
+        assert!(truncated.truncated());
+        assert!(truncated.answers.is_empty());
+        assert_eq!(truncated.questions, query.questions);
+
+        // TCP에는 크기 제한이 없다
+        let full = ask_tcp(addr, &query).await;
+        assert!(!full.truncated());
+        assert_eq!(full.answers.len(), 6);
+        assert!(full.to_bytes().unwrap().len() > CLASSIC_UDP_PAYLOAD);
+
+        // EDNS로 1232를 알리면 UDP로도 다 온다
+        let mut edns_query = query.clone();
+        edns_query.set_edns(Some(server_edns()));
+        let edns = ask_udp(addr, &edns_query).await;
+        assert!(!edns.truncated());
+        assert_eq!(edns.answers.len(), 6);
+        assert_eq!(edns.edns().unwrap().udp_payload_size, EDNS_PAYLOAD);
+    }
+
+    #[tokio::test]
+    async fn tcp_connection_carries_several_queries() {
+        let (addr, _) = start(DnsServer::new(vec![example_zone()], None)).await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+
+        for (id, name) in [(8, "www.example.test"), (9, "mail.example.test")] {
+            let query = DnsMessage::query(id, name, TYPE_A);
+            let data = exchange_stream(&mut stream, &query.to_bytes().unwrap())
+                .await
+                .unwrap();
+            let response = DnsMessage::parse(&data).unwrap();
+            assert_eq!(response.id, id);
+            assert_eq!(response.answers.len(), 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn tcp_connections_wait_for_a_free_slot() {
+        let server = DnsServer::new(vec![example_zone()], None).max_connections(1);
+        let (addr, _) = start(server).await;
+        let query = DnsMessage::query(10, "www.example.test", TYPE_A).to_bytes().unwrap();
+
+        let mut first = TcpStream::connect(addr).await.unwrap();
+        exchange_stream(&mut first, &query).await.unwrap();
+
+        // 두 번째 연결은 listen backlog에 들어가지만 첫 연결이 닫히기 전에는 답이 없다
+        let mut second = TcpStream::connect(addr).await.unwrap();
+        assert!(
+            timeout(Duration::from_millis(200), exchange_stream(&mut second, &query))
+                .await
+                .is_err()
+        );
+
+        drop(first);
+        let data = timeout(Duration::from_secs(5), exchange_stream(&mut second, &query))
+            .await
+            .expect("second connection was never served")
+            .unwrap();
+        assert_eq!(DnsMessage::parse(&data).unwrap().id, 10);
+    }
+}
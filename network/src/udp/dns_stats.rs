@@ -0,0 +1,513 @@
+use crate::udp::dns_message::{rcode_name, type_name, DnsMessage, RCODE_NXDOMAIN};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::time::Duration;
+
+// 캡처한 DNS query와 response를 (client, server, tx_id, question)으로 짝지어 응답 시간을 재고,
+// 끝내 응답이 없는 query는 timeout으로 센다. resolver별, 이름별로 rcode 분포와 p50/p99를 모아
+// flow.rs의 print_flows처럼 주기적으로 표로 찍는다.
+// 시각은 모두 캡처 시각(UNIX epoch 기준 Duration)이라 pcap을 읽어도 같은 결과가 나온다.
+
+// ==================== CONFIG ====================
+
+// 응답을 이만큼 기다리고 못 받으면 unanswered
+const QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+// percentile 계산용으로 key마다 최근 샘플만 남긴다
+const MAX_SAMPLES: usize = 1000;
+// 이 시간 동안 조회가 없던 이름과 resolver는 표에서 뺀다
+const STATS_IDLE: Duration = Duration::from_secs(30 * 60);
+
+// ==================== TYPES ====================
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct QueryKey {
+    client: SocketAddr,
+    server: SocketAddr,
+    id: u16,
+    // 0x20 대소문자 섞기를 하는 resolver가 있어서 소문자로 맞춘다
+    name: String,
+    qtype: u16,
+}
+
+impl QueryKey {
+    fn new(client: SocketAddr, server: SocketAddr, message: &DnsMessage) -> Option<Self> {
+        let question = message.questions.first()?;
+        Some(Self {
+            client,
+            server,
+            id: message.id,
+            name: question.name.to_ascii_lowercase(),
+            qtype: question.qtype,
+        })
+    }
+}
+
+struct PendingQuery {
+    // 처음 보낸 시각. 재전송돼도 사용자가 기다린 시간은 여기서부터다
+    sent: Duration,
+    retransmits: u32,
+}
+
+#[derive(Debug, Default)]
+pub struct LatencyStats {
+    pub queries: u64,
+    pub answered: u64,
+    pub unanswered: u64,
+    pub retransmits: u64,
+    pub rcodes: BTreeMap<u16, u64>,
+    samples: VecDeque<Duration>,
+    last_seen: Duration,
+}
+
+impl LatencyStats {
+    fn record(&mut self, latency: Duration, rcode: u16) {
+        self.answered += 1;
+        *self.rcodes.entry(rcode).or_insert(0) += 1;
+
+        if self.samples.len() == MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(latency);
+    }
+
+    // 최근 샘플 기준 백분위수
+    pub fn percentile(&self, p: f64) -> Option<Duration> {
+        if self.samples.is_empty() {
+            return None;
+        }
+
+        let mut sorted: Vec<Duration> = self.samples.iter().copied().collect();
+        sorted.sort_unstable();
+
+        let rank = ((sorted.len() as f64 * p).ceil() as usize).clamp(1, sorted.len());
+        Some(sorted[rank - 1])
+    }
+
+    pub fn nxdomain(&self) -> u64 {
+        self.rcodes.get(&RCODE_NXDOMAIN).copied().unwrap_or(0)
+    }
+}
+
+// DNS 한 번의 결과. 호출한 쪽이 로그를 남길 때 쓴다
+#[derive(Debug, Clone)]
+pub struct Resolution {
+    pub client: SocketAddr,
+    pub server: SocketAddr,
+    pub name: String,
+    pub qtype: u16,
+    pub rcode: u16,
+    pub latency: Duration,
+}
+
+// ==================== TRACKER ====================
+
+#[derive(Default)]
+pub struct DnsTracker {
+    pending: HashMap<QueryKey, PendingQuery>,
+    resolvers: HashMap<SocketAddr, LatencyStats>,
+    names: HashMap<String, LatencyStats>,
+    rcodes: BTreeMap<u16, u64>,
+    // 재전송은 빼고 센 query 수
+    pub queries: u64,
+    pub responses: u64,
+    pub unanswered: u64,
+    // 짝이 되는 query를 못 본 response (캡처 시작 전에 나간 query 등)
+    pub unmatched: u64,
+}
+
+impl DnsTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    // src에서 dst로 간 패킷 하나. response면 짝을 찾아 결과를 돌려준다
+    pub fn process(
+        &mut self,
+        src: SocketAddr,
+        dst: SocketAddr,
+        message: &DnsMessage,
+        now: Duration,
+    ) -> Option<Resolution> {
+        if message.is_response() {
+            self.on_response(src, dst, message, now)
+        } else {
+            self.on_query(src, dst, message, now);
+            None
+        }
+    }
+
+    fn on_query(
+        &mut self,
+        client: SocketAddr,
+        server: SocketAddr,
+        message: &DnsMessage,
+        now: Duration,
+    ) {
+        let Some(key) = QueryKey::new(client, server, message) else {
+            return;
+        };
+
+        let resolver = self.resolvers.entry(server).or_default();
+        let name = self.names.entry(key.name.clone()).or_default();
+
+        match self.pending.get_mut(&key) {
+            // 같은 query가 다시 나갔다
+            Some(pending) => {
+                pending.retransmits += 1;
+                resolver.retransmits += 1;
+                name.retransmits += 1;
+            }
+            None => {
+                self.queries += 1;
+                resolver.queries += 1;
+                name.queries += 1;
+                self.pending.insert(
+                    key,
+                    PendingQuery {
+                        sent: now,
+                        retransmits: 0,
+                    },
+                );
+            }
+        }
+
+        resolver.last_seen = now;
+        name.last_seen = now;
+    }
+
+    fn on_response(
+        &mut self,
+        server: SocketAddr,
+        client: SocketAddr,
+        message: &DnsMessage,
+        now: Duration,
+    ) -> Option<Resolution> {
+        self.responses += 1;
+
+        let key = QueryKey::new(client, server, message);
+        let Some(pending) = key.as_ref().and_then(|key| self.pending.remove(key)) else {
+            self.unmatched += 1;
+            return None;
+        };
+        let key = key?;
+
+        let rcode = message.rcode();
+        let latency = now.saturating_sub(pending.sent);
+
+        *self.rcodes.entry(rcode).or_insert(0) += 1;
+
+        if let Some(resolver) = self.resolvers.get_mut(&server) {
+            resolver.record(latency, rcode);
+        }
+        if let Some(name) = self.names.get_mut(&key.name) {
+            name.record(latency, rcode);
+        }
+
+        Some(Resolution {
+            client,
+            server,
+            name: key.name,
+            qtype: key.qtype,
+            rcode,
+            latency,
+        })
+    }
+
+    // 오래 기다린 query를 unanswered로 넘기고, 한동안 안 보인 이름과 resolver를 지운다
+    pub fn expire(&mut self, now: Duration) {
+        let mut expired = Vec::new();
+
+        self.pending.retain(|key, pending| {
+            let alive = now.saturating_sub(pending.sent) < QUERY_TIMEOUT;
+            if !alive {
+                expired.push((key.server, key.name.clone()));
+            }
+            alive
+        });
+
+        for (server, name) in expired {
+            self.unanswered += 1;
+            if let Some(resolver) = self.resolvers.get_mut(&server) {
+                resolver.unanswered += 1;
+            }
+            if let Some(name) = self.names.get_mut(&name) {
+                name.unanswered += 1;
+            }
+        }
+
+        // 캡처에서는 목적지 주소마다 resolver가 생기므로 이름처럼 정리해야 메모리가 늘지 않는다
+        let idle = |stats: &LatencyStats| now.saturating_sub(stats.last_seen) >= STATS_IDLE;
+        self.names.retain(|_, stats| !idle(stats));
+        self.resolvers.retain(|_, stats| !idle(stats));
+    }
+}
+
+// ==================== REPORT ====================
+
+fn format_rcodes(rcodes: &BTreeMap<u16, u64>) -> String {
+    if rcodes.is_empty() {
+        return "-".to_string();
+    }
+
+    rcodes
+        .iter()
+        .map(|(rcode, count)| format!("{}:{}", rcode_name(*rcode), count))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn format_latency(latency: Option<Duration>) -> String {
+    match latency {
+        Some(latency) => format!("{:.1}ms", latency.as_secs_f64() * 1000.0),
+        None => "-".to_string(),
+    }
+}
+
+pub fn print_dns_stats(tracker: &DnsTracker) {
+    println!();
+    println!("================ DNS STATS ================");
+    println!(
+        "queries={} responses={} unanswered={} unmatched={} pending={}",
+        tracker.queries,
+        tracker.responses,
+        tracker.unanswered,
+        tracker.unmatched,
+        tracker.pending()
+    );
+    println!("rcodes: {}", format_rcodes(&tracker.rcodes));
+
+    let mut resolvers: Vec<(&SocketAddr, &LatencyStats)> = tracker.resolvers.iter().collect();
+    resolvers.sort_by_key(|(_, stats)| std::cmp::Reverse(stats.queries));
+
+    for (server, stats) in resolvers.iter().take(10) {
+        println!(
+            "[RESOLVER] {} | queries={} answered={} unanswered={} retrans={} p50={} p99={} rcodes={}",
+            server,
+            stats.queries,
+            stats.answered,
+            stats.unanswered,
+            stats.retransmits,
+            format_latency(stats.percentile(0.50)),
+            format_latency(stats.percentile(0.99)),
+            format_rcodes(&stats.rcodes),
+        );
+    }
+
+    // 많이 조회된 순
+    let mut names: Vec<(&String, &LatencyStats)> = tracker.names.iter().collect();
+    names.sort_by(|a, b| b.1.queries.cmp(&a.1.queries).then_with(|| a.0.cmp(b.0)));
+
+    for (name, stats) in names.iter().take(20) {
+        println!(
+            "[NAME] {} | queries={} nxdomain={} unanswered={} p50={} p99={}",
+            name,
+            stats.queries,
+            stats.nxdomain(),
+            stats.unanswered,
+            format_latency(stats.percentile(0.50)),
+            format_latency(stats.percentile(0.99)),
+        );
+    }
+
+    println!("total names: {}", tracker.names.len());
+    println!("============================================");
+    println!();
+}
+
+impl Resolution {
+    pub fn summary(&self) -> String {
+        format!(
+            "{} -> {} {} {} {} in {}",
+            self.client,
+            self.server,
+            self.name,
+            type_name(self.qtype),
+            rcode_name(self.rcode),
+            format_latency(Some(self.latency)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::udp::dns_message::{Question, CLASS_IN, TYPE_A, TYPE_AAAA};
+
+    const CLIENT: &str = "10.0.0.5:53000";
+    const SERVER: &str = "8.8.8.8:53";
+
+    fn addr(s: &str) -> SocketAddr {
+        s.parse().unwrap()
+    }
+
+    fn at(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    // RD를 켠 A 질문
+    fn query(id: u16, name: &str) -> DnsMessage {
+        DnsMessage {
+            id,
+            flags: 0x0100,
+            questions: vec![Question {
+                name: name.to_string(),
+                qtype: TYPE_A,
+                qclass: CLASS_IN,
+            }],
+            answers: Vec::new(),
+            authority: Vec::new(),
+            additional: Vec::new(),
+        }
+    }
+
+    // 같은 질문에 QR과 rcode만 켠 응답
+    fn response(id: u16, name: &str, rcode: u16) -> DnsMessage {
+        let mut reply = query(id, name);
+        reply.flags |= 0x8000 | rcode;
+        reply
+    }
+
+    // query를 보내고 응답을 받은 결과
+    fn exchange(
+        tracker: &mut DnsTracker,
+        id: u16,
+        name: &str,
+        sent: u64,
+        answered: u64,
+    ) -> Resolution {
+        assert!(tracker
+            .process(addr(CLIENT), addr(SERVER), &query(id, name), at(sent))
+            .is_none());
+        tracker
+            .process(
+                addr(SERVER),
+                addr(CLIENT),
+                &response(id, name, 0),
+                at(answered),
+            )
+            .expect("response should match the query")
+    }
+
+    #[test]
+    fn response_is_matched_to_its_query() {
+        let mut tracker = DnsTracker::new();
+        let resolution = exchange(&mut tracker, 1, "example.com", 1_000, 1_025);
+
+        assert_eq!(resolution.client, addr(CLIENT));
+        assert_eq!(resolution.server, addr(SERVER));
+        assert_eq!(resolution.name, "example.com");
+        assert_eq!(resolution.latency, at(25));
+        assert_eq!(tracker.pending(), 0);
+        assert_eq!(tracker.resolvers[&addr(SERVER)].answered, 1);
+    }
+
+    #[test]
+    fn mixed_case_question_still_matches() {
+        let mut tracker = DnsTracker::new();
+        tracker.process(addr(CLIENT), addr(SERVER), &query(7, "ExAmPlE.CoM"), at(0));
+        let resolution = tracker.process(
+            addr(SERVER),
+            addr(CLIENT),
+            &response(7, "eXaMpLe.cOm", 0),
+            at(3),
+        );
+        assert_eq!(resolution.unwrap().name, "example.com");
+    }
+
+    #[test]
+    fn retransmit_keeps_the_first_send_time() {
+        let mut tracker = DnsTracker::new();
+        tracker.process(addr(CLIENT), addr(SERVER), &query(2, "slow.example"), at(0));
+        let resolution = exchange(&mut tracker, 2, "slow.example", 1_000, 1_010);
+
+        assert_eq!(resolution.latency, at(1_010));
+        assert_eq!(tracker.queries, 1);
+        assert_eq!(tracker.resolvers[&addr(SERVER)].retransmits, 1);
+    }
+
+    #[test]
+    fn responses_without_a_query_are_unmatched() {
+        let mut tracker = DnsTracker::new();
+        tracker.process(addr(CLIENT), addr(SERVER), &query(3, "a.example"), at(0));
+
+        // id가 다르거나 질문 타입이 다르면 짝이 아니다
+        assert!(tracker
+            .process(
+                addr(SERVER),
+                addr(CLIENT),
+                &response(4, "a.example", 0),
+                at(1)
+            )
+            .is_none());
+        let mut other_type = response(3, "a.example", 0);
+        other_type.questions[0].qtype = TYPE_AAAA;
+        assert!(tracker
+            .process(addr(SERVER), addr(CLIENT), &other_type, at(1))
+            .is_none());
+
+        assert_eq!(tracker.unmatched, 2);
+        assert_eq!(tracker.pending(), 1);
+    }
+
+    #[test]
+    fn rcodes_are_counted_per_name() {
+        let mut tracker = DnsTracker::new();
+        tracker.process(
+            addr(CLIENT),
+            addr(SERVER),
+            &query(5, "missing.example"),
+            at(0),
+        );
+        tracker.process(
+            addr(SERVER),
+            addr(CLIENT),
+            &response(5, "missing.example", RCODE_NXDOMAIN),
+            at(1),
+        );
+
+        assert_eq!(tracker.names["missing.example"].nxdomain(), 1);
+        assert_eq!(tracker.rcodes[&RCODE_NXDOMAIN], 1);
+    }
+
+    #[test]
+    fn expire_counts_timeouts_and_prunes_idle_names_and_resolvers() {
+        let mut tracker = DnsTracker::new();
+        tracker.process(addr(CLIENT), addr(SERVER), &query(6, "lost.example"), at(0));
+
+        tracker.expire(at(1_000));
+        assert_eq!(tracker.unanswered, 0);
+
+        tracker.expire(QUERY_TIMEOUT);
+        assert_eq!(tracker.unanswered, 1);
+        assert_eq!(tracker.pending(), 0);
+        assert_eq!(tracker.resolvers[&addr(SERVER)].unanswered, 1);
+        assert_eq!(tracker.names["lost.example"].unanswered, 1);
+
+        // 다른 resolver는 계속 쓰이고 있다
+        let busy = addr("1.1.1.1:53");
+        tracker.process(addr(CLIENT), busy, &query(8, "busy.example"), STATS_IDLE);
+
+        tracker.expire(STATS_IDLE);
+        assert!(!tracker.resolvers.contains_key(&addr(SERVER)));
+        assert!(!tracker.names.contains_key("lost.example"));
+        assert!(tracker.resolvers.contains_key(&busy));
+        assert!(tracker.names.contains_key("busy.example"));
+    }
+
+    #[test]
+    fn percentiles_use_nearest_rank() {
+        let mut stats = LatencyStats::default();
+        assert_eq!(stats.percentile(0.5), None);
+
+        for ms in (1..=100).rev() {
+            stats.record(at(ms), 0);
+        }
+        assert_eq!(stats.percentile(0.50), Some(at(50)));
+        assert_eq!(stats.percentile(0.99), Some(at(99)));
+        assert_eq!(stats.percentile(0.0), Some(at(1)));
+    }
+}
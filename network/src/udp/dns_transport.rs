@@ -0,0 +1,777 @@
+use crate::tcp::tls::{self, ClientTls};
+use crate::udp::dns_message::{
+    normalize_name, random_id, rcode_name, DnsError, DnsMessage, Edns, Question, RData,
+    RCODE_NOERROR, RCODE_NXDOMAIN, TYPE_OPT,
+};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use rustls::pki_types::CertificateDer;
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::time::timeout;
+use tokio_rustls::TlsConnector;
+
+// DNS server에 query 하나를 보내고 응답 하나를 받는 client 쪽 부품.
+// dns_resolver(stub resolver)와 dns_server(upstream forwarding)가 같이 쓰고,
+// dns_encrypted는 그 위에서 DoT/DoH 서버를 띄운다. 그래서 이 모듈은 그 셋 중 어느 것도 쓰지 않는다.
+// 1. UDP로 묻고 잘리면(TC) 같은 서버에 TCP로 다시 묻는다 (exchange)
+// 2. DoT/DoH 서버에는 EncryptedClients로 묻는다
+// 3. 응답은 TTL만큼 DnsCache에 둔다
+
+// ==================== CONFIG ====================
+
+pub const DOT_PORT: u16 = 853;
+pub const DNS_MESSAGE: &str = "application/dns-message";
+// 우리가 광고하고 받아들이는 EDNS0 UDP payload 크기 (DNS flag day 2020 권장값).
+// resolver가 묻을 때와 dns_server가 답할 때 같은 값을 쓴다
+pub const EDNS_PAYLOAD: u16 = 1232;
+const CACHE_CAPACITY: usize = 10_000;
+// 아주 긴 TTL을 줘도 하루 넘게 들고 있지 않는다
+const MAX_CACHE_TTL: u32 = 86_400;
+
+// 포트를 빼먹으면 53
+pub fn parse_nameserver(value: &str) -> Result<SocketAddr, std::net::AddrParseError> {
+    match value.parse::<SocketAddr>() {
+        Ok(addr) => Ok(addr),
+        Err(e) => match value.parse::<IpAddr>() {
+            Ok(ip) => Ok(SocketAddr::new(ip, 53)),
+            Err(_) => Err(e),
+        },
+    }
+}
+
+// ==================== ERROR ====================
+
+#[derive(Debug)]
+pub enum ResolveError {
+    // 이름이 없다. CNAME을 따라갔으면 마지막 이름
+    NxDomain(String),
+    // 이름은 있지만 물은 타입의 record가 없다
+    NoRecords(String),
+    // SERVFAIL, REFUSED 등
+    Rcode(u16),
+    CnameLoop(String),
+    Timeout,
+    NoNameservers,
+    // 응답을 해석할 수 없거나 다른 query에 대한 응답
+    Protocol(String),
+    Io(io::Error),
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResolveError::NxDomain(name) => write!(f, "{}: no such name", name),
+            ResolveError::NoRecords(name) => write!(f, "{}: no records of that type", name),
+            ResolveError::Rcode(rcode) => write!(f, "server answered {}", rcode_name(*rcode)),
+            ResolveError::CnameLoop(name) => write!(f, "{}: CNAME chain too long or looping", name),
+            ResolveError::Timeout => write!(f, "no nameserver answered"),
+            ResolveError::NoNameservers => write!(f, "no nameservers configured"),
+            ResolveError::Protocol(msg) => write!(f, "bad response: {}", msg),
+            ResolveError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ResolveError {}
+
+impl From<io::Error> for ResolveError {
+    fn from(e: io::Error) -> Self {
+        ResolveError::Io(e)
+    }
+}
+
+impl From<DnsError> for ResolveError {
+    fn from(e: DnsError) -> Self {
+        ResolveError::Protocol(e.to_string())
+    }
+}
+
+// ==================== CACHE ====================
+
+type CacheKey = (String, u16, u16);
+
+struct CacheEntry {
+    // id와 OPT를 뺀 응답
+    response: DnsMessage,
+    stored: Instant,
+    ttl: u32,
+}
+
+#[derive(Default)]
+pub struct DnsCache {
+    entries: HashMap<CacheKey, CacheEntry>,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+fn cache_key(question: &Question) -> CacheKey {
+    (
+        normalize_name(&question.name),
+        question.qtype,
+        question.qclass,
+    )
+}
+
+// 얼마나 들고 있을지. None이면 cache하지 않는다
+fn cache_ttl(response: &DnsMessage) -> Option<u32> {
+    if response.truncated() {
+        return None;
+    }
+
+    let records = || {
+        response
+            .answers
+            .iter()
+            .chain(&response.authority)
+            .chain(&response.additional)
+            .filter(|r| r.rtype != TYPE_OPT)
+    };
+
+    let ttl = match response.rcode() {
+        // NODATA는 answer가 비어 있어서 SOA로 정한다
+        RCODE_NOERROR if !response.answers.is_empty() => records().map(|r| r.ttl).min()?,
+        RCODE_NOERROR | RCODE_NXDOMAIN => response.authority.iter().find_map(|r| match r.data {
+            RData::Soa { minimum, .. } => Some(r.ttl.min(minimum)),
+            _ => None,
+        })?,
+        _ => return None,
+    };
+
+    match ttl.min(MAX_CACHE_TTL) {
+        0 => None,
+        ttl => Some(ttl),
+    }
+}
+
+impl DnsCache {
+    // 남은 시간만큼 TTL을 줄여서 돌려준다
+    pub fn get(&mut self, question: &Question, now: Instant) -> Option<DnsMessage> {
+        let key = cache_key(question);
+
+        let elapsed = match self.entries.get(&key) {
+            Some(entry) => now.saturating_duration_since(entry.stored).as_secs(),
+            None => {
+                self.misses += 1;
+                return None;
+            }
+        };
+
+        let entry = &self.entries[&key];
+        if elapsed >= entry.ttl as u64 {
+            self.entries.remove(&key);
+            self.misses += 1;
+            return None;
+        }
+
+        self.hits += 1;
+        let mut response = entry.response.clone();
+        for record in response
+            .answers
+            .iter_mut()
+            .chain(&mut response.authority)
+            .chain(&mut response.additional)
+        {
+            record.ttl = record.ttl.saturating_sub(elapsed as u32);
+        }
+
+        Some(response)
+    }
+
+    pub fn insert(&mut self, question: &Question, response: &DnsMessage, now: Instant) {
+        let Some(ttl) = cache_ttl(response) else {
+            return;
+        };
+
+        if self.entries.len() >= CACHE_CAPACITY {
+            self.entries.retain(|_, entry| {
+                now.saturating_duration_since(entry.stored).as_secs() < entry.ttl as u64
+            });
+            // 그래도 꽉 차 있으면 이번 것은 버린다
+            if self.entries.len() >= CACHE_CAPACITY {
+                return;
+            }
+        }
+
+        let mut response = response.clone();
+        response.id = 0;
+        response.set_edns(None);
+
+        self.entries.insert(
+            cache_key(question),
+            CacheEntry {
+                response,
+                stored: now,
+                ttl,
+            },
+        );
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+// ==================== UDP / TCP ====================
+
+// 우리가 보낸 query에 대한 응답인지 (id와 question이 같아야 한다)
+pub fn answers_query(query: &DnsMessage, response: &DnsMessage) -> bool {
+    response.is_response()
+        && response.id == query.id
+        && response.questions.len() == query.questions.len()
+        && response
+            .questions
+            .iter()
+            .zip(&query.questions)
+            .all(|(a, b)| {
+                a.qtype == b.qtype
+                    && a.qclass == b.qclass
+                    && normalize_name(&a.name) == normalize_name(&b.name)
+            })
+}
+
+// 위조 응답을 맞히기 어렵도록 source port도 id처럼 무작위로 고른다. 계속 겹치면 OS에 맡긴다
+async fn bind_random_port(server: SocketAddr) -> io::Result<UdpSocket> {
+    let ip: IpAddr = if server.is_ipv4() {
+        Ipv4Addr::UNSPECIFIED.into()
+    } else {
+        Ipv6Addr::UNSPECIFIED.into()
+    };
+
+    for _ in 0..8 {
+        let port = 1024 + random_id()? % (u16::MAX - 1024);
+        match UdpSocket::bind((ip, port)).await {
+            Ok(socket) => return Ok(socket),
+            Err(e) if e.kind() == io::ErrorKind::AddrInUse => continue,
+            Err(e) => return Err(e),
+        }
+    }
+
+    UdpSocket::bind((ip, 0)).await
+}
+
+// EDNS0를 붙인 query. 모든 transport가 같은 모양으로 보낸다
+pub fn new_query(id: u16, question: &Question) -> DnsMessage {
+    let mut query = DnsMessage::query(id, &question.name, question.qtype);
+    query.questions[0].qclass = question.qclass;
+    query.set_edns(Some(Edns {
+        udp_payload_size: EDNS_PAYLOAD,
+        extended_rcode: 0,
+        version: 0,
+        dnssec_ok: false,
+        options: Vec::new(),
+    }));
+    query
+}
+
+// TCP와 DoT는 메시지 앞에 2바이트 길이를 붙인다. query 하나를 보내고 응답 하나를 읽는다
+pub async fn exchange_stream<S>(stream: &mut S, bytes: &[u8]) -> io::Result<Vec<u8>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut frame = Vec::with_capacity(bytes.len() + 2);
+    frame.extend_from_slice(&(bytes.len() as u16).to_be_bytes());
+    frame.extend_from_slice(bytes);
+    stream.write_all(&frame).await?;
+
+    let len = stream.read_u16().await?;
+    let mut data = vec![0u8; len as usize];
+    stream.read_exact(&mut data).await?;
+    Ok(data)
+}
+
+// 한 서버에 UDP로 한 번 묻는다. 잘려서 오면 같은 서버에 TCP로 다시 묻는다
+pub async fn ask_udp(
+    server: SocketAddr,
+    question: &Question,
+    wait: Duration,
+) -> Result<DnsMessage, ResolveError> {
+    let query = new_query(random_id()?, question);
+    let bytes = query.to_bytes()?;
+
+    // connect해 두면 다른 주소에서 온 datagram은 커널이 버린다
+    let socket = bind_random_port(server).await?;
+    socket.connect(server).await?;
+    socket.send(&bytes).await?;
+
+    let mut buf = vec![0u8; 65535];
+    let deadline = tokio::time::Instant::now() + wait;
+
+    // id나 question이 다른 datagram은 버리고 남은 시간 동안 계속 기다린다
+    loop {
+        let received = match tokio::time::timeout_at(deadline, socket.recv(&mut buf)).await {
+            Ok(received) => received?,
+            Err(_) => return Err(ResolveError::Timeout),
+        };
+
+        let Ok(response) = DnsMessage::parse(&buf[..received]) else {
+            continue;
+        };
+        if !answers_query(&query, &response) {
+            continue;
+        }
+
+        if response.truncated() {
+            return ask_tcp(server, &query, &bytes, wait).await;
+        }
+        return Ok(response);
+    }
+}
+
+async fn ask_tcp(
+    server: SocketAddr,
+    query: &DnsMessage,
+    bytes: &[u8],
+    wait: Duration,
+) -> Result<DnsMessage, ResolveError> {
+    let exchange = async {
+        let mut stream = TcpStream::connect(server).await?;
+        exchange_stream(&mut stream, bytes).await
+    };
+
+    let data = timeout(wait, exchange)
+        .await
+        .map_err(|_| ResolveError::Timeout)??;
+
+    let response = DnsMessage::parse(&data)?;
+    if !answers_query(query, &response) {
+        return Err(ResolveError::Protocol(format!(
+            "{} answered a different query over tcp",
+            server
+        )));
+    }
+
+    Ok(response)
+}
+
+// 한 서버에 attempts번까지 묻는다. 매번 id와 port를 새로 뽑는다
+pub async fn exchange(
+    server: SocketAddr,
+    question: &Question,
+    wait: Duration,
+    attempts: usize,
+) -> Result<DnsMessage, ResolveError> {
+    for _ in 0..attempts {
+        match ask_udp(server, question, wait).await {
+            Err(ResolveError::Timeout) => continue,
+            other => return other,
+        }
+    }
+
+    Err(ResolveError::Timeout)
+}
+
+// ==================== DoT / DoH ====================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DohMethod {
+    // HTTP cache에 잘 맞는다
+    Get,
+    Post,
+}
+
+impl fmt::Display for DohMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DohMethod::Get => write!(f, "GET"),
+            DohMethod::Post => write!(f, "POST"),
+        }
+    }
+}
+
+// Resolver가 DoT와 DoH 서버에 물을 때 쓰는 client. reqwest는 연결을 pool에 두고 다시 쓴다
+pub struct EncryptedClients {
+    tls: TlsConnector,
+    http: reqwest::Client,
+}
+
+impl EncryptedClients {
+    // DoT는 roots만 믿고, DoH는 시스템 root에 roots를 더한다
+    pub fn new(
+        roots: &[CertificateDer<'static>],
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let mut tls = ClientTls::new().alpn(b"dot");
+        let mut http = reqwest::Client::builder();
+
+        for root in roots {
+            tls = tls.root(root.clone())?;
+            http = http.add_root_certificate(reqwest::Certificate::from_der(root)?);
+        }
+
+        Ok(Self {
+            tls: tls.build()?,
+            http: http.build()?,
+        })
+    }
+
+    // DoT 서버에 한 번 묻는다. 연결은 query마다 새로 연다
+    pub async fn ask_tls(
+        &self,
+        addr: SocketAddr,
+        server_name: &str,
+        question: &Question,
+        wait: Duration,
+    ) -> Result<DnsMessage, ResolveError> {
+        let query = new_query(random_id()?, question);
+        let bytes = query.to_bytes()?;
+
+        let exchange = async {
+            let mut stream = tls::connect(&self.tls, &addr.to_string(), server_name)
+                .await
+                .map_err(|e| ResolveError::Protocol(format!("tls {}: {}", addr, e)))?;
+            Ok::<_, ResolveError>(exchange_stream(&mut stream, &bytes).await?)
+        };
+
+        let data = timeout(wait, exchange)
+            .await
+            .map_err(|_| ResolveError::Timeout)??;
+
+        let response = DnsMessage::parse(&data)?;
+        if !answers_query(&query, &response) {
+            return Err(ResolveError::Protocol(format!(
+                "{} answered a different query over tls",
+                addr
+            )));
+        }
+
+        Ok(response)
+    }
+
+    // DoH 서버에 한 번 묻는다
+    pub async fn ask_https(
+        &self,
+        url: &str,
+        method: DohMethod,
+        question: &Question,
+        wait: Duration,
+    ) -> Result<DnsMessage, ResolveError> {
+        // RFC 8484 4.1: 같은 질문이 같은 URL이 되도록 id는 0
+        let query = new_query(0, question);
+        let bytes = query.to_bytes()?;
+
+        let request = match method {
+            DohMethod::Get => self
+                .http
+                .get(url)
+                .query(&[("dns", URL_SAFE_NO_PAD.encode(&bytes))]),
+            DohMethod::Post => self
+                .http
+                .post(url)
+                .header(reqwest::header::CONTENT_TYPE, DNS_MESSAGE)
+                .body(bytes),
+        };
+
+        let response = request
+            .header(reqwest::header::ACCEPT, DNS_MESSAGE)
+            .timeout(wait)
+            .send()
+            .await
+            .map_err(http_error)?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(ResolveError::Protocol(format!(
+                "{} answered HTTP {}",
+                url, status
+            )));
+        }
+
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        if !content_type.eq_ignore_ascii_case(DNS_MESSAGE) {
+            return Err(ResolveError::Protocol(format!(
+                "{} answered content-type {:?}",
+                url, content_type
+            )));
+        }
+
+        let body = response.bytes().await.map_err(http_error)?;
+        let message = DnsMessage::parse(&body)?;
+        if !answers_query(&query, &message) {
+            return Err(ResolveError::Protocol(format!(
+                "{} answered a different query",
+                url
+            )));
+        }
+
+        Ok(message)
+    }
+}
+
+fn http_error(e: reqwest::Error) -> ResolveError {
+    if e.is_timeout() {
+        ResolveError::Timeout
+    } else {
+        ResolveError::Protocol(format!("https: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::udp::dns_message::{
+        Record, CLASS_IN, FLAG_TC, RCODE_SERVFAIL, TYPE_A, TYPE_SOA, TYPE_TXT,
+    };
+    use std::net::Ipv4Addr;
+    use tokio::net::TcpListener;
+
+    fn question(name: &str) -> Question {
+        Question {
+            name: name.to_string(),
+            qtype: TYPE_A,
+            qclass: CLASS_IN,
+        }
+    }
+
+    fn a_record(name: &str, ttl: u32, last: u8) -> Record {
+        Record {
+            name: name.to_string(),
+            rtype: TYPE_A,
+            class: CLASS_IN,
+            ttl,
+            data: RData::A(Ipv4Addr::new(127, 0, 0, last)),
+        }
+    }
+
+    fn soa_record(ttl: u32, minimum: u32) -> Record {
+        Record {
+            name: "example.test".to_string(),
+            rtype: TYPE_SOA,
+            class: CLASS_IN,
+            ttl,
+            data: RData::Soa {
+                mname: "ns.example.test".to_string(),
+                rname: "hostmaster.example.test".to_string(),
+                serial: 1,
+                refresh: 3600,
+                retry: 600,
+                expire: 604800,
+                minimum,
+            },
+        }
+    }
+
+    fn answer(name: &str, records: Vec<Record>) -> DnsMessage {
+        let mut response = DnsMessage::query(9, name, TYPE_A).reply();
+        response.answers = records;
+        response
+    }
+
+    fn ttls(response: &DnsMessage) -> Vec<u32> {
+        response.answers.iter().map(|r| r.ttl).collect()
+    }
+
+    #[test]
+    fn cache_counts_down_ttl_and_expires_with_the_shortest_record() {
+        let mut cache = DnsCache::default();
+        let now = Instant::now();
+        let name = "www.example.test";
+        let response = answer(name, vec![a_record(name, 300, 1), a_record(name, 60, 2)]);
+
+        cache.insert(&question(name), &response, now);
+        assert_eq!(cache.len(), 1);
+
+        // 이름은 대소문자를 가리지 않는다
+        let cached = cache
+            .get(&question("WWW.Example.Test"), now + Duration::from_secs(10))
+            .unwrap();
+        assert_eq!(ttls(&cached), vec![290, 50]);
+
+        assert!(cache
+            .get(&question(name), now + Duration::from_secs(60))
+            .is_none());
+        assert!(cache.is_empty());
+        assert_eq!((cache.hits, cache.misses), (1, 1));
+    }
+
+    #[test]
+    fn cache_forgets_the_id_and_opt() {
+        let mut cache = DnsCache::default();
+        let now = Instant::now();
+        let name = "www.example.test";
+        let mut response = answer(name, vec![a_record(name, 300, 1)]);
+        response.id = 77;
+        response.set_edns(Some(Edns {
+            udp_payload_size: EDNS_PAYLOAD,
+            extended_rcode: 0,
+            version: 0,
+            dnssec_ok: false,
+            options: Vec::new(),
+        }));
+
+        cache.insert(&question(name), &response, now);
+        let cached = cache.get(&question(name), now).unwrap();
+        assert_eq!(cached.id, 0);
+        assert!(cached.edns().is_none());
+    }
+
+    #[test]
+    fn negative_answers_are_cached_for_the_soa_minimum() {
+        let mut cache = DnsCache::default();
+        let now = Instant::now();
+        let name = "nope.example.test";
+        let mut response = answer(name, Vec::new());
+        response.set_rcode(RCODE_NXDOMAIN);
+        response.authority.push(soa_record(300, 30));
+
+        cache.insert(&question(name), &response, now);
+        let cached = cache
+            .get(&question(name), now + Duration::from_secs(29))
+            .unwrap();
+        assert_eq!(cached.rcode(), RCODE_NXDOMAIN);
+        assert!(cache
+            .get(&question(name), now + Duration::from_secs(30))
+            .is_none());
+
+        // SOA가 없으면 얼마나 들고 있을지 모르므로 두지 않는다
+        let mut bare = answer(name, Vec::new());
+        bare.set_rcode(RCODE_NXDOMAIN);
+        cache.insert(&question(name), &bare, now);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn failures_truncated_and_zero_ttl_answers_are_not_cached() {
+        let mut cache = DnsCache::default();
+        let now = Instant::now();
+        let name = "www.example.test";
+
+        let mut servfail = answer(name, Vec::new());
+        servfail.set_rcode(RCODE_SERVFAIL);
+        cache.insert(&question(name), &servfail, now);
+
+        let mut truncated = answer(name, vec![a_record(name, 300, 1)]);
+        truncated.set_flag(FLAG_TC, true);
+        cache.insert(&question(name), &truncated, now);
+
+        cache.insert(
+            &question(name),
+            &answer(name, vec![a_record(name, 0, 1)]),
+            now,
+        );
+
+        assert!(cache.is_empty());
+    }
+
+    // 같은 port에 UDP는 늘 TC로, TCP는 다 담아서 답하는 서버
+    async fn truncating_server() -> SocketAddr {
+        let udp = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = udp.local_addr().unwrap();
+        let tcp = TcpListener::bind(addr).await.unwrap();
+
+        tokio::spawn(async move {
+            let mut buf = vec![0u8; 65535];
+            loop {
+                let (n, peer) = udp.recv_from(&mut buf).await.unwrap();
+                let mut reply = DnsMessage::parse(&buf[..n]).unwrap().reply();
+                reply.set_flag(FLAG_TC, true);
+                udp.send_to(&reply.to_bytes().unwrap(), peer).await.unwrap();
+            }
+        });
+
+        tokio::spawn(async move {
+            let (mut stream, _) = tcp.accept().await.unwrap();
+            let len = stream.read_u16().await.unwrap();
+            let mut data = vec![0u8; len as usize];
+            stream.read_exact(&mut data).await.unwrap();
+
+            let query = DnsMessage::parse(&data).unwrap();
+            let mut reply = query.reply();
+            reply
+                .answers
+                .push(a_record(&query.questions[0].name, 300, 7));
+            let bytes = reply.to_bytes().unwrap();
+
+            stream.write_u16(bytes.len() as u16).await.unwrap();
+            stream.write_all(&bytes).await.unwrap();
+        });
+
+        addr
+    }
+
+    #[tokio::test]
+    async fn truncated_udp_answer_is_asked_again_over_tcp() {
+        let server = truncating_server().await;
+
+        let response = exchange(
+            server,
+            &question("big.example.test"),
+            Duration::from_secs(2),
+            1,
+        )
+        .await
+        .unwrap();
+
+        assert!(!response.truncated());
+        assert_eq!(response.answers, vec![a_record("big.example.test", 300, 7)]);
+    }
+
+    #[tokio::test]
+    async fn datagrams_for_other_queries_are_ignored() {
+        let udp = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let server = udp.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let mut buf = vec![0u8; 65535];
+            let (n, peer) = udp.recv_from(&mut buf).await.unwrap();
+            let query = DnsMessage::parse(&buf[..n]).unwrap();
+
+            // id가 다른 것, 질문이 다른 것을 먼저 보낸다
+            let mut wrong_id = query.reply();
+            wrong_id.id = query.id.wrapping_add(1);
+            let mut wrong_question = query.reply();
+            wrong_question.questions[0].qtype = TYPE_TXT;
+            let mut right = query.reply();
+            right.answers.push(a_record("www.example.test", 300, 8));
+
+            for reply in [wrong_id, wrong_question, right] {
+                udp.send_to(&reply.to_bytes().unwrap(), peer).await.unwrap();
+            }
+        });
+
+        let response = exchange(
+            server,
+            &question("www.example.test"),
+            Duration::from_secs(2),
+            1,
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.answers, vec![a_record("www.example.test", 300, 8)]);
+    }
+
+    #[tokio::test]
+    async fn every_attempt_is_sent_before_timing_out() {
+        let udp = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let server = udp.local_addr().unwrap();
+
+        let result = exchange(
+            server,
+            &question("www.example.test"),
+            Duration::from_millis(100),
+            2,
+        )
+        .await;
+        assert!(matches!(result, Err(ResolveError::Timeout)));
+
+        let mut buf = vec![0u8; 65535];
+        for _ in 0..2 {
+            let (n, _) = udp.try_recv_from(&mut buf).unwrap();
+            let query = DnsMessage::parse(&buf[..n]).unwrap();
+            assert_eq!(query.questions, vec![question("www.example.test")]);
+        }
+        assert!(udp.try_recv_from(&mut buf).is_err());
+    }
+}
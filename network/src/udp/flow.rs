@@ -1,26 +1,45 @@
+use crate::udp::flow_export::{EndReason, FlowExportConfig, FlowExporter, FlowRecord};
+use nix::sys::signal::{SigSet, Signal};
+use nix::sys::signalfd::{SfdFlags, SignalFd};
 use pnet::datalink::{self, Channel::Ethernet};
 use pnet::packet::{
     ethernet::{EtherTypes, EthernetPacket},
     ip::IpNextHeaderProtocols,
     ipv4::Ipv4Packet,
-    tcp::TcpPacket,
+    tcp::{TcpFlags, TcpPacket},
     udp::UdpPacket,
     Packet,
 };
 use std::collections::HashMap;
+use std::net::Ipv4Addr;
 use std::time::{Duration, Instant};
 
+// 캡처한 IPv4 TCP/UDP packet을 5-tuple flow로 모으고,
+// active/inactive timeout이 지난 flow는 flow_export로 collector에 NetFlow v5/v9, IPFIX로 내보낸다.
+// FLOW_COLLECTOR가 없으면 예전처럼 표만 찍고 만료된 flow는 버린다.
+// SIGINT/SIGTERM을 받으면 아직 끝나지 않은 flow도 forced로 내보내고 끝낸다.
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 enum Proto {
     Tcp,
     Udp,
 }
 
+impl Proto {
+    // IP protocol 번호
+    fn number(self) -> u8 {
+        match self {
+            Proto::Tcp => 6,
+            Proto::Udp => 17,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 struct FlowKey {
-    src_ip: String,
+    src_ip: Ipv4Addr,
     src_port: u16,
-    dst_ip: String,
+    dst_ip: Ipv4Addr,
     dst_port: u16,
     proto: Proto,
 }
@@ -31,26 +50,77 @@ struct FlowStats {
     bytes: u64,
     first_seen: Instant,
     last_seen: Instant,
+    // 본 TCP flag를 모두 OR한 값
+    tcp_flags: u8,
+    tos: u8,
 }
 
 impl FlowStats {
-    fn new(now: Instant, bytes: usize) -> Self {
+    fn new(now: Instant, tos: u8) -> Self {
         Self {
-            packets: 1,
-            bytes: bytes as u64,
+            packets: 0,
+            bytes: 0,
             first_seen: now,
             last_seen: now,
+            tcp_flags: 0,
+            tos,
         }
     }
 
-    fn update(&mut self, now: Instant, bytes: usize) {
+    fn update(&mut self, now: Instant, bytes: usize, tcp_flags: u8) {
+        // active timeout으로 내보낸 뒤 처음 온 packet부터 다시 센다
+        if self.packets == 0 {
+            self.first_seen = now;
+        }
         self.packets += 1;
         self.bytes += bytes as u64;
+        self.tcp_flags |= tcp_flags;
         self.last_seen = now;
     }
+
+    fn ended(&self) -> bool {
+        self.tcp_flags & (TcpFlags::FIN | TcpFlags::RST) != 0
+    }
+}
+
+fn record_packet(
+    flows: &mut HashMap<FlowKey, FlowStats>,
+    key: FlowKey,
+    ipv4: &Ipv4Packet,
+    tcp_flags: u8,
+) {
+    let now = Instant::now();
+    let tos = (ipv4.get_dscp() << 2) | ipv4.get_ecn();
+
+    // NetFlow처럼 IP 헤더부터 센다
+    flows
+        .entry(key)
+        .or_insert_with(|| FlowStats::new(now, tos))
+        .update(now, ipv4.get_total_length() as usize, tcp_flags);
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+pub fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // 종료 시그널은 막아 두고 signalfd로 받아서 loop를 빠져나간 뒤 남은 flow를 내보낸다
+    let mut mask = SigSet::empty();
+    mask.add(Signal::SIGTERM);
+    mask.add(Signal::SIGINT);
+    mask.thread_block()?;
+    let signal_fd = SignalFd::with_flags(&mask, SfdFlags::SFD_NONBLOCK | SfdFlags::SFD_CLOEXEC)?;
+
+    let export_config = FlowExportConfig::from_env()?;
+    let mut exporter = FlowExporter::from_config(&export_config)?;
+
+    match &exporter {
+        Some(exporter) => println!(
+            "[INFO] exporting flows to {} as {} (active={:?} inactive={:?})",
+            exporter.collector(),
+            exporter.format(),
+            export_config.active_timeout,
+            export_config.inactive_timeout
+        ),
+        None => println!("[INFO] FLOW_COLLECTOR not set, flows are only printed"),
+    }
+
     let interface_name = "en0";
 
     let interfaces = datalink::interfaces();
@@ -62,8 +132,11 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     println!("[INFO] listening on interface: {}", interface.name);
 
-    let mut config = datalink::Config::default();
-    config.read_timeout = None;
+    // 조용할 때도 timeout 검사와 시그널 확인이 돌도록 1초마다 깨어난다
+    let config = datalink::Config {
+        read_timeout: Some(Duration::from_secs(1)),
+        ..Default::default()
+    };
 
     let (_, mut rx) = match datalink::channel(&interface, config)? {
         Ethernet(tx, rx) => (tx, rx),
@@ -72,6 +145,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let mut flows: HashMap<FlowKey, FlowStats> = HashMap::new();
     let mut last_report = Instant::now();
+    let mut last_expire = Instant::now();
 
     loop {
         match rx.next() {
@@ -83,35 +157,27 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                                 IpNextHeaderProtocols::Tcp => {
                                     if let Some(tcp) = TcpPacket::new(ipv4.payload()) {
                                         let key = FlowKey {
-                                            src_ip: ipv4.get_source().to_string(),
+                                            src_ip: ipv4.get_source(),
                                             src_port: tcp.get_source(),
-                                            dst_ip: ipv4.get_destination().to_string(),
+                                            dst_ip: ipv4.get_destination(),
                                             dst_port: tcp.get_destination(),
                                             proto: Proto::Tcp,
                                         };
 
-                                        let now = Instant::now();
-                                        let entry = flows
-                                            .entry(key)
-                                            .or_insert_with(|| FlowStats::new(now, packet.len()));
-                                        entry.update(now, packet.len());
+                                        record_packet(&mut flows, key, &ipv4, tcp.get_flags());
                                     }
                                 }
                                 IpNextHeaderProtocols::Udp => {
                                     if let Some(udp) = UdpPacket::new(ipv4.payload()) {
                                         let key = FlowKey {
-                                            src_ip: ipv4.get_source().to_string(),
+                                            src_ip: ipv4.get_source(),
                                             src_port: udp.get_source(),
-                                            dst_ip: ipv4.get_destination().to_string(),
+                                            dst_ip: ipv4.get_destination(),
                                             dst_port: udp.get_destination(),
                                             proto: Proto::Udp,
                                         };
 
-                                        let now = Instant::now();
-                                        let entry = flows
-                                            .entry(key)
-                                            .or_insert_with(|| FlowStats::new(now, packet.len()));
-                                        entry.update(now, packet.len());
+                                        record_packet(&mut flows, key, &ipv4, 0);
                                     }
                                 }
                                 _ => {}
@@ -119,18 +185,56 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                         }
                     }
                 }
-
-                // 5초마다 flow 요약 출력
-                if last_report.elapsed() >= Duration::from_secs(5) {
-                    print_flows(&flows);
-                    remove_stale_flows(&mut flows, Duration::from_secs(30));
-                    last_report = Instant::now();
-                }
             }
+            Err(e) if e.kind() == std::io::ErrorKind::TimedOut => {}
             Err(e) => {
                 eprintln!("[ERROR] failed to read packet: {}", e);
             }
         }
+
+        if signal_fd.read_signal()?.is_some() {
+            break;
+        }
+
+        // 1초마다 timeout이 지난 flow를 내보낸다
+        if last_expire.elapsed() >= Duration::from_secs(1) {
+            let records = expire_flows(&mut flows, &export_config, Instant::now());
+            export_records(exporter.as_mut(), &records);
+            last_expire = Instant::now();
+        }
+
+        // 5초마다 flow 요약 출력
+        if last_report.elapsed() >= Duration::from_secs(5) {
+            print_flows(&flows);
+            last_report = Instant::now();
+        }
+    }
+
+    let records = flush_flows(&mut flows);
+    println!(
+        "[INFO] shutting down, flushing {} active flows",
+        records.len()
+    );
+    export_records(exporter.as_mut(), &records);
+    Ok(())
+}
+
+fn export_records(exporter: Option<&mut FlowExporter>, records: &[FlowRecord]) {
+    let Some(exporter) = exporter else {
+        return;
+    };
+    if records.is_empty() {
+        return;
+    }
+
+    match exporter.export(records) {
+        Ok(packets) => println!(
+            "[INFO] exported {} flows in {} packets to {}",
+            records.len(),
+            packets,
+            exporter.collector()
+        ),
+        Err(e) => eprintln!("[ERROR] failed to export flows: {}", e),
     }
 }
 
@@ -141,7 +245,7 @@ fn print_flows(flows: &HashMap<FlowKey, FlowStats>) {
     let mut items: Vec<(&FlowKey, &FlowStats)> = flows.iter().collect();
 
     // bytes 기준 내림차순
-    items.sort_by(|a, b| b.1.bytes.cmp(&a.1.bytes));
+    items.sort_by_key(|(_, stats)| std::cmp::Reverse(stats.bytes));
 
     for (key, stats) in items.iter().take(20) {
         let proto = match key.proto {
@@ -171,6 +275,160 @@ fn print_flows(flows: &HashMap<FlowKey, FlowStats>) {
     println!();
 }
 
-fn remove_stale_flows(flows: &mut HashMap<FlowKey, FlowStats>, timeout: Duration) {
-    flows.retain(|_, stats| stats.last_seen.elapsed() < timeout);
-}
\ No newline at end of file
+fn flow_record(key: &FlowKey, stats: &FlowStats, end_reason: EndReason) -> FlowRecord {
+    FlowRecord {
+        src_ip: key.src_ip,
+        dst_ip: key.dst_ip,
+        src_port: key.src_port,
+        dst_port: key.dst_port,
+        protocol: key.proto.number(),
+        tcp_flags: stats.tcp_flags,
+        tos: stats.tos,
+        packets: stats.packets,
+        bytes: stats.bytes,
+        first_seen: stats.first_seen,
+        last_seen: stats.last_seen,
+        end_reason,
+    }
+}
+
+// 1. inactive timeout 동안 조용했거나 FIN/RST를 본 flow는 내보내고 지운다
+// 2. active timeout보다 오래된 flow는 지금까지의 증가분을 내보내고 카운터만 비운다
+fn expire_flows(
+    flows: &mut HashMap<FlowKey, FlowStats>,
+    config: &FlowExportConfig,
+    now: Instant,
+) -> Vec<FlowRecord> {
+    let mut records = Vec::new();
+
+    flows.retain(|key, stats| {
+        let idle = now.saturating_duration_since(stats.last_seen) >= config.inactive_timeout;
+
+        if idle || stats.ended() {
+            // active timeout으로 내보낸 뒤 packet이 더 없었으면 보낼 것이 없다
+            if stats.packets > 0 {
+                let reason = if stats.ended() {
+                    EndReason::EndOfFlow
+                } else {
+                    EndReason::Idle
+                };
+                records.push(flow_record(key, stats, reason));
+            }
+            return false;
+        }
+
+        if stats.packets > 0
+            && now.saturating_duration_since(stats.first_seen) >= config.active_timeout
+        {
+            records.push(flow_record(key, stats, EndReason::Active));
+            stats.packets = 0;
+            stats.bytes = 0;
+            stats.tcp_flags = 0;
+        }
+
+        true
+    });
+
+    records
+}
+
+// 종료할 때 남은 flow를 모두 forced로 내보내고 비운다
+fn flush_flows(flows: &mut HashMap<FlowKey, FlowStats>) -> Vec<FlowRecord> {
+    flows
+        .drain()
+        // active timeout으로 막 내보내서 비어 있는 flow는 보낼 것이 없다
+        .filter(|(_, stats)| stats.packets > 0)
+        .map(|(key, stats)| flow_record(&key, &stats, EndReason::Forced))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(src_port: u16, proto: Proto) -> FlowKey {
+        FlowKey {
+            src_ip: Ipv4Addr::new(10, 0, 0, 1),
+            src_port,
+            dst_ip: Ipv4Addr::new(10, 0, 0, 2),
+            dst_port: 443,
+            proto,
+        }
+    }
+
+    fn stats(start: Instant, packets: u64, tcp_flags: u8) -> FlowStats {
+        let mut stats = FlowStats::new(start, 0);
+        for _ in 0..packets {
+            stats.update(start, 100, tcp_flags);
+        }
+        stats
+    }
+
+    fn config() -> FlowExportConfig {
+        FlowExportConfig {
+            active_timeout: Duration::from_secs(60),
+            inactive_timeout: Duration::from_secs(15),
+            ..FlowExportConfig::default()
+        }
+    }
+
+    fn reasons(records: &[FlowRecord]) -> Vec<(u16, EndReason)> {
+        let mut reasons: Vec<_> = records
+            .iter()
+            .map(|record| (record.src_port, record.end_reason))
+            .collect();
+        reasons.sort_by_key(|(port, _)| *port);
+        reasons
+    }
+
+    #[test]
+    fn expire_sends_idle_ended_and_active_flows() {
+        let start = Instant::now();
+        let mut flows = HashMap::new();
+        flows.insert(key(1, Proto::Udp), stats(start, 3, 0));
+        flows.insert(key(2, Proto::Tcp), stats(start, 2, TcpFlags::FIN));
+        flows.insert(key(3, Proto::Tcp), stats(start, 1, TcpFlags::ACK));
+
+        // 3번 flow만 최근까지 packet이 왔다
+        let now = start + Duration::from_secs(60);
+        flows
+            .get_mut(&key(3, Proto::Tcp))
+            .unwrap()
+            .update(now, 100, 0);
+
+        let records = expire_flows(&mut flows, &config(), now);
+        assert_eq!(
+            reasons(&records),
+            vec![
+                (1, EndReason::Idle),
+                (2, EndReason::EndOfFlow),
+                (3, EndReason::Active),
+            ]
+        );
+
+        // active로 내보낸 flow는 남고 카운터만 비운다
+        assert_eq!(flows.len(), 1);
+        assert_eq!(flows[&key(3, Proto::Tcp)].packets, 0);
+        let active = records.iter().find(|r| r.src_port == 3).unwrap();
+        assert_eq!(active.packets, 2);
+    }
+
+    #[test]
+    fn flush_sends_every_active_flow_as_forced() {
+        let start = Instant::now();
+        let mut flows = HashMap::new();
+        flows.insert(key(1, Proto::Udp), stats(start, 3, 0));
+        flows.insert(key(2, Proto::Tcp), stats(start, 1, TcpFlags::SYN));
+        // active timeout 직후라 보낼 것이 없는 flow
+        flows.insert(key(3, Proto::Tcp), stats(start, 0, 0));
+
+        let records = flush_flows(&mut flows);
+
+        assert!(flows.is_empty());
+        assert_eq!(
+            reasons(&records),
+            vec![(1, EndReason::Forced), (2, EndReason::Forced)]
+        );
+        assert_eq!(records.iter().map(|r| r.bytes).sum::<u64>(), 400);
+    }
+}
@@ -0,0 +1,729 @@
+use std::fmt;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// flow.rs가 만료시킨 flow를 collector(nfdump, pmacct, ntopng 등)로 UDP로 내보낸다.
+// 1. NetFlow v5: 고정된 48바이트 record, 한 packet에 30개까지
+// 2. NetFlow v9 (RFC 3954): template FlowSet(id 0)으로 record 모양을 알리고 data FlowSet(id 256)을 보낸다
+// 3. IPFIX (RFC 7011): v9와 같은 방식에 template set id 2, 시각은 epoch ms, flowEndReason까지 담는다
+// UDP에서는 collector가 중간에 켜질 수 있어서 template을 주기적으로 다시 보낸다.
+
+// ==================== CONFIG ====================
+
+// 이더넷 MTU에서 IP/UDP 헤더를 빼고도 남도록
+const MAX_PACKET: usize = 1400;
+const V5_MAX_RECORDS: usize = 30;
+const TEMPLATE_ID: u16 = 256;
+// template은 이 시간이나 packet 수 중 먼저 오는 쪽마다 다시 보낸다
+const TEMPLATE_REFRESH: Duration = Duration::from_secs(60);
+const TEMPLATE_REFRESH_PACKETS: u32 = 20;
+
+// (field type, length). v9 field 번호와 IPFIX IE 번호는 1~127이 같다
+const V9_FIELDS: &[(u16, u16)] = &[
+    (8, 4),  // IPV4_SRC_ADDR
+    (12, 4), // IPV4_DST_ADDR
+    (7, 2),  // L4_SRC_PORT
+    (11, 2), // L4_DST_PORT
+    (4, 1),  // PROTOCOL
+    (6, 1),  // TCP_FLAGS
+    (5, 1),  // SRC_TOS
+    (1, 8),  // IN_BYTES
+    (2, 8),  // IN_PKTS
+    (22, 4), // FIRST_SWITCHED (sysUptime ms)
+    (21, 4), // LAST_SWITCHED
+];
+
+const IPFIX_FIELDS: &[(u16, u16)] = &[
+    (8, 4),   // sourceIPv4Address
+    (12, 4),  // destinationIPv4Address
+    (7, 2),   // sourceTransportPort
+    (11, 2),  // destinationTransportPort
+    (4, 1),   // protocolIdentifier
+    (6, 1),   // tcpControlBits (reduced-size encoding)
+    (5, 1),   // ipClassOfService
+    (1, 8),   // octetDeltaCount
+    (2, 8),   // packetDeltaCount
+    (152, 8), // flowStartMilliseconds
+    (153, 8), // flowEndMilliseconds
+    (136, 1), // flowEndReason
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    NetflowV5,
+    NetflowV9,
+    Ipfix,
+}
+
+impl ExportFormat {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "5" | "v5" | "netflow5" => Some(ExportFormat::NetflowV5),
+            "9" | "v9" | "netflow9" => Some(ExportFormat::NetflowV9),
+            "10" | "ipfix" => Some(ExportFormat::Ipfix),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportFormat::NetflowV5 => write!(f, "netflow v5"),
+            ExportFormat::NetflowV9 => write!(f, "netflow v9"),
+            ExportFormat::Ipfix => write!(f, "ipfix"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FlowExportConfig {
+    // 없으면 내보내지 않고 표만 찍는다
+    pub collector: Option<SocketAddr>,
+    pub format: ExportFormat,
+    // 이만큼 오래된 flow는 끝나지 않았어도 그때까지의 증가분을 내보낸다
+    pub active_timeout: Duration,
+    // 이만큼 packet이 없으면 flow가 끝난 것으로 본다
+    pub inactive_timeout: Duration,
+    // v9 source id, IPFIX observation domain id. v5에서는 engine id(하위 8비트)
+    pub domain_id: u32,
+}
+
+impl Default for FlowExportConfig {
+    fn default() -> Self {
+        Self {
+            collector: None,
+            format: ExportFormat::Ipfix,
+            active_timeout: Duration::from_secs(60),
+            inactive_timeout: Duration::from_secs(15),
+            domain_id: 0,
+        }
+    }
+}
+
+impl FlowExportConfig {
+    // FLOW_COLLECTOR (127.0.0.1:2055), FLOW_FORMAT (v5 | v9 | ipfix),
+    // FLOW_ACTIVE_TIMEOUT, FLOW_INACTIVE_TIMEOUT (초), FLOW_DOMAIN_ID 로 덮어쓴다
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let mut config = Self::default();
+
+        if let Ok(collector) = std::env::var("FLOW_COLLECTOR") {
+            config.collector = Some(collector.parse()?);
+        }
+
+        if let Ok(format) = std::env::var("FLOW_FORMAT") {
+            config.format = ExportFormat::parse(&format)
+                .ok_or_else(|| format!("unknown FLOW_FORMAT: {}", format))?;
+        }
+
+        if let Ok(secs) = std::env::var("FLOW_ACTIVE_TIMEOUT") {
+            config.active_timeout = Duration::from_secs(secs.parse()?);
+        }
+
+        if let Ok(secs) = std::env::var("FLOW_INACTIVE_TIMEOUT") {
+            config.inactive_timeout = Duration::from_secs(secs.parse()?);
+        }
+
+        if let Ok(id) = std::env::var("FLOW_DOMAIN_ID") {
+            config.domain_id = id.parse()?;
+        }
+
+        Ok(config)
+    }
+}
+
+// ==================== RECORD ====================
+
+// IPFIX flowEndReason 값
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EndReason {
+    Idle = 1,
+    Active = 2,
+    // TCP FIN/RST
+    EndOfFlow = 3,
+    // 프로그램 종료 등으로 강제로 내보냄
+    Forced = 4,
+}
+
+impl fmt::Display for EndReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EndReason::Idle => write!(f, "idle"),
+            EndReason::Active => write!(f, "active"),
+            EndReason::EndOfFlow => write!(f, "end"),
+            EndReason::Forced => write!(f, "forced"),
+        }
+    }
+}
+
+// 내보낼 flow 하나. 카운터는 지난번에 내보낸 뒤의 증가분이다 (active timeout 뒤에도 flow는 이어진다)
+#[derive(Debug, Clone)]
+pub struct FlowRecord {
+    pub src_ip: Ipv4Addr,
+    pub dst_ip: Ipv4Addr,
+    pub src_port: u16,
+    pub dst_port: u16,
+    pub protocol: u8,
+    // 본 TCP flag를 모두 OR한 값
+    pub tcp_flags: u8,
+    pub tos: u8,
+    pub packets: u64,
+    // IP 헤더부터 센 bytes
+    pub bytes: u64,
+    pub first_seen: Instant,
+    pub last_seen: Instant,
+    pub end_reason: EndReason,
+}
+
+// Instant를 NetFlow의 sysUptime(ms)과 epoch ms로 바꾼다
+struct Clock {
+    boot: Instant,
+    boot_epoch: Duration,
+}
+
+impl Clock {
+    fn new() -> Self {
+        Self {
+            boot: Instant::now(),
+            boot_epoch: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default(),
+        }
+    }
+
+    // 32비트라 49.7일마다 한 바퀴 돈다 (collector도 그렇게 해석한다)
+    fn uptime_ms(&self, at: Instant) -> u32 {
+        at.saturating_duration_since(self.boot).as_millis() as u32
+    }
+
+    fn epoch(&self, at: Instant) -> Duration {
+        self.boot_epoch + at.saturating_duration_since(self.boot)
+    }
+}
+
+fn saturate_u32(value: u64) -> u32 {
+    value.min(u32::MAX as u64) as u32
+}
+
+fn fields_len(fields: &[(u16, u16)]) -> usize {
+    fields.iter().map(|(_, len)| *len as usize).sum()
+}
+
+// set/FlowSet은 4바이트 경계로 끝나야 한다
+fn pad_to_4(out: &mut Vec<u8>, start: usize) {
+    while !(out.len() - start).is_multiple_of(4) {
+        out.push(0);
+    }
+}
+
+fn write_template(out: &mut Vec<u8>, set_id: u16, fields: &[(u16, u16)]) {
+    let start = out.len();
+    out.extend_from_slice(&set_id.to_be_bytes());
+    out.extend_from_slice(&0u16.to_be_bytes()); // 길이는 끝에서 채운다
+    out.extend_from_slice(&TEMPLATE_ID.to_be_bytes());
+    out.extend_from_slice(&(fields.len() as u16).to_be_bytes());
+
+    for (field_type, len) in fields {
+        out.extend_from_slice(&field_type.to_be_bytes());
+        out.extend_from_slice(&len.to_be_bytes());
+    }
+
+    let len = (out.len() - start) as u16;
+    out[start + 2..start + 4].copy_from_slice(&len.to_be_bytes());
+}
+
+fn write_field(out: &mut Vec<u8>, field_type: u16, record: &FlowRecord, clock: &Clock) {
+    match field_type {
+        8 => out.extend_from_slice(&record.src_ip.octets()),
+        12 => out.extend_from_slice(&record.dst_ip.octets()),
+        7 => out.extend_from_slice(&record.src_port.to_be_bytes()),
+        11 => out.extend_from_slice(&record.dst_port.to_be_bytes()),
+        4 => out.push(record.protocol),
+        6 => out.push(record.tcp_flags),
+        5 => out.push(record.tos),
+        1 => out.extend_from_slice(&record.bytes.to_be_bytes()),
+        2 => out.extend_from_slice(&record.packets.to_be_bytes()),
+        22 => out.extend_from_slice(&clock.uptime_ms(record.first_seen).to_be_bytes()),
+        21 => out.extend_from_slice(&clock.uptime_ms(record.last_seen).to_be_bytes()),
+        152 => out
+            .extend_from_slice(&(clock.epoch(record.first_seen).as_millis() as u64).to_be_bytes()),
+        153 => {
+            out.extend_from_slice(&(clock.epoch(record.last_seen).as_millis() as u64).to_be_bytes())
+        }
+        136 => out.push(record.end_reason as u8),
+        _ => {}
+    }
+}
+
+fn write_data_set(out: &mut Vec<u8>, fields: &[(u16, u16)], records: &[FlowRecord], clock: &Clock) {
+    let start = out.len();
+    out.extend_from_slice(&TEMPLATE_ID.to_be_bytes());
+    out.extend_from_slice(&0u16.to_be_bytes());
+
+    for record in records {
+        for (field_type, _) in fields {
+            write_field(out, *field_type, record, clock);
+        }
+    }
+
+    pad_to_4(out, start);
+    let len = (out.len() - start) as u16;
+    out[start + 2..start + 4].copy_from_slice(&len.to_be_bytes());
+}
+
+// ==================== EXPORTER ====================
+
+pub struct FlowExporter {
+    socket: UdpSocket,
+    collector: SocketAddr,
+    format: ExportFormat,
+    domain_id: u32,
+    clock: Clock,
+    // v5: 지금까지 보낸 flow 수, v9: 보낸 packet 수, IPFIX: 보낸 data record 수
+    sequence: u32,
+    template_sent: Option<Instant>,
+    packets_since_template: u32,
+    pub packets_sent: u64,
+    pub records_sent: u64,
+}
+
+impl FlowExporter {
+    pub fn new(collector: SocketAddr, format: ExportFormat, domain_id: u32) -> io::Result<Self> {
+        let bind: SocketAddr = if collector.is_ipv4() {
+            SocketAddr::from(([0, 0, 0, 0], 0))
+        } else {
+            SocketAddr::from(([0u16; 8], 0))
+        };
+
+        Ok(Self {
+            socket: UdpSocket::bind(bind)?,
+            collector,
+            format,
+            domain_id,
+            clock: Clock::new(),
+            sequence: 0,
+            template_sent: None,
+            packets_since_template: 0,
+            packets_sent: 0,
+            records_sent: 0,
+        })
+    }
+
+    pub fn from_config(config: &FlowExportConfig) -> io::Result<Option<Self>> {
+        match config.collector {
+            Some(collector) => Ok(Some(Self::new(collector, config.format, config.domain_id)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn format(&self) -> ExportFormat {
+        self.format
+    }
+
+    pub fn collector(&self) -> SocketAddr {
+        self.collector
+    }
+
+    // records를 packet 여러 개로 나눠 보낸다. 보낸 packet 수를 돌려준다
+    pub fn export(&mut self, records: &[FlowRecord]) -> io::Result<usize> {
+        let mut sent = 0;
+        let mut rest = records;
+
+        // v9/IPFIX는 보낼 record가 없어도 template 주기가 되면 template만 보낸다
+        while !rest.is_empty() || (sent == 0 && self.template_due()) {
+            let (packet, used) = self.encode(rest);
+            self.socket.send_to(&packet, self.collector)?;
+
+            rest = &rest[used..];
+            sent += 1;
+            self.packets_sent += 1;
+            self.records_sent += used as u64;
+        }
+
+        Ok(sent)
+    }
+
+    fn template_due(&self) -> bool {
+        if self.format == ExportFormat::NetflowV5 {
+            return false;
+        }
+
+        match self.template_sent {
+            None => true,
+            Some(at) => {
+                at.elapsed() >= TEMPLATE_REFRESH
+                    || self.packets_since_template >= TEMPLATE_REFRESH_PACKETS
+            }
+        }
+    }
+
+    // packet 하나를 만들고 그 안에 넣은 record 수를 돌려준다
+    fn encode(&mut self, records: &[FlowRecord]) -> (Vec<u8>, usize) {
+        match self.format {
+            ExportFormat::NetflowV5 => self.encode_v5(records),
+            ExportFormat::NetflowV9 => self.encode_v9(records),
+            ExportFormat::Ipfix => self.encode_ipfix(records),
+        }
+    }
+
+    fn encode_v5(&mut self, records: &[FlowRecord]) -> (Vec<u8>, usize) {
+        let records = &records[..records.len().min(V5_MAX_RECORDS)];
+        let now = Instant::now();
+        let epoch = self.clock.epoch(now);
+
+        let mut out = Vec::with_capacity(24 + records.len() * 48);
+        out.extend_from_slice(&5u16.to_be_bytes());
+        out.extend_from_slice(&(records.len() as u16).to_be_bytes());
+        out.extend_from_slice(&self.clock.uptime_ms(now).to_be_bytes());
+        out.extend_from_slice(&(epoch.as_secs() as u32).to_be_bytes());
+        out.extend_from_slice(&epoch.subsec_nanos().to_be_bytes());
+        out.extend_from_slice(&self.sequence.to_be_bytes());
+        out.push(0); // engine_type
+        out.push(self.domain_id as u8); // engine_id
+        out.extend_from_slice(&0u16.to_be_bytes()); // sampling 없음
+
+        for record in records {
+            out.extend_from_slice(&record.src_ip.octets());
+            out.extend_from_slice(&record.dst_ip.octets());
+            out.extend_from_slice(&[0; 4]); // nexthop
+            out.extend_from_slice(&[0; 4]); // input, output ifIndex
+            out.extend_from_slice(&saturate_u32(record.packets).to_be_bytes());
+            out.extend_from_slice(&saturate_u32(record.bytes).to_be_bytes());
+            out.extend_from_slice(&self.clock.uptime_ms(record.first_seen).to_be_bytes());
+            out.extend_from_slice(&self.clock.uptime_ms(record.last_seen).to_be_bytes());
+            out.extend_from_slice(&record.src_port.to_be_bytes());
+            out.extend_from_slice(&record.dst_port.to_be_bytes());
+            out.push(0); // pad1
+            out.push(record.tcp_flags);
+            out.push(record.protocol);
+            out.push(record.tos);
+            out.extend_from_slice(&[0; 4]); // src_as, dst_as
+            out.extend_from_slice(&[0; 2]); // src_mask, dst_mask
+            out.extend_from_slice(&[0; 2]); // pad2
+        }
+
+        self.sequence = self.sequence.wrapping_add(records.len() as u32);
+        (out, records.len())
+    }
+
+    fn encode_v9(&mut self, records: &[FlowRecord]) -> (Vec<u8>, usize) {
+        let now = Instant::now();
+        let with_template = self.template_due();
+
+        // header 20, template FlowSet 8 + 4*fields, data FlowSet 4 + padding 3
+        let mut room = MAX_PACKET - 20 - 4 - 3;
+        if with_template {
+            room -= 8 + 4 * V9_FIELDS.len();
+        }
+        let count = records.len().min(room / fields_len(V9_FIELDS));
+        let records = &records[..count];
+
+        let mut out = Vec::with_capacity(MAX_PACKET);
+        out.extend_from_slice(&9u16.to_be_bytes());
+        // count는 template record와 data record를 합한 수
+        out.extend_from_slice(&((count + with_template as usize) as u16).to_be_bytes());
+        out.extend_from_slice(&self.clock.uptime_ms(now).to_be_bytes());
+        out.extend_from_slice(&(self.clock.epoch(now).as_secs() as u32).to_be_bytes());
+        out.extend_from_slice(&self.sequence.to_be_bytes());
+        out.extend_from_slice(&self.domain_id.to_be_bytes());
+
+        if with_template {
+            write_template(&mut out, 0, V9_FIELDS);
+        }
+        if !records.is_empty() {
+            write_data_set(&mut out, V9_FIELDS, records, &self.clock);
+        }
+
+        self.sequence = self.sequence.wrapping_add(1);
+        self.after_packet(with_template, now);
+        (out, count)
+    }
+
+    fn encode_ipfix(&mut self, records: &[FlowRecord]) -> (Vec<u8>, usize) {
+        let now = Instant::now();
+        let with_template = self.template_due();
+
+        // header 16, template set 8 + 4*fields, data set 4 + padding 3
+        let mut room = MAX_PACKET - 16 - 4 - 3;
+        if with_template {
+            room -= 8 + 4 * IPFIX_FIELDS.len();
+        }
+        let count = records.len().min(room / fields_len(IPFIX_FIELDS));
+        let records = &records[..count];
+
+        let mut out = Vec::with_capacity(MAX_PACKET);
+        out.extend_from_slice(&10u16.to_be_bytes());
+        out.extend_from_slice(&0u16.to_be_bytes()); // 전체 길이는 끝에서 채운다
+        out.extend_from_slice(&(self.clock.epoch(now).as_secs() as u32).to_be_bytes());
+        // 이 message 앞까지 보낸 data record 수
+        out.extend_from_slice(&self.sequence.to_be_bytes());
+        out.extend_from_slice(&self.domain_id.to_be_bytes());
+
+        if with_template {
+            write_template(&mut out, 2, IPFIX_FIELDS);
+        }
+        if !records.is_empty() {
+            write_data_set(&mut out, IPFIX_FIELDS, records, &self.clock);
+        }
+
+        let len = out.len() as u16;
+        out[2..4].copy_from_slice(&len.to_be_bytes());
+
+        self.sequence = self.sequence.wrapping_add(count as u32);
+        self.after_packet(with_template, now);
+        (out, count)
+    }
+
+    fn after_packet(&mut self, with_template: bool, now: Instant) {
+        if with_template {
+            self.template_sent = Some(now);
+            self.packets_since_template = 0;
+        } else {
+            self.packets_since_template += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const V9_RECORD: usize = 39;
+    const IPFIX_RECORD: usize = 48;
+
+    fn u16_at(data: &[u8], offset: usize) -> u16 {
+        u16::from_be_bytes(data[offset..offset + 2].try_into().unwrap())
+    }
+
+    fn u32_at(data: &[u8], offset: usize) -> u32 {
+        u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn u64_at(data: &[u8], offset: usize) -> u64 {
+        u64::from_be_bytes(data[offset..offset + 8].try_into().unwrap())
+    }
+
+    fn exporter(format: ExportFormat) -> FlowExporter {
+        FlowExporter::new(SocketAddr::from(([127, 0, 0, 1], 9)), format, 0x0102_0304).unwrap()
+    }
+
+    // exporter가 켜진 지 1초에 시작해서 2.5초 동안 이어진 flow
+    fn record(exporter: &FlowExporter, n: u8) -> FlowRecord {
+        let first_seen = exporter.clock.boot + Duration::from_millis(1_000);
+        FlowRecord {
+            src_ip: Ipv4Addr::new(10, 0, 0, n),
+            dst_ip: Ipv4Addr::new(192, 168, 1, 1),
+            src_port: 40_000 + n as u16,
+            dst_port: 443,
+            protocol: 6,
+            tcp_flags: 0x1b,
+            tos: 0x10,
+            packets: 10,
+            bytes: 1_500,
+            first_seen,
+            last_seen: first_seen + Duration::from_millis(2_500),
+            end_reason: EndReason::Forced,
+        }
+    }
+
+    fn records(exporter: &FlowExporter, count: usize) -> Vec<FlowRecord> {
+        (0..count).map(|n| record(exporter, n as u8)).collect()
+    }
+
+    // (type, length) 목록
+    fn template_fields(set: &[u8]) -> Vec<(u16, u16)> {
+        let count = u16_at(set, 6) as usize;
+        (0..count)
+            .map(|i| (u16_at(set, 8 + i * 4), u16_at(set, 10 + i * 4)))
+            .collect()
+    }
+
+    #[test]
+    fn v5_has_a_24_byte_header_and_48_byte_records() {
+        let mut exporter = exporter(ExportFormat::NetflowV5);
+        let mut big = record(&exporter, 1);
+        big.bytes = 5_000_000_000;
+        let flows = vec![record(&exporter, 0), big];
+
+        let (packet, used) = exporter.encode(&flows);
+        assert_eq!(used, 2);
+        assert_eq!(packet.len(), 24 + 2 * 48);
+
+        assert_eq!(u16_at(&packet, 0), 5);
+        assert_eq!(u16_at(&packet, 2), 2);
+        assert_eq!(u32_at(&packet, 16), 0); // flow_sequence
+        assert_eq!(packet[20], 0); // engine_type
+        assert_eq!(packet[21], 0x04); // engine_id는 domain id 하위 8비트
+        assert_eq!(u16_at(&packet, 22), 0);
+
+        let r = &packet[24..72];
+        assert_eq!(&r[0..4], &[10, 0, 0, 0]);
+        assert_eq!(&r[4..8], &[192, 168, 1, 1]);
+        assert_eq!(&r[8..16], &[0; 8]); // nexthop, ifIndex
+        assert_eq!(u32_at(r, 16), 10);
+        assert_eq!(u32_at(r, 20), 1_500);
+        assert_eq!(u32_at(r, 24), 1_000);
+        assert_eq!(u32_at(r, 28), 3_500);
+        assert_eq!(u16_at(r, 32), 40_000);
+        assert_eq!(u16_at(r, 34), 443);
+        assert_eq!(&r[36..40], &[0, 0x1b, 6, 0x10]);
+        assert_eq!(&r[40..48], &[0; 8]);
+
+        // 32비트를 넘는 카운터는 잘리지 않고 최댓값에 머문다
+        assert_eq!(u32_at(&packet[72..], 20), u32::MAX);
+
+        // sequence는 지금까지 보낸 flow 수
+        let (packet, _) = exporter.encode(&flows[..1]);
+        assert_eq!(u32_at(&packet, 16), 2);
+    }
+
+    #[test]
+    fn v5_sends_at_most_30_records_per_packet() {
+        let collector = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let mut exporter =
+            FlowExporter::new(collector.local_addr().unwrap(), ExportFormat::NetflowV5, 0).unwrap();
+
+        let flows = records(&exporter, 31);
+        assert_eq!(exporter.export(&flows).unwrap(), 2);
+
+        let mut buf = [0u8; 2048];
+        let n = collector.recv(&mut buf).unwrap();
+        assert_eq!(
+            (n, u16_at(&buf, 2), u32_at(&buf, 16)),
+            (24 + 30 * 48, 30, 0)
+        );
+        let n = collector.recv(&mut buf).unwrap();
+        assert_eq!((n, u16_at(&buf, 2), u32_at(&buf, 16)), (24 + 48, 1, 30));
+
+        assert_eq!((exporter.packets_sent, exporter.records_sent), (2, 31));
+    }
+
+    #[test]
+    fn v9_template_then_padded_data_flowset() {
+        let mut exporter = exporter(ExportFormat::NetflowV9);
+        let flows = records(&exporter, 1);
+
+        let (packet, used) = exporter.encode(&flows);
+        assert_eq!(used, 1);
+
+        assert_eq!(u16_at(&packet, 0), 9);
+        // template record 하나와 data record 하나
+        assert_eq!(u16_at(&packet, 2), 2);
+        // sysUptime(ms)와 unix secs
+        assert!(u32_at(&packet, 4) < 60_000);
+        assert!(u32_at(&packet, 8) as u64 >= exporter.clock.boot_epoch.as_secs());
+        assert_eq!(u32_at(&packet, 12), 0); // package_sequence
+        assert_eq!(u32_at(&packet, 16), 0x0102_0304); // source id
+
+        let template = &packet[20..];
+        assert_eq!(u16_at(template, 0), 0);
+        assert_eq!(u16_at(template, 2) as usize, 8 + 4 * V9_FIELDS.len());
+        assert_eq!(u16_at(template, 4), TEMPLATE_ID);
+        assert_eq!(template_fields(template), V9_FIELDS);
+        assert_eq!(fields_len(V9_FIELDS), V9_RECORD);
+
+        // 4 + 39 = 43 → 44로 padding
+        let data = &packet[20 + 8 + 4 * V9_FIELDS.len()..];
+        assert_eq!(u16_at(data, 0), TEMPLATE_ID);
+        assert_eq!(u16_at(data, 2), 44);
+        assert_eq!(data.len(), 44);
+        assert_eq!(data[4 + V9_RECORD], 0);
+
+        let r = &data[4..4 + V9_RECORD];
+        assert_eq!(&r[0..4], &[10, 0, 0, 0]);
+        assert_eq!(&r[4..8], &[192, 168, 1, 1]);
+        assert_eq!(u16_at(r, 8), 40_000);
+        assert_eq!(u16_at(r, 10), 443);
+        assert_eq!(&r[12..15], &[6, 0x1b, 0x10]);
+        assert_eq!(u64_at(r, 15), 1_500);
+        assert_eq!(u64_at(r, 23), 10);
+        assert_eq!(u32_at(r, 31), 1_000);
+        assert_eq!(u32_at(r, 35), 3_500);
+
+        // 다음 packet은 template 없이, sequence는 packet마다 하나씩
+        let (packet, _) = exporter.encode(&records(&exporter, 2));
+        assert_eq!(u16_at(&packet, 2), 2);
+        assert_eq!(u32_at(&packet, 12), 1);
+        assert_eq!(u16_at(&packet, 20), TEMPLATE_ID);
+        // 4 + 78 = 82 → 84
+        assert_eq!(u16_at(&packet, 22), 84);
+        assert_eq!(packet.len(), 20 + 84);
+    }
+
+    #[test]
+    fn ipfix_message_length_sets_and_record_sequence() {
+        let mut exporter = exporter(ExportFormat::Ipfix);
+        let flows = records(&exporter, 3);
+
+        let (packet, used) = exporter.encode(&flows);
+        assert_eq!(used, 3);
+
+        assert_eq!(u16_at(&packet, 0), 10);
+        assert_eq!(u16_at(&packet, 2) as usize, packet.len());
+        assert_eq!(u32_at(&packet, 8), 0); // 이 message 앞까지 보낸 data record 수
+        assert_eq!(u32_at(&packet, 12), 0x0102_0304);
+
+        let template = &packet[16..];
+        assert_eq!(u16_at(template, 0), 2);
+        assert_eq!(u16_at(template, 2) as usize, 8 + 4 * IPFIX_FIELDS.len());
+        assert_eq!(template_fields(template), IPFIX_FIELDS);
+        assert_eq!(fields_len(IPFIX_FIELDS), IPFIX_RECORD);
+
+        let data = &packet[16 + 8 + 4 * IPFIX_FIELDS.len()..];
+        assert_eq!(u16_at(data, 0), TEMPLATE_ID);
+        assert_eq!(u16_at(data, 2) as usize, 4 + 3 * IPFIX_RECORD);
+        assert_eq!(data.len(), 4 + 3 * IPFIX_RECORD);
+
+        let r = &data[4..4 + IPFIX_RECORD];
+        assert_eq!(u64_at(r, 15), 1_500);
+        assert_eq!(u64_at(r, 23), 10);
+        // 시각은 epoch ms
+        let start = u64_at(r, 31);
+        let end = u64_at(r, 39);
+        assert_eq!(end - start, 2_500);
+        assert_eq!(
+            start,
+            exporter.clock.epoch(flows[0].first_seen).as_millis() as u64
+        );
+        assert_eq!(r[47], EndReason::Forced as u8);
+
+        let (packet, _) = exporter.encode(&flows[..1]);
+        assert_eq!(u32_at(&packet, 8), 3);
+        assert_eq!(u16_at(&packet, 16), TEMPLATE_ID);
+        assert_eq!(packet.len(), 16 + 4 + IPFIX_RECORD);
+    }
+
+    #[test]
+    fn packets_stay_under_the_mtu_and_templates_are_resent() {
+        let collector = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let mut exporter =
+            FlowExporter::new(collector.local_addr().unwrap(), ExportFormat::NetflowV9, 0).unwrap();
+
+        // record가 없어도 처음에는 template만 보낸다
+        assert_eq!(exporter.export(&[]).unwrap(), 1);
+        let mut buf = [0u8; 2048];
+        let n = collector.recv(&mut buf).unwrap();
+        assert_eq!((n, u16_at(&buf, 2)), (20 + 8 + 4 * V9_FIELDS.len(), 1));
+        assert_eq!(exporter.export(&[]).unwrap(), 0);
+
+        let flows = records(&exporter, 100);
+        let packets = exporter.export(&flows).unwrap();
+
+        let mut total = 0;
+        for _ in 0..packets {
+            let n = collector.recv(&mut buf).unwrap();
+            assert!(n <= MAX_PACKET);
+            total += u16_at(&buf, 2) as usize;
+        }
+        assert_eq!(total, 100);
+
+        // 20 packet마다 template을 다시 싣는다
+        while exporter.packets_since_template < TEMPLATE_REFRESH_PACKETS {
+            exporter.encode(&flows[..1]);
+        }
+        let (packet, _) = exporter.encode(&flows[..1]);
+        assert_eq!(u16_at(&packet, 20), 0);
+        assert_eq!(exporter.packets_since_template, 0);
+    }
+}
@@ -1,3 +1,7 @@
+use crate::tcp::conversation::ConversationTracker;
+use crate::tcp::stream_reassembly::{parse_segment, ReassemblyConfig};
+use crate::tcp::tls_fingerprint::{print_hello, TlsInspector};
+use crate::udp::dns_message::DnsMessage;
 use pnet::datalink::{self, Channel::Ethernet};
 use pnet::packet::{
     ethernet::{EtherTypes, EthernetPacket},
@@ -7,59 +11,7 @@ use pnet::packet::{
     udp::UdpPacket,
     Packet,
 };
-
-fn read_u16_be(data: &[u8], offset: usize) -> Option<u16> {
-    if offset + 1 >= data.len() {
-        return None;
-    }
-    Some(u16::from_be_bytes([data[offset], data[offset + 1]]))
-}
-
-fn parse_dns_name(data: &[u8], mut offset: usize) -> Option<(String, usize)> {
-    let mut labels = Vec::new();
-
-    loop {
-        if offset >= data.len() {
-            return None;
-        }
-
-        let len = data[offset] as usize;
-        offset += 1;
-
-        if len == 0 {
-            break;
-        }
-
-        // 이번 단계에서는 압축 포인터 미지원
-        if len & 0b1100_0000 != 0 {
-            return None;
-        }
-
-        if offset + len > data.len() {
-            return None;
-        }
-
-        let label = std::str::from_utf8(&data[offset..offset + len]).ok()?;
-        labels.push(label.to_string());
-        offset += len;
-    }
-
-    Some((labels.join("."), offset))
-}
-
-fn dns_type_to_string(qtype: u16) -> &'static str {
-    match qtype {
-        1 => "A",
-        2 => "NS",
-        5 => "CNAME",
-        6 => "SOA",
-        12 => "PTR",
-        15 => "MX",
-        16 => "TXT",
-        28 => "AAAA",
-        _ => "OTHER",
-    }
-}
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 fn tcp_flags_to_string(flags: u8) -> String {
     let mut parts = Vec::new();
@@ -96,40 +48,14 @@ fn tcp_flags_to_string(flags: u8) -> String {
     }
 }
 
-fn handle_dns(payload: &[u8]) -> Option<String> {
-    if payload.len() < 12 {
-        return None;
-    }
-
-    let tx_id = read_u16_be(payload, 0)?;
-    let flags = read_u16_be(payload, 2)?;
-    let qdcount = read_u16_be(payload, 4)?;
-
-    let qr = (flags & 0x8000) != 0;
-    let kind = if qr { "RESPONSE" } else { "QUERY" };
-
-    if qdcount == 0 {
-        return Some(format!(
-            "[DNS] kind={} tx_id=0x{:04x} qdcount=0",
-            kind, tx_id
-        ));
+fn handle_dns(payload: &[u8]) -> String {
+    match DnsMessage::parse(payload) {
+        Ok(message) => format!("[DNS] {}", message),
+        Err(e) => format!("[DNS] parse_failed: {}", e),
     }
-
-    let (domain, next_offset) = parse_dns_name(payload, 12)?;
-    let qtype = read_u16_be(payload, next_offset)?;
-    let qclass = read_u16_be(payload, next_offset + 2)?;
-
-    Some(format!(
-        "[DNS] kind={} tx_id=0x{:04x} domain={} type={} class={}",
-        kind,
-        tx_id,
-        domain,
-        dns_type_to_string(qtype),
-        qclass
-    ))
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+pub fn main() -> Result<(), Box<dyn std::error::Error>> {
     let interface_name = "en0";
 
     let interfaces = datalink::interfaces();
@@ -141,17 +67,34 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     println!("[INFO] listening on interface: {}", interface.name);
 
-    let mut config = datalink::Config::default();
-    config.read_timeout = None;
+    let config = datalink::Config {
+        read_timeout: None,
+        ..Default::default()
+    };
 
     let (_, mut rx) = match datalink::channel(&interface, config)? {
         Ethernet(tx, rx) => (tx, rx),
         _ => return Err("지원되지 않는 channel type".into()),
     };
 
+    // TCP payload는 재조립해서 TLS hello를 찾는다 (segment 여러 개에 걸쳐 와도 된다)
+    let mut tls =
+        ConversationTracker::new(ReassemblyConfig::default(), TlsInspector::new(print_hello));
+    let mut last_expire = Instant::now();
+
     loop {
         match rx.next() {
             Ok(packet) => {
+                let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+                if let Some(segment) = parse_segment(packet, now) {
+                    tls.process(&segment);
+                }
+
+                if last_expire.elapsed() >= Duration::from_secs(1) {
+                    tls.expire(now);
+                    last_expire = Instant::now();
+                }
+
                 // 1. raw bytes -> Ethernet
                 if let Some(eth) = EthernetPacket::new(packet) {
                     // 2. Ethernet 안에 IPv4만 처리
@@ -191,9 +134,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
                                         // 5. DNS(53)면 추가 파싱
                                         if src_port == 53 || dst_port == 53 {
-                                            if let Some(dns_info) = handle_dns(udp.payload()) {
-                                                println!("  {}", dns_info);
-                                            }
+                                            println!("  {}", handle_dns(udp.payload()));
                                         }
                                     }
                                 }
@@ -208,4 +149,4 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
         }
     }
-}
\ No newline at end of file
+}
@@ -1,4 +1,13 @@
 pub mod udp_basic;
 pub mod udp_echo;
 pub mod pnet;
-pub mod dns;
\ No newline at end of file
+pub mod dns;
+pub mod dns_message;
+pub mod integration;
+pub mod dns_stats;
+pub mod dns_transport;
+pub mod dns_server;
+pub mod dns_resolver;
+pub mod dns_encrypted;
+pub mod flow;
+pub mod flow_export;
\ No newline at end of file
@@ -0,0 +1,38 @@
+// 여러 예제가 같이 쓰는 작은 도구들
+
+// 커널 난수(getrandom)로 buf를 채운다. 세션 토큰, DNS transaction id처럼
+// 예측되면 안 되는 값에 쓴다. 시그널에 끊기거나 덜 채워지면 나머지를 다시 요청한다.
+pub fn fill_random(buf: &mut [u8]) -> std::io::Result<()> {
+    let mut filled = 0;
+
+    while filled < buf.len() {
+        let rest = &mut buf[filled..];
+        let n = unsafe { libc::getrandom(rest.as_mut_ptr().cast(), rest.len(), 0) };
+        if n < 0 {
+            let err = std::io::Error::last_os_error();
+            if err.kind() == std::io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(err);
+        }
+        filled += n as usize;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fill_random_fills_the_whole_buffer() {
+        // 4096바이트가 전부 0일 확률은 무시할 수 있다
+        let mut buf = vec![0u8; 4096];
+        fill_random(&mut buf).unwrap();
+        assert!(buf.iter().any(|b| *b != 0));
+
+        let mut empty = [0u8; 0];
+        fill_random(&mut empty).unwrap();
+    }
+}
@@ -0,0 +1,872 @@
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufRead, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+use tokio_tungstenite::tungstenite::handshake::derive_accept_key;
+use tokio_tungstenite::tungstenite::protocol::Role;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use crate::tcp::chat_auth::{AuthError, MAX_LOGIN_ATTEMPTS};
+use crate::tcp::nic_chat::{Event, Hub, Session};
+use crate::tcp::slow_consumer::{LineReader, Outbox};
+use crate::tcp::tls;
+
+// 브라우저용 chat gateway.
+// GET / 는 index.html, GET /ws 는 WebSocket으로 올려서 nic_chat과 같은 Hub에 붙인다.
+//
+// client -> server
+//   {"type":"hello","nick":"alice"}         계정이 있으면 "password"(또는 "token")도
+//   {"type":"join","room":"rust"}            {"type":"part","room":"rust"}
+//   {"type":"message","room":"rust","text":"hi"}
+//   {"type":"private","to":"bob","text":"hi"}
+//   {"type":"nick","nick":"alice2"}
+// server -> client
+//   welcome / join / part / message / private / nick / quit / notice / history / error
+//   로그인 실패 error에는 code: password_required, invalid_credentials, banned
+
+const INDEX_HTML: &str = include_str!("../../index.html");
+const DEFAULT_ROOM: &str = "lobby";
+const MAX_HEADER_LINES: usize = 64;
+// 요청 줄 / header 한 줄의 최대 바이트 수
+const MAX_REQUEST_LINE: usize = 8 * 1024;
+const MAX_HEADER_LINE: usize = 8 * 1024;
+// RFC 6455에서 정의된 유일한 버전
+const WEBSOCKET_VERSION: &str = "13";
+
+// ==================== ENVELOPES ====================
+
+fn event_json(event: &Event) -> Value {
+    match event {
+        Event::Message { room, from, text } => {
+            json!({ "type": "message", "room": room, "from": from, "text": text })
+        }
+        Event::Private { from, to, text } => {
+            json!({ "type": "private", "from": from, "to": to, "text": text })
+        }
+        Event::Join { room, nick } => json!({ "type": "join", "room": room, "nick": nick }),
+        Event::Part { room, nick } => json!({ "type": "part", "room": room, "nick": nick }),
+        Event::Nick { old, new } => json!({ "type": "nick", "old": old, "new": new }),
+        Event::Quit { nick } => json!({ "type": "quit", "nick": nick }),
+        Event::Notice(text) => json!({ "type": "notice", "text": text }),
+        Event::History(stored) => json!({
+            "type": "history",
+            "room": stored.room,
+            "nick": stored.nick,
+            "text": stored.to_string(),
+            "at": stored.at.to_rfc3339(),
+        }),
+    }
+}
+
+fn error_json(message: impl std::fmt::Display) -> Value {
+    json!({ "type": "error", "message": message.to_string() })
+}
+
+// ==================== HTTP ====================
+
+struct Request {
+    method: String,
+    path: String,
+    // 소문자 이름
+    headers: Vec<(String, String)>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn is_websocket(&self) -> bool {
+        self.header("upgrade")
+            .is_some_and(|v| v.eq_ignore_ascii_case("websocket"))
+    }
+
+    // Origin이 없으면 브라우저가 아니므로 통과. 있으면 허용 목록에 있거나 같은 host여야 한다
+    fn origin_allowed(&self, allowed: &[String]) -> bool {
+        let Some(origin) = self.header("origin") else {
+            return true;
+        };
+
+        if allowed.iter().any(|a| a.eq_ignore_ascii_case(origin)) {
+            return true;
+        }
+
+        let origin_host = origin
+            .strip_prefix("https://")
+            .or_else(|| origin.strip_prefix("http://"));
+        match (origin_host, self.header("host")) {
+            (Some(origin_host), Some(host)) => origin_host.eq_ignore_ascii_case(host),
+            _ => false,
+        }
+    }
+}
+
+// 요청을 거절할 때 돌려줄 status
+type Rejection = &'static str;
+
+// 헤더를 다 읽기 전에 연결이 닫혔으면 Ok(None)
+async fn read_request<R>(reader: &mut R) -> std::io::Result<Option<Result<Request, Rejection>>>
+where
+    R: AsyncBufRead + Unpin,
+{
+    // 줄 단위로 상한을 둔다. 완성된 줄만 돌려주므로 뒤따르는 WebSocket 바이트는 reader에 남는다
+    let mut lines = LineReader::new(reader, MAX_REQUEST_LINE);
+
+    let Some(line) = lines.next_line().await? else {
+        return Ok(None);
+    };
+    if lines.truncated() {
+        return Ok(Some(Err("414 URI Too Long")));
+    }
+
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        return Ok(Some(Err("400 Bad Request")));
+    };
+    let method = method.to_string();
+    let path = path.to_string();
+
+    let mut lines = LineReader::new(lines.into_inner(), MAX_HEADER_LINE);
+    let mut headers = Vec::new();
+    for _ in 0..=MAX_HEADER_LINES {
+        let Some(line) = lines.next_line().await? else {
+            return Ok(None);
+        };
+        if lines.truncated() {
+            return Ok(Some(Err("431 Request Header Fields Too Large")));
+        }
+
+        let header = line.trim_end();
+        if header.is_empty() {
+            return Ok(Some(Ok(Request {
+                method,
+                path,
+                headers,
+            })));
+        }
+
+        if headers.len() == MAX_HEADER_LINES {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+    }
+
+    // header가 너무 많음
+    Ok(Some(Err("431 Request Header Fields Too Large")))
+}
+
+async fn respond<W>(writer: &mut W, status: &str, content_type: &str, body: &str) -> std::io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    respond_with(writer, status, "", content_type, body).await
+}
+
+// extra_headers는 "Name: value\r\n" 형태로 이어 붙인 문자열
+async fn respond_with<W>(
+    writer: &mut W,
+    status: &str,
+    extra_headers: &str,
+    content_type: &str,
+    body: &str,
+) -> std::io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let response = format!(
+        "HTTP/1.1 {}\r\n{}Content-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        extra_headers,
+        content_type,
+        body.len(),
+        body
+    );
+    writer.write_all(response.as_bytes()).await?;
+    writer.shutdown().await
+}
+
+async fn handle_http<S>(socket: S, addr: SocketAddr, hub: Arc<Hub>, origins: Arc<Vec<String>>)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    // BufReader째로 WebSocket에 넘겨야 이미 읽어 둔 바이트를 잃지 않는다
+    let mut stream = BufReader::new(socket);
+
+    let request = match read_request(&mut stream).await {
+        Ok(Some(Ok(request))) => request,
+        Ok(Some(Err(status))) => {
+            println!("[WARN] Rejected HTTP request from {}: {}", addr, status);
+            let _ = respond(&mut stream, status, "text/plain", status).await;
+            return;
+        }
+        Ok(None) => {
+            let _ = respond(&mut stream, "400 Bad Request", "text/plain", "bad request").await;
+            return;
+        }
+        Err(e) => {
+            println!("[SERVER] HTTP read error from {}: {}", addr, e);
+            return;
+        }
+    };
+
+    println!("[SERVER] {} {} {}", addr, request.method, request.path);
+
+    let result = match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/ws") if request.is_websocket() => {
+            let Some(key) = request.header("sec-websocket-key") else {
+                let _ = respond(&mut stream, "400 Bad Request", "text/plain", "missing key").await;
+                return;
+            };
+
+            if request.header("sec-websocket-version") != Some(WEBSOCKET_VERSION) {
+                let _ = respond_with(
+                    &mut stream,
+                    "426 Upgrade Required",
+                    "Sec-WebSocket-Version: 13\r\n",
+                    "text/plain",
+                    "unsupported websocket version",
+                )
+                .await;
+                return;
+            }
+
+            // 다른 사이트의 페이지가 방문자 브라우저로 붙는 것을 막는다
+            if !request.origin_allowed(&origins) {
+                println!(
+                    "[WARN] Rejected WebSocket from {} with origin {:?}",
+                    addr,
+                    request.header("origin")
+                );
+                let _ = respond(&mut stream, "403 Forbidden", "text/plain", "origin not allowed").await;
+                return;
+            }
+
+            let accept = derive_accept_key(key.as_bytes());
+            let response = format!(
+                "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+                accept
+            );
+            if let Err(e) = stream.write_all(response.as_bytes()).await {
+                println!("[SERVER] Upgrade failed for {}: {}", addr, e);
+                return;
+            }
+
+            let ws = WebSocketStream::from_raw_socket(stream, Role::Server, None).await;
+            handle_websocket(ws, addr, hub).await;
+            return;
+        }
+        ("GET", "/") | ("GET", "/index.html") => {
+            respond(&mut stream, "200 OK", "text/html; charset=utf-8", INDEX_HTML).await
+        }
+        ("GET", _) => respond(&mut stream, "404 Not Found", "text/plain", "not found").await,
+        _ => {
+            respond(&mut stream, "405 Method Not Allowed", "text/plain", "method not allowed")
+                .await
+        }
+    };
+
+    if let Err(e) = result {
+        println!("[SERVER] HTTP write error to {}: {}", addr, e);
+    }
+}
+
+// ==================== WEBSOCKET ====================
+
+fn str_field<'a>(value: &'a Value, name: &str) -> Option<&'a str> {
+    value.get(name).and_then(Value::as_str)
+}
+
+// 등록 후 받은 envelope 처리. 결과로 바로 돌려줄 envelope
+fn handle_envelope(hub: &Arc<Hub>, session: &mut Session, msg: &Value) -> Option<Value> {
+    let result = match str_field(msg, "type") {
+        Some("join") => match str_field(msg, "room") {
+            Some(room) => session.join(hub, room),
+            None => return Some(error_json("join needs room")),
+        },
+        Some("part") => match str_field(msg, "room") {
+            Some(room) => session.part(hub, room),
+            None => return Some(error_json("part needs room")),
+        },
+        Some("message") => match (str_field(msg, "room"), str_field(msg, "text")) {
+            (Some(room), Some(text)) if !text.trim().is_empty() => {
+                session.say(hub, room, text.trim())
+            }
+            _ => return Some(error_json("message needs room and text")),
+        },
+        Some("private") => match (str_field(msg, "to"), str_field(msg, "text")) {
+            (Some(to), Some(text)) if !text.trim().is_empty() => {
+                session.private(hub, to, text.trim())
+            }
+            _ => return Some(error_json("private needs to and text")),
+        },
+        Some("nick") => match str_field(msg, "nick") {
+            Some(nick) => session.rename(hub, nick.to_string()),
+            None => return Some(error_json("nick needs nick")),
+        },
+        Some("who") => match str_field(msg, "room") {
+            Some(room) => {
+                return Some(match hub.who(room) {
+                    Ok(members) => json!({ "type": "who", "room": room, "members": members }),
+                    Err(e) => error_json(e),
+                })
+            }
+            None => return Some(error_json("who needs room")),
+        },
+        Some("list") => {
+            let rooms: Vec<Value> = hub
+                .list()
+                .into_iter()
+                .map(|(room, count)| json!({ "room": room, "members": count }))
+                .collect();
+            return Some(json!({ "type": "list", "rooms": rooms }));
+        }
+        Some(other) => return Some(error_json(format!("unknown type '{}'", other))),
+        None => return Some(error_json("missing type")),
+    };
+
+    result.err().map(error_json)
+}
+
+// 로그인하고 Hub에 등록한다. 실패하면 돌려줄 error envelope.
+// 페이지가 비밀번호를 다시 물을 수 있도록 code를 붙인다
+async fn hello(
+    hub: &Arc<Hub>,
+    msg: &Value,
+    addr: SocketAddr,
+    outbox: &Arc<Outbox<Event>>,
+) -> Result<Session, Value> {
+    let Some(nick) = str_field(msg, "nick") else {
+        return Err(error_json("hello needs nick"));
+    };
+    let secret = str_field(msg, "password").or_else(|| str_field(msg, "token"));
+
+    let role = match hub.auth().login(nick, secret, addr.ip()).await {
+        Ok(role) => role,
+        Err(e) => {
+            let code = match e {
+                AuthError::SecretRequired => "password_required",
+                AuthError::InvalidCredentials => "invalid_credentials",
+                AuthError::Banned(_) => "banned",
+                _ => "auth",
+            };
+            println!("[WARN] WebSocket login for {} from {} failed: {}", nick, addr, e);
+            return Err(json!({ "type": "error", "code": code, "message": e.to_string() }));
+        }
+    };
+
+    if let Err(e) = hub.register(nick, addr.ip(), role, outbox.clone()) {
+        return Err(error_json(e));
+    }
+
+    println!("[SERVER] {} set nickname: {} (websocket)", addr, nick);
+    Ok(Session::new(nick.to_string(), outbox.clone(), hub.config()))
+}
+
+async fn handle_websocket<S>(ws: WebSocketStream<S>, addr: SocketAddr, hub: Arc<Hub>)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    println!("[SERVER] WebSocket client connected: {}", addr);
+
+    let (mut sink, mut stream) = ws.split();
+
+    if let Err(e) = hub.auth().check_ban(None, addr.ip()) {
+        println!("[WARN] Rejected banned WebSocket address {}", addr);
+        let _ = sink.send(Message::text(error_json(e).to_string())).await;
+        let _ = sink.send(Message::Close(None)).await;
+        return;
+    }
+
+    let config = hub.config().clone();
+    let (outbox, mut inbox) = Outbox::new(config.outbound_capacity, config.kick_threshold);
+    let mut session: Option<Session> = None;
+    let mut failures = 0;
+
+    loop {
+        let reply = tokio::select! {
+            biased;
+
+            _ = outbox.kicked() => {
+                let reason = outbox.kick_reason();
+                println!("[WARN] Disconnecting WebSocket client {}: {}", addr, reason);
+                let notice = error_json(format!("disconnected: {}", reason));
+                let _ = tokio::time::timeout(std::time::Duration::from_millis(100), async {
+                    sink.send(Message::text(notice.to_string())).await?;
+                    sink.send(Message::Close(None)).await
+                })
+                .await;
+                break;
+            }
+
+            Some(event) = inbox.recv() => {
+                let dropped = outbox.take_dropped();
+                if dropped > 0 {
+                    let notice = json!({ "type": "dropped", "count": dropped });
+                    if sink.send(Message::text(notice.to_string())).await.is_err() {
+                        break;
+                    }
+                }
+                Some(event_json(&event))
+            }
+
+            message = stream.next() => {
+                let text = match message {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | None => {
+                        println!("[SERVER] WebSocket client disconnected: {}", addr);
+                        break;
+                    }
+                    // ping은 tungstenite가 알아서 pong
+                    Some(Ok(_)) => continue,
+                    Some(Err(e)) => {
+                        println!("[SERVER] WebSocket error from {}: {}", addr, e);
+                        break;
+                    }
+                };
+
+                let msg: Value = match serde_json::from_str(text.as_str()) {
+                    Ok(msg) => msg,
+                    Err(e) => {
+                        let _ = sink.send(Message::text(error_json(format!("invalid json: {}", e)).to_string())).await;
+                        continue;
+                    }
+                };
+
+                match session.as_mut() {
+                    Some(session) => handle_envelope(&hub, session, &msg),
+                    // 처음에는 hello로 닉네임부터 정한다
+                    None if str_field(&msg, "type") == Some("hello") => {
+                        match hello(&hub, &msg, addr, &outbox).await {
+                            Ok(mut new_session) => {
+                                if let Err(e) = new_session.join(&hub, DEFAULT_ROOM) {
+                                    println!("[SERVER] Failed to join {} for {}: {}", DEFAULT_ROOM, addr, e);
+                                }
+                                let welcome = json!({ "type": "welcome", "nick": new_session.nick(), "room": DEFAULT_ROOM });
+                                session = Some(new_session);
+                                Some(welcome)
+                            }
+                            Err(error) if error["code"] == "invalid_credentials" => {
+                                failures += 1;
+                                if failures >= MAX_LOGIN_ATTEMPTS {
+                                    let _ = sink.send(Message::text(error_json("too many failed logins").to_string())).await;
+                                    let _ = sink.send(Message::Close(None)).await;
+                                    break;
+                                }
+                                // 비밀번호 대입을 늦춘다
+                                tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                                Some(error)
+                            }
+                            Err(error) => Some(error),
+                        }
+                    }
+                    None => Some(error_json("send {\"type\":\"hello\",\"nick\":...} first")),
+                }
+            }
+        };
+
+        if let Some(reply) = reply {
+            let send = sink.send(Message::text(reply.to_string()));
+
+            // 상대가 안 읽어서 send가 막혀 있어도 kick되면 빠져나온다
+            let result = tokio::select! {
+                result = send => result.map_err(std::io::Error::other),
+                _ = outbox.kicked() => Err(std::io::Error::other(outbox.kick_reason())),
+            };
+
+            if let Err(e) = result {
+                println!("[SERVER] WebSocket write error to {}: {}", addr, e);
+                break;
+            }
+        }
+    }
+
+    if let Some(session) = session {
+        session.close(&hub);
+    }
+}
+
+// GATEWAY_ORIGINS=https://chat.example.com,http://localhost:3000
+// 같은 host에서 연 페이지는 목록 없이도 허용된다
+fn allowed_origins() -> Vec<String> {
+    std::env::var("GATEWAY_ORIGINS")
+        .map(|v| {
+            v.split(',')
+                .map(|o| o.trim().trim_end_matches('/').to_string())
+                .filter(|o| !o.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// nic_chat 서버가 같은 Hub로 같이 띄운다
+pub async fn serve(
+    addr: &str,
+    hub: Arc<Hub>,
+    acceptor: Option<TlsAcceptor>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let listener = TcpListener::bind(addr).await?;
+    let origins = Arc::new(allowed_origins());
+
+    if let Some(acceptor) = acceptor {
+        println!("[SERVER] WebSocket gateway (TLS) listening on https://{}", addr);
+        return tls::serve(listener, acceptor, move |socket, addr| {
+            handle_http(socket, addr, hub.clone(), origins.clone())
+        })
+        .await;
+    }
+
+    println!("[SERVER] WebSocket gateway listening on http://{}", addr);
+
+    loop {
+        let (socket, addr) = listener.accept().await?;
+        let hub = hub.clone();
+        let origins = origins.clone();
+
+        tokio::spawn(async move {
+            handle_http(socket, addr, hub, origins).await;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tcp::chat_auth::Auth;
+    use crate::tcp::slow_consumer::ChatConfig;
+    use crate::tcp::nic_chat;
+    use serde_json::json;
+    use tokio::io::{AsyncReadExt, DuplexStream, ReadHalf, WriteHalf};
+    use tokio::time::{timeout, Duration};
+
+    const KEY: &str = "dGhlIHNhbXBsZSBub25jZQ==";
+
+    fn hub() -> Arc<Hub> {
+        Hub::new(ChatConfig::default(), None, Auth::disabled())
+    }
+
+    fn outbox() -> (Arc<Outbox<Event>>, tokio::sync::mpsc::Receiver<Event>) {
+        Outbox::new(64, 1024)
+    }
+
+    // 핸드셰이크를 건너뛰고 duplex 위에서 바로 WebSocket 세션을 돌린다
+    struct WsClient {
+        ws: WebSocketStream<DuplexStream>,
+    }
+
+    impl WsClient {
+        async fn connect(hub: &Arc<Hub>) -> Self {
+            let (client, server) = tokio::io::duplex(64 * 1024);
+            let hub = hub.clone();
+            tokio::spawn(async move {
+                let ws = WebSocketStream::from_raw_socket(server, Role::Server, None).await;
+                handle_websocket(ws, "127.0.0.1:2".parse().unwrap(), hub).await;
+            });
+            Self {
+                ws: WebSocketStream::from_raw_socket(client, Role::Client, None).await,
+            }
+        }
+
+        async fn send(&mut self, msg: Value) {
+            self.ws.send(Message::text(msg.to_string())).await.unwrap();
+        }
+
+        // 조건에 맞는 envelope이 올 때까지 읽는다
+        async fn expect(&mut self, wanted: impl Fn(&Value) -> bool) -> Value {
+            timeout(Duration::from_secs(5), async {
+                loop {
+                    let message = self.ws.next().await.expect("closed").unwrap();
+                    if let Message::Text(text) = message {
+                        let value: Value = serde_json::from_str(text.as_str()).unwrap();
+                        if wanted(&value) {
+                            return value;
+                        }
+                    }
+                }
+            })
+            .await
+            .expect("envelope never arrived")
+        }
+    }
+
+    // nic_chat 평문 클라이언트
+    struct LineClient {
+        reader: BufReader<ReadHalf<DuplexStream>>,
+        writer: WriteHalf<DuplexStream>,
+    }
+
+    impl LineClient {
+        async fn login(hub: &Arc<Hub>, nick: &str) -> Self {
+            let (client, server) = tokio::io::duplex(64 * 1024);
+            tokio::spawn(nic_chat::handle_client(server, "127.0.0.1:3".parse().unwrap(), hub.clone()));
+
+            let (reader, writer) = tokio::io::split(client);
+            let mut client = Self {
+                reader: BufReader::new(reader),
+                writer,
+            };
+            client.expect("Enter your nickname: ").await;
+            client.send(nick).await;
+            client.expect(&format!("Welcome, {}!", nick)).await;
+            client
+        }
+
+        async fn send(&mut self, line: &str) {
+            self.writer.write_all(format!("{}\n", line).as_bytes()).await.unwrap();
+        }
+
+        // prompt에는 개행이 없으니 바이트 단위로 읽는다
+        async fn expect(&mut self, needle: &str) {
+            let mut seen = Vec::new();
+            let found = timeout(Duration::from_secs(5), async {
+                while !String::from_utf8_lossy(&seen).contains(needle) {
+                    seen.push(self.reader.read_u8().await.expect("connection closed"));
+                }
+            })
+            .await;
+            assert!(found.is_ok(), "never saw {:?} in {:?}", needle, String::from_utf8_lossy(&seen));
+        }
+    }
+
+    fn of_type<'a>(kind: &'a str) -> impl Fn(&Value) -> bool + 'a {
+        move |value| value["type"] == kind
+    }
+
+    fn start(origins: &[&str]) -> DuplexStream {
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let hub = Hub::new(ChatConfig::default(), None, Auth::disabled());
+        let origins = Arc::new(origins.iter().map(|o| o.to_string()).collect());
+        let addr = "127.0.0.1:1".parse().unwrap();
+        tokio::spawn(handle_http(server, addr, hub, origins));
+        client
+    }
+
+    fn upgrade(extra: &str) -> String {
+        format!(
+            "GET /ws HTTP/1.1\r\nHost: chat.local:8081\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: {}\r\n{}\r\n",
+            KEY, extra
+        )
+    }
+
+    // 응답 header 부분만 읽는다. 101 뒤에는 연결이 열린 채로 남는다
+    async fn status_of(origins: &[&str], request: &str) -> String {
+        let mut client = start(origins);
+        client.write_all(request.as_bytes()).await.unwrap();
+
+        let mut response = Vec::new();
+        let mut byte = [0u8; 1];
+        while !response.ends_with(b"\r\n\r\n") {
+            if client.read(&mut byte).await.unwrap() == 0 {
+                break;
+            }
+            response.push(byte[0]);
+        }
+        String::from_utf8(response).unwrap()
+    }
+
+    #[tokio::test]
+    async fn same_host_upgrade_switches_protocols() {
+        let response = status_of(
+            &[],
+            &upgrade("Sec-WebSocket-Version: 13\r\nOrigin: http://chat.local:8081\r\n"),
+        )
+        .await;
+        assert!(response.starts_with("HTTP/1.1 101"), "{}", response);
+        assert!(response.contains(&derive_accept_key(KEY.as_bytes())));
+    }
+
+    #[tokio::test]
+    async fn unsupported_version_gets_426_with_supported_version() {
+        let response = status_of(&[], &upgrade("Sec-WebSocket-Version: 8\r\n")).await;
+        assert!(response.starts_with("HTTP/1.1 426"), "{}", response);
+        assert!(response.contains("Sec-WebSocket-Version: 13\r\n"));
+
+        let response = status_of(&[], &upgrade("")).await;
+        assert!(response.starts_with("HTTP/1.1 426"), "{}", response);
+    }
+
+    #[tokio::test]
+    async fn foreign_origin_is_forbidden_unless_listed() {
+        let request = upgrade("Sec-WebSocket-Version: 13\r\nOrigin: https://evil.example\r\n");
+        let response = status_of(&[], &request).await;
+        assert!(response.starts_with("HTTP/1.1 403"), "{}", response);
+
+        let response = status_of(&["https://evil.example"], &request).await;
+        assert!(response.starts_with("HTTP/1.1 101"), "{}", response);
+
+        // Origin이 없는 비브라우저 client는 통과
+        let response = status_of(&[], &upgrade("Sec-WebSocket-Version: 13\r\n")).await;
+        assert!(response.starts_with("HTTP/1.1 101"), "{}", response);
+    }
+
+    #[tokio::test]
+    async fn oversized_request_line_gets_414() {
+        let request = format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(MAX_REQUEST_LINE));
+        let response = status_of(&[], &request).await;
+        assert!(response.starts_with("HTTP/1.1 414"), "{}", response);
+    }
+
+    #[tokio::test]
+    async fn oversized_header_line_gets_431() {
+        let request = format!("GET / HTTP/1.1\r\nX-Big: {}\r\n\r\n", "a".repeat(MAX_HEADER_LINE));
+        let response = status_of(&[], &request).await;
+        assert!(response.starts_with("HTTP/1.1 431"), "{}", response);
+    }
+
+    #[tokio::test]
+    async fn too_many_headers_gets_431() {
+        let headers: String = (0..=MAX_HEADER_LINES)
+            .map(|i| format!("X-{}: v\r\n", i))
+            .collect();
+        let response = status_of(&[], &format!("GET / HTTP/1.1\r\n{}\r\n", headers)).await;
+        assert!(response.starts_with("HTTP/1.1 431"), "{}", response);
+
+        let headers: String = (0..MAX_HEADER_LINES)
+            .map(|i| format!("X-{}: v\r\n", i))
+            .collect();
+        let response = status_of(&[], &format!("GET / HTTP/1.1\r\n{}\r\n", headers)).await;
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    }
+
+    #[tokio::test]
+    async fn hello_registers_the_nickname_once() {
+        let hub = hub();
+        let addr = "127.0.0.1:2".parse().unwrap();
+        let (outbox, _inbox) = outbox();
+
+        let error = hello(&hub, &json!({ "type": "hello" }), addr, &outbox).await.err().unwrap();
+        assert_eq!(error, json!({ "type": "error", "message": "hello needs nick" }));
+
+        let session = hello(&hub, &json!({ "type": "hello", "nick": "alice" }), addr, &outbox)
+            .await
+            .unwrap();
+        assert_eq!(session.nick(), "alice");
+
+        let error = hello(&hub, &json!({ "type": "hello", "nick": "Alice" }), addr, &outbox)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(error["message"], "nickname 'Alice' is already in use");
+
+        let error = hello(&hub, &json!({ "type": "hello", "nick": "no spaces" }), addr, &outbox)
+            .await
+            .err()
+            .unwrap();
+        assert!(error["message"].as_str().unwrap().starts_with("invalid nickname"));
+    }
+
+    #[tokio::test]
+    async fn envelopes_drive_the_session() {
+        let hub = hub();
+        let addr = "127.0.0.1:2".parse().unwrap();
+        let (alice_outbox, mut alice_inbox) = outbox();
+        let (bob_outbox, mut bob_inbox) = outbox();
+        let hello_as = |nick: &str| json!({ "type": "hello", "nick": nick });
+        let mut alice = hello(&hub, &hello_as("alice"), addr, &alice_outbox).await.unwrap();
+        let mut bob = hello(&hub, &hello_as("bob"), addr, &bob_outbox).await.unwrap();
+
+        let send = |session: &mut Session, msg: Value| handle_envelope(&hub, session, &msg);
+
+        assert_eq!(send(&mut alice, json!({ "type": "join", "room": "#Rust" })), None);
+        assert_eq!(send(&mut bob, json!({ "type": "join", "room": "rust" })), None);
+        assert_eq!(
+            send(&mut alice, json!({ "type": "who", "room": "rust" })),
+            Some(json!({ "type": "who", "room": "rust", "members": ["alice", "bob"] }))
+        );
+        assert_eq!(
+            send(&mut alice, json!({ "type": "list" })),
+            Some(json!({ "type": "list", "rooms": [{ "room": "rust", "members": 2 }] }))
+        );
+
+        assert_eq!(
+            send(&mut alice, json!({ "type": "message", "room": "rust", "text": "  hi  " })),
+            None
+        );
+        assert_eq!(send(&mut alice, json!({ "type": "private", "to": "BOB", "text": "psst" })), None);
+
+        // 방 이벤트는 forward task를 거쳐 오므로 기다린다
+        let mut seen = Vec::new();
+        while seen.len() < 3 {
+            let event = timeout(Duration::from_secs(5), bob_inbox.recv()).await.unwrap().unwrap();
+            seen.push(event_json(&event));
+        }
+        assert!(seen.contains(&json!({ "type": "message", "room": "rust", "from": "alice", "text": "hi" })));
+        assert!(seen.contains(&json!({ "type": "private", "from": "alice", "to": "bob", "text": "psst" })));
+        assert!(alice_inbox.try_recv().is_ok());
+
+        let error_of = |reply: Option<Value>| reply.unwrap()["message"].as_str().unwrap().to_string();
+        assert_eq!(
+            error_of(send(&mut alice, json!({ "type": "message", "room": "elsewhere", "text": "x" }))),
+            "you are not in #elsewhere"
+        );
+        assert_eq!(
+            error_of(send(&mut alice, json!({ "type": "private", "to": "ghost", "text": "x" }))),
+            "no such nickname 'ghost'"
+        );
+        assert_eq!(
+            error_of(send(&mut alice, json!({ "type": "message", "room": "rust", "text": " " }))),
+            "message needs room and text"
+        );
+        assert_eq!(error_of(send(&mut alice, json!({ "type": "join" }))), "join needs room");
+        assert_eq!(error_of(send(&mut alice, json!({ "type": "who" }))), "who needs room");
+        assert_eq!(error_of(send(&mut alice, json!({ "type": "dance" }))), "unknown type 'dance'");
+        assert_eq!(error_of(send(&mut alice, json!({ "room": "rust" }))), "missing type");
+
+        assert_eq!(send(&mut bob, json!({ "type": "part", "room": "rust" })), None);
+        assert_eq!(
+            send(&mut bob, json!({ "type": "who", "room": "rust" })),
+            Some(json!({ "type": "who", "room": "rust", "members": ["alice"] }))
+        );
+        assert_eq!(send(&mut bob, json!({ "type": "nick", "nick": "robert" })), None);
+        assert_eq!(bob.nick(), "robert");
+
+        alice.close(&hub);
+        bob.close(&hub);
+        assert!(hub.list().is_empty());
+    }
+
+    #[tokio::test]
+    async fn browser_and_tcp_clients_share_a_room() {
+        let hub = hub();
+        let mut bob = LineClient::login(&hub, "bob").await;
+
+        let mut alice = WsClient::connect(&hub).await;
+        alice.send(json!({ "type": "join", "room": "lobby" })).await;
+        let error = alice.expect(of_type("error")).await;
+        assert!(error["message"].as_str().unwrap().contains("hello"));
+
+        alice.send(json!({ "type": "hello", "nick": "alice" })).await;
+        let welcome = alice.expect(of_type("welcome")).await;
+        assert_eq!(welcome, json!({ "type": "welcome", "nick": "alice", "room": "lobby" }));
+        bob.expect("*** alice joined #lobby").await;
+
+        alice
+            .send(json!({ "type": "message", "room": "lobby", "text": "hi from the browser" }))
+            .await;
+        bob.expect("[#lobby] <alice> hi from the browser").await;
+
+        bob.send("hello back").await;
+        let message = alice.expect(|v| v["type"] == "message" && v["from"] == "bob").await;
+        assert_eq!(message["text"], "hello back");
+
+        alice.send(json!({ "type": "private", "to": "bob", "text": "psst" })).await;
+        bob.expect("[PM alice -> bob] psst").await;
+
+        alice.send(json!({ "type": "who", "room": "lobby" })).await;
+        let who = alice.expect(of_type("who")).await;
+        assert_eq!(who["members"], json!(["alice", "bob"]));
+
+        bob.send("/quit").await;
+        let quit = alice.expect(of_type("quit")).await;
+        assert_eq!(quit["nick"], "bob");
+    }
+}
@@ -1 +1,2 @@
-pub mod basic;
\ No newline at end of file
+pub mod basic;
+pub mod gateway;